@@ -0,0 +1,142 @@
+#!/usr/bin/env bash
+# ci/scripts/audit-atomic-symbols.sh - Check atomic libcalls against nextstep-atomics
+#
+# Purpose: Build tests/probes/atomic-probe (every core atomic operation) for
+#          each target spec, list the __atomic_*/__sync_* symbols the probe
+#          references, and fail if nextstep-atomics does not export one of them.
+# Usage: ./ci/scripts/audit-atomic-symbols.sh [spec-name ...]
+#        (default: m68k-next-nextstep m68k-next-nextstep-68030 m68k-next-nextstep-68040)
+# Environment: NM - nm to use (default: toolchain/bin/llvm-nm, then llvm-nm)
+#
+set -euo pipefail
+
+# Color codes for output
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+YELLOW='\033[1;33m'
+NC='\033[0m' # No Color
+
+PROJECT_ROOT="$(cd "$(dirname "${BASH_SOURCE[0]}")/../.." && pwd)"
+SPEC_DIR="$PROJECT_ROOT/target-specs"
+PROBE_MANIFEST="$PROJECT_ROOT/tests/probes/atomic-probe/Cargo.toml"
+AUDIT_DIR="$PROJECT_ROOT/target/atomic-audit"
+TEST_RESULTS_DIR="$PROJECT_ROOT/docs/ci-status/test-results/atomic-audit"
+
+if [[ $# -gt 0 ]]; then
+    SPECS=("$@")
+else
+    SPECS=(
+        "m68k-next-nextstep"
+        "m68k-next-nextstep-68030"
+        "m68k-next-nextstep-68040"
+    )
+fi
+
+if [[ -n "${NM:-}" ]]; then
+    :
+elif [[ -x "$PROJECT_ROOT/toolchain/bin/llvm-nm" ]]; then
+    NM="$PROJECT_ROOT/toolchain/bin/llvm-nm"
+else
+    NM="llvm-nm"
+fi
+
+# comm needs both lists sorted the same way
+export LC_ALL=C
+
+mkdir -p "$AUDIT_DIR" "$TEST_RESULTS_DIR"
+
+# Atomic libcall names in an archive, one per line, without the Mach-O
+# leading underscore. $1 = archive, remaining args = nm filter flags.
+atomic_symbols() {
+    local archive="$1"
+    shift
+    "$NM" "$@" --format=just-symbols "$archive" 2>/dev/null \
+        | sed -n -E 's/^_?(__(atomic|sync)_[A-Za-z0-9_]+)$/\1/p' \
+        | sort -u
+}
+
+audit_spec() {
+    local spec="$1"
+    local spec_file="$SPEC_DIR/$spec.json"
+    local target_dir="$AUDIT_DIR/$spec"
+    local log_file="$AUDIT_DIR/$spec.log"
+
+    echo "=== $spec ==="
+    if [[ ! -f "$spec_file" ]]; then
+        echo -e "${RED}ERROR: target spec not found: $spec_file${NC}"
+        return 1
+    fi
+
+    if ! cargo +nightly build --release \
+        --manifest-path "$PROBE_MANIFEST" \
+        --target "$spec_file" \
+        --target-dir "$target_dir" \
+        -Z build-std=core \
+        > "$log_file" 2>&1; then
+        echo -e "${RED}ERROR: probe build failed (see $log_file)${NC}"
+        return 1
+    fi
+
+    # The rlib carries the same symbols as nextstep-atomics-staticlib
+    if ! (cd "$PROJECT_ROOT" && cargo +nightly rustc --release \
+        -p nextstep-atomics \
+        --crate-type rlib \
+        --target "$spec_file" \
+        --target-dir "$target_dir" \
+        -Z build-std=core \
+        >> "$log_file" 2>&1); then
+        echo -e "${RED}ERROR: nextstep-atomics build failed (see $log_file)${NC}"
+        return 1
+    fi
+
+    local probe_lib="$target_dir/$spec/release/libatomic_probe.rlib"
+    local atomics_lib="$target_dir/$spec/release/libnextstep_atomics.rlib"
+    local referenced="$AUDIT_DIR/$spec.referenced"
+    local exported="$AUDIT_DIR/$spec.exported"
+    local missing="$AUDIT_DIR/$spec.missing"
+
+    atomic_symbols "$probe_lib" --undefined-only > "$referenced"
+    atomic_symbols "$atomics_lib" --defined-only --extern-only > "$exported"
+    comm -23 "$referenced" "$exported" > "$missing"
+
+    local n_referenced n_exported n_missing
+    n_referenced=$(wc -l < "$referenced" | tr -d ' ')
+    n_exported=$(wc -l < "$exported" | tr -d ' ')
+    n_missing=$(wc -l < "$missing" | tr -d ' ')
+
+    echo "  Referenced by probe: $n_referenced"
+    sed 's/^/    /' "$referenced"
+    echo "  Exported by nextstep-atomics: $n_exported"
+
+    echo "{
+  \"spec\": \"$spec\",
+  \"referenced\": $n_referenced,
+  \"exported\": $n_exported,
+  \"missing\": [$(sed 's/.*/"&"/' "$missing" | paste -sd, -)],
+  \"timestamp\": \"$(date -u +%Y-%m-%dT%H:%M:%SZ)\"
+}" > "$TEST_RESULTS_DIR/$spec-result.json"
+
+    if [[ "$n_missing" -gt 0 ]]; then
+        echo -e "  ${RED}MISSING from nextstep-atomics:${NC}"
+        sed 's/^/    /' "$missing"
+        return 1
+    fi
+    if [[ "$n_referenced" -eq 0 ]]; then
+        echo -e "  ${YELLOW}No atomic libcalls referenced (inline or no atomics)${NC}"
+    fi
+    echo -e "  ${GREEN}OK${NC}"
+}
+
+failed=0
+for spec in "${SPECS[@]}"; do
+    if ! audit_spec "$spec"; then
+        failed=$((failed + 1))
+    fi
+    echo ""
+done
+
+if [[ $failed -gt 0 ]]; then
+    echo -e "${RED}$failed of ${#SPECS[@]} target specs failed the atomic symbol audit${NC}"
+    exit 1
+fi
+echo -e "${GREEN}All ${#SPECS[@]} target specs passed the atomic symbol audit${NC}"
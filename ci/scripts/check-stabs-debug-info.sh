@@ -0,0 +1,121 @@
+#!/usr/bin/env bash
+# ci/scripts/check-stabs-debug-info.sh - Check a debug build carries usable STABS
+#
+# Purpose: Build tests/probes/stabs-probe with debuginfo for each target spec,
+#          link it with nextstep-ld (which keeps stabs), and run
+#          `nextrust-stabs --check` on the executable: NeXTSTEP's gdb only
+#          reads STABS, so the binary needs source file, function, line and
+#          type stabs for source-level debugging on real hardware.
+# Usage: ./ci/scripts/check-stabs-debug-info.sh [spec-name ...]
+#        (default: m68k-next-nextstep)
+#
+set -euo pipefail
+
+# Color codes for output
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+YELLOW='\033[1;33m'
+NC='\033[0m' # No Color
+
+PROJECT_ROOT="$(cd "$(dirname "${BASH_SOURCE[0]}")/../.." && pwd)"
+SPEC_DIR="$PROJECT_ROOT/target-specs"
+PROBE_MANIFEST="$PROJECT_ROOT/tests/probes/stabs-probe/Cargo.toml"
+CHECK_DIR="$PROJECT_ROOT/target/stabs-check"
+TEST_RESULTS_DIR="$PROJECT_ROOT/docs/ci-status/test-results/stabs"
+
+if [[ $# -gt 0 ]]; then
+    SPECS=("$@")
+else
+    SPECS=("m68k-next-nextstep")
+fi
+
+FUNCTIONS=(probe_add probe_length probe_count)
+TYPES=(Point Shape)
+
+mkdir -p "$CHECK_DIR" "$TEST_RESULTS_DIR"
+
+echo "Building host tools..."
+if ! (cd "$PROJECT_ROOT" && cargo build --release -q \
+    -p nextstep-macho -p nextstep-ld -p nextstep-ar); then
+    echo -e "${RED}ERROR: could not build the host tools${NC}"
+    exit 1
+fi
+TOOLS="$PROJECT_ROOT/target/release"
+
+# The target spec's post-link-args ask for -lgcc; nothing in the probe
+# needs it, so an empty archive stands in
+STUB_DIR="$CHECK_DIR/stub"
+mkdir -p "$STUB_DIR"
+rm -f "$STUB_DIR/libgcc.a"
+"$TOOLS/nextstep-ar" rc "$STUB_DIR/libgcc.a"
+
+check_spec() {
+    local spec="$1"
+    local spec_file="$SPEC_DIR/$spec.json"
+    local target_dir="$CHECK_DIR/$spec"
+    local log_file="$CHECK_DIR/$spec.log"
+    local report="$CHECK_DIR/$spec.report"
+    local binary="$target_dir/$spec/debug/stabs-probe"
+    local status="failing"
+
+    echo "=== $spec ==="
+    if [[ ! -f "$spec_file" ]]; then
+        echo -e "${RED}ERROR: target spec not found: $spec_file${NC}"
+        return 1
+    fi
+
+    if ! cargo +nightly rustc \
+        --manifest-path "$PROBE_MANIFEST" \
+        --target "$spec_file" \
+        --target-dir "$target_dir" \
+        -Z build-std=core \
+        -- -C debuginfo=2 \
+        -C linker="$TOOLS/nextstep-ld" \
+        -C link-arg=-L"$STUB_DIR" \
+        > "$log_file" 2>&1; then
+        echo -e "${RED}ERROR: probe build failed (see $log_file)${NC}"
+        return 1
+    fi
+
+    local args=()
+    for f in "${FUNCTIONS[@]}"; do args+=(--function "$f"); done
+    for t in "${TYPES[@]}"; do args+=(--type "$t"); done
+    if "$TOOLS/nextrust-stabs" --check "${args[@]}" "$binary" > "$report"; then
+        status="passing"
+        sed 's/^/  /' "$report"
+        "$TOOLS/nextrust-stabs" --summary "$binary" | sed 's/^/    /'
+    else
+        sed 's/^/  /' "$report"
+        # Say why when the backend produced DWARF instead
+        if "$TOOLS/nextrust-objdump" -h "$binary" 2>/dev/null | grep -q "__DWARF"; then
+            echo -e "  ${YELLOW}The binary has __DWARF sections but no usable STABS:" \
+                "the m68k backend emits DWARF, which NeXTSTEP's gdb cannot read${NC}"
+        fi
+    fi
+
+    echo "{
+  \"spec\": \"$spec\",
+  \"status\": \"$status\",
+  \"problems\": [$(grep ': error: ' "$report" | sed 's/.*: error: //; s/"/\\"/g; s/.*/"&"/' | paste -sd, -)],
+  \"timestamp\": \"$(date -u +%Y-%m-%dT%H:%M:%SZ)\"
+}" > "$TEST_RESULTS_DIR/$spec-result.json"
+
+    if [[ "$status" != "passing" ]]; then
+        return 1
+    fi
+    echo -e "  ${GREEN}OK${NC}"
+}
+
+failed=0
+for spec in "${SPECS[@]}"; do
+    if ! check_spec "$spec"; then
+        failed=$((failed + 1))
+    fi
+    echo ""
+done
+
+if [[ $failed -gt 0 ]]; then
+    echo -e "${RED}$failed of ${#SPECS[@]} target specs lack usable STABS${NC}"
+    exit 1
+fi
+echo -e "${GREEN}All ${#SPECS[@]} target specs carry usable STABS${NC}"
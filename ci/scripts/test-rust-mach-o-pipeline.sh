@@ -185,6 +185,31 @@ else
     fi
 fi
 
+# Test 7: NeXTSTEP 3.3 conformance of the generated objects
+log_step "Test 7: NeXTSTEP 3.3 Mach-O conformance"
+
+VALIDATE_STATUS="Skipped"
+if cargo build --release -q -p nextstep-macho --bin nextrust-macho-validate; then
+    VALIDATOR="$PROJECT_ROOT/target/release/nextrust-macho-validate"
+    OBJECTS=()
+    for obj in test_ir.o test_c.o test_reloc.o; do
+        [ -f "$obj" ] && OBJECTS+=("$obj")
+    done
+    if [ ${#OBJECTS[@]} -eq 0 ]; then
+        log_warning "⚠️  No objects to validate"
+    elif "$VALIDATOR" --cpu 68040 "${OBJECTS[@]}" > validate_output.txt; then
+        VALIDATE_STATUS="Passing"
+        log_info "✅ All objects conform to NeXTSTEP 3.3 rules"
+        sed 's/^/    /' validate_output.txt
+    else
+        VALIDATE_STATUS="Failing"
+        log_error "❌ Conformance violations:"
+        grep "error\[" validate_output.txt | sed 's/^/    /' >&2
+    fi
+else
+    log_warning "⚠️  Could not build nextrust-macho-validate"
+fi
+
 # Summary
 log_step "Pipeline Test Summary"
 
@@ -196,6 +221,7 @@ echo "✅ C → M68k Mach-O compilation: Working"
 echo "✅ Custom target specification: Available"
 echo "ℹ️  Symbol relocations: Partial (needs testing)"
 echo "⚠️  Linking: Needs investigation"
+echo "ℹ️  NeXTSTEP 3.3 conformance: $VALIDATE_STATUS"
 
 echo ""
 echo "Pipeline Status: Rust compilation blocked by scheduler bug"
@@ -204,6 +230,6 @@ echo "LLVM backend: Fully functional for M68k Mach-O generation"
 
 # Clean up
 rm -f test_ir.ll test_ir.o test_ir.s test_c.c test_c.o test_reloc.c test_reloc.o 
-rm -f reloc_info.txt link_output.txt simple.ld test_linked
+rm -f reloc_info.txt link_output.txt validate_output.txt simple.ld test_linked
 
 log_info "✅ Pipeline test completed!"
\ No newline at end of file
@@ -0,0 +1,276 @@
+//! `.app` wrappers
+//!
+//! A NeXTSTEP application is a directory, `Name.app`, holding an
+//! executable of the same name, its icon and its resources. Interface
+//! files go in a language directory (`English.lproj/Name.nib`); other
+//! resources sit at the top level unless they already name one. The
+//! wrapper also gets an `Info-nextstep.plist` naming the executable, icon
+//! and main nib, which Workspace Manager and `NSBundle` read.
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::plist::Plist;
+use nextstep_macho::consts::{CPU_TYPE_MC680X0, MH_EXECUTE};
+use nextstep_macho::{is_fat, FatFile, MachO};
+use std::path::Path;
+
+/// Language directory for resources that do not name one
+pub const DEFAULT_LPROJ: &str = "English.lproj";
+
+/// Mode bits of written files and directories
+pub const FILE_MODE: u32 = 0o644;
+pub const EXEC_MODE: u32 = 0o755;
+
+/// What a tree entry holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Contents {
+    Dir,
+    File(Vec<u8>),
+}
+
+/// One file or directory of a wrapper or package, by relative path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: String,
+    pub mode: u32,
+    pub contents: Contents,
+}
+
+impl Entry {
+    pub fn dir(path: &str) -> Entry {
+        Entry { path: path.to_string(), mode: EXEC_MODE, contents: Contents::Dir }
+    }
+
+    pub fn file(path: &str, mode: u32, data: Vec<u8>) -> Entry {
+        Entry { path: path.to_string(), mode, contents: Contents::File(data) }
+    }
+
+    /// Size of a file's data, 0 for directories
+    pub fn size(&self) -> usize {
+        match &self.contents {
+            Contents::Dir => 0,
+            Contents::File(data) => data.len(),
+        }
+    }
+}
+
+/// Entries in the order they were added, each directory before its contents
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Tree {
+    pub entries: Vec<Entry>,
+}
+
+impl Tree {
+    fn has(&self, path: &str) -> bool {
+        self.entries.iter().any(|e| e.path == path)
+    }
+
+    /// Add `entry`, and any of its directories not yet there
+    pub fn add(&mut self, entry: Entry) -> Result<()> {
+        if let Some((parent, _)) = entry.path.rsplit_once('/') {
+            if !self.has(parent) {
+                self.add(Entry::dir(parent))?;
+            }
+        }
+        if self.has(&entry.path) {
+            return Err(Error::Metadata(format!("{} is in the wrapper twice", entry.path)));
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Add the file or directory at `source` as `path`, recursively
+    pub fn add_path(&mut self, path: &str, source: &Path) -> Result<()> {
+        let metadata = std::fs::metadata(source).map_err(|e| Error::io(source, e))?;
+        if !metadata.is_dir() {
+            let data = std::fs::read(source).map_err(|e| Error::io(source, e))?;
+            return self.add(Entry::file(path, mode_of(&metadata), data));
+        }
+        self.add(Entry::dir(path))?;
+        let mut children = std::fs::read_dir(source)
+            .and_then(|dir| dir.map(|e| e.map(|e| e.file_name())).collect::<std::io::Result<Vec<_>>>())
+            .map_err(|e| Error::io(source, e))?;
+        children.sort();
+        for child in children {
+            let name = child.to_string_lossy();
+            self.add_path(&format!("{}/{}", path, name), &source.join(&child))?;
+        }
+        Ok(())
+    }
+
+    /// Write the tree under `dir`, replacing what is there
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        for entry in &self.entries {
+            let path = dir.join(&entry.path);
+            match &entry.contents {
+                Contents::Dir => {
+                    if !entry.path.contains('/') && path.exists() {
+                        std::fs::remove_dir_all(&path).map_err(|e| Error::io(&path, e))?;
+                    }
+                    std::fs::create_dir_all(&path).map_err(|e| Error::io(&path, e))?;
+                }
+                Contents::File(data) => std::fs::write(&path, data).map_err(|e| Error::io(&path, e))?,
+            }
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let permissions = std::fs::Permissions::from_mode(entry.mode);
+                std::fs::set_permissions(&path, permissions).map_err(|e| Error::io(&path, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn mode_of(metadata: &std::fs::Metadata) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o111 != 0 {
+            return EXEC_MODE;
+        }
+    }
+    let _ = metadata;
+    FILE_MODE
+}
+
+/// Check `data` is an m68k `MH_EXECUTE`, thin or with an m68k slice
+pub fn check_executable(path: &Path, data: &[u8]) -> Result<()> {
+    let not = |reason: String| Error::NotExecutable { path: path.display().to_string(), reason };
+    let slice = if is_fat(data) {
+        let fat = FatFile::parse(data).map_err(|e| not(e.to_string()))?;
+        fat.find(CPU_TYPE_MC680X0).ok_or_else(|| not("no m68k slice".into()))?.1
+    } else {
+        data
+    };
+    let macho = MachO::parse(slice).map_err(|e| not(e.to_string()))?;
+    if macho.header.cputype != CPU_TYPE_MC680X0 {
+        return Err(not(format!("cputype {}", macho.header.cputype)));
+    }
+    if macho.header.filetype != MH_EXECUTE {
+        return Err(not(format!("file type {:#x}, not MH_EXECUTE", macho.header.filetype)));
+    }
+    Ok(())
+}
+
+/// Where resource `source` goes in the wrapper
+pub fn resource_path(source: &Path) -> String {
+    let name = source.file_name().map_or_else(String::new, |n| n.to_string_lossy().into_owned());
+    let parent = source.parent().and_then(Path::file_name).map(|p| p.to_string_lossy().into_owned());
+    match parent {
+        Some(lproj) if lproj.ends_with(".lproj") => format!("{}/{}", lproj, name),
+        _ if name.ends_with(".nib") => format!("{}/{}", DEFAULT_LPROJ, name),
+        _ => name,
+    }
+}
+
+/// The `Info-nextstep.plist` for `config`
+pub fn info_plist(config: &Config) -> Plist {
+    let mut entries = vec![("NSExecutable".to_string(), Plist::from(config.app_name.as_str()))];
+    if config.icon.is_some() {
+        entries.push(("NSIcon".into(), Plist::String(format!("{}.tiff", config.app_name))));
+    }
+    if let Some(nib) = &config.main_nib {
+        entries.push(("NSMainNibFile".into(), Plist::from(nib.as_str())));
+    }
+    entries.push(("NSAppVersion".into(), Plist::from(config.version.as_str())));
+    for (key, value) in &config.info {
+        entries.retain(|(k, _)| k != key);
+        entries.push((key.clone(), value.clone()));
+    }
+    Plist::Dict(entries)
+}
+
+/// The wrapper for `config` around `executable` (already checked)
+pub fn wrapper(config: &Config, executable: Vec<u8>) -> Result<Tree> {
+    let app = format!("{}.app", config.app_name);
+    let mut tree = Tree::default();
+    tree.add(Entry::dir(&app))?;
+    tree.add(Entry::file(&format!("{}/{}", app, config.app_name), EXEC_MODE, executable))?;
+    let plist = info_plist(config).to_text().into_bytes();
+    tree.add(Entry::file(&format!("{}/Info-nextstep.plist", app), FILE_MODE, plist))?;
+    if let Some(icon) = &config.icon {
+        let data = std::fs::read(icon).map_err(|e| Error::io(icon, e))?;
+        tree.add(Entry::file(&format!("{}/{}.tiff", app, config.app_name), FILE_MODE, data))?;
+    }
+    for resource in &config.resources {
+        tree.add_path(&format!("{}/{}", app, resource_path(resource)), resource)?;
+    }
+    Ok(tree)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::DEFAULT_INSTALL_LOCATION;
+    use nextstep_macho::consts::{CPU_SUBTYPE_MC68040, MH_MAGIC};
+    use std::path::PathBuf;
+
+    /// Header of an m68k executable with no load commands
+    pub(crate) fn executable() -> Vec<u8> {
+        let words = [MH_MAGIC, CPU_TYPE_MC680X0 as u32, CPU_SUBTYPE_MC68040 as u32, MH_EXECUTE, 0, 0, 0];
+        words.iter().flat_map(|w| w.to_be_bytes()).collect()
+    }
+
+    pub(crate) fn config(resources: Vec<PathBuf>) -> Config {
+        Config {
+            package: "hello".into(),
+            version: "1.0".into(),
+            description: Some("Says hello".into()),
+            manifest_dir: PathBuf::from("."),
+            target_dir: PathBuf::from("target"),
+            bin: "hello".into(),
+            app_name: "Hello".into(),
+            icon: None,
+            main_nib: Some("Hello".into()),
+            resources,
+            info: vec![("NSAppVersion".into(), "1.0b1".into())],
+            install_location: DEFAULT_INSTALL_LOCATION.into(),
+            title: "Hello".into(),
+        }
+    }
+
+    #[test]
+    fn test_wrapper() {
+        let dir = std::env::temp_dir().join(format!("cargo-nextstep-app-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("Hello.nib")).unwrap();
+        std::fs::write(dir.join("Hello.nib/objects.nib"), b"nib").unwrap();
+        std::fs::write(dir.join("Help.tiff"), b"tiff").unwrap();
+        let config = config(vec![dir.join("Hello.nib"), dir.join("Help.tiff")]);
+
+        let tree = wrapper(&config, executable()).unwrap();
+        let paths: Vec<_> = tree.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "Hello.app",
+                "Hello.app/Hello",
+                "Hello.app/Info-nextstep.plist",
+                "Hello.app/English.lproj",
+                "Hello.app/English.lproj/Hello.nib",
+                "Hello.app/English.lproj/Hello.nib/objects.nib",
+                "Hello.app/Help.tiff",
+            ]
+        );
+        assert_eq!(tree.entries[1].mode, EXEC_MODE);
+        assert_eq!(
+            tree.entries[2].contents,
+            Contents::File(b"{\n    NSExecutable = Hello;\n    NSMainNibFile = Hello;\n    NSAppVersion = 1.0b1;\n}\n".to_vec())
+        );
+
+        tree.write(&dir).unwrap();
+        assert_eq!(std::fs::read(dir.join("Hello.app/English.lproj/Hello.nib/objects.nib")).unwrap(), b"nib");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_executable() {
+        let path = Path::new("hello");
+        assert!(check_executable(path, &executable()).is_ok());
+        let mut object = executable();
+        object[15] = 1;
+        assert!(matches!(check_executable(path, &object), Err(Error::NotExecutable { .. })));
+        assert!(check_executable(path, b"#!/bin/sh\n").is_err());
+        assert_eq!(resource_path(Path::new("res/German.lproj/Hello.nib")), "German.lproj/Hello.nib");
+    }
+}
@@ -0,0 +1,328 @@
+//! cargo-nextstep - `cargo nextstep` subcommands
+//!
+//! Usage: cargo nextstep build [OPTIONS] [CARGO ARGS...]
+//!        cargo nextstep run [OPTIONS] [CARGO ARGS...] [-- ARGS...]
+//!        cargo nextstep test [OPTIONS] [--no-run] [CARGO ARGS...] [-- ARGS...]
+//!        cargo nextstep bundle [OPTIONS]
+//!
+//! build, run, test: build for NeXTSTEP with -Zbuild-std and nextstep-ld;
+//! run and test hand the executables to a runner (an emulator)
+//!   --cpu generic|68030|68040  CPU to build for, picking its target spec (generic)
+//!   --target-spec FILE         Use this target spec instead
+//!   --toolchain NAME           Rustup toolchain (NEXTRUST_TOOLCHAIN, or nightly)
+//!   --linker FILE              nextstep-ld to link with (NEXTSTEP_LD, or the one
+//!                              next to cargo-nextstep, or on PATH)
+//!   -L DIR                     Library directory for the link, e.g. with libgcc.a
+//!   --message-format human|json
+//!   --runner COMMAND           Runner for run and test (or CARGO_TARGET_<SPEC>_RUNNER)
+//!   --no-run                   With test, only build the test executables
+//! Other arguments (--release, -p, --bin, --features, ...) go to cargo;
+//! those after `--` go to the program.
+//!
+//! bundle: wrap a built binary as Name.app and package it as Name.pkg
+//!   --manifest-path PATH  Cargo.toml of the package
+//!   -p, --package NAME    Package to bundle, in a workspace
+//!   --bin NAME            Binary to wrap, when there are several
+//!   --target TRIPLE       Target the binary was built for (m68k-next-nextstep)
+//!   --release             Take the release build
+//!   --profile NAME        Take the build of another profile
+//!   --executable FILE     Wrap FILE instead of the built binary
+//!   --out-dir DIR         Where to write (target/TRIPLE/PROFILE/bundle)
+//!   --no-pkg              Only make the .app wrapper
+//!
+//! Files in the package payload are dated from `SOURCE_DATE_EPOCH`, or the
+//! current time.
+//!
+//! Exits 101 when cargo fails or a test fails, as cargo does, and with
+//! the program's status after run.
+
+use cargo_nextstep::config::cargo_metadata;
+use cargo_nextstep::driver::{self, Artifact, BuildOptions, MessageFormat, DEFAULT_TOOLCHAIN};
+use cargo_nextstep::json::Value;
+use cargo_nextstep::target::{find_spec_dir, Cpu, TargetSpec};
+use cargo_nextstep::{app, pkg, Config};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const USAGE: &str = "usage: cargo nextstep build|run|test [--cpu generic|68030|68040] [--target-spec FILE] [--toolchain NAME]
+                             [--linker FILE] [-L DIR]... [--message-format human|json] [--runner COMMAND]
+                             [--no-run] [CARGO ARGS...] [-- ARGS...]
+       cargo nextstep bundle [--manifest-path PATH] [-p NAME] [--bin NAME] [--target TRIPLE]
+                             [--release | --profile NAME] [--executable FILE] [--out-dir DIR] [--no-pkg]";
+
+// Cargo's exit status for a failed build or test
+const CARGO_FAILURE: u8 = 101;
+
+#[derive(Default)]
+struct DriverArgs {
+    cpu: Cpu,
+    target_spec: Option<PathBuf>,
+    toolchain: Option<String>,
+    linker: Option<PathBuf>,
+    lib_dirs: Vec<PathBuf>,
+    message_format: MessageFormat,
+    runner: Option<String>,
+    no_run: bool,
+    cargo_args: Vec<String>,
+    program_args: Vec<String>,
+}
+
+fn parse_driver_args(mut args: impl Iterator<Item = String>) -> Result<DriverArgs, String> {
+    let mut parsed = DriverArgs::default();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || inline.clone().or_else(|| args.next()).ok_or_else(|| format!("{} needs a value\n{}", flag, USAGE));
+        match flag.as_str() {
+            "--cpu" => {
+                let name = value()?;
+                parsed.cpu = Cpu::from_name(&name).ok_or_else(|| format!("unknown CPU {}\n{}", name, USAGE))?;
+            }
+            "--target-spec" => parsed.target_spec = Some(value()?.into()),
+            "--toolchain" => parsed.toolchain = Some(value()?),
+            "--linker" => parsed.linker = Some(value()?.into()),
+            "-L" => parsed.lib_dirs.push(value()?.into()),
+            "--message-format" => {
+                parsed.message_format = match value()?.as_str() {
+                    "human" | "short" => MessageFormat::Human,
+                    "json" => MessageFormat::Json,
+                    other => return Err(format!("unknown message format {}\n{}", other, USAGE)),
+                }
+            }
+            "--runner" => parsed.runner = Some(value()?),
+            "--no-run" => parsed.no_run = true,
+            "--" => {
+                parsed.program_args.extend(args.by_ref());
+            }
+            _ => parsed.cargo_args.push(arg),
+        }
+    }
+    Ok(parsed)
+}
+
+// The value of cargo's --manifest-path among the arguments passed to it
+fn manifest_path(cargo_args: &[String]) -> Option<PathBuf> {
+    let at = cargo_args.iter().position(|a| a == "--manifest-path")?;
+    cargo_args.get(at + 1).map(PathBuf::from)
+}
+
+fn build_options(args: &DriverArgs) -> Result<BuildOptions, String> {
+    let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+    let spec_path = match &args.target_spec {
+        Some(path) => path.clone(),
+        None => find_spec_dir(&cwd).map_err(|e| e.to_string())?.join(format!("{}.json", args.cpu.spec_name())),
+    };
+    let spec = TargetSpec::load(&spec_path).map_err(|e| e.to_string())?;
+    let metadata = cargo_metadata(manifest_path(&args.cargo_args).as_deref()).map_err(|e| e.to_string())?;
+    let target_dir = metadata.get("target_directory").and_then(Value::as_str).unwrap_or("target");
+    let toolchain = args.toolchain.clone().or_else(|| std::env::var("NEXTRUST_TOOLCHAIN").ok());
+    Ok(BuildOptions {
+        stub_dir: Path::new(target_dir).join("nextstep").join(&spec.name),
+        spec,
+        toolchain: toolchain.unwrap_or_else(|| DEFAULT_TOOLCHAIN.to_string()),
+        linker: args.linker.clone().unwrap_or_else(driver::default_linker),
+        lib_dirs: args.lib_dirs.clone(),
+        message_format: args.message_format,
+        cargo_args: args.cargo_args.clone(),
+    })
+}
+
+fn runner(args: &DriverArgs, options: &BuildOptions) -> Result<String, String> {
+    let variable = format!("{}RUNNER", options.spec.env_prefix());
+    args.runner
+        .clone()
+        .or_else(|| std::env::var(&variable).ok())
+        .ok_or_else(|| format!("no runner for {}; pass --runner or set {}", options.spec.name, variable))
+}
+
+fn exit(status: std::process::ExitStatus) -> ExitCode {
+    match status.code() {
+        Some(code) => ExitCode::from(code as u8),
+        None => ExitCode::from(CARGO_FAILURE),
+    }
+}
+
+// Build, then run the one binary or example built
+fn drive_run(args: DriverArgs) -> Result<ExitCode, String> {
+    let options = build_options(&args)?;
+    let (ok, artifacts) = driver::build("build", &options).map_err(|e| e.to_string())?;
+    if !ok {
+        return Ok(ExitCode::from(CARGO_FAILURE));
+    }
+    let runnable: Vec<&Artifact> = artifacts
+        .iter()
+        .filter(|a| !a.test && a.kinds.iter().any(|k| k == "bin" || k == "example"))
+        .collect();
+    let executable = match runnable.as_slice() {
+        [one] => &one.executable,
+        [] => return Err("nothing to run: no binary was built".to_string()),
+        _ => {
+            let names: Vec<&str> = runnable.iter().map(|a| a.name.as_str()).collect();
+            return Err(format!("several executables built ({}); pick one with --bin or --example", names.join(", ")));
+        }
+    };
+    let runner = runner(&args, &options)?;
+    eprintln!("     Running `{} {}`", runner, executable.display());
+    let status = driver::run(&runner, executable, &args.program_args).map_err(|e| e.to_string())?;
+    Ok(exit(status))
+}
+
+// Build the test executables, then run each
+fn drive_test(mut args: DriverArgs) -> Result<ExitCode, String> {
+    args.cargo_args.push("--no-run".to_string());
+    let options = build_options(&args)?;
+    let (ok, artifacts) = driver::build("test", &options).map_err(|e| e.to_string())?;
+    if !ok {
+        return Ok(ExitCode::from(CARGO_FAILURE));
+    }
+    let tests: Vec<&Artifact> = artifacts.iter().filter(|a| a.test).collect();
+    if args.no_run {
+        for test in &tests {
+            eprintln!("  Executable {}", test.executable.display());
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    let runner = runner(&args, &options)?;
+    let mut failed = Vec::new();
+    for test in &tests {
+        eprintln!("     Running {}", test.executable.display());
+        let status = driver::run(&runner, &test.executable, &args.program_args).map_err(|e| e.to_string())?;
+        if !status.success() {
+            failed.push(test.name.as_str());
+        }
+    }
+    if !failed.is_empty() {
+        eprintln!("error: test failed in {}", failed.join(", "));
+        return Ok(ExitCode::from(CARGO_FAILURE));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn drive(command: &str, args: DriverArgs) -> Result<ExitCode, String> {
+    match command {
+        "run" => drive_run(args),
+        "test" => drive_test(args),
+        _ => {
+            let options = build_options(&args)?;
+            let (ok, artifacts) = driver::build("build", &options).map_err(|e| e.to_string())?;
+            if args.message_format == MessageFormat::Human {
+                for artifact in artifacts.iter().filter(|a| !a.test) {
+                    eprintln!("       Built {}", artifact.executable.display());
+                }
+            }
+            Ok(if ok { ExitCode::SUCCESS } else { ExitCode::from(CARGO_FAILURE) })
+        }
+    }
+}
+
+const DEFAULT_TARGET: &str = "m68k-next-nextstep";
+
+struct BundleArgs {
+    manifest_path: Option<PathBuf>,
+    package: Option<String>,
+    bin: Option<String>,
+    target: String,
+    profile: String,
+    executable: Option<PathBuf>,
+    out_dir: Option<PathBuf>,
+    pkg: bool,
+}
+
+fn parse_bundle_args(mut args: impl Iterator<Item = String>) -> Result<BundleArgs, String> {
+    let mut parsed = BundleArgs {
+        manifest_path: None,
+        package: None,
+        bin: None,
+        target: DEFAULT_TARGET.to_string(),
+        profile: "dev".to_string(),
+        executable: None,
+        out_dir: None,
+        pkg: true,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value\n{}", arg, USAGE));
+        match arg.as_str() {
+            "--manifest-path" => parsed.manifest_path = Some(value()?.into()),
+            "-p" | "--package" => parsed.package = Some(value()?),
+            "--bin" => parsed.bin = Some(value()?),
+            "--target" => parsed.target = value()?,
+            "--release" => parsed.profile = "release".to_string(),
+            "--profile" => parsed.profile = value()?,
+            "--executable" => parsed.executable = Some(value()?.into()),
+            "--out-dir" => parsed.out_dir = Some(value()?.into()),
+            "--no-pkg" => parsed.pkg = false,
+            _ => return Err(format!("unknown argument {}\n{}", arg, USAGE)),
+        }
+    }
+    Ok(parsed)
+}
+
+fn timestamp() -> u32 {
+    if let Some(epoch) = std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|v| v.parse().ok()) {
+        return epoch;
+    }
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as u32)
+}
+
+fn bundle(args: BundleArgs) -> Result<(), String> {
+    let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+    let metadata = cargo_metadata(args.manifest_path.as_deref()).map_err(|e| e.to_string())?;
+    let config = Config::from_metadata(&metadata, args.package.as_deref(), args.bin.as_deref(), &cwd)
+        .map_err(|e| e.to_string())?;
+
+    // Cargo's directory for the dev profile is still "debug"
+    let profile_dir = if args.profile == "dev" { "debug" } else { args.profile.as_str() };
+    let build_dir = config.target_dir.join(&args.target).join(profile_dir);
+    let executable = args.executable.unwrap_or_else(|| build_dir.join(&config.bin));
+    let data = std::fs::read(&executable)
+        .map_err(|e| format!("{}: {} (build it first, or pass --executable)", executable.display(), e))?;
+    app::check_executable(&executable, &data).map_err(|e| e.to_string())?;
+
+    let out_dir = args.out_dir.unwrap_or_else(|| build_dir.join("bundle"));
+    std::fs::create_dir_all(&out_dir).map_err(|e| format!("{}: {}", out_dir.display(), e))?;
+    let wrapper = app::wrapper(&config, data).map_err(|e| e.to_string())?;
+    wrapper.write(&out_dir).map_err(|e| e.to_string())?;
+    println!("{}", out_dir.join(format!("{}.app", config.app_name)).display());
+    if args.pkg {
+        let package = pkg::package(&config, &wrapper, timestamp()).map_err(|e| e.to_string())?;
+        package.write(&out_dir).map_err(|e| e.to_string())?;
+        println!("{}", out_dir.join(format!("{}.pkg", config.app_name)).display());
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    // Cargo runs `cargo-nextstep nextstep ARGS...`; allow running it directly too
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("nextstep") {
+        args.next();
+    }
+    let mut format = MessageFormat::Human;
+    let result = match args.next().as_deref() {
+        Some(command @ ("build" | "run" | "test")) => parse_driver_args(args).and_then(|args| {
+            format = args.message_format;
+            drive(command, args)
+        }),
+        Some("bundle") => parse_bundle_args(args).and_then(bundle).map(|()| ExitCode::SUCCESS),
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Some(command) => Err(format!("unknown command {}\n{}", command, USAGE)),
+        None => Err(USAGE.to_string()),
+    };
+    match result {
+        Ok(code) => code,
+        Err(message) if format == MessageFormat::Json => {
+            println!("{}", driver::diagnostic("error", &message));
+            ExitCode::FAILURE
+        }
+        Err(message) => {
+            eprintln!("cargo-nextstep: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
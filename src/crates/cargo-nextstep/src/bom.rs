@@ -0,0 +1,275 @@
+//! Bills of materials
+//!
+//! Installer.app lists what a package installs, and checks it, from the
+//! package's `.bom`: the `BOMStore` file `mkbom` writes and `lsbom`
+//! reads. It is a big-endian block store, a 512-byte header, then blocks
+//! found through an index table and named through a variable table:
+//!
+//! - `BomInfo`: version and path count
+//! - `Paths`: a B-tree whose leaves list each path as its parent's id
+//!   and its name, with type, mode, owner, size and `cksum` CRC
+//! - `HLIndex`, `VIndex`, `Size64`: empty trees for hard links, virtual
+//!   paths and large files
+//!
+//! Paths are written as `.` and `./Name.app/...`, as in the payload.
+
+use crate::app::{Contents, Entry};
+use crate::error::{Error, Result};
+
+pub const MAGIC: &[u8; 8] = b"BOMStore";
+const HEADER_SIZE: usize = 512;
+const TREE_BLOCK_SIZE: u32 = 4096;
+/// Paths per leaf, so a leaf fits in a tree block
+const LEAF_PATHS: usize = 256;
+
+const TYPE_FILE: u8 = 1;
+const TYPE_DIR: u8 = 2;
+const S_IFREG: u32 = 0o100000;
+const S_IFDIR: u32 = 0o040000;
+
+/// The POSIX `cksum` CRC of `data`
+pub fn cksum(data: &[u8]) -> u32 {
+    fn update(crc: u32, byte: u8) -> u32 {
+        (0..8).fold(crc ^ (u32::from(byte) << 24), |crc, _| {
+            if crc & 0x8000_0000 != 0 {
+                crc << 1 ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            }
+        })
+    }
+    let mut crc = data.iter().fold(0, |crc, &b| update(crc, b));
+    let mut len = data.len();
+    while len != 0 {
+        crc = update(crc, len as u8);
+        len >>= 8;
+    }
+    !crc
+}
+
+#[derive(Default)]
+struct Store {
+    /// Block 0 is the null block
+    blocks: Vec<Vec<u8>>,
+}
+
+impl Store {
+    fn add(&mut self, block: Vec<u8>) -> u32 {
+        if self.blocks.is_empty() {
+            self.blocks.push(Vec::new());
+        }
+        self.blocks.push(block);
+        self.blocks.len() as u32 - 1
+    }
+
+    fn tree(&mut self, child: u32, block_size: u32, paths: u32) -> u32 {
+        let mut block = b"tree".to_vec();
+        for word in [1, child, block_size, paths] {
+            block.extend_from_slice(&word.to_be_bytes());
+        }
+        block.push(0);
+        self.add(block)
+    }
+
+    // A B-tree node: leaf or not, sibling leaves, then (value, key) pairs
+    fn node(&mut self, leaf: bool, forward: u32, backward: u32, pairs: &[(u32, u32)]) -> u32 {
+        let mut block = Vec::with_capacity(12 + 8 * pairs.len());
+        block.extend_from_slice(&u16::from(leaf).to_be_bytes());
+        block.extend_from_slice(&(pairs.len() as u16).to_be_bytes());
+        block.extend_from_slice(&forward.to_be_bytes());
+        block.extend_from_slice(&backward.to_be_bytes());
+        for (value, key) in pairs {
+            block.extend_from_slice(&value.to_be_bytes());
+            block.extend_from_slice(&key.to_be_bytes());
+        }
+        self.add(block)
+    }
+
+    fn empty_tree(&mut self, block_size: u32) -> u32 {
+        let leaf = self.node(true, 0, 0, &[]);
+        self.tree(leaf, block_size, 0)
+    }
+}
+
+fn path_info(entry: &Entry, mtime: u32) -> Vec<u8> {
+    let (kind, mode, checksum) = match &entry.contents {
+        Contents::Dir => (TYPE_DIR, S_IFDIR | entry.mode, 0),
+        Contents::File(data) => (TYPE_FILE, S_IFREG | entry.mode, cksum(data)),
+    };
+    let mut block = vec![kind, 1];
+    block.extend_from_slice(&0u16.to_be_bytes());
+    block.extend_from_slice(&(mode as u16).to_be_bytes());
+    for word in [0, 0, mtime, entry.size() as u32] {
+        block.extend_from_slice(&word.to_be_bytes());
+    }
+    block.push(1);
+    block.extend_from_slice(&checksum.to_be_bytes());
+    // No link name
+    block.extend_from_slice(&0u32.to_be_bytes());
+    block
+}
+
+/// A `.bom` listing `.` and `entries`, all dated `mtime`
+pub fn bom(entries: &[Entry], mtime: u32) -> Result<Vec<u8>> {
+    let root = Entry::dir("");
+    let all: Vec<&Entry> = std::iter::once(&root).chain(entries).collect();
+    if all.len() > u16::MAX as usize * LEAF_PATHS || all.iter().any(|e| e.size() > u32::MAX as usize) {
+        return Err(Error::TooLarge { what: "bill of materials".into() });
+    }
+    let mut store = Store::default();
+
+    let mut info = Vec::new();
+    for word in [1, all.len() as u32, 1, 0, 0, 0, 0] {
+        info.extend_from_slice(&word.to_be_bytes());
+    }
+    let info = store.add(info);
+
+    // Ids count from 1 in tree order; a path's parent is named by id
+    let mut pairs = Vec::with_capacity(all.len());
+    for (index, entry) in all.iter().enumerate() {
+        let id = index as u32 + 1;
+        let (parent, name) = match entry.path.rsplit_once('/') {
+            _ if entry.path.is_empty() => (0, "."),
+            Some((parent, name)) => {
+                let parent = all.iter().position(|e| e.path == parent).map_or(1, |p| p as u32 + 1);
+                (parent, name)
+            }
+            None => (1, entry.path.as_str()),
+        };
+        let info2 = store.add(path_info(entry, mtime));
+        let mut info1 = id.to_be_bytes().to_vec();
+        info1.extend_from_slice(&info2.to_be_bytes());
+        let value = store.add(info1);
+        let mut file = parent.to_be_bytes().to_vec();
+        file.extend_from_slice(name.as_bytes());
+        file.push(0);
+        pairs.push((value, store.add(file)));
+    }
+
+    // Leaves are numbered before they are written, to link siblings
+    let chunks: Vec<&[(u32, u32)]> = pairs.chunks(LEAF_PATHS).collect();
+    let first = store.blocks.len() as u32;
+    let mut leaves = Vec::with_capacity(chunks.len());
+    for (n, chunk) in chunks.iter().enumerate() {
+        let index = first + n as u32;
+        let forward = if n + 1 < chunks.len() { index + 1 } else { 0 };
+        let backward = if n > 0 { index - 1 } else { 0 };
+        leaves.push((store.node(true, forward, backward, chunk), chunk[chunk.len() - 1].1));
+    }
+    let top = match leaves.as_slice() {
+        [(leaf, _)] => *leaf,
+        _ => store.node(false, 0, 0, &leaves),
+    };
+    let paths = store.tree(top, TREE_BLOCK_SIZE, all.len() as u32);
+    let hard_links = store.empty_tree(TREE_BLOCK_SIZE);
+    let virtual_tree = store.empty_tree(128);
+    let mut vindex = Vec::new();
+    for word in [1, virtual_tree, 0] {
+        vindex.extend_from_slice(&word.to_be_bytes());
+    }
+    vindex.push(0);
+    let vindex = store.add(vindex);
+    let size64 = store.empty_tree(128);
+
+    let mut out = vec![0; HEADER_SIZE];
+    let mut table = Vec::new();
+    table.extend_from_slice(&(store.blocks.len() as u32).to_be_bytes());
+    for block in &store.blocks {
+        let address = if block.is_empty() { 0 } else { out.len() as u32 };
+        table.extend_from_slice(&address.to_be_bytes());
+        table.extend_from_slice(&(block.len() as u32).to_be_bytes());
+        out.extend_from_slice(block);
+    }
+    // Empty free list
+    table.extend_from_slice(&0u32.to_be_bytes());
+
+    let mut vars = Vec::new();
+    let named = [("BomInfo", info), ("Paths", paths), ("HLIndex", hard_links), ("VIndex", vindex), ("Size64", size64)];
+    vars.extend_from_slice(&(named.len() as u32).to_be_bytes());
+    for (name, index) in named {
+        vars.extend_from_slice(&index.to_be_bytes());
+        vars.push(name.len() as u8);
+        vars.extend_from_slice(name.as_bytes());
+    }
+
+    let index_offset = out.len() as u32;
+    out.extend_from_slice(&table);
+    let vars_offset = out.len() as u32;
+    out.extend_from_slice(&vars);
+    let header = [1, store.blocks.len() as u32 - 1, index_offset, table.len() as u32, vars_offset, vars.len() as u32];
+    out[..8].copy_from_slice(MAGIC);
+    for (chunk, word) in out[8..32].chunks_exact_mut(4).zip(header) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{EXEC_MODE, FILE_MODE};
+
+    fn be32(data: &[u8], at: usize) -> u32 {
+        u32::from_be_bytes(data[at..at + 4].try_into().unwrap())
+    }
+
+    // Block `index` of a store
+    fn block(data: &[u8], index: u32) -> &[u8] {
+        let at = be32(data, 16) as usize + 4 + 8 * index as usize;
+        let (address, len) = (be32(data, at) as usize, be32(data, at + 4) as usize);
+        &data[address..address + len]
+    }
+
+    // Paths from the first leaf, as lsbom would print them
+    fn list(data: &[u8]) -> Vec<String> {
+        let vars = be32(data, 24) as usize;
+        assert_eq!(&data[vars + 9..vars + 16], b"BomInfo");
+        let paths_var = vars + 16;
+        assert_eq!(&data[paths_var + 5..paths_var + 10], b"Paths");
+        let tree = block(data, be32(data, paths_var));
+        assert_eq!(&tree[..4], b"tree");
+        let leaf = block(data, be32(tree, 8));
+        let count = u16::from_be_bytes([leaf[2], leaf[3]]) as usize;
+        let mut names: Vec<String> = Vec::new();
+        let mut out = Vec::new();
+        for n in 0..count {
+            let info1 = block(data, be32(leaf, 12 + 8 * n));
+            let file = block(data, be32(leaf, 16 + 8 * n));
+            let name = String::from_utf8(file[4..file.len() - 1].to_vec()).unwrap();
+            let parent = be32(file, 0) as usize;
+            let path = if parent == 0 { name } else { format!("{}/{}", names[parent - 1], name) };
+            let info2 = block(data, be32(info1, 4));
+            let mode = u16::from_be_bytes([info2[4], info2[5]]);
+            out.push(format!("{} {:o} {} {}", path, mode, be32(info2, 18), be32(info2, 23)));
+            names.push(path);
+        }
+        out
+    }
+
+    #[test]
+    fn test_bom() {
+        let entries = [
+            Entry::dir("Hello.app"),
+            Entry::file("Hello.app/Hello", EXEC_MODE, b"hello".to_vec()),
+            Entry::file("Hello.app/Hello.tiff", FILE_MODE, Vec::new()),
+        ];
+        let data = bom(&entries, 0).unwrap();
+        assert_eq!(&data[..12], b"BOMStore\0\0\0\x01");
+        assert_eq!(
+            list(&data),
+            [
+                ". 40755 0 0",
+                "./Hello.app 40755 0 0",
+                format!("./Hello.app/Hello 100755 5 {}", cksum(b"hello")).as_str(),
+                "./Hello.app/Hello.tiff 100644 0 4294967295",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cksum() {
+        // `printf 'hello\n' | cksum`
+        assert_eq!(cksum(b"hello\n"), 3015617425);
+        assert_eq!(cksum(b""), 4294967295);
+    }
+}
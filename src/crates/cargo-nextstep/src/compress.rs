@@ -0,0 +1,132 @@
+//! Unix `compress` (`.Z`) output
+//!
+//! Package payloads are `.tar.Z`: LZW with codes growing from 9 to 16
+//! bits, in block mode, as `compress` writes them. The table is never
+//! cleared: once all 16-bit codes are used the rest of the input is coded
+//! with the table as it is, which `uncompress` also handles. Without
+//! CLEAR codes each width change falls at the end of a group of eight
+//! codes, so none of the padding `compress` adds after a CLEAR is needed.
+
+use std::collections::HashMap;
+
+/// `.Z` magic
+pub const MAGIC: [u8; 2] = [0x1f, 0x9d];
+
+const MAX_BITS: u32 = 16;
+const BLOCK_MODE: u8 = 0x80;
+const INIT_BITS: u32 = 9;
+/// First free code: 0-255 are bytes, 256 is CLEAR in block mode
+const FIRST: u32 = 257;
+
+struct Output {
+    out: Vec<u8>,
+    bits: u32,
+    /// Bits written
+    offset: usize,
+}
+
+impl Output {
+    fn put(&mut self, code: u32) {
+        for bit in 0..self.bits {
+            if self.offset & 7 == 0 {
+                self.out.push(0);
+            }
+            if code >> bit & 1 != 0 {
+                *self.out.last_mut().unwrap() |= 1 << (self.offset % 8);
+            }
+            self.offset += 1;
+        }
+    }
+}
+
+/// `data` compressed as `compress -b 16` would
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut output = Output { out: Vec::new(), bits: INIT_BITS, offset: 0 };
+    let header = [MAGIC[0], MAGIC[1], BLOCK_MODE | MAX_BITS as u8];
+    let Some((&first, rest)) = data.split_first() else {
+        return header.to_vec();
+    };
+    let mut table: HashMap<(u32, u8), u32> = HashMap::new();
+    let mut next = FIRST;
+    let mut prefix = u32::from(first);
+    for &byte in rest {
+        if let Some(&code) = table.get(&(prefix, byte)) {
+            prefix = code;
+            continue;
+        }
+        output.put(prefix);
+        // The width grows once the next code would not fit
+        if output.bits < MAX_BITS && next > (1 << output.bits) - 1 {
+            output.bits += 1;
+        }
+        if next < 1 << MAX_BITS {
+            table.insert((prefix, byte), next);
+            next += 1;
+        }
+        prefix = u32::from(byte);
+    }
+    output.put(prefix);
+
+    let mut out = header.to_vec();
+    out.extend_from_slice(&output.out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reference decoder, written from uncompress's description
+    fn uncompress(data: &[u8]) -> Vec<u8> {
+        assert_eq!(data[..3], [0x1f, 0x9d, 0x90]);
+        let data = &data[3..];
+        let mut table: Vec<Vec<u8>> = (0..=255).map(|b| vec![b as u8]).collect();
+        table.push(Vec::new());
+        let (mut bits, mut offset) = (9u32, 0usize);
+        let mut out = Vec::new();
+        let mut previous: Option<Vec<u8>> = None;
+        while offset + bits as usize <= data.len() * 8 {
+            if table.len() as u32 > (1 << bits) - 1 && bits < 16 {
+                bits += 1;
+            }
+            let code = (0..bits).fold(0, |code, bit| {
+                let at = offset + bit as usize;
+                code | u32::from(data[at / 8] >> (at % 8) & 1) << bit
+            }) as usize;
+            offset += bits as usize;
+            let entry = match (&previous, table.get(code)) {
+                (_, Some(entry)) => entry.clone(),
+                (Some(p), None) => [p.clone(), vec![p[0]]].concat(),
+                (None, None) => panic!("bad first code"),
+            };
+            if let Some(p) = previous {
+                if table.len() < 1 << 16 {
+                    table.push([p, vec![entry[0]]].concat());
+                }
+            }
+            out.extend_from_slice(&entry);
+            previous = Some(entry);
+        }
+        out
+    }
+
+    #[test]
+    fn test_compress() {
+        assert_eq!(compress(b""), [0x1f, 0x9d, 0x90]);
+        // "a" and "b" as 9-bit codes, then "ab" (257)
+        assert_eq!(compress(b"abab"), [0x1f, 0x9d, 0x90, 0x61, 0xc4, 0x04, 0x04]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut data = Vec::new();
+        let mut x = 1u32;
+        for i in 0..300_000u32 {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            data.push(if i % 7 == 0 { (x >> 16) as u8 } else { b"NeXTSTEP "[i as usize % 9] });
+        }
+        let packed = compress(&data);
+        assert!(packed.len() < data.len());
+        assert_eq!(uncompress(&packed), data);
+    }
+}
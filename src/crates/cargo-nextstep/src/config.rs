@@ -0,0 +1,243 @@
+//! Bundle settings from a Cargo package
+//!
+//! Everything comes from `cargo metadata`: the package's name, version,
+//! description and binaries, plus an optional table in its manifest:
+//!
+//! ```toml
+//! [package.metadata.nextstep]
+//! app-name = "Browser"              # wrapper is Browser.app (default: the binary's name)
+//! bin = "nextstep-browser"          # binary to wrap, when there are several
+//! icon = "resources/Browser.tiff"   # application icon
+//! main-nib = "Browser"              # NSMainNibFile
+//! resources = ["resources/Browser.nib", "resources/images"]
+//! install-location = "/LocalApps"   # Installer's default location
+//! title = "Web Browser"             # package title (default: app-name)
+//!
+//! [package.metadata.nextstep.info]  # extra Info-nextstep.plist keys
+//! NSHelpFile = "Browser.help"
+//! ```
+//!
+//! Paths are relative to the manifest's directory.
+
+use crate::error::{Error, Result};
+use crate::json::Value;
+use crate::plist::Plist;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Where packages are installed unless the metadata says otherwise
+pub const DEFAULT_INSTALL_LOCATION: &str = "/LocalApps";
+
+/// What to bundle and how
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub package: String,
+    pub version: String,
+    pub description: Option<String>,
+    pub manifest_dir: PathBuf,
+    pub target_dir: PathBuf,
+    /// Name of the binary target to wrap
+    pub bin: String,
+    /// Wrapper and executable name, without `.app`
+    pub app_name: String,
+    pub icon: Option<PathBuf>,
+    pub main_nib: Option<String>,
+    pub resources: Vec<PathBuf>,
+    /// Extra `Info-nextstep.plist` entries
+    pub info: Vec<(String, Plist)>,
+    pub install_location: String,
+    pub title: String,
+}
+
+/// Output of `cargo metadata --no-deps` for `manifest_path`, or the
+/// package around the current directory
+pub fn cargo_metadata(manifest_path: Option<&Path>) -> Result<Value> {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let mut command = Command::new(cargo);
+    command.args(["metadata", "--format-version", "1", "--no-deps"]);
+    if let Some(path) = manifest_path {
+        command.arg("--manifest-path").arg(path);
+    }
+    let output = command.output().map_err(|e| Error::Cargo(e.to_string()))?;
+    if !output.status.success() {
+        return Err(Error::Cargo(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    let text = String::from_utf8(output.stdout).map_err(|_| Error::Cargo("output is not UTF-8".into()))?;
+    Value::parse(&text)
+}
+
+fn bad(reason: impl Into<String>) -> Error {
+    Error::Metadata(reason.into())
+}
+
+fn string(table: &Value, key: &str) -> Result<Option<String>> {
+    match table.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(bad(format!("{} must be a string", key))),
+    }
+}
+
+fn plist(key: &str, value: &Value) -> Result<Plist> {
+    match value {
+        Value::String(s) => Ok(Plist::String(s.clone())),
+        Value::Bool(b) => Ok(Plist::from(if *b { "YES" } else { "NO" })),
+        Value::Number(n) => Ok(Plist::String(n.to_string())),
+        Value::Array(items) => items.iter().map(|item| plist(key, item)).collect::<Result<_>>().map(Plist::Array),
+        Value::Object(members) => members
+            .iter()
+            .map(|(k, v)| Ok((k.clone(), plist(key, v)?)))
+            .collect::<Result<_>>()
+            .map(Plist::Dict),
+        Value::Null => Err(bad(format!("info.{} has no value", key))),
+    }
+}
+
+// The package to bundle: the one named, the only one, or the one whose
+// directory holds `cwd` most closely
+fn select_package<'a>(packages: &'a [Value], name: Option<&str>, cwd: &Path) -> Result<&'a Value> {
+    if let Some(name) = name {
+        return packages
+            .iter()
+            .find(|p| p.get("name").and_then(Value::as_str) == Some(name))
+            .ok_or_else(|| bad(format!("no package named {} in the workspace", name)));
+    }
+    if packages.len() == 1 {
+        return Ok(&packages[0]);
+    }
+    packages
+        .iter()
+        .filter_map(|p| {
+            let dir = Path::new(p.get("manifest_path")?.as_str()?).parent()?;
+            cwd.starts_with(dir).then(|| (dir.components().count(), p))
+        })
+        .max_by_key(|(depth, _)| *depth)
+        .map(|(_, p)| p)
+        .ok_or_else(|| bad("several packages in the workspace; pick one with -p"))
+}
+
+impl Config {
+    /// Settings for package `package` (or the current one) and binary
+    /// `bin` (or the metadata's, or the only one) from `cargo metadata`
+    pub fn from_metadata(metadata: &Value, package: Option<&str>, bin: Option<&str>, cwd: &Path) -> Result<Config> {
+        let packages = metadata.get("packages").and_then(Value::as_array).ok_or_else(|| bad("no packages"))?;
+        let target_dir = metadata
+            .get("target_directory")
+            .and_then(Value::as_str)
+            .ok_or_else(|| bad("no target directory"))?;
+        let pkg = select_package(packages, package, cwd)?;
+        let field = |key: &str| pkg.get(key).and_then(Value::as_str).map(str::to_string);
+        let name = field("name").ok_or_else(|| bad("package without a name"))?;
+        let manifest_path = field("manifest_path").ok_or_else(|| bad("package without a manifest path"))?;
+        let manifest_dir = Path::new(&manifest_path).parent().unwrap_or(Path::new(".")).to_path_buf();
+
+        let empty = Value::Object(Vec::new());
+        let table = match pkg.get("metadata").and_then(|m| m.get("nextstep")) {
+            Some(table @ Value::Object(_)) => table,
+            Some(_) => return Err(bad("[package.metadata.nextstep] must be a table")),
+            None => &empty,
+        };
+
+        let bins: Vec<&str> = pkg
+            .get("targets")
+            .and_then(Value::as_array)
+            .unwrap_or(&[])
+            .iter()
+            .filter(|t| t.get("kind").and_then(Value::as_array).unwrap_or(&[]).iter().any(|k| k.as_str() == Some("bin")))
+            .filter_map(|t| t.get("name").and_then(Value::as_str))
+            .collect();
+        let bin = match bin.map(str::to_string).or(string(table, "bin")?) {
+            Some(bin) if bins.contains(&bin.as_str()) => bin,
+            Some(bin) => return Err(bad(format!("package {} has no binary named {}", name, bin))),
+            None if bins.len() == 1 => bins[0].to_string(),
+            None if bins.is_empty() => return Err(bad(format!("package {} has no binaries", name))),
+            None => return Err(bad(format!("package {} has several binaries; pick one with --bin", name))),
+        };
+
+        let app_name = string(table, "app-name")?.unwrap_or_else(|| bin.clone());
+        if app_name.is_empty() || app_name.contains('/') {
+            return Err(bad(format!("bad app-name {:?}", app_name)));
+        }
+        let resources = match table.get("resources") {
+            None => Vec::new(),
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|item| item.as_str().map(|path| manifest_dir.join(path)))
+                .collect::<Option<_>>()
+                .ok_or_else(|| bad("resources must be a list of paths"))?,
+            Some(_) => return Err(bad("resources must be a list of paths")),
+        };
+        let info = match table.get("info") {
+            None => Vec::new(),
+            Some(Value::Object(members)) => {
+                members.iter().map(|(key, value)| Ok((key.clone(), plist(key, value)?))).collect::<Result<_>>()?
+            }
+            Some(_) => return Err(bad("info must be a table")),
+        };
+        Ok(Config {
+            version: field("version").unwrap_or_else(|| "0.0.0".into()),
+            description: field("description"),
+            target_dir: PathBuf::from(target_dir),
+            icon: string(table, "icon")?.map(|path| manifest_dir.join(path)),
+            main_nib: string(table, "main-nib")?,
+            resources,
+            info,
+            install_location: string(table, "install-location")?.unwrap_or_else(|| DEFAULT_INSTALL_LOCATION.into()),
+            title: string(table, "title")?.unwrap_or_else(|| app_name.clone()),
+            package: name,
+            manifest_dir,
+            bin,
+            app_name,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) const METADATA: &str = r#"{
+        "packages": [
+            {"name": "nextrust-examples", "version": "0.1.0", "description": null,
+             "manifest_path": "/src/Cargo.toml", "metadata": null,
+             "targets": [{"kind": ["example"], "name": "hello"}]},
+            {"name": "nextstep-browser", "version": "0.2.0", "description": "A web browser",
+             "manifest_path": "/src/examples/browser/Cargo.toml",
+             "targets": [{"kind": ["bin"], "name": "nextstep-browser"}],
+             "metadata": {"nextstep": {
+                 "app-name": "Browser", "icon": "Browser.tiff", "main-nib": "Browser",
+                 "resources": ["Browser.nib", "English.lproj/Help.rtf"],
+                 "info": {"NSTypes": ["html", "htm"], "NSServices": {"NSPortName": "Browser"}}
+             }}}
+        ],
+        "target_directory": "/src/target",
+        "version": 1
+    }"#;
+
+    #[test]
+    fn test_from_metadata() {
+        let metadata = Value::parse(METADATA).unwrap();
+        let config = Config::from_metadata(&metadata, None, None, Path::new("/src/examples/browser/src")).unwrap();
+        assert_eq!(config.package, "nextstep-browser");
+        assert_eq!((config.bin.as_str(), config.app_name.as_str()), ("nextstep-browser", "Browser"));
+        assert_eq!(config.icon, Some(PathBuf::from("/src/examples/browser/Browser.tiff")));
+        assert_eq!(config.resources[1], PathBuf::from("/src/examples/browser/English.lproj/Help.rtf"));
+        assert_eq!(config.info[0], ("NSTypes".into(), Plist::Array(vec!["html".into(), "htm".into()])));
+        assert_eq!(config.install_location, DEFAULT_INSTALL_LOCATION);
+        assert_eq!(config.title, "Browser");
+        assert_eq!(config.target_dir, PathBuf::from("/src/target"));
+
+        let by_name = Config::from_metadata(&metadata, Some("nextstep-browser"), None, Path::new("/")).unwrap();
+        assert_eq!(by_name, config);
+    }
+
+    #[test]
+    fn test_bad_metadata() {
+        let metadata = Value::parse(METADATA).unwrap();
+        let error = |package, bin, cwd| Config::from_metadata(&metadata, package, bin, Path::new(cwd)).unwrap_err();
+        assert!(matches!(error(None, None, "/src"), Error::Metadata(m) if m.contains("no binaries")));
+        assert!(matches!(error(None, None, "/elsewhere"), Error::Metadata(m) if m.contains("-p")));
+        assert!(matches!(error(Some("nope"), None, "/src"), Error::Metadata(_)));
+        assert!(matches!(error(Some("nextstep-browser"), Some("other"), "/src"), Error::Metadata(_)));
+    }
+}
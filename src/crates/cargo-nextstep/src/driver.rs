@@ -0,0 +1,286 @@
+//! Building, linking and running target binaries through cargo
+//!
+//! The driver runs the nightly cargo with the CPU's target spec and
+//! `-Zbuild-std`, so `core`, `alloc` and the `nextstep-*` crates are all
+//! built for the target in one go, and `nextstep-ld` as the linker. Cargo
+//! always reports in `--message-format json`; the driver collects the
+//! executables it built from the `compiler-artifact` messages and either
+//! passes every message through (`--message-format json`) or prints the
+//! rendered diagnostics, as cargo would. Its own errors are reported as
+//! `compiler-message`s in the same format.
+//!
+//! The target specs link with `-lgcc`. An empty one from [`stub_libgcc`]
+//! is searched after the library directories given, so it only stands in
+//! when there is no real one; anything that needs libgcc then fails as an
+//! undefined symbol.
+
+use crate::error::{Error, Result};
+use crate::json::Value;
+use crate::target::TargetSpec;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+/// Toolchain used unless `NEXTRUST_TOOLCHAIN` or `--toolchain` says otherwise
+pub const DEFAULT_TOOLCHAIN: &str = "nightly";
+/// Crates `-Zbuild-std` builds
+pub const BUILD_STD: &str = "core,alloc";
+
+/// How cargo's messages are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MessageFormat {
+    /// Rendered diagnostics on stderr
+    #[default]
+    Human,
+    /// Cargo's JSON messages on stdout, one per line
+    Json,
+}
+
+/// How to build
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildOptions {
+    pub spec: TargetSpec,
+    /// Rustup toolchain, passed as `cargo +TOOLCHAIN`
+    pub toolchain: String,
+    /// The `nextstep-ld` to link with
+    pub linker: PathBuf,
+    /// Library directories, searched before the stub `libgcc.a`
+    pub lib_dirs: Vec<PathBuf>,
+    /// Where the stub `libgcc.a` goes
+    pub stub_dir: PathBuf,
+    pub message_format: MessageFormat,
+    /// Passed to cargo as is (`--release`, `--features`, `-p`, ...)
+    pub cargo_args: Vec<String>,
+}
+
+/// An executable cargo built
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    pub package_id: String,
+    /// Target name, e.g. the binary's
+    pub name: String,
+    /// Target kinds, e.g. `["bin"]` or `["test"]`
+    pub kinds: Vec<String>,
+    /// Built as a test harness
+    pub test: bool,
+    pub executable: PathBuf,
+}
+
+/// The `nextstep-ld` to use: `NEXTSTEP_LD`, the one installed next to
+/// this program, or the one on `PATH`
+pub fn default_linker() -> PathBuf {
+    if let Some(path) = std::env::var_os("NEXTSTEP_LD") {
+        return PathBuf::from(path);
+    }
+    let name = format!("nextstep-ld{}", std::env::consts::EXE_SUFFIX);
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(&name)))
+        .filter(|path| path.is_file())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+/// Write an empty `libgcc.a` in `dir`, unless there is one
+pub fn stub_libgcc(dir: &Path) -> Result<PathBuf> {
+    let path = dir.join("libgcc.a");
+    if !path.exists() {
+        std::fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))?;
+        let data = nextstep_ar::Builder::new().write().map_err(|e| Error::Metadata(e.to_string()))?;
+        std::fs::write(&path, data).map_err(|e| Error::io(&path, e))?;
+    }
+    Ok(path)
+}
+
+/// The cargo command for `subcommand` (`build`, or `test` with
+/// `--no-run`) under `options`
+pub fn cargo_command(subcommand: &str, options: &BuildOptions) -> Command {
+    let mut command = Command::new("cargo");
+    command.arg(format!("+{}", options.toolchain)).arg(subcommand);
+    command.arg("--target").arg(&options.spec.path);
+    command.arg(format!("-Zbuild-std={}", BUILD_STD));
+    command.arg("-Zbuild-std-features=compiler-builtins-mem");
+    // Colour the rendered diagnostics only when they are printed here
+    command.arg(match options.message_format {
+        MessageFormat::Human => "--message-format=json-diagnostic-rendered-ansi",
+        MessageFormat::Json => "--message-format=json",
+    });
+    command.args(&options.cargo_args);
+    command.env(format!("{}LINKER", options.spec.env_prefix()), &options.linker);
+
+    // Library directories go through rustc, which hands them to the linker
+    // as -L; add to whatever flags are already set
+    let mut flags = Vec::new();
+    for dir in options.lib_dirs.iter().chain([&options.stub_dir]) {
+        flags.push("-L".to_string());
+        flags.push(format!("native={}", dir.display()));
+    }
+    match std::env::var("CARGO_ENCODED_RUSTFLAGS") {
+        Ok(encoded) => {
+            let all: Vec<&str> = encoded.split('\x1f').filter(|f| !f.is_empty()).collect();
+            let joined = all.into_iter().map(str::to_string).chain(flags).collect::<Vec<_>>().join("\x1f");
+            command.env("CARGO_ENCODED_RUSTFLAGS", joined);
+        }
+        Err(_) => {
+            let existing = std::env::var("RUSTFLAGS").unwrap_or_default();
+            let joined = existing.split_whitespace().map(str::to_string).chain(flags).collect::<Vec<_>>().join(" ");
+            command.env("RUSTFLAGS", joined);
+        }
+    }
+    command
+}
+
+/// Cargo's JSON for a diagnostic of our own
+pub fn diagnostic(level: &str, message: &str) -> Value {
+    Value::Object(vec![
+        ("reason".into(), "compiler-message".into()),
+        ("package_id".into(), Value::Null),
+        ("manifest_path".into(), Value::Null),
+        ("target".into(), Value::Null),
+        (
+            "message".into(),
+            Value::Object(vec![
+                ("$message_type".into(), "diagnostic".into()),
+                ("message".into(), message.into()),
+                ("code".into(), Value::Null),
+                ("level".into(), level.into()),
+                ("spans".into(), Value::Array(Vec::new())),
+                ("children".into(), Value::Array(Vec::new())),
+                ("rendered".into(), Value::String(format!("{}: {}\n", level, message))),
+            ]),
+        ),
+    ])
+}
+
+/// The executable an artifact message reports, if any
+pub fn artifact(message: &Value) -> Option<Artifact> {
+    if message.get("reason").and_then(Value::as_str) != Some("compiler-artifact") {
+        return None;
+    }
+    let executable = message.get("executable").and_then(Value::as_str)?;
+    let target = message.get("target")?;
+    let strings = |value: Option<&Value>| -> Vec<String> {
+        value.and_then(Value::as_array).unwrap_or(&[]).iter().filter_map(Value::as_str).map(str::to_string).collect()
+    };
+    Some(Artifact {
+        package_id: message.get("package_id").and_then(Value::as_str).unwrap_or("").to_string(),
+        name: target.get("name").and_then(Value::as_str).unwrap_or("").to_string(),
+        kinds: strings(target.get("kind")),
+        test: message.get("profile").and_then(|p| p.get("test")).and_then(Value::as_bool).unwrap_or(false),
+        executable: PathBuf::from(executable),
+    })
+}
+
+/// Show one line of cargo's output in `format`, noting any executable
+pub fn handle_line(line: &str, format: MessageFormat, artifacts: &mut Vec<Artifact>, out: &mut impl Write) {
+    let Ok(message) = Value::parse(line) else {
+        // Not a message (a build script printing to stdout): pass it on
+        let _ = writeln!(out, "{}", line);
+        return;
+    };
+    artifacts.extend(artifact(&message));
+    match format {
+        MessageFormat::Json => {
+            let _ = writeln!(out, "{}", line);
+        }
+        MessageFormat::Human => {
+            let rendered = message.get("message").and_then(|m| m.get("rendered")).and_then(Value::as_str);
+            if let Some(rendered) = rendered {
+                eprint!("{}", rendered);
+            }
+        }
+    }
+}
+
+/// Run cargo `subcommand`, returning whether it succeeded and the
+/// executables it built
+pub fn build(subcommand: &str, options: &BuildOptions) -> Result<(bool, Vec<Artifact>)> {
+    stub_libgcc(&options.stub_dir)?;
+    let mut command = cargo_command(subcommand, options);
+    let mut child = command
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::Cargo(format!("cannot run cargo +{}: {}", options.toolchain, e)))?;
+    let mut artifacts = Vec::new();
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    if let Some(pipe) = child.stdout.take() {
+        for line in BufReader::new(pipe).lines() {
+            let line = line.map_err(|e| Error::Cargo(e.to_string()))?;
+            handle_line(&line, options.message_format, &mut artifacts, &mut out);
+        }
+    }
+    let status = child.wait().map_err(|e| Error::Cargo(e.to_string()))?;
+    Ok((status.success(), artifacts))
+}
+
+/// Run `executable` with `args` through `runner` (a command line, split
+/// on whitespace as cargo splits `runner`)
+pub fn run(runner: &str, executable: &Path, args: &[String]) -> Result<ExitStatus> {
+    let mut words = runner.split_whitespace();
+    let program = words.next().ok_or_else(|| Error::Metadata("empty runner".into()))?;
+    Command::new(program)
+        .args(words)
+        .arg(executable)
+        .args(args)
+        .status()
+        .map_err(|e| Error::io(program, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::target::{find_spec_dir, Cpu};
+
+    fn options() -> BuildOptions {
+        let dir = find_spec_dir(Path::new(env!("CARGO_MANIFEST_DIR"))).unwrap();
+        let spec = TargetSpec::load(&dir.join(format!("{}.json", Cpu::M68030.spec_name()))).unwrap();
+        BuildOptions {
+            spec,
+            toolchain: DEFAULT_TOOLCHAIN.into(),
+            linker: PathBuf::from("/opt/nextrust/bin/nextstep-ld"),
+            lib_dirs: vec![PathBuf::from("/NextDeveloper/lib")],
+            stub_dir: PathBuf::from("target/nextstep/stub"),
+            message_format: MessageFormat::Json,
+            cargo_args: vec!["--release".into()],
+        }
+    }
+
+    #[test]
+    fn test_cargo_command() {
+        let command = cargo_command("build", &options());
+        let args: Vec<_> = command.get_args().map(|a| a.to_string_lossy().into_owned()).collect();
+        assert_eq!(args[..2], ["+nightly", "build"]);
+        assert!(args[3].ends_with("target-specs/m68k-next-nextstep-68030.json"));
+        assert!(args.contains(&"-Zbuild-std=core,alloc".to_string()));
+        assert_eq!(args.last().unwrap(), "--release");
+        let env: Vec<_> = command.get_envs().map(|(k, v)| (k.to_string_lossy(), v.map(|v| v.to_string_lossy()))).collect();
+        assert!(env.iter().any(|(k, v)| k == "CARGO_TARGET_M68K_NEXT_NEXTSTEP_68030_LINKER"
+            && v.as_deref() == Some("/opt/nextrust/bin/nextstep-ld")));
+        let flags = env.iter().find(|(k, _)| k.ends_with("RUSTFLAGS")).and_then(|(_, v)| v.clone()).unwrap();
+        let lib = flags.find("native=/NextDeveloper/lib").unwrap();
+        assert!(lib < flags.find("native=target/nextstep/stub").unwrap());
+    }
+
+    #[test]
+    fn test_messages() {
+        let lines = [
+            r#"{"reason":"compiler-message","package_id":"hello 0.1.0","message":{"rendered":"warning: unused\n","level":"warning"}}"#,
+            r#"{"reason":"compiler-artifact","package_id":"hello 0.1.0","target":{"kind":["bin"],"name":"hello"},"profile":{"test":false},"executable":"/t/hello"}"#,
+            r#"{"reason":"compiler-artifact","package_id":"core 0.0.0","target":{"kind":["lib"],"name":"core"},"profile":{"test":false},"executable":null}"#,
+            "not json",
+        ];
+        let mut artifacts = Vec::new();
+        let mut out = Vec::new();
+        for line in lines {
+            handle_line(line, MessageFormat::Json, &mut artifacts, &mut out);
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), lines.join("\n") + "\n");
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].kinds, ["bin"]);
+        assert_eq!(artifacts[0].executable, PathBuf::from("/t/hello"));
+
+        let message = diagnostic("error", "no runner");
+        let parsed = Value::parse(&message.to_string()).unwrap();
+        assert_eq!(parsed.get("message").and_then(|m| m.get("rendered")).and_then(Value::as_str), Some("error: no runner\n"));
+    }
+}
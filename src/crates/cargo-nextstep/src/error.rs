@@ -0,0 +1,44 @@
+//! Packaging errors
+
+use std::fmt;
+
+/// Why a bundle or package could not be made
+#[derive(Debug)]
+pub enum Error {
+    /// A file could not be read or written
+    Io { path: String, error: std::io::Error },
+    /// `cargo metadata` failed or printed something unexpected
+    Cargo(String),
+    /// Malformed JSON at a byte offset
+    Json { offset: usize, reason: &'static str },
+    /// `[package.metadata.nextstep]` or the package layout is not usable
+    Metadata(String),
+    /// The file to wrap is not an m68k Mach-O executable
+    NotExecutable { path: String, reason: String },
+    /// Something does not fit a fixed-width field of the output format
+    TooLarge { what: String },
+}
+
+impl Error {
+    pub(crate) fn io(path: impl AsRef<std::path::Path>, error: std::io::Error) -> Error {
+        Error::Io { path: path.as_ref().display().to_string(), error }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, error } => write!(f, "{}: {}", path, error),
+            Error::Cargo(reason) => write!(f, "cargo metadata: {}", reason),
+            Error::Json { offset, reason } => write!(f, "bad JSON at offset {}: {}", offset, reason),
+            Error::Metadata(reason) => write!(f, "package metadata: {}", reason),
+            Error::NotExecutable { path, reason } => write!(f, "{}: not an m68k executable: {}", path, reason),
+            Error::TooLarge { what } => write!(f, "{} is too large for the output format", what),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Result of packaging
+pub type Result<T> = core::result::Result<T, Error>;
@@ -0,0 +1,39 @@
+//! cargo-nextstep - Cargo subcommand for building and shipping NeXTSTEP programs
+//!
+//! `cargo nextstep build`, `run` and `test` replace the Xargo scripts: they
+//! pick the CPU's spec from `target-specs/` ([`target`]), build with
+//! `-Zbuild-std`, link with `nextstep-ld`, and hand executables to a
+//! runner such as an emulator ([`driver`]), reporting in cargo's human or
+//! JSON message format.
+//!
+//! `cargo nextstep bundle` turns a built m68k executable into what a NeXT
+//! user installs: a `Name.app` wrapper with its `Info-nextstep.plist`,
+//! `.tiff` icon and `.nib` resources, and a `Name.pkg` Installer.app
+//! package holding the wrapper as a `gnutar` `.tar.Z` payload with its
+//! `.info`, `.bom` and `.sizes` files. Settings come from the package's
+//! Cargo metadata (see [`config`]), read through `cargo metadata`.
+//!
+//! ```ignore
+//! let metadata = cargo_nextstep::config::cargo_metadata(None)?;
+//! let config = cargo_nextstep::Config::from_metadata(&metadata, None, None, &std::env::current_dir()?)?;
+//! let app = cargo_nextstep::app::wrapper(&config, std::fs::read("target/m68k-next-nextstep/release/hello")?)?;
+//! cargo_nextstep::pkg::package(&config, &app, 0)?.write("dist".as_ref())?;
+//! ```
+//!
+//! Bundles are written on the build host, with no NeXT tools.
+
+pub mod app;
+pub mod bom;
+pub mod compress;
+pub mod config;
+pub mod driver;
+mod error;
+pub mod json;
+pub mod pkg;
+pub mod plist;
+pub mod tar;
+pub mod target;
+
+pub use app::{Contents, Entry, Tree};
+pub use config::Config;
+pub use error::{Error, Result};
@@ -0,0 +1,102 @@
+//! Installer.app packages
+//!
+//! A NeXTSTEP package, `Name.pkg`, is a directory Installer.app opens:
+//!
+//! - `Name.tar.Z`: the payload, a compressed `gnutar` archive unpacked
+//!   in the install location
+//! - `Name.info`: `Key value` lines with the title, version, description
+//!   and default location Installer shows and uses
+//! - `Name.bom`: the bill of materials, for listing and checking
+//! - `Name.sizes`: file count and installed and compressed sizes in
+//!   kilobytes, for the disk space check
+//! - `Name.tiff`: the icon Installer shows, when the app has one
+
+use crate::app::{Contents, Entry, Tree, FILE_MODE};
+use crate::bom::bom;
+use crate::compress::compress;
+use crate::config::Config;
+use crate::error::Result;
+use crate::tar::tar;
+
+/// The `.info` file for `config`
+pub fn info(config: &Config) -> String {
+    let description = config.description.as_deref().unwrap_or(&config.title);
+    let mut out = String::from("# Installer package description\n");
+    let mut line = |key: &str, value: &str| {
+        out.push_str(key);
+        out.push(' ');
+        out.push_str(&value.split_whitespace().collect::<Vec<_>>().join(" "));
+        out.push('\n');
+    };
+    line("Title", &config.title);
+    line("Version", &config.version);
+    line("Description", description);
+    line("DefaultLocation", &config.install_location);
+    line("Relocatable", "YES");
+    line("NeedsAuthorization", "NO");
+    line("DisableStop", "NO");
+    line("UseUserMask", "NO");
+    line("Application", "NO");
+    line("Required", "NO");
+    line("InstallOnly", "NO");
+    line("RequiresReboot", "NO");
+    out
+}
+
+fn kilobytes(bytes: usize) -> usize {
+    bytes.div_ceil(1024)
+}
+
+/// The `.pkg` directory installing `payload` (an app wrapper tree)
+pub fn package(config: &Config, payload: &Tree, mtime: u32) -> Result<Tree> {
+    let name = &config.app_name;
+    let pkg = format!("{}.pkg", name);
+    let archive = compress(&tar(&payload.entries, mtime)?);
+    let files = payload.entries.iter().filter(|e| matches!(e.contents, Contents::File(_)));
+    let installed: usize = files.clone().map(|e| kilobytes(e.size())).sum();
+    let sizes = format!(
+        "NumFiles {}\nInstalledSize {}\nCompressedSize {}\n",
+        files.count(),
+        installed,
+        kilobytes(archive.len())
+    );
+
+    let mut tree = Tree::default();
+    tree.add(Entry::dir(&pkg))?;
+    tree.add(Entry::file(&format!("{}/{}.info", pkg, name), FILE_MODE, info(config).into_bytes()))?;
+    tree.add(Entry::file(&format!("{}/{}.bom", pkg, name), FILE_MODE, bom(&payload.entries, mtime)?))?;
+    tree.add(Entry::file(&format!("{}/{}.sizes", pkg, name), FILE_MODE, sizes.into_bytes()))?;
+    tree.add(Entry::file(&format!("{}/{}.tar.Z", pkg, name), FILE_MODE, archive))?;
+    let icon = format!("{}.app/{}.tiff", name, name);
+    if let Some(entry) = payload.entries.iter().find(|e| e.path == icon) {
+        tree.add(Entry { path: format!("{}/{}.tiff", pkg, name), ..entry.clone() })?;
+    }
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::tests::{config, executable};
+    use crate::app::wrapper;
+    use crate::compress::MAGIC;
+
+    #[test]
+    fn test_package() {
+        let config = config(Vec::new());
+        let app = wrapper(&config, executable()).unwrap();
+        let pkg = package(&config, &app, 0).unwrap();
+        let paths: Vec<_> = pkg.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["Hello.pkg", "Hello.pkg/Hello.info", "Hello.pkg/Hello.bom", "Hello.pkg/Hello.sizes", "Hello.pkg/Hello.tar.Z"]
+        );
+        let text = |n: usize| match &pkg.entries[n].contents {
+            Contents::File(data) => String::from_utf8_lossy(data).into_owned(),
+            Contents::Dir => String::new(),
+        };
+        assert!(text(1).contains("\nTitle Hello\nVersion 1.0\nDescription Says hello\nDefaultLocation /LocalApps\n"));
+        assert_eq!(text(3), "NumFiles 2\nInstalledSize 2\nCompressedSize 1\n");
+        assert!(matches!(&pkg.entries[4].contents, Contents::File(data) if data[..2] == MAGIC));
+    }
+}
@@ -0,0 +1,101 @@
+//! NeXT ASCII property lists
+//!
+//! The format NeXTSTEP's `Info-nextstep.plist` and `defaults` use:
+//! strings, `( ... )` arrays and `{ key = value; }` dictionaries. Strings
+//! that are plain words are written bare, anything else quoted.
+
+/// A property list value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Plist {
+    String(String),
+    Array(Vec<Plist>),
+    /// Keys in the order they are written
+    Dict(Vec<(String, Plist)>),
+}
+
+impl From<&str> for Plist {
+    fn from(s: &str) -> Plist {
+        Plist::String(s.to_string())
+    }
+}
+
+impl Plist {
+    /// The list as text, ending in a newline
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, 0);
+        out.push('\n');
+        out
+    }
+
+    fn write(&self, out: &mut String, depth: usize) {
+        let indent = "    ";
+        match self {
+            Plist::String(s) => quote(out, s),
+            Plist::Array(items) => {
+                out.push('(');
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        out.push_str(", ");
+                    }
+                    item.write(out, depth);
+                }
+                out.push(')');
+            }
+            Plist::Dict(entries) => {
+                out.push_str("{\n");
+                for (key, value) in entries {
+                    out.push_str(&indent.repeat(depth + 1));
+                    quote(out, key);
+                    out.push_str(" = ");
+                    value.write(out, depth + 1);
+                    out.push_str(";\n");
+                }
+                out.push_str(&indent.repeat(depth));
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn is_bare(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric() || b"_$+/:.-".contains(&b))
+}
+
+fn quote(out: &mut String, s: &str) {
+    if is_bare(s) {
+        out.push_str(s);
+        return;
+    }
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_text() {
+        let plist = Plist::Dict(vec![
+            ("NSExecutable".into(), "Browser".into()),
+            ("NSHumanReadableName".into(), "Web \"Browser\"".into()),
+            ("NSTypes".into(), Plist::Array(vec!["html".into(), "".into()])),
+            ("Nested".into(), Plist::Dict(vec![("a".into(), "1.0".into())])),
+        ]);
+        assert_eq!(
+            plist.to_text(),
+            "{\n    NSExecutable = Browser;\n    NSHumanReadableName = \"Web \\\"Browser\\\"\";\n    \
+             NSTypes = (html, \"\");\n    Nested = {\n        a = 1.0;\n    };\n}\n"
+        );
+    }
+}
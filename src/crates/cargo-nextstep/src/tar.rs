@@ -0,0 +1,125 @@
+//! `gnutar` archives
+//!
+//! Installer.app unpacks a package's payload with NeXTSTEP's `gnutar`
+//! (GNU tar 1.x), so the archive uses the old GNU header: `ustar  \0`
+//! magic, and a `././@LongLink` entry before any path longer than the
+//! 100-byte name field. Paths start with `./` and everything is owned by
+//! root, as NeXT's own packages are.
+
+use crate::app::{Contents, Entry};
+use crate::error::{Error, Result};
+
+pub const BLOCK_SIZE: usize = 512;
+/// gnutar's default blocking factor of 20
+pub const RECORD_SIZE: usize = 20 * BLOCK_SIZE;
+
+const NAME_SIZE: usize = 100;
+const LONG_LINK: &str = "././@LongLink";
+
+// `value` in octal, zero-padded to fill `field` but its NUL
+fn octal(field: &mut [u8], value: u64, what: &str) -> Result<()> {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    if digits.len() >= field.len() {
+        return Err(Error::TooLarge { what: what.to_string() });
+    }
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    Ok(())
+}
+
+fn header(name: &[u8], typeflag: u8, mode: u32, size: u64, mtime: u32) -> Result<[u8; BLOCK_SIZE]> {
+    let mut block = [0; BLOCK_SIZE];
+    block[..name.len()].copy_from_slice(name);
+    let what = String::from_utf8_lossy(name);
+    octal(&mut block[100..108], mode.into(), &what)?;
+    octal(&mut block[108..116], 0, &what)?;
+    octal(&mut block[116..124], 0, &what)?;
+    octal(&mut block[124..136], size, &what)?;
+    octal(&mut block[136..148], mtime.into(), &what)?;
+    block[156] = typeflag;
+    block[257..265].copy_from_slice(b"ustar  \0");
+    block[265..269].copy_from_slice(b"root");
+    block[297..302].copy_from_slice(b"wheel");
+    // The checksum is taken with its own field as spaces
+    block[148..156].fill(b' ');
+    let sum: u32 = block.iter().map(|&b| u32::from(b)).sum();
+    let digits = format!("{:06o}\0 ", sum);
+    block[148..156].copy_from_slice(digits.as_bytes());
+    Ok(block)
+}
+
+fn pad(out: &mut Vec<u8>, to: usize) {
+    let len = out.len().div_ceil(to) * to;
+    out.resize(len, 0);
+}
+
+/// A tar archive of `entries` under `./`, all dated `mtime`
+pub fn tar(entries: &[Entry], mtime: u32) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&header(b"./", b'5', 0o755, 0, mtime)?);
+    for entry in entries {
+        let mut name = format!("./{}", entry.path);
+        let (typeflag, data) = match &entry.contents {
+            Contents::Dir => {
+                name.push('/');
+                (b'5', &[][..])
+            }
+            Contents::File(data) => (b'0', data.as_slice()),
+        };
+        let mut field = name.as_bytes();
+        if field.len() > NAME_SIZE {
+            out.extend_from_slice(&header(LONG_LINK.as_bytes(), b'L', 0, field.len() as u64 + 1, 0)?);
+            out.extend_from_slice(field);
+            out.push(0);
+            pad(&mut out, BLOCK_SIZE);
+            field = &field[..NAME_SIZE];
+        }
+        out.extend_from_slice(&header(field, typeflag, entry.mode, data.len() as u64, mtime)?);
+        out.extend_from_slice(data);
+        pad(&mut out, BLOCK_SIZE);
+    }
+    out.resize(out.len() + 2 * BLOCK_SIZE, 0);
+    pad(&mut out, RECORD_SIZE);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{EXEC_MODE, FILE_MODE};
+
+    #[test]
+    fn test_tar() {
+        let long = format!("Hello.app/{}.tiff", "x".repeat(120));
+        let entries = [
+            Entry::dir("Hello.app"),
+            Entry::file("Hello.app/Hello", EXEC_MODE, vec![0x4e; 600]),
+            Entry::file(&long, FILE_MODE, b"tiff".to_vec()),
+        ];
+        let data = tar(&entries, 0x5000_0000).unwrap();
+        assert_eq!(data.len(), RECORD_SIZE);
+
+        let block = |n: usize| &data[n * BLOCK_SIZE..(n + 1) * BLOCK_SIZE];
+        assert_eq!(&block(0)[..3], b"./\0");
+        assert_eq!(&block(1)[..12], b"./Hello.app/");
+        assert_eq!(block(1)[156], b'5');
+        assert_eq!(&block(2)[..17], b"./Hello.app/Hello");
+        assert_eq!(&block(2)[100..108], b"0000755\0");
+        assert_eq!(&block(2)[124..136], b"00000001130\0");
+        assert_eq!(&block(2)[136..148], b"12000000000\0");
+        assert_eq!(&block(2)[257..265], b"ustar  \0");
+        // Header, 600 bytes of data, then the long name
+        assert_eq!(&block(5)[..13], LONG_LINK.as_bytes());
+        assert_eq!(block(5)[156], b'L');
+        assert_eq!(&block(6)[..long.len() + 3], format!("./{}\0", long).as_bytes());
+        assert_eq!(block(7)[156], b'0');
+        assert_eq!(&block(8)[..4], b"tiff");
+
+        // Checksums are what tar computes
+        for n in [0, 1, 2, 5, 7] {
+            let mut copy = block(n).to_vec();
+            copy[148..156].fill(b' ');
+            let sum: u32 = copy.iter().map(|&b| u32::from(b)).sum();
+            assert_eq!(&block(n)[148..156], format!("{:06o}\0 ", sum).as_bytes());
+        }
+    }
+}
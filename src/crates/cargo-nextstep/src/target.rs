@@ -0,0 +1,115 @@
+//! Target specs for each NeXT CPU
+//!
+//! `target-specs/` holds one rustc target spec per CPU: the generic
+//! `m68k-next-nextstep` (no atomics, runs on any NeXT) and the 68030 and
+//! 68040 ones, which enable the CPU's instructions and `CAS` atomics. The
+//! driver finds the directory from `NEXTRUST_TARGET_SPECS`, or by walking
+//! up from the current directory, and checks the spec is an m68k NeXTSTEP
+//! one before handing it to cargo.
+
+use crate::error::{Error, Result};
+use crate::json::Value;
+use std::path::{Path, PathBuf};
+
+/// Directory of the specs in the repository
+pub const SPEC_DIR: &str = "target-specs";
+
+/// Which CPU to build for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Cpu {
+    /// Plain 68000 code and no atomics: any NeXT
+    #[default]
+    Generic,
+    M68030,
+    M68040,
+}
+
+impl Cpu {
+    pub fn from_name(name: &str) -> Option<Cpu> {
+        match name {
+            "generic" | "m68k" => Some(Cpu::Generic),
+            "68030" | "m68030" => Some(Cpu::M68030),
+            "68040" | "m68040" => Some(Cpu::M68040),
+            _ => None,
+        }
+    }
+
+    /// File stem of the CPU's spec, which is also the target name cargo uses
+    pub fn spec_name(self) -> &'static str {
+        match self {
+            Cpu::Generic => "m68k-next-nextstep",
+            Cpu::M68030 => "m68k-next-nextstep-68030",
+            Cpu::M68040 => "m68k-next-nextstep-68040",
+        }
+    }
+}
+
+/// A checked target spec file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetSpec {
+    pub path: PathBuf,
+    /// Target name: the file stem, used by cargo for `target/<name>/` and
+    /// `CARGO_TARGET_<NAME>_*`
+    pub name: String,
+    /// The spec's `cpu`
+    pub cpu: String,
+}
+
+impl TargetSpec {
+    /// Read and check the spec at `path`
+    pub fn load(path: &Path) -> Result<TargetSpec> {
+        let text = std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        let spec = Value::parse(&text)?;
+        let bad = |reason: &str| Error::Metadata(format!("{}: {}", path.display(), reason));
+        let field = |key: &str| spec.get(key).and_then(Value::as_str);
+        if field("arch") != Some("m68k") || field("os") != Some("nextstep") {
+            return Err(bad("not an m68k NeXTSTEP target spec"));
+        }
+        let name = path.file_stem().map(|s| s.to_string_lossy().into_owned()).ok_or_else(|| bad("no file name"))?;
+        Ok(TargetSpec { path: path.to_path_buf(), name, cpu: field("cpu").unwrap_or("generic").to_string() })
+    }
+
+    /// Cargo's environment variable prefix for this target, e.g.
+    /// `CARGO_TARGET_M68K_NEXT_NEXTSTEP_68040_`
+    pub fn env_prefix(&self) -> String {
+        format!("CARGO_TARGET_{}_", self.name.to_uppercase().replace(['-', '.'], "_"))
+    }
+}
+
+/// The spec directory: `NEXTRUST_TARGET_SPECS`, or the nearest
+/// `target-specs/` above `start`
+pub fn find_spec_dir(start: &Path) -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("NEXTRUST_TARGET_SPECS") {
+        return Ok(PathBuf::from(dir));
+    }
+    start
+        .ancestors()
+        .map(|dir| dir.join(SPEC_DIR))
+        .find(|dir| dir.is_dir())
+        .ok_or_else(|| {
+            Error::Metadata(format!(
+                "no {} directory above {}; set NEXTRUST_TARGET_SPECS or pass --target-spec",
+                SPEC_DIR,
+                start.display()
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repo_specs() {
+        let dir = find_spec_dir(Path::new(env!("CARGO_MANIFEST_DIR"))).unwrap();
+        for cpu in [Cpu::Generic, Cpu::M68030, Cpu::M68040] {
+            let spec = TargetSpec::load(&dir.join(format!("{}.json", cpu.spec_name()))).unwrap();
+            assert_eq!(spec.name, cpu.spec_name());
+        }
+        let spec = TargetSpec::load(&dir.join("m68k-next-nextstep-68040.json")).unwrap();
+        assert_eq!(spec.cpu, "M68040");
+        assert_eq!(spec.env_prefix(), "CARGO_TARGET_M68K_NEXT_NEXTSTEP_68040_");
+        assert_eq!(Cpu::from_name("m68030"), Some(Cpu::M68030));
+        assert!(TargetSpec::load(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml").as_path()).is_err());
+    }
+}
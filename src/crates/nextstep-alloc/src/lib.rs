@@ -31,7 +31,11 @@ unsafe impl GlobalAlloc for MachAllocator {
         
         // Allocate anywhere
         match sys_vm_allocate(size, true) {
-            Ok(ptr) => ptr as *mut u8,
+            Ok(ptr) => {
+                #[cfg(feature = "stats")]
+                stats::record_alloc(size);
+                ptr as *mut u8
+            }
             Err(_) => ptr::null_mut(),
         }
     }
@@ -39,6 +43,8 @@ unsafe impl GlobalAlloc for MachAllocator {
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         let size = round_up_to_page(layout.size());
         let _ = sys_vm_deallocate(ptr as *mut core::ffi::c_void, size);
+        #[cfg(feature = "stats")]
+        stats::record_dealloc(size);
     }
     
     unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
@@ -47,6 +53,77 @@ unsafe impl GlobalAlloc for MachAllocator {
     }
 }
 
+/// Allocation statistics, enabled with the `stats` feature
+#[cfg(feature = "stats")]
+pub mod stats {
+    use core::fmt::{self, Write};
+
+    /// Counters kept by [`MachAllocator`](super::MachAllocator)
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct AllocStats {
+        pub allocations: usize,
+        pub deallocations: usize,
+        /// Bytes currently mapped (page-rounded)
+        pub bytes_in_use: usize,
+        /// High-water mark of `bytes_in_use`
+        pub peak_bytes: usize,
+    }
+
+    static mut STATS: AllocStats = AllocStats {
+        allocations: 0,
+        deallocations: 0,
+        bytes_in_use: 0,
+        peak_bytes: 0,
+    };
+
+    pub(crate) fn record_alloc(size: usize) {
+        unsafe {
+            STATS.allocations += 1;
+            STATS.bytes_in_use += size;
+            if STATS.bytes_in_use > STATS.peak_bytes {
+                STATS.peak_bytes = STATS.bytes_in_use;
+            }
+        }
+    }
+
+    pub(crate) fn record_dealloc(size: usize) {
+        unsafe {
+            STATS.deallocations += 1;
+            STATS.bytes_in_use -= size;
+        }
+    }
+
+    /// Current counters
+    pub fn snapshot() -> AllocStats {
+        unsafe { STATS }
+    }
+
+    struct RawStderr;
+
+    impl Write for RawStderr {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            nextstep_sys::sys_write(nextstep_sys::STDERR_FILENO, s.as_bytes())
+                .map(|_| ())
+                .map_err(|_| fmt::Error)
+        }
+    }
+
+    /// Print the counters to stderr
+    pub fn dump() {
+        let s = snapshot();
+        let _ = writeln!(
+            RawStderr,
+            "alloc: {} allocations, {} deallocations, {} bytes in use, {} bytes peak",
+            s.allocations, s.deallocations, s.bytes_in_use, s.peak_bytes
+        );
+    }
+
+    /// Print the counters when the process exits
+    pub fn dump_at_exit() -> Result<(), i32> {
+        nextstep_sys::sys_atexit(dump)
+    }
+}
+
 /// Global allocator instance
 #[global_allocator]
 pub static ALLOCATOR: MachAllocator = MachAllocator;
@@ -0,0 +1,222 @@
+//! nextstep-ar - Create, list and update NeXTSTEP static libraries
+//!
+//! Usage: nextstep-ar [-]OPERATION[MODIFIERS] ARCHIVE [FILE...]
+//!        nextstep-ar --symdef ARCHIVE
+//!
+//! Operations:
+//!   r   Add FILEs, replacing members of the same name
+//!   q   Append FILEs
+//!   d   Delete the members named FILE
+//!   t   List members (all, or those named)
+//!   x   Extract members (all, or those named)
+//!   s   Only rewrite the __.SYMDEF table, like ranlib
+//!
+//! Modifiers:
+//!   c   Create the archive without a warning
+//!   v   Verbose
+//!   S   Do not write a __.SYMDEF table
+//!   D   Deterministic: zero member dates and owners, mode 0644
+//!
+//! `--symdef` prints the table as `symbol in member`.
+//!
+//! The table's date comes from `SOURCE_DATE_EPOCH`, or the current time,
+//! and the archive's modification time is set to match, so NeXT `ld` does
+//! not think the table is out of date.
+
+use nextstep_ar::{Archive, Builder, NewMember};
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const USAGE: &str = "usage: nextstep-ar [-]{r|q|d|t|x|s}[cvSD] ARCHIVE [FILE...]\n       nextstep-ar --symdef ARCHIVE";
+
+struct Modifiers {
+    create: bool,
+    verbose: bool,
+    symdef: bool,
+    deterministic: bool,
+}
+
+fn timestamp() -> u32 {
+    if let Some(epoch) = std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|v| v.parse().ok()) {
+        return epoch;
+    }
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as u32)
+}
+
+// Member for `path`, named by its last component and stamped like ar does
+fn new_member(path: &str, modifiers: &Modifiers) -> Result<NewMember, String> {
+    let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let name = Path::new(path).file_name().map_or(path.into(), |n| n.to_string_lossy());
+    let mut member = NewMember::new(&name, data);
+    if modifiers.deterministic {
+        return Ok(member);
+    }
+    let metadata = std::fs::metadata(path).map_err(|e| format!("{}: {}", path, e))?;
+    member.date = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs() as u32);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        member.uid = metadata.uid();
+        member.gid = metadata.gid();
+        member.mode = metadata.mode();
+    }
+    Ok(member)
+}
+
+fn write_archive(path: &str, builder: &Builder) -> Result<(), String> {
+    let bytes = builder.write().map_err(|e| format!("{}: {}", path, e))?;
+    let error = |e: std::io::Error| format!("{}: {}", path, e);
+    std::fs::write(path, bytes).map_err(error)?;
+    if builder.symdef {
+        let file = std::fs::File::options().write(true).open(path).map_err(error)?;
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(builder.timestamp.into())).map_err(error)?;
+    }
+    Ok(())
+}
+
+fn list(archive: &Archive<'_>, names: &[String], verbose: bool) {
+    for member in &archive.members {
+        if !names.is_empty() && !names.contains(&member.name) {
+            continue;
+        }
+        if verbose {
+            println!("{:06o} {}/{} {:>8} {:>10} {}", member.mode, member.uid, member.gid, member.data.len(), member.date, member.name);
+        } else {
+            println!("{}", member.name);
+        }
+    }
+}
+
+fn extract(archive: &Archive<'_>, names: &[String], verbose: bool) -> Result<(), String> {
+    for name in names {
+        if archive.member(name).is_none() {
+            return Err(format!("{}: not in archive", name));
+        }
+    }
+    for member in &archive.members {
+        if !names.is_empty() && !names.contains(&member.name) {
+            continue;
+        }
+        if member.name.contains('/') || member.name == ".." {
+            return Err(format!("{}: refusing to extract outside the current directory", member.name));
+        }
+        if verbose {
+            println!("x - {}", member.name);
+        }
+        std::fs::write(&member.name, member.data).map_err(|e| format!("{}: {}", member.name, e))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = if member.mode == 0 { nextstep_ar::DEFAULT_MODE } else { member.mode };
+            std::fs::set_permissions(&member.name, std::fs::Permissions::from_mode(mode & 0o777))
+                .map_err(|e| format!("{}: {}", member.name, e))?;
+        }
+    }
+    Ok(())
+}
+
+fn print_symdef(path: &str) -> Result<(), String> {
+    let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let archive = Archive::parse(&data).map_err(|e| format!("{}: {}", path, e))?;
+    let entries = archive
+        .ranlib()
+        .map_err(|e| format!("{}: {} (run nextstep-ar s to rebuild it)", path, e))?
+        .ok_or_else(|| format!("{}: no __.SYMDEF table", path))?;
+    for entry in entries {
+        let member = archive.members.iter().find(|m| m.offset == entry.offset as usize);
+        println!("{} in {}", entry.name, member.map_or("?", |m| m.name.as_str()));
+    }
+    Ok(())
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    if args.len() == 2 && args[0] == "--symdef" {
+        return print_symdef(&args[1]);
+    }
+    if args.len() < 2 {
+        return Err(USAGE.to_string());
+    }
+    let flags = args[0].trim_start_matches('-');
+    let mut chars = flags.chars();
+    let operation = chars.next().ok_or(USAGE)?;
+    if !"rqdtxs".contains(operation) {
+        return Err(format!("unknown operation {}\n{}", operation, USAGE));
+    }
+    let mut modifiers = Modifiers { create: false, verbose: false, symdef: true, deterministic: false };
+    for modifier in chars {
+        match modifier {
+            'c' => modifiers.create = true,
+            'v' => modifiers.verbose = true,
+            'S' => modifiers.symdef = false,
+            'D' => modifiers.deterministic = true,
+            's' | 'u' => {}
+            _ => return Err(format!("unknown modifier {}\n{}", modifier, USAGE)),
+        }
+    }
+    let path = &args[1];
+    let files = &args[2..];
+
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && "rq".contains(operation) => {
+            if !modifiers.create {
+                eprintln!("nextstep-ar: creating {}", path);
+            }
+            nextstep_ar::ARMAG.to_vec()
+        }
+        Err(e) => return Err(format!("{}: {}", path, e)),
+    };
+    let archive = Archive::parse(&data).map_err(|e| format!("{}: {}", path, e))?;
+    match operation {
+        't' => {
+            list(&archive, files, modifiers.verbose);
+            return Ok(());
+        }
+        'x' => return extract(&archive, files, modifiers.verbose),
+        _ => {}
+    }
+
+    let mut builder = Builder::from_archive(&archive);
+    builder.symdef = modifiers.symdef;
+    builder.timestamp = timestamp();
+    for file in files {
+        match operation {
+            'r' | 'q' => {
+                let member = new_member(file, &modifiers)?;
+                let name = member.name.clone();
+                let replaced = match operation {
+                    'r' => builder.insert(member),
+                    _ => {
+                        builder.append(member);
+                        false
+                    }
+                };
+                if modifiers.verbose {
+                    println!("{} - {}", if replaced { 'r' } else { 'a' }, name);
+                }
+            }
+            'd' => match builder.remove(file) {
+                Some(_) if modifiers.verbose => println!("d - {}", file),
+                Some(_) => {}
+                None => eprintln!("nextstep-ar: {}: not in archive", file),
+            },
+            _ => return Err(format!("operation s takes no files\n{}", USAGE)),
+        }
+    }
+    write_archive(path, &builder)
+}
+
+fn main() -> ExitCode {
+    match run(std::env::args().skip(1).collect()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("nextstep-ar: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Archive errors
+
+use std::fmt;
+
+/// Why an archive could not be read or written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The file does not start with `!<arch>\n`
+    BadMagic,
+    /// A structure runs past the end of the archive
+    Truncated { what: &'static str, offset: usize },
+    /// A member header is malformed
+    BadHeader { offset: usize, reason: String },
+    /// The `__.SYMDEF` member is not a big-endian ranlib table for this archive
+    BadSymdef(String),
+    /// A member does not fit the fixed-width header fields
+    TooLarge { member: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadMagic => f.write_str("not an archive (missing !<arch> magic)"),
+            Error::Truncated { what, offset } => write!(f, "truncated {} at offset {:#x}", what, offset),
+            Error::BadHeader { offset, reason } => write!(f, "member header at {:#x}: {}", offset, reason),
+            Error::BadSymdef(reason) => write!(f, "bad __.SYMDEF table: {}", reason),
+            Error::TooLarge { member } => write!(f, "member {} is too large for an ar header", member),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Result of reading or writing an archive
+pub type Result<T> = core::result::Result<T, Error>;
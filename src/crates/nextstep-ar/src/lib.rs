@@ -0,0 +1,27 @@
+//! nextstep-ar - BSD `ar` archives with the NeXTSTEP `__.SYMDEF` table
+//!
+//! NeXT `ld` only searches an archive through its `__.SYMDEF` member, a
+//! 4.3BSD ranlib table in big-endian byte order. Host tools do not write
+//! that: LLVM's BSD writer (used for the target's `archive-format: bsd`
+//! staticlibs) emits a little-endian table, and GNU `ar` a `/` index. This
+//! crate reads BSD and GNU archives and writes BSD ones with a NeXT table,
+//! so static libraries for NeXTSTEP C programs can be packaged on Linux:
+//!
+//! ```ignore
+//! let data = std::fs::read("libnextstep_atomics_staticlib.a")?;
+//! let archive = nextstep_ar::Archive::parse(&data)?;
+//! std::fs::write("libnextstep_atomics_staticlib.a", nextstep_ar::Builder::from_archive(&archive).write()?)?;
+//! ```
+//!
+//! `nextstep-ar` does the same from the command line, with the usual
+//! `ar` operations (`nextstep-ar s` being `ranlib`).
+
+mod error;
+mod read;
+pub mod symdef;
+mod write;
+
+pub use error::{Error, Result};
+pub use read::{is_archive, Archive, Member, ARMAG, HEADER_SIZE, SYMDEF};
+pub use symdef::Ranlib;
+pub use write::{Builder, NewMember, DEFAULT_MODE};
@@ -0,0 +1,198 @@
+//! Reading archives
+//!
+//! Handles BSD archives (long names as `#1/<len>` prefixed to the member
+//! data), which NeXT `ar` and the target's `archive-format: bsd` write,
+//! and plain GNU ones (`//` name table), so archives from a host `ar` can
+//! be re-indexed too.
+
+use crate::error::{Error, Result};
+use crate::symdef::{self, Ranlib};
+
+/// Magic string every archive starts with
+pub const ARMAG: &[u8] = b"!<arch>\n";
+/// Size of a member header
+pub const HEADER_SIZE: usize = 60;
+/// Name of the NeXT ranlib table member
+pub const SYMDEF: &str = "__.SYMDEF";
+
+/// One archive member
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member<'a> {
+    pub name: String,
+    /// Modification time, seconds since the epoch
+    pub date: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u32,
+    pub data: &'a [u8],
+    /// Offset of the member header in the archive
+    pub offset: usize,
+}
+
+/// A parsed archive
+#[derive(Debug, Clone)]
+pub struct Archive<'a> {
+    /// Members in file order, without the symbol index
+    pub members: Vec<Member<'a>>,
+    /// The `__.SYMDEF` (or GNU `/`) index member, if there is one
+    pub index: Option<Member<'a>>,
+}
+
+/// Whether `data` starts like an archive
+pub fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(ARMAG)
+}
+
+fn field(bytes: &[u8]) -> &str {
+    std::str::from_utf8(bytes).unwrap_or("").trim_end()
+}
+
+// A numeric header field; blank counts as zero, as ar writes for uid/gid
+fn number(bytes: &[u8], radix: u32, what: &str, offset: usize) -> Result<u32> {
+    let text = field(bytes);
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u32::from_str_radix(text, radix)
+        .map_err(|_| Error::BadHeader { offset, reason: format!("bad {} {:?}", what, text) })
+}
+
+impl<'a> Archive<'a> {
+    /// Parse the archive `data`
+    pub fn parse(data: &'a [u8]) -> Result<Archive<'a>> {
+        if !is_archive(data) {
+            return Err(Error::BadMagic);
+        }
+        let mut archive = Archive { members: Vec::new(), index: None };
+        let mut gnu_names: &[u8] = &[];
+        let mut at = ARMAG.len();
+        while at < data.len() {
+            let header = data
+                .get(at..at + HEADER_SIZE)
+                .ok_or(Error::Truncated { what: "member header", offset: at })?;
+            if &header[58..60] != b"`\n" {
+                return Err(Error::BadHeader { offset: at, reason: "missing `\\n terminator".to_string() });
+            }
+            let size = number(&header[48..58], 10, "size", at)? as usize;
+            let start = at + HEADER_SIZE;
+            let mut body = data
+                .get(start..start + size)
+                .ok_or(Error::Truncated { what: "member data", offset: start })?;
+
+            let raw = field(&header[0..16]);
+            let mut gnu_index = false;
+            let name = if let Some(len) = raw.strip_prefix("#1/") {
+                let len: usize = len
+                    .parse()
+                    .map_err(|_| Error::BadHeader { offset: at, reason: format!("bad long name {:?}", raw) })?;
+                if len > body.len() {
+                    return Err(Error::BadHeader { offset: at, reason: "long name runs past member".to_string() });
+                }
+                let (name, rest) = body.split_at(len);
+                body = rest;
+                let end = name.iter().position(|&b| b == 0).unwrap_or(len);
+                String::from_utf8_lossy(&name[..end]).into_owned()
+            } else if raw == "//" {
+                gnu_names = body;
+                at = start + size + (size & 1);
+                continue;
+            } else if raw == "/" || raw == "/SYM64/" {
+                gnu_index = true;
+                raw.to_string()
+            } else if let Some(offset) = raw.strip_prefix('/').and_then(|n| n.parse::<usize>().ok()) {
+                let rest = gnu_names.get(offset..).unwrap_or(&[]);
+                let end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+                String::from_utf8_lossy(&rest[..end]).trim_end_matches('/').to_string()
+            } else {
+                raw.trim_end_matches('/').to_string()
+            };
+
+            let member = Member {
+                date: number(&header[16..28], 10, "date", at)?,
+                uid: number(&header[28..34], 10, "uid", at)?,
+                gid: number(&header[34..40], 10, "gid", at)?,
+                mode: number(&header[40..48], 8, "mode", at)?,
+                name,
+                data: body,
+                offset: at,
+            };
+            if gnu_index || member.name.starts_with(SYMDEF) {
+                archive.index = Some(member);
+            } else {
+                archive.members.push(member);
+            }
+            at = start + size + (size & 1);
+        }
+        Ok(archive)
+    }
+
+    /// The member called `name`
+    pub fn member(&self, name: &str) -> Option<&Member<'a>> {
+        self.members.iter().find(|m| m.name == name)
+    }
+
+    /// The entries of the `__.SYMDEF` table, or `None` without one
+    ///
+    /// Fails if the index is not a big-endian NeXT table whose offsets
+    /// point at this archive's members, which is what a GNU index or the
+    /// little-endian one LLVM writes look like.
+    pub fn ranlib(&self) -> Result<Option<Vec<Ranlib>>> {
+        let Some(index) = &self.index else {
+            return Ok(None);
+        };
+        if !index.name.starts_with(SYMDEF) {
+            return Err(Error::BadSymdef(format!("{} is a GNU symbol table", index.name)));
+        }
+        let entries = symdef::parse(index.data)?;
+        for entry in &entries {
+            if !self.members.iter().any(|m| m.offset == entry.offset as usize) {
+                return Err(Error::BadSymdef(format!(
+                    "{} points at {:#x}, which is not a member",
+                    entry.name, entry.offset
+                )));
+            }
+        }
+        Ok(Some(entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(out: &mut Vec<u8>, name: &str, size: usize) {
+        out.extend_from_slice(format!("{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", name, 0, 0, 0, 644, size).as_bytes());
+    }
+
+    #[test]
+    fn test_gnu_members() {
+        let mut archive = ARMAG.to_vec();
+        header(&mut archive, "/", 4);
+        archive.extend_from_slice(&[0; 4]);
+        header(&mut archive, "//", 24);
+        archive.extend_from_slice(b"very_long_object_name.o/\n");
+        archive.pop();
+        header(&mut archive, "/0", 2);
+        archive.extend_from_slice(b"xy");
+        header(&mut archive, "b.o/", 1);
+        archive.extend_from_slice(b"z\n");
+        let archive = Archive::parse(&archive).unwrap();
+        let names: Vec<&str> = archive.members.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["very_long_object_name.o", "b.o"]);
+        assert_eq!(archive.members[1].data, b"z");
+        assert_eq!(archive.members[1].mode, 0o644);
+        assert!(matches!(archive.ranlib(), Err(Error::BadSymdef(_))));
+    }
+
+    #[test]
+    fn test_truncated_archive() {
+        let mut archive = ARMAG.to_vec();
+        header(&mut archive, "a.o", 4);
+        archive.extend_from_slice(b"ab");
+        assert_eq!(
+            Archive::parse(&archive).unwrap_err(),
+            Error::Truncated { what: "member data", offset: ARMAG.len() + HEADER_SIZE }
+        );
+        assert_eq!(Archive::parse(b"!<arch>").unwrap_err(), Error::BadMagic);
+    }
+}
@@ -0,0 +1,142 @@
+//! The `__.SYMDEF` ranlib table
+//!
+//! NeXT `ld` finds archive members through this table rather than by
+//! reading every member. Its layout is the 4.3BSD one, in the target's
+//! big-endian byte order:
+//!
+//! ```text
+//! u32 ranlib_size               bytes of ranlib entries that follow
+//! { u32 ran_strx; u32 ran_off } one per symbol; ran_off is the offset
+//!                               of the defining member's header
+//! u32 strsize                   bytes of string table that follow
+//! char strings[strsize]         NUL-terminated names, padded to 4 bytes
+//! ```
+
+use crate::error::{Error, Result};
+use nextstep_macho::consts::{MH_OBJECT, N_ABS, N_SECT};
+use nextstep_macho::MachO;
+
+/// Size of one `struct ranlib`
+pub const RANLIB_SIZE: usize = 8;
+
+/// One symbol in the table and the header offset of the member defining it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ranlib {
+    pub name: String,
+    pub offset: u32,
+}
+
+fn be32(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Parse a `__.SYMDEF` member's contents
+pub fn parse(data: &[u8]) -> Result<Vec<Ranlib>> {
+    let bad = |reason: String| Error::BadSymdef(reason);
+    let ranlib_size = be32(data, 0).ok_or_else(|| bad("shorter than its size word".to_string()))? as usize;
+    if ranlib_size & (RANLIB_SIZE - 1) != 0 {
+        return Err(bad(format!("ranlib size {:#x} is not a multiple of {}", ranlib_size, RANLIB_SIZE)));
+    }
+    let strsize = 4usize
+        .checked_add(ranlib_size)
+        .and_then(|at| be32(data, at))
+        .ok_or_else(|| bad(format!("ranlib size {:#x} runs past the table", ranlib_size)))?
+        as usize;
+    let strings = data
+        .get(8 + ranlib_size..)
+        .and_then(|rest| rest.get(..strsize))
+        .ok_or_else(|| bad(format!("string table size {:#x} runs past the table", strsize)))?;
+
+    (0..ranlib_size / RANLIB_SIZE)
+        .map(|i| {
+            let at = 4 + i * RANLIB_SIZE;
+            let strx = be32(data, at).unwrap_or(0) as usize;
+            let offset = be32(data, at + 4).unwrap_or(0);
+            let rest = strings
+                .get(strx..)
+                .filter(|rest| !rest.is_empty())
+                .ok_or_else(|| bad(format!("entry {} has string index {:#x} outside the string table", i, strx)))?;
+            let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+            Ok(Ranlib { name: String::from_utf8_lossy(&rest[..end]).into_owned(), offset })
+        })
+        .collect()
+}
+
+/// Build a `__.SYMDEF` member's contents for `entries`
+pub fn build(entries: &[Ranlib]) -> Vec<u8> {
+    let mut strings = Vec::new();
+    let mut out = Vec::with_capacity(8 + entries.len() * RANLIB_SIZE);
+    out.extend_from_slice(&((entries.len() * RANLIB_SIZE) as u32).to_be_bytes());
+    for entry in entries {
+        out.extend_from_slice(&(strings.len() as u32).to_be_bytes());
+        out.extend_from_slice(&entry.offset.to_be_bytes());
+        strings.extend_from_slice(entry.name.as_bytes());
+        strings.push(0);
+    }
+    strings.resize((strings.len() + 3) & !3, 0);
+    out.extend_from_slice(&(strings.len() as u32).to_be_bytes());
+    out.extend_from_slice(&strings);
+    out
+}
+
+/// Size of the table [`build`] makes for these symbol names
+pub fn size<'n>(names: impl IntoIterator<Item = &'n str>) -> usize {
+    let (count, strings) = names.into_iter().fold((0, 0), |(count, strings), name| (count + 1, strings + name.len() + 1));
+    8 + count * RANLIB_SIZE + ((strings + 3) & !3)
+}
+
+/// External symbols an archive member defines, which are what the table lists
+///
+/// Members that are not Mach-O objects (an rlib's `lib.rmeta`, say)
+/// define nothing. Common symbols are left out, as NeXT `ranlib` does by
+/// default, so `ld` does not pull a member in just for a tentative
+/// definition.
+pub fn defined_symbols(data: &[u8]) -> Vec<String> {
+    let Ok(macho) = MachO::parse(data) else {
+        return Vec::new();
+    };
+    if macho.header.filetype != MH_OBJECT {
+        return Vec::new();
+    }
+    let Ok(symbols) = macho.symbols() else {
+        return Vec::new();
+    };
+    symbols
+        .iter()
+        .filter(|s| !s.is_stab() && s.is_external() && matches!(s.kind(), Some(N_SECT) | Some(N_ABS)))
+        .map(|s| s.name_lossy().into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let entries = vec![
+            Ranlib { name: "_main".to_string(), offset: 0x44 },
+            Ranlib { name: "_helper".to_string(), offset: 0x1a0 },
+        ];
+        let table = build(&entries);
+        assert_eq!(table.len(), size(["_main", "_helper"]));
+        assert_eq!(&table[..4], &16u32.to_be_bytes());
+        assert_eq!(&table[4..12], &[0, 0, 0, 0, 0, 0, 0, 0x44]);
+        // "_main\0_helper\0" padded from 14 to 16 bytes
+        assert_eq!(&table[20..24], &16u32.to_be_bytes());
+        assert_eq!(parse(&table).unwrap(), entries);
+        assert_eq!(parse(&build(&[])).unwrap(), []);
+    }
+
+    #[test]
+    fn test_little_endian_table_rejected() {
+        // What LLVM writes: the same layout in little-endian words
+        let mut table = Vec::new();
+        table.extend_from_slice(&8u32.to_le_bytes());
+        table.extend_from_slice(&0u32.to_le_bytes());
+        table.extend_from_slice(&0x44u32.to_le_bytes());
+        table.extend_from_slice(&8u32.to_le_bytes());
+        table.extend_from_slice(b"_main\0\0\0");
+        assert!(matches!(parse(&table), Err(Error::BadSymdef(_))));
+    }
+}
@@ -0,0 +1,296 @@
+//! Writing archives
+//!
+//! Output is always BSD format with the `__.SYMDEF` table first, as NeXT
+//! `ar` and `ranlib` leave it. Names longer than 16 bytes or containing
+//! spaces use the `#1/<len>` form, with the name NUL-padded to a multiple
+//! of 4 bytes so member data keeps the alignment of its header.
+
+use crate::error::{Error, Result};
+use crate::read::{Archive, Member, ARMAG, HEADER_SIZE, SYMDEF};
+use crate::symdef::{self, Ranlib};
+use std::collections::HashSet;
+
+/// Mode `ar` records for members added without one
+pub const DEFAULT_MODE: u32 = 0o100644;
+
+/// A member to be written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewMember {
+    pub name: String,
+    pub date: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u32,
+    pub data: Vec<u8>,
+}
+
+impl NewMember {
+    /// A member with zero date and owner, and mode 0644
+    pub fn new(name: &str, data: Vec<u8>) -> NewMember {
+        NewMember { name: name.to_string(), date: 0, uid: 0, gid: 0, mode: DEFAULT_MODE, data }
+    }
+}
+
+impl From<&Member<'_>> for NewMember {
+    fn from(member: &Member<'_>) -> NewMember {
+        NewMember {
+            name: member.name.clone(),
+            date: member.date,
+            uid: member.uid,
+            gid: member.gid,
+            mode: member.mode,
+            data: member.data.to_vec(),
+        }
+    }
+}
+
+/// Builds an archive from members, in order
+#[derive(Debug, Clone)]
+pub struct Builder {
+    members: Vec<NewMember>,
+    /// Whether to write a `__.SYMDEF` table
+    pub symdef: bool,
+    /// Date recorded on the `__.SYMDEF` member
+    ///
+    /// NeXT `ld` warns that the table is out of date when this is older
+    /// than the archive file's modification time.
+    pub timestamp: u32,
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder { members: Vec::new(), symdef: true, timestamp: 0 }
+    }
+}
+
+// `#1/<len>` name and its padded length, or None if the name fits the header
+fn long_name(name: &str) -> Option<usize> {
+    (name.len() > 16 || name.contains(' ') || name.starts_with("#1/")).then_some((name.len() + 3) & !3)
+}
+
+fn header(out: &mut Vec<u8>, name: &str, member: &NewMember, size: usize) -> Result<()> {
+    let fields = format!(
+        "{:<16}{:<12}{:<6}{:<6}{:<8o}{:<10}",
+        name, member.date, member.uid, member.gid, member.mode, size
+    );
+    if fields.len() != HEADER_SIZE - 2 {
+        return Err(Error::TooLarge { member: member.name.clone() });
+    }
+    out.extend_from_slice(fields.as_bytes());
+    out.extend_from_slice(b"`\n");
+    Ok(())
+}
+
+fn push_member(out: &mut Vec<u8>, member: &NewMember) -> Result<()> {
+    match long_name(&member.name) {
+        Some(len) => {
+            header(out, &format!("#1/{}", len), member, len + member.data.len())?;
+            out.extend_from_slice(member.name.as_bytes());
+            out.resize(out.len() + len - member.name.len(), 0);
+        }
+        None => header(out, &member.name, member, member.data.len())?,
+    }
+    out.extend_from_slice(&member.data);
+    if out.len() & 1 != 0 {
+        out.push(b'\n');
+    }
+    Ok(())
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder::default()
+    }
+
+    /// A builder holding `archive`'s members, to update it
+    pub fn from_archive(archive: &Archive<'_>) -> Builder {
+        Builder { members: archive.members.iter().map(NewMember::from).collect(), ..Builder::default() }
+    }
+
+    pub fn members(&self) -> &[NewMember] {
+        &self.members
+    }
+
+    /// Replace the member with the same name, or add `member` at the end
+    ///
+    /// Returns whether a member was replaced.
+    pub fn insert(&mut self, member: NewMember) -> bool {
+        match self.members.iter_mut().find(|m| m.name == member.name) {
+            Some(existing) => {
+                *existing = member;
+                true
+            }
+            None => {
+                self.members.push(member);
+                false
+            }
+        }
+    }
+
+    /// Add `member` at the end, even if one with its name exists
+    pub fn append(&mut self, member: NewMember) {
+        self.members.push(member);
+    }
+
+    /// Remove the first member called `name`
+    pub fn remove(&mut self, name: &str) -> Option<NewMember> {
+        let index = self.members.iter().position(|m| m.name == name)?;
+        Some(self.members.remove(index))
+    }
+
+    /// The `__.SYMDEF` entries [`write`](Builder::write) will record
+    ///
+    /// Each symbol is listed once, for the first member defining it, which
+    /// is the one NeXT `ld` would link.
+    pub fn ranlib(&self) -> Vec<Ranlib> {
+        let mut seen = HashSet::new();
+        let mut symbols = Vec::new();
+        for (index, member) in self.members.iter().enumerate() {
+            for name in symdef::defined_symbols(&member.data) {
+                if seen.insert(name.clone()) {
+                    symbols.push((index, name));
+                }
+            }
+        }
+
+        // Header offset of each member, after the table itself
+        let mut at = ARMAG.len();
+        if self.symdef {
+            at += HEADER_SIZE + symdef::size(symbols.iter().map(|(_, name)| name.as_str()));
+        }
+        let mut offsets = Vec::with_capacity(self.members.len());
+        for member in &self.members {
+            offsets.push(at as u32);
+            let size = HEADER_SIZE + long_name(&member.name).unwrap_or(0) + member.data.len();
+            at += size + (size & 1);
+        }
+        symbols.into_iter().map(|(index, name)| Ranlib { name, offset: offsets[index] }).collect()
+    }
+
+    /// The archive's bytes
+    pub fn write(&self) -> Result<Vec<u8>> {
+        let mut out = ARMAG.to_vec();
+        if self.symdef {
+            let table = symdef::build(&self.ranlib());
+            let member = NewMember { date: self.timestamp, ..NewMember::new(SYMDEF, Vec::new()) };
+            header(&mut out, SYMDEF, &member, table.len())?;
+            out.extend_from_slice(&table);
+        }
+        for member in &self.members {
+            push_member(&mut out, member)?;
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nextstep_macho::consts::*;
+
+    // A minimal MH_OBJECT with just a symbol table: `defined` as external
+    // N_SECT symbols, `undefined` as external undefined ones
+    fn object(defined: &[&str], undefined: &[&str]) -> Vec<u8> {
+        let symbols: Vec<(&str, u8, u8)> = defined
+            .iter()
+            .map(|name| (*name, N_SECT | N_EXT, 1))
+            .chain(undefined.iter().map(|name| (*name, N_UNDF | N_EXT, 0)))
+            .collect();
+        let symoff = MACH_HEADER_SIZE + SYMTAB_COMMAND_SIZE;
+        let stroff = symoff + symbols.len() * NLIST_SIZE;
+        let mut strings = vec![0u8];
+        let mut out = Vec::new();
+        for word in [MH_MAGIC, CPU_TYPE_MC680X0 as u32, CPU_SUBTYPE_MC680X0_ALL as u32, MH_OBJECT, 1, SYMTAB_COMMAND_SIZE as u32, 0] {
+            out.extend_from_slice(&word.to_be_bytes());
+        }
+        let mut nlists = Vec::new();
+        for (name, n_type, n_sect) in &symbols {
+            nlists.extend_from_slice(&(strings.len() as u32).to_be_bytes());
+            nlists.extend_from_slice(&[*n_type, *n_sect, 0, 0, 0, 0, 0, 0]);
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+        }
+        strings.resize((strings.len() + 3) & !3, 0);
+        let symtab = [LC_SYMTAB, SYMTAB_COMMAND_SIZE as u32, symoff as u32, symbols.len() as u32, stroff as u32, strings.len() as u32];
+        for word in symtab {
+            out.extend_from_slice(&word.to_be_bytes());
+        }
+        out.extend_from_slice(&nlists);
+        out.extend_from_slice(&strings);
+        out
+    }
+
+    #[test]
+    fn test_write_and_read_back() {
+        let mut builder = Builder::new();
+        builder.timestamp = 1_000_000;
+        builder.insert(NewMember::new("a.o", object(&["_a", "_shared"], &["_b"])));
+        builder.insert(NewMember::new("a_rather_long_member_name.o", object(&["_b", "_shared"], &[])));
+        builder.insert(NewMember::new("lib.rmeta", b"odd".to_vec()));
+        let bytes = builder.write().unwrap();
+
+        let archive = Archive::parse(&bytes).unwrap();
+        let names: Vec<&str> = archive.members.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["a.o", "a_rather_long_member_name.o", "lib.rmeta"]);
+        assert_eq!(archive.members[2].data, b"odd");
+        assert_eq!(archive.members[1].mode, DEFAULT_MODE);
+        // Long names are padded so the object stays 4-byte aligned
+        let long = &archive.members[1];
+        assert_eq!((long.data.as_ptr() as usize - bytes.as_ptr() as usize) & 3, 0);
+
+        let index = archive.index.as_ref().unwrap();
+        assert_eq!((index.name.as_str(), index.date), (SYMDEF, 1_000_000));
+        let ranlib = archive.ranlib().unwrap().unwrap();
+        let a = archive.members[0].offset as u32;
+        let b = archive.members[1].offset as u32;
+        assert_eq!(
+            ranlib,
+            [
+                Ranlib { name: "_a".to_string(), offset: a },
+                Ranlib { name: "_shared".to_string(), offset: a },
+                Ranlib { name: "_b".to_string(), offset: b },
+            ]
+        );
+        assert_eq!(ranlib, builder.ranlib());
+    }
+
+    #[test]
+    fn test_update_members() {
+        let mut builder = Builder::new();
+        builder.insert(NewMember::new("a.o", object(&["_a"], &[])));
+        builder.insert(NewMember::new("b.o", object(&["_b"], &[])));
+        let bytes = builder.write().unwrap();
+
+        let mut builder = Builder::from_archive(&Archive::parse(&bytes).unwrap());
+        assert!(builder.insert(NewMember::new("a.o", object(&["_a2"], &[]))));
+        assert!(builder.remove("b.o").is_some());
+        assert!(builder.remove("b.o").is_none());
+        builder.append(NewMember::new("c.o", object(&["_c"], &[])));
+        let bytes = builder.write().unwrap();
+
+        let archive = Archive::parse(&bytes).unwrap();
+        let names: Vec<&str> = archive.members.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["a.o", "c.o"]);
+        let symbols: Vec<String> = archive.ranlib().unwrap().unwrap().into_iter().map(|r| r.name).collect();
+        assert_eq!(symbols, ["_a2", "_c"]);
+    }
+
+    #[test]
+    fn test_without_symdef() {
+        let mut builder = Builder::new();
+        builder.symdef = false;
+        builder.insert(NewMember::new("a.o", object(&["_a"], &[])));
+        let bytes = builder.write().unwrap();
+        let archive = Archive::parse(&bytes).unwrap();
+        assert!(archive.index.is_none());
+        assert_eq!(archive.ranlib().unwrap(), None);
+        assert_eq!(archive.members[0].offset, ARMAG.len());
+    }
+
+    #[test]
+    fn test_field_overflow() {
+        let mut builder = Builder::new();
+        builder.insert(NewMember { uid: 10_000_000, ..NewMember::new("a.o", Vec::new()) });
+        assert_eq!(builder.write().unwrap_err(), Error::TooLarge { member: "a.o".to_string() });
+    }
+}
@@ -0,0 +1,18 @@
+//! nextstep-atomics-staticlib - nextstep-atomics as a C link archive
+//!
+//! Builds `libnextstep_atomics_staticlib.a`, which carries the
+//! `__atomic_*`/`__sync_*` libcalls for programs linked by NeXT cc or
+//! nextstep-ld without a Rust crate graph. Rust code depends on
+//! `nextstep-atomics` directly; keeping the staticlib here leaves that crate
+//! a plain rlib, which host tests and dependents can build.
+//!
+//! A staticlib is a final artifact, so on the target it needs a panic
+//! handler; `nextstep-panic` provides it (an ordering misuse in a libcall
+//! panics). On the host the archive links std instead.
+
+#![cfg_attr(target_arch = "m68k", no_std)]
+
+extern crate nextstep_atomics;
+
+#[cfg(target_arch = "m68k")]
+extern crate nextstep_panic;
@@ -0,0 +1,69 @@
+// build.rs - Pick the atomic implementation for the target CPU
+//
+// The 68020 and later have CAS/CAS2/TAS, so every NeXT machine (68030 or
+// 68040) can do atomics natively. The spinlock fallback is only needed
+// for 68000/68010 targets.
+//
+// It also sizes the spinlock table from NEXTSTEP_ATOMICS_LOCK_COUNT.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// CPUs with the CAS instruction
+const CAS_CPUS: [&str; 4] = ["68020", "68030", "68040", "68060"];
+
+// Spinlock table size used when NEXTSTEP_ATOMICS_LOCK_COUNT is not set
+const DEFAULT_LOCK_COUNT: u32 = 64;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rustc-check-cfg=cfg(native_cas)");
+
+    write_lock_config();
+
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    if arch != "m68k" {
+        return;
+    }
+
+    // CPU-specific target specs are named e.g. m68k-next-nextstep-68040
+    let target = env::var("TARGET").unwrap_or_default();
+    let features = env::var("CARGO_CFG_TARGET_FEATURE").unwrap_or_default();
+    let cpu_has_cas = CAS_CPUS.iter().any(|cpu| {
+        target.contains(cpu) || features.split(',').any(|f| f == format!("isa-{}", cpu))
+    });
+    let forced = env::var_os("CARGO_FEATURE_NATIVE_CAS").is_some();
+
+    if cpu_has_cas || forced {
+        println!("cargo:rustc-cfg=native_cas");
+    }
+}
+
+// Size the spinlock table from NEXTSTEP_ATOMICS_LOCK_COUNT (a power of 2)
+fn write_lock_config() {
+    println!("cargo:rerun-if-env-changed=NEXTSTEP_ATOMICS_LOCK_COUNT");
+
+    let count = match env::var("NEXTSTEP_ATOMICS_LOCK_COUNT") {
+        Ok(value) => value
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|n| n.is_power_of_two() && *n <= 4096)
+            .unwrap_or_else(|| {
+                panic!("NEXTSTEP_ATOMICS_LOCK_COUNT must be a power of 2 up to 4096, got {:?}", value)
+            }),
+        Err(_) => DEFAULT_LOCK_COUNT,
+    };
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let config = format!(
+        "// Generated by build.rs from NEXTSTEP_ATOMICS_LOCK_COUNT\n\
+         pub(crate) const SPINLOCK_COUNT: usize = {};\n\
+         #[cfg(feature = \"mixing-hash\")]\n\
+         pub(crate) const SPINLOCK_BITS: u32 = {};\n",
+        count,
+        count.trailing_zeros()
+    );
+    fs::write(Path::new(&out_dir).join("lock_config.rs"), config).unwrap();
+}
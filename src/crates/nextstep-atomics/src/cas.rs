@@ -0,0 +1,28 @@
+//! Native compare-and-swap for 68020 and later CPUs
+//!
+//! `cas.<size> Dc,Du,<ea>` compares `<ea>` with Dc; if they are equal it
+//! writes Du to `<ea>`, otherwise it loads `<ea>` into Dc. Either way Dc
+//! ends up holding the previous value. The instruction uses an indivisible
+//! read-modify-write bus cycle, so it is atomic with respect to interrupts
+//! and signal handlers.
+
+macro_rules! cas_fn {
+    ($name:ident, $t:ty, $size:literal) => {
+        #[inline(always)]
+        pub(crate) unsafe fn $name(ptr: *mut $t, old: $t, new: $t) -> $t {
+            let mut prev = old;
+            core::arch::asm!(
+                concat!("cas.", $size, " {prev}, {new}, ({ptr})"),
+                prev = inout(reg_data) prev,
+                new = in(reg_data) new,
+                ptr = in(reg_addr) ptr,
+                options(nostack),
+            );
+            prev
+        }
+    };
+}
+
+cas_fn!(cas_1, u8, "b");
+cas_fn!(cas_2, u16, "w");
+cas_fn!(cas_4, u32, "l");
@@ -0,0 +1,218 @@
+//! Critical sections that hold off signal handlers and interrupts
+//!
+//! On a single CPU, a handler that spins on a lock held by the code it
+//! interrupted never gets the lock. Every spinlock hold therefore runs in
+//! a critical section: user-space builds block signals with `sigblock`,
+//! bare-metal builds (feature `bare-metal`, supervisor mode) raise the SR
+//! interrupt mask to level 7.
+//!
+//! The API mirrors the `critical-section` crate, so other crates can use
+//! it directly. With the `critical-section-impl` feature this module is
+//! also registered as that crate's implementation.
+//!
+//! ```ignore
+//! let value = nextstep_atomics::critical_section::with(|_cs| {
+//!     // Neither signal handlers nor interrupts run here
+//!     read_shared_state()
+//! });
+//! ```
+
+use core::marker::PhantomData;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+/// Token proving that a critical section is held for `'cs`
+#[derive(Debug, Clone, Copy)]
+pub struct CriticalSection<'cs> {
+    _private: PhantomData<&'cs ()>,
+}
+
+impl<'cs> CriticalSection<'cs> {
+    /// Create a token for a critical section entered with [`acquire`]
+    ///
+    /// # Safety
+    ///
+    /// A critical section must be held for all of `'cs`.
+    #[inline(always)]
+    pub unsafe fn new() -> CriticalSection<'cs> {
+        CriticalSection { _private: PhantomData }
+    }
+}
+
+/// Signal mask or status register to put back when a section is released
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestoreState(u32);
+
+/// Enter a critical section
+///
+/// # Safety
+///
+/// The returned state must be passed to [`release`], with nested sections
+/// released in reverse order of acquisition.
+#[inline(always)]
+pub unsafe fn acquire() -> RestoreState {
+    let state = RestoreState(imp::acquire());
+    compiler_fence(Ordering::Acquire);
+    state
+}
+
+/// Leave a critical section entered with [`acquire`]
+///
+/// # Safety
+///
+/// `state` must come from the matching [`acquire`].
+#[inline(always)]
+pub unsafe fn release(state: RestoreState) {
+    compiler_fence(Ordering::Release);
+    imp::release(state.0);
+}
+
+/// Run `f` in a critical section
+#[inline]
+pub fn with<R>(f: impl FnOnce(CriticalSection<'_>) -> R) -> R {
+    unsafe {
+        let state = acquire();
+        let result = f(CriticalSection::new());
+        release(state);
+        result
+    }
+}
+
+// User space: block every signal except the synchronous fault signals, so
+// a crash inside a section is still reported
+#[cfg(all(target_arch = "m68k", not(feature = "bare-metal")))]
+mod imp {
+    use nextstep_sys::{
+        c_int, sigblock, sigmask, sigsetmask, SIGBUS, SIGEMT, SIGFPE, SIGILL, SIGSEGV, SIGTRAP,
+    };
+
+    const UNBLOCKED: c_int = sigmask(SIGBUS)
+        | sigmask(SIGSEGV)
+        | sigmask(SIGILL)
+        | sigmask(SIGFPE)
+        | sigmask(SIGTRAP)
+        | sigmask(SIGEMT);
+
+    #[inline(always)]
+    pub(crate) unsafe fn acquire() -> u32 {
+        sigblock(!UNBLOCKED) as u32
+    }
+
+    #[inline(always)]
+    pub(crate) unsafe fn release(state: u32) {
+        sigsetmask(state as c_int);
+    }
+}
+
+// Bare metal: raise the interrupt priority mask in SR to 7
+#[cfg(all(target_arch = "m68k", feature = "bare-metal"))]
+mod imp {
+    #[inline(always)]
+    pub(crate) unsafe fn acquire() -> u32 {
+        let sr: u16;
+        core::arch::asm!(
+            "move.w %sr, {sr}",
+            "ori.w #0x0700, %sr",
+            sr = out(reg_data) sr,
+            options(nostack),
+        );
+        sr as u32
+    }
+
+    #[inline(always)]
+    pub(crate) unsafe fn release(state: u32) {
+        core::arch::asm!(
+            "move.w {sr}, %sr",
+            sr = in(reg_data) state as u16,
+            options(nostack),
+        );
+    }
+}
+
+// Host builds have no handlers that touch the lock table
+#[cfg(all(not(target_arch = "m68k"), not(test)))]
+mod imp {
+    #[inline(always)]
+    pub(crate) unsafe fn acquire() -> u32 {
+        0
+    }
+
+    #[inline(always)]
+    pub(crate) unsafe fn release(_state: u32) {}
+}
+
+// Host tests keep a per-thread "signals blocked" flag, restored like a
+// signal mask, so `model` knows when a simulated signal may be delivered
+#[cfg(all(not(target_arch = "m68k"), test))]
+mod imp {
+    extern crate std;
+
+    use core::cell::Cell;
+
+    std::thread_local! {
+        static BLOCKED: Cell<u32> = const { Cell::new(0) };
+    }
+
+    #[inline(always)]
+    pub(crate) unsafe fn acquire() -> u32 {
+        BLOCKED.with(|blocked| blocked.replace(1))
+    }
+
+    #[inline(always)]
+    pub(crate) unsafe fn release(state: u32) {
+        BLOCKED.with(|blocked| blocked.set(state))
+    }
+
+    pub(crate) fn signals_blocked() -> bool {
+        BLOCKED.with(|blocked| blocked.get() != 0)
+    }
+}
+
+#[cfg(all(not(target_arch = "m68k"), test))]
+pub(crate) use imp::signals_blocked;
+
+#[cfg(feature = "critical-section-impl")]
+mod register {
+    struct NextstepCriticalSection;
+    ::critical_section::set_impl!(NextstepCriticalSection);
+
+    unsafe impl ::critical_section::Impl for NextstepCriticalSection {
+        unsafe fn acquire() -> ::critical_section::RawRestoreState {
+            super::imp::acquire()
+        }
+
+        unsafe fn release(state: ::critical_section::RawRestoreState) {
+            super::imp::release(state)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_nests() {
+        let value = with(|_| with(|_| 42) + 1);
+        assert_eq!(value, 43);
+    }
+
+    #[test]
+    fn test_nested_release_keeps_signals_blocked() {
+        assert!(!signals_blocked());
+        with(|_| {
+            with(|_| assert!(signals_blocked()));
+            assert!(signals_blocked());
+        });
+        assert!(!signals_blocked());
+    }
+
+    #[test]
+    fn test_acquire_release_pairs() {
+        unsafe {
+            let outer = acquire();
+            let inner = acquire();
+            release(inner);
+            release(outer);
+        }
+    }
+}
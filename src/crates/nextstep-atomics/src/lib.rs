@@ -1,131 +1,216 @@
-//! nextstep-atomics - Atomic operations for M68k without native CAS
-//! 
-//! Provides software-based atomic operations using spinlocks for
-//! processors that lack Compare-And-Swap instructions.
+//! nextstep-atomics - Atomic operations for M68k
+//!
+//! Provides the `__atomic_*`/`__sync_*` libcalls LLVM emits for m68k,
+//! covering the libatomic ABI for 1, 2, 4 and 8 byte objects plus the
+//! generic arbitrary-size entry points. On 68020 and later CPUs (every
+//! NeXT machine) 1, 2 and 4 byte operations use the native `CAS`
+//! instruction; on 68000/68010 they fall back to software spinlocks.
+//! 8 byte and generic operations always use the spinlocks. `build.rs`
+//! selects the implementation from the target CPU.
+//!
+//! The `__atomic_*` ordering arguments are honoured: relaxed loads of
+//! aligned words are plain moves, stronger orderings add fences, and an
+//! ordering the operation does not accept (e.g. a release load) panics.
+//!
+//! Spinlocks are held inside a [`critical_section`], which blocks signals
+//! (or masks interrupts on bare metal) so a handler cannot deadlock on a
+//! lock taken by the code it interrupted.
 
 #![no_std]
 #![feature(core_intrinsics)]
 #![feature(asm_experimental_arch)]
 
 use core::intrinsics;
-use core::sync::atomic::Ordering;
+use core::sync::atomic::{fence, Ordering};
 
-// Number of spinlocks (must be power of 2)
-const SPINLOCK_COUNT: usize = 64;
-const SPINLOCK_MASK: usize = SPINLOCK_COUNT - 1;
+#[cfg(native_cas)]
+mod cas;
+pub mod critical_section;
+mod lock;
+#[cfg(test)]
+mod model;
+mod ordering;
+#[cfg(feature = "lock-stats")]
+pub mod stats;
+
+use ordering::{checked_ordering, fence_after, fence_before, Access};
+pub use ordering::{
+    ATOMIC_ACQUIRE, ATOMIC_ACQ_REL, ATOMIC_CONSUME, ATOMIC_RELAXED, ATOMIC_RELEASE, ATOMIC_SEQ_CST,
+};
+
+/// Integer widths with atomic libcalls
+pub(crate) trait Word: Copy + Eq {
+    /// Whether accesses use plain moves and `CAS` rather than the lock table
+    const LOCK_FREE: bool;
+
+    /// Whether an aligned access is a single `move`, which no interrupt can split
+    const SINGLE_MOVE: bool;
+
+    /// Compare-and-swap, returning the previous value
+    unsafe fn cas(ptr: *mut Self, old: Self, new: Self) -> Self;
+}
+
+macro_rules! impl_word {
+    ($($t:ty => $cas:ident),*) => {$(
+        impl Word for $t {
+            const LOCK_FREE: bool = cfg!(native_cas);
+            const SINGLE_MOVE: bool = true;
+
+            #[inline(always)]
+            unsafe fn cas(ptr: *mut Self, old: Self, new: Self) -> Self {
+                #[cfg(native_cas)]
+                {
+                    cas::$cas(ptr, old, new)
+                }
+                #[cfg(not(native_cas))]
+                {
+                    locked_cas(ptr, old, new)
+                }
+            }
+        }
+    )*};
+}
+
+impl_word!(u8 => cas_1, u16 => cas_2, u32 => cas_4);
+
+// A 64-bit access takes two bus cycles, so u64 always goes through the locks
+impl Word for u64 {
+    const LOCK_FREE: bool = false;
+    const SINGLE_MOVE: bool = false;
 
-// Cache line size for padding (M68k typically 16 bytes)
-const CACHE_LINE_SIZE: usize = 16;
+    #[inline(always)]
+    unsafe fn cas(ptr: *mut Self, old: Self, new: Self) -> Self {
+        locked_cas(ptr, old, new)
+    }
+}
+
+// Run `f` with the spinlock covering `addr` held
+#[inline(always)]
+unsafe fn with_lock<R>(addr: usize, f: impl FnOnce() -> R) -> R {
+    lock::LOCKS.with(addr, f)
+}
 
-#[repr(C, align(16))]
-struct PaddedSpinlock {
-    locked: u8,
-    _padding: [u8; CACHE_LINE_SIZE - 1],
+#[inline(always)]
+unsafe fn locked_cas<T: Word>(ptr: *mut T, oldval: T, newval: T) -> T {
+    with_lock(ptr as usize, || {
+        let current = *ptr;
+        lock::preempt();
+        if current == oldval {
+            *ptr = newval;
+        }
+        current
+    })
 }
 
-// Global spinlock array
-static mut SPINLOCKS: [PaddedSpinlock; SPINLOCK_COUNT] = [PaddedSpinlock {
-    locked: 0,
-    _padding: [0; CACHE_LINE_SIZE - 1],
-}; SPINLOCK_COUNT];
+// Aligned byte, word and long accesses are single bus cycles on m68k, so
+// plain loads and stores are atomic once CAS handles the read-modify-writes.
+// A relaxed load needs no ordering either, so it skips the lock even on
+// 68000/68010.
+#[inline(always)]
+unsafe fn atomic_load<T: Word>(src: *const T, order: Ordering) -> T {
+    if T::LOCK_FREE || (T::SINGLE_MOVE && order == Ordering::Relaxed) {
+        let val = core::ptr::read_volatile(src);
+        fence_after(order);
+        val
+    } else {
+        with_lock(src as usize, || *src)
+    }
+}
 
-// Hash function to map addresses to spinlock indices
+// Stores keep the lock on 68000/68010 whatever the ordering: a plain store
+// landing inside a locked read-modify-write would be lost
 #[inline(always)]
-fn addr_to_lock_idx(addr: usize) -> usize {
-    // Simple hash: use middle bits of address
-    (addr >> 4) & SPINLOCK_MASK
-}
-
-// Acquire spinlock (busy wait)
-#[inline(never)]
-unsafe fn acquire_spinlock(lock: &mut PaddedSpinlock) {
-    // For single-core M68k, we can use interrupt masking
-    // For now, use a simple spinlock
-    while lock.locked != 0 {
-        // Busy wait - in real implementation would use pause/yield
-        core::hint::spin_loop();
+unsafe fn atomic_store<T: Word>(dst: *mut T, val: T, order: Ordering) {
+    if T::LOCK_FREE {
+        fence_before(order);
+        core::ptr::write_volatile(dst, val);
+        if order == Ordering::SeqCst {
+            fence(Ordering::SeqCst);
+        }
+    } else {
+        with_lock(dst as usize, || *dst = val)
     }
-    lock.locked = 1;
-    
-    // Memory barrier
-    core::sync::atomic::fence(Ordering::Acquire);
 }
 
-// Release spinlock
-#[inline(never)]
-unsafe fn release_spinlock(lock: &mut PaddedSpinlock) {
-    // Memory barrier
-    core::sync::atomic::fence(Ordering::Release);
-    
-    lock.locked = 0;
+#[inline(always)]
+unsafe fn atomic_cas<T: Word>(
+    ptr: *mut T,
+    oldval: T,
+    newval: T,
+    success: Ordering,
+    failure: Ordering,
+) -> T {
+    fence_before(success);
+    let current = T::cas(ptr, oldval, newval);
+    fence_after(if current == oldval { success } else { failure });
+    current
+}
+
+// Read-modify-write: store `op(old)` and return `old`
+#[inline(always)]
+unsafe fn atomic_rmw<T: Word>(ptr: *mut T, order: Ordering, op: impl Fn(T) -> T) -> T {
+    if T::LOCK_FREE {
+        fence_before(order);
+        let mut old = core::ptr::read_volatile(ptr);
+        loop {
+            let current = T::cas(ptr, old, op(old));
+            if current == old {
+                break;
+            }
+            old = current;
+        }
+        fence_after(order);
+        old
+    } else {
+        with_lock(ptr as usize, || {
+            let old = *ptr;
+            lock::preempt();
+            *ptr = op(old);
+            old
+        })
+    }
 }
 
 // Atomic load implementation
 #[no_mangle]
-pub unsafe extern "C" fn __atomic_load_1(src: *const u8, _ordering: i32) -> u8 {
-    let idx = addr_to_lock_idx(src as usize);
-    let lock = &mut SPINLOCKS[idx];
-    
-    acquire_spinlock(lock);
-    let val = *src;
-    release_spinlock(lock);
-    
-    val
+pub unsafe extern "C" fn __atomic_load_1(src: *const u8, ordering: i32) -> u8 {
+    atomic_load(src, checked_ordering(ordering, Access::Load))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn __atomic_load_2(src: *const u16, _ordering: i32) -> u16 {
-    let idx = addr_to_lock_idx(src as usize);
-    let lock = &mut SPINLOCKS[idx];
-    
-    acquire_spinlock(lock);
-    let val = *src;
-    release_spinlock(lock);
-    
-    val
+pub unsafe extern "C" fn __atomic_load_2(src: *const u16, ordering: i32) -> u16 {
+    atomic_load(src, checked_ordering(ordering, Access::Load))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn __atomic_load_4(src: *const u32, _ordering: i32) -> u32 {
-    let idx = addr_to_lock_idx(src as usize);
-    let lock = &mut SPINLOCKS[idx];
-    
-    acquire_spinlock(lock);
-    let val = *src;
-    release_spinlock(lock);
-    
-    val
+pub unsafe extern "C" fn __atomic_load_4(src: *const u32, ordering: i32) -> u32 {
+    atomic_load(src, checked_ordering(ordering, Access::Load))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_load_8(src: *const u64, ordering: i32) -> u64 {
+    atomic_load(src, checked_ordering(ordering, Access::Load))
 }
 
 // Atomic store implementation
 #[no_mangle]
-pub unsafe extern "C" fn __atomic_store_1(dst: *mut u8, val: u8, _ordering: i32) {
-    let idx = addr_to_lock_idx(dst as usize);
-    let lock = &mut SPINLOCKS[idx];
-    
-    acquire_spinlock(lock);
-    *dst = val;
-    release_spinlock(lock);
+pub unsafe extern "C" fn __atomic_store_1(dst: *mut u8, val: u8, ordering: i32) {
+    atomic_store(dst, val, checked_ordering(ordering, Access::Store))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn __atomic_store_2(dst: *mut u16, val: u16, _ordering: i32) {
-    let idx = addr_to_lock_idx(dst as usize);
-    let lock = &mut SPINLOCKS[idx];
-    
-    acquire_spinlock(lock);
-    *dst = val;
-    release_spinlock(lock);
+pub unsafe extern "C" fn __atomic_store_2(dst: *mut u16, val: u16, ordering: i32) {
+    atomic_store(dst, val, checked_ordering(ordering, Access::Store))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn __atomic_store_4(dst: *mut u32, val: u32, _ordering: i32) {
-    let idx = addr_to_lock_idx(dst as usize);
-    let lock = &mut SPINLOCKS[idx];
-    
-    acquire_spinlock(lock);
-    *dst = val;
-    release_spinlock(lock);
+pub unsafe extern "C" fn __atomic_store_4(dst: *mut u32, val: u32, ordering: i32) {
+    atomic_store(dst, val, checked_ordering(ordering, Access::Store))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_store_8(dst: *mut u64, val: u64, ordering: i32) {
+    atomic_store(dst, val, checked_ordering(ordering, Access::Store))
 }
 
 // Compare and swap implementation
@@ -135,17 +220,7 @@ pub unsafe extern "C" fn __sync_val_compare_and_swap_1(
     oldval: u8,
     newval: u8,
 ) -> u8 {
-    let idx = addr_to_lock_idx(ptr as usize);
-    let lock = &mut SPINLOCKS[idx];
-    
-    acquire_spinlock(lock);
-    let current = *ptr;
-    if current == oldval {
-        *ptr = newval;
-    }
-    release_spinlock(lock);
-    
-    current
+    atomic_cas(ptr, oldval, newval, Ordering::SeqCst, Ordering::SeqCst)
 }
 
 #[no_mangle]
@@ -154,17 +229,7 @@ pub unsafe extern "C" fn __sync_val_compare_and_swap_2(
     oldval: u16,
     newval: u16,
 ) -> u16 {
-    let idx = addr_to_lock_idx(ptr as usize);
-    let lock = &mut SPINLOCKS[idx];
-    
-    acquire_spinlock(lock);
-    let current = *ptr;
-    if current == oldval {
-        *ptr = newval;
-    }
-    release_spinlock(lock);
-    
-    current
+    atomic_cas(ptr, oldval, newval, Ordering::SeqCst, Ordering::SeqCst)
 }
 
 #[no_mangle]
@@ -173,17 +238,16 @@ pub unsafe extern "C" fn __sync_val_compare_and_swap_4(
     oldval: u32,
     newval: u32,
 ) -> u32 {
-    let idx = addr_to_lock_idx(ptr as usize);
-    let lock = &mut SPINLOCKS[idx];
-    
-    acquire_spinlock(lock);
-    let current = *ptr;
-    if current == oldval {
-        *ptr = newval;
-    }
-    release_spinlock(lock);
-    
-    current
+    atomic_cas(ptr, oldval, newval, Ordering::SeqCst, Ordering::SeqCst)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __sync_val_compare_and_swap_8(
+    ptr: *mut u64,
+    oldval: u64,
+    newval: u64,
+) -> u64 {
+    atomic_cas(ptr, oldval, newval, Ordering::SeqCst, Ordering::SeqCst)
 }
 
 // Atomic exchange (swap)
@@ -191,91 +255,36 @@ pub unsafe extern "C" fn __sync_val_compare_and_swap_4(
 pub unsafe extern "C" fn __atomic_exchange_1(
     ptr: *mut u8,
     val: u8,
-    _ordering: i32,
+    ordering: i32,
 ) -> u8 {
-    let idx = addr_to_lock_idx(ptr as usize);
-    let lock = &mut SPINLOCKS[idx];
-    
-    acquire_spinlock(lock);
-    let old = *ptr;
-    *ptr = val;
-    release_spinlock(lock);
-    
-    old
+    atomic_rmw(ptr, checked_ordering(ordering, Access::ReadModifyWrite), |_| val)
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn __atomic_exchange_2(
     ptr: *mut u16,
     val: u16,
-    _ordering: i32,
+    ordering: i32,
 ) -> u16 {
-    let idx = addr_to_lock_idx(ptr as usize);
-    let lock = &mut SPINLOCKS[idx];
-    
-    acquire_spinlock(lock);
-    let old = *ptr;
-    *ptr = val;
-    release_spinlock(lock);
-    
-    old
+    atomic_rmw(ptr, checked_ordering(ordering, Access::ReadModifyWrite), |_| val)
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn __atomic_exchange_4(
     ptr: *mut u32,
     val: u32,
-    _ordering: i32,
+    ordering: i32,
 ) -> u32 {
-    let idx = addr_to_lock_idx(ptr as usize);
-    let lock = &mut SPINLOCKS[idx];
-    
-    acquire_spinlock(lock);
-    let old = *ptr;
-    *ptr = val;
-    release_spinlock(lock);
-    
-    old
-}
-
-// Atomic fetch and add
-#[no_mangle]
-pub unsafe extern "C" fn __sync_fetch_and_add_1(ptr: *mut u8, val: u8) -> u8 {
-    let idx = addr_to_lock_idx(ptr as usize);
-    let lock = &mut SPINLOCKS[idx];
-    
-    acquire_spinlock(lock);
-    let old = *ptr;
-    *ptr = old.wrapping_add(val);
-    release_spinlock(lock);
-    
-    old
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn __sync_fetch_and_add_2(ptr: *mut u16, val: u16) -> u16 {
-    let idx = addr_to_lock_idx(ptr as usize);
-    let lock = &mut SPINLOCKS[idx];
-    
-    acquire_spinlock(lock);
-    let old = *ptr;
-    *ptr = old.wrapping_add(val);
-    release_spinlock(lock);
-    
-    old
+    atomic_rmw(ptr, checked_ordering(ordering, Access::ReadModifyWrite), |_| val)
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn __sync_fetch_and_add_4(ptr: *mut u32, val: u32) -> u32 {
-    let idx = addr_to_lock_idx(ptr as usize);
-    let lock = &mut SPINLOCKS[idx];
-    
-    acquire_spinlock(lock);
-    let old = *ptr;
-    *ptr = old.wrapping_add(val);
-    release_spinlock(lock);
-    
-    old
+pub unsafe extern "C" fn __atomic_exchange_8(
+    ptr: *mut u64,
+    val: u64,
+    ordering: i32,
+) -> u64 {
+    atomic_rmw(ptr, checked_ordering(ordering, Access::ReadModifyWrite), |_| val)
 }
 
 // Boolean compare and swap
@@ -306,10 +315,312 @@ pub unsafe extern "C" fn __sync_bool_compare_and_swap_4(
     __sync_val_compare_and_swap_4(ptr, oldval, newval) == oldval
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn __sync_bool_compare_and_swap_8(
+    ptr: *mut u64,
+    oldval: u64,
+    newval: u64,
+) -> bool {
+    __sync_val_compare_and_swap_8(ptr, oldval, newval) == oldval
+}
+
+// C11-style compare and exchange: on failure the current value is
+// written back to `*expected`
+macro_rules! compare_exchange_libcalls {
+    ($($name:ident: $t:ty),*) => {$(
+        #[no_mangle]
+        pub unsafe extern "C" fn $name(
+            ptr: *mut $t,
+            expected: *mut $t,
+            desired: $t,
+            success: i32,
+            failure: i32,
+        ) -> bool {
+            let success = checked_ordering(success, Access::ReadModifyWrite);
+            let failure = checked_ordering(failure, Access::CompareFailure);
+            let oldval = *expected;
+            let current = atomic_cas(ptr, oldval, desired, success, failure);
+            if current != oldval {
+                *expected = current;
+            }
+            current == oldval
+        }
+    )*};
+}
+
+compare_exchange_libcalls!(
+    __atomic_compare_exchange_1: u8,
+    __atomic_compare_exchange_2: u16,
+    __atomic_compare_exchange_4: u32,
+    __atomic_compare_exchange_8: u64
+);
+
+// Read-modify-write operations in all four flavours: `__atomic_fetch_<op>`
+// and `__sync_fetch_and_<op>` return the old value, `__atomic_<op>_fetch`
+// and `__sync_<op>_and_fetch` the new one
+macro_rules! rmw_libcalls {
+    (|$old:ident, $val:ident| $new:expr; $(
+        $t:ty => $atomic_fetch_op:ident, $atomic_op_fetch:ident,
+                 $sync_fetch_op:ident, $sync_op_fetch:ident;
+    )*) => {$(
+        #[no_mangle]
+        pub unsafe extern "C" fn $atomic_fetch_op(ptr: *mut $t, $val: $t, ordering: i32) -> $t {
+            atomic_rmw(ptr, checked_ordering(ordering, Access::ReadModifyWrite), |$old| $new)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $atomic_op_fetch(ptr: *mut $t, $val: $t, ordering: i32) -> $t {
+            let $old = atomic_rmw(ptr, checked_ordering(ordering, Access::ReadModifyWrite), |$old| $new);
+            $new
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $sync_fetch_op(ptr: *mut $t, $val: $t) -> $t {
+            atomic_rmw(ptr, Ordering::SeqCst, |$old| $new)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $sync_op_fetch(ptr: *mut $t, $val: $t) -> $t {
+            let $old = atomic_rmw(ptr, Ordering::SeqCst, |$old| $new);
+            $new
+        }
+    )*};
+}
+
+// Atomic fetch and add
+rmw_libcalls! { |old, val| old.wrapping_add(val);
+    u8 => __atomic_fetch_add_1, __atomic_add_fetch_1, __sync_fetch_and_add_1, __sync_add_and_fetch_1;
+    u16 => __atomic_fetch_add_2, __atomic_add_fetch_2, __sync_fetch_and_add_2, __sync_add_and_fetch_2;
+    u32 => __atomic_fetch_add_4, __atomic_add_fetch_4, __sync_fetch_and_add_4, __sync_add_and_fetch_4;
+    u64 => __atomic_fetch_add_8, __atomic_add_fetch_8, __sync_fetch_and_add_8, __sync_add_and_fetch_8;
+}
+
+// Atomic fetch and subtract
+rmw_libcalls! { |old, val| old.wrapping_sub(val);
+    u8 => __atomic_fetch_sub_1, __atomic_sub_fetch_1, __sync_fetch_and_sub_1, __sync_sub_and_fetch_1;
+    u16 => __atomic_fetch_sub_2, __atomic_sub_fetch_2, __sync_fetch_and_sub_2, __sync_sub_and_fetch_2;
+    u32 => __atomic_fetch_sub_4, __atomic_sub_fetch_4, __sync_fetch_and_sub_4, __sync_sub_and_fetch_4;
+    u64 => __atomic_fetch_sub_8, __atomic_sub_fetch_8, __sync_fetch_and_sub_8, __sync_sub_and_fetch_8;
+}
+
+// Atomic bitwise and
+rmw_libcalls! { |old, val| old & val;
+    u8 => __atomic_fetch_and_1, __atomic_and_fetch_1, __sync_fetch_and_and_1, __sync_and_and_fetch_1;
+    u16 => __atomic_fetch_and_2, __atomic_and_fetch_2, __sync_fetch_and_and_2, __sync_and_and_fetch_2;
+    u32 => __atomic_fetch_and_4, __atomic_and_fetch_4, __sync_fetch_and_and_4, __sync_and_and_fetch_4;
+    u64 => __atomic_fetch_and_8, __atomic_and_fetch_8, __sync_fetch_and_and_8, __sync_and_and_fetch_8;
+}
+
+// Atomic bitwise or
+rmw_libcalls! { |old, val| old | val;
+    u8 => __atomic_fetch_or_1, __atomic_or_fetch_1, __sync_fetch_and_or_1, __sync_or_and_fetch_1;
+    u16 => __atomic_fetch_or_2, __atomic_or_fetch_2, __sync_fetch_and_or_2, __sync_or_and_fetch_2;
+    u32 => __atomic_fetch_or_4, __atomic_or_fetch_4, __sync_fetch_and_or_4, __sync_or_and_fetch_4;
+    u64 => __atomic_fetch_or_8, __atomic_or_fetch_8, __sync_fetch_and_or_8, __sync_or_and_fetch_8;
+}
+
+// Atomic bitwise xor
+rmw_libcalls! { |old, val| old ^ val;
+    u8 => __atomic_fetch_xor_1, __atomic_xor_fetch_1, __sync_fetch_and_xor_1, __sync_xor_and_fetch_1;
+    u16 => __atomic_fetch_xor_2, __atomic_xor_fetch_2, __sync_fetch_and_xor_2, __sync_xor_and_fetch_2;
+    u32 => __atomic_fetch_xor_4, __atomic_xor_fetch_4, __sync_fetch_and_xor_4, __sync_xor_and_fetch_4;
+    u64 => __atomic_fetch_xor_8, __atomic_xor_fetch_8, __sync_fetch_and_xor_8, __sync_xor_and_fetch_8;
+}
+
+// Atomic bitwise nand
+rmw_libcalls! { |old, val| !(old & val);
+    u8 => __atomic_fetch_nand_1, __atomic_nand_fetch_1, __sync_fetch_and_nand_1, __sync_nand_and_fetch_1;
+    u16 => __atomic_fetch_nand_2, __atomic_nand_fetch_2, __sync_fetch_and_nand_2, __sync_nand_and_fetch_2;
+    u32 => __atomic_fetch_nand_4, __atomic_nand_fetch_4, __sync_fetch_and_nand_4, __sync_nand_and_fetch_4;
+    u64 => __atomic_fetch_nand_8, __atomic_nand_fetch_8, __sync_fetch_and_nand_8, __sync_nand_and_fetch_8;
+}
+
+// Min/max only come as fetch-then-op. The signed variants compare the
+// bit pattern as two's complement (`max`/`min`), the unsigned ones as is
+// (`umax`/`umin`), matching LLVM's atomicrmw libcall names.
+macro_rules! minmax_libcalls {
+    ($($t:ty, $s:ty => $atomic_max:ident, $sync_max:ident, $atomic_min:ident, $sync_min:ident,
+                       $atomic_umax:ident, $sync_umax:ident, $atomic_umin:ident, $sync_umin:ident;)*) => {$(
+        #[no_mangle]
+        pub unsafe extern "C" fn $atomic_max(ptr: *mut $t, val: $t, ordering: i32) -> $t {
+            atomic_rmw(ptr, checked_ordering(ordering, Access::ReadModifyWrite), |old| core::cmp::max(old as $s, val as $s) as $t)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $sync_max(ptr: *mut $t, val: $t) -> $t {
+            atomic_rmw(ptr, Ordering::SeqCst, |old| core::cmp::max(old as $s, val as $s) as $t)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $atomic_min(ptr: *mut $t, val: $t, ordering: i32) -> $t {
+            atomic_rmw(ptr, checked_ordering(ordering, Access::ReadModifyWrite), |old| core::cmp::min(old as $s, val as $s) as $t)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $sync_min(ptr: *mut $t, val: $t) -> $t {
+            atomic_rmw(ptr, Ordering::SeqCst, |old| core::cmp::min(old as $s, val as $s) as $t)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $atomic_umax(ptr: *mut $t, val: $t, ordering: i32) -> $t {
+            atomic_rmw(ptr, checked_ordering(ordering, Access::ReadModifyWrite), |old| core::cmp::max(old, val))
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $sync_umax(ptr: *mut $t, val: $t) -> $t {
+            atomic_rmw(ptr, Ordering::SeqCst, |old| core::cmp::max(old, val))
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $atomic_umin(ptr: *mut $t, val: $t, ordering: i32) -> $t {
+            atomic_rmw(ptr, checked_ordering(ordering, Access::ReadModifyWrite), |old| core::cmp::min(old, val))
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $sync_umin(ptr: *mut $t, val: $t) -> $t {
+            atomic_rmw(ptr, Ordering::SeqCst, |old| core::cmp::min(old, val))
+        }
+    )*};
+}
+
+minmax_libcalls! {
+    u8, i8 => __atomic_fetch_max_1, __sync_fetch_and_max_1, __atomic_fetch_min_1, __sync_fetch_and_min_1,
+              __atomic_fetch_umax_1, __sync_fetch_and_umax_1, __atomic_fetch_umin_1, __sync_fetch_and_umin_1;
+    u16, i16 => __atomic_fetch_max_2, __sync_fetch_and_max_2, __atomic_fetch_min_2, __sync_fetch_and_min_2,
+                __atomic_fetch_umax_2, __sync_fetch_and_umax_2, __atomic_fetch_umin_2, __sync_fetch_and_umin_2;
+    u32, i32 => __atomic_fetch_max_4, __sync_fetch_and_max_4, __atomic_fetch_min_4, __sync_fetch_and_min_4,
+                __atomic_fetch_umax_4, __sync_fetch_and_umax_4, __atomic_fetch_umin_4, __sync_fetch_and_umin_4;
+    u64, i64 => __atomic_fetch_max_8, __sync_fetch_and_max_8, __atomic_fetch_min_8, __sync_fetch_and_min_8,
+                __atomic_fetch_umax_8, __sync_fetch_and_umax_8, __atomic_fetch_umin_8, __sync_fetch_and_umin_8;
+}
+
+// __sync lock acquire/release: test-and-set stores `val` and returns the
+// old value with acquire ordering, release stores zero with release ordering
+macro_rules! sync_lock_libcalls {
+    ($($t:ty => $test_and_set:ident, $release:ident;)*) => {$(
+        #[no_mangle]
+        pub unsafe extern "C" fn $test_and_set(ptr: *mut $t, val: $t) -> $t {
+            atomic_rmw(ptr, Ordering::Acquire, |_| val)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $release(ptr: *mut $t) {
+            atomic_store(ptr, 0, Ordering::Release)
+        }
+    )*};
+}
+
+sync_lock_libcalls! {
+    u8 => __sync_lock_test_and_set_1, __sync_lock_release_1;
+    u16 => __sync_lock_test_and_set_2, __sync_lock_release_2;
+    u32 => __sync_lock_test_and_set_4, __sync_lock_release_4;
+    u64 => __sync_lock_test_and_set_8, __sync_lock_release_8;
+}
+
+// Generic libcalls for objects of any size. Sizes with a sized libcall go
+// through it when the object is naturally aligned, so both entry points
+// agree on the locking used for an object; everything else copies bytes
+// under the lock covering the object's address.
+macro_rules! by_size {
+    ($size:expr, $ptr:expr, $T:ident => $sized:expr, _ => $bytes:expr) => {{
+        let aligned = |align: usize| $ptr as usize % align == 0;
+        match $size {
+            1 => {
+                type $T = u8;
+                $sized
+            }
+            2 if aligned(2) => {
+                type $T = u16;
+                $sized
+            }
+            4 if aligned(4) => {
+                type $T = u32;
+                $sized
+            }
+            8 if aligned(8) => {
+                type $T = u64;
+                $sized
+            }
+            _ => $bytes,
+        }
+    }};
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_load(size: usize, src: *const u8, dst: *mut u8, ordering: i32) {
+    let order = checked_ordering(ordering, Access::Load);
+    by_size!(size, src, T => (dst as *mut T).write_unaligned(atomic_load(src as *const T, order)),
+        _ => with_lock(src as usize, || core::ptr::copy_nonoverlapping(src, dst, size)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_store(size: usize, dst: *mut u8, src: *const u8, ordering: i32) {
+    let order = checked_ordering(ordering, Access::Store);
+    by_size!(size, dst, T => atomic_store(dst as *mut T, (src as *const T).read_unaligned(), order),
+        _ => with_lock(dst as usize, || core::ptr::copy_nonoverlapping(src, dst, size)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_exchange(
+    size: usize,
+    ptr: *mut u8,
+    val: *const u8,
+    ret: *mut u8,
+    ordering: i32,
+) {
+    let order = checked_ordering(ordering, Access::ReadModifyWrite);
+    by_size!(size, ptr, T => {
+        let new = (val as *const T).read_unaligned();
+        (ret as *mut T).write_unaligned(atomic_rmw(ptr as *mut T, order, |_| new))
+    }, _ => with_lock(ptr as usize, || {
+        core::ptr::copy_nonoverlapping(ptr, ret, size);
+        core::ptr::copy_nonoverlapping(val, ptr, size);
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_compare_exchange(
+    size: usize,
+    ptr: *mut u8,
+    expected: *mut u8,
+    desired: *const u8,
+    success: i32,
+    failure: i32,
+) -> bool {
+    let success = checked_ordering(success, Access::ReadModifyWrite);
+    let failure = checked_ordering(failure, Access::CompareFailure);
+    by_size!(size, ptr, T => {
+        let oldval = (expected as *const T).read_unaligned();
+        let newval = (desired as *const T).read_unaligned();
+        let current = atomic_cas(ptr as *mut T, oldval, newval, success, failure);
+        if current != oldval {
+            (expected as *mut T).write_unaligned(current);
+        }
+        current == oldval
+    }, _ => with_lock(ptr as usize, || {
+        let current = core::slice::from_raw_parts(ptr, size);
+        if current == core::slice::from_raw_parts(expected, size) {
+            core::ptr::copy_nonoverlapping(desired, ptr, size);
+            true
+        } else {
+            core::ptr::copy_nonoverlapping(ptr, expected, size);
+            false
+        }
+    }))
+}
+
+/// Whether objects of `size` bytes at `ptr` are handled without locks
+///
+/// A null `ptr` asks about a naturally aligned object.
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_is_lock_free(size: usize, ptr: *const u8) -> bool {
+    by_size!(size, ptr, T => T::LOCK_FREE, _ => false)
+}
+
 // Memory barrier
 #[no_mangle]
 pub unsafe extern "C" fn __sync_synchronize() {
-    core::sync::atomic::fence(Ordering::SeqCst);
+    fence(Ordering::SeqCst);
 }
 
 #[cfg(test)]
@@ -349,4 +660,325 @@ mod tests {
             assert_eq!(counter, 3);
         }
     }
-}
\ No newline at end of file
+
+    const SEQ_CST: i32 = ATOMIC_SEQ_CST;
+
+    #[test]
+    fn test_relaxed_load_skips_lock() {
+        let val: u32 = 7;
+        let lock = lock::LOCKS.lock_for(&val as *const u32 as usize);
+        lock.lock();
+        // Spins forever if the relaxed load takes the lock
+        let loaded = unsafe { __atomic_load_4(&val, ATOMIC_RELAXED) };
+        lock.unlock();
+        assert_eq!(loaded, 7);
+    }
+
+    #[test]
+    fn test_orderings_accepted() {
+        unsafe {
+            let mut val: u32 = 0;
+            __atomic_store_4(&mut val, 1, ATOMIC_RELAXED);
+            __atomic_store_4(&mut val, 2, ATOMIC_RELEASE);
+            assert_eq!(__atomic_load_4(&val, ATOMIC_CONSUME), 2);
+            assert_eq!(__atomic_load_4(&val, ATOMIC_ACQUIRE), 2);
+            assert_eq!(__atomic_fetch_add_4(&mut val, 1, ATOMIC_ACQ_REL), 2);
+            let mut expected = 0;
+            assert!(!__atomic_compare_exchange_4(&mut val, &mut expected, 9, ATOMIC_RELEASE, ATOMIC_RELAXED));
+            assert_eq!(expected, 3);
+        }
+    }
+
+    #[test]
+    fn test_load_store_all_sizes() {
+        unsafe {
+            let (mut a, mut b, mut c, mut d) = (0u8, 0u16, 0u32, 0u64);
+            __atomic_store_1(&mut a, 0xa5, SEQ_CST);
+            __atomic_store_2(&mut b, 0xa5a5, SEQ_CST);
+            __atomic_store_4(&mut c, 0xa5a5_a5a5, SEQ_CST);
+            __atomic_store_8(&mut d, 0xa5a5_a5a5_5a5a_5a5a, SEQ_CST);
+            assert_eq!(__atomic_load_1(&a, SEQ_CST), 0xa5);
+            assert_eq!(__atomic_load_2(&b, SEQ_CST), 0xa5a5);
+            assert_eq!(__atomic_load_4(&c, SEQ_CST), 0xa5a5_a5a5);
+            assert_eq!(__atomic_load_8(&d, SEQ_CST), 0xa5a5_a5a5_5a5a_5a5a);
+        }
+    }
+
+    #[test]
+    fn test_exchange_all_sizes() {
+        unsafe {
+            let (mut a, mut b, mut c, mut d) = (1u8, 2u16, 3u32, 4u64);
+            assert_eq!(__atomic_exchange_1(&mut a, 10, SEQ_CST), 1);
+            assert_eq!(__atomic_exchange_2(&mut b, 20, SEQ_CST), 2);
+            assert_eq!(__atomic_exchange_4(&mut c, 30, SEQ_CST), 3);
+            assert_eq!(__atomic_exchange_8(&mut d, 1 << 40, SEQ_CST), 4);
+            assert_eq!((a, b, c, d), (10, 20, 30, 1 << 40));
+        }
+    }
+
+    #[test]
+    fn test_sync_compare_and_swap_all_sizes() {
+        unsafe {
+            let (mut a, mut b, mut c, mut d) = (1u8, 2u16, 3u32, 4u64);
+            assert_eq!(__sync_val_compare_and_swap_1(&mut a, 1, 11), 1);
+            assert_eq!(__sync_val_compare_and_swap_2(&mut b, 0, 22), 2);
+            assert_eq!(__sync_val_compare_and_swap_4(&mut c, 3, 33), 3);
+            assert_eq!(__sync_val_compare_and_swap_8(&mut d, 0, 44), 4);
+            assert_eq!((a, b, c, d), (11, 2, 33, 4));
+
+            assert!(__sync_bool_compare_and_swap_1(&mut a, 11, 1));
+            assert!(!__sync_bool_compare_and_swap_2(&mut b, 0, 1));
+            assert!(__sync_bool_compare_and_swap_4(&mut c, 33, 3));
+            assert!(__sync_bool_compare_and_swap_8(&mut d, 4, u64::MAX));
+            assert_eq!((a, b, c, d), (1, 2, 3, u64::MAX));
+        }
+    }
+
+    #[test]
+    fn test_compare_exchange_all_sizes() {
+        macro_rules! check {
+            ($f:ident, $t:ty) => {{
+                let mut val: $t = 10;
+                let mut expected: $t = 10;
+                assert!($f(&mut val, &mut expected, 20, SEQ_CST, SEQ_CST));
+                assert_eq!(val, 20);
+
+                // Failure reports the current value through `expected`
+                assert!(!$f(&mut val, &mut expected, 30, SEQ_CST, SEQ_CST));
+                assert_eq!((val, expected), (20, 20));
+            }};
+        }
+        unsafe {
+            check!(__atomic_compare_exchange_1, u8);
+            check!(__atomic_compare_exchange_2, u16);
+            check!(__atomic_compare_exchange_4, u32);
+            check!(__atomic_compare_exchange_8, u64);
+        }
+    }
+
+    // Check all four flavours of one read-modify-write libcall
+    macro_rules! check_rmw {
+        ($t:ty, $old:expr, $val:expr, $new:expr =>
+         $atomic_fetch_op:ident, $atomic_op_fetch:ident, $sync_fetch_op:ident, $sync_op_fetch:ident) => {{
+            let (old, val, new): ($t, $t, $t) = ($old, $val, $new);
+            let mut x = old;
+            assert_eq!($atomic_fetch_op(&mut x, val, SEQ_CST), old);
+            assert_eq!(x, new);
+            x = old;
+            assert_eq!($atomic_op_fetch(&mut x, val, SEQ_CST), new);
+            assert_eq!(x, new);
+            x = old;
+            assert_eq!($sync_fetch_op(&mut x, val), old);
+            assert_eq!(x, new);
+            x = old;
+            assert_eq!($sync_op_fetch(&mut x, val), new);
+            assert_eq!(x, new);
+        }};
+    }
+
+    #[test]
+    fn test_fetch_add_sub_all_sizes() {
+        unsafe {
+            check_rmw!(u8, 0xff, 2, 1 => __atomic_fetch_add_1, __atomic_add_fetch_1, __sync_fetch_and_add_1, __sync_add_and_fetch_1);
+            check_rmw!(u16, 100, 23, 123 => __atomic_fetch_add_2, __atomic_add_fetch_2, __sync_fetch_and_add_2, __sync_add_and_fetch_2);
+            check_rmw!(u32, 100, 23, 123 => __atomic_fetch_add_4, __atomic_add_fetch_4, __sync_fetch_and_add_4, __sync_add_and_fetch_4);
+            check_rmw!(u64, u32::MAX as u64, 1, 1 << 32 => __atomic_fetch_add_8, __atomic_add_fetch_8, __sync_fetch_and_add_8, __sync_add_and_fetch_8);
+
+            check_rmw!(u8, 0, 1, 0xff => __atomic_fetch_sub_1, __atomic_sub_fetch_1, __sync_fetch_and_sub_1, __sync_sub_and_fetch_1);
+            check_rmw!(u16, 200, 50, 150 => __atomic_fetch_sub_2, __atomic_sub_fetch_2, __sync_fetch_and_sub_2, __sync_sub_and_fetch_2);
+            check_rmw!(u32, 200, 50, 150 => __atomic_fetch_sub_4, __atomic_sub_fetch_4, __sync_fetch_and_sub_4, __sync_sub_and_fetch_4);
+            check_rmw!(u64, 1 << 32, 1, u32::MAX as u64 => __atomic_fetch_sub_8, __atomic_sub_fetch_8, __sync_fetch_and_sub_8, __sync_sub_and_fetch_8);
+        }
+    }
+
+    #[test]
+    fn test_fetch_bitwise_all_sizes() {
+        unsafe {
+            check_rmw!(u8, 0b1100, 0b1010, 0b1000 => __atomic_fetch_and_1, __atomic_and_fetch_1, __sync_fetch_and_and_1, __sync_and_and_fetch_1);
+            check_rmw!(u16, 0b1100, 0b1010, 0b1000 => __atomic_fetch_and_2, __atomic_and_fetch_2, __sync_fetch_and_and_2, __sync_and_and_fetch_2);
+            check_rmw!(u32, 0b1100, 0b1010, 0b1000 => __atomic_fetch_and_4, __atomic_and_fetch_4, __sync_fetch_and_and_4, __sync_and_and_fetch_4);
+            check_rmw!(u64, 0b1100, 0b1010, 0b1000 => __atomic_fetch_and_8, __atomic_and_fetch_8, __sync_fetch_and_and_8, __sync_and_and_fetch_8);
+
+            check_rmw!(u8, 0b1100, 0b1010, 0b1110 => __atomic_fetch_or_1, __atomic_or_fetch_1, __sync_fetch_and_or_1, __sync_or_and_fetch_1);
+            check_rmw!(u16, 0b1100, 0b1010, 0b1110 => __atomic_fetch_or_2, __atomic_or_fetch_2, __sync_fetch_and_or_2, __sync_or_and_fetch_2);
+            check_rmw!(u32, 0b1100, 0b1010, 0b1110 => __atomic_fetch_or_4, __atomic_or_fetch_4, __sync_fetch_and_or_4, __sync_or_and_fetch_4);
+            check_rmw!(u64, 0b1100, 0b1010, 0b1110 => __atomic_fetch_or_8, __atomic_or_fetch_8, __sync_fetch_and_or_8, __sync_or_and_fetch_8);
+
+            check_rmw!(u8, 0b1100, 0b1010, 0b0110 => __atomic_fetch_xor_1, __atomic_xor_fetch_1, __sync_fetch_and_xor_1, __sync_xor_and_fetch_1);
+            check_rmw!(u16, 0b1100, 0b1010, 0b0110 => __atomic_fetch_xor_2, __atomic_xor_fetch_2, __sync_fetch_and_xor_2, __sync_xor_and_fetch_2);
+            check_rmw!(u32, 0b1100, 0b1010, 0b0110 => __atomic_fetch_xor_4, __atomic_xor_fetch_4, __sync_fetch_and_xor_4, __sync_xor_and_fetch_4);
+            check_rmw!(u64, 0b1100, 0b1010, 0b0110 => __atomic_fetch_xor_8, __atomic_xor_fetch_8, __sync_fetch_and_xor_8, __sync_xor_and_fetch_8);
+
+            check_rmw!(u8, 0b1100, 0b1010, !0b1000 => __atomic_fetch_nand_1, __atomic_nand_fetch_1, __sync_fetch_and_nand_1, __sync_nand_and_fetch_1);
+            check_rmw!(u16, 0b1100, 0b1010, !0b1000 => __atomic_fetch_nand_2, __atomic_nand_fetch_2, __sync_fetch_and_nand_2, __sync_nand_and_fetch_2);
+            check_rmw!(u32, 0b1100, 0b1010, !0b1000 => __atomic_fetch_nand_4, __atomic_nand_fetch_4, __sync_fetch_and_nand_4, __sync_nand_and_fetch_4);
+            check_rmw!(u64, 0b1100, 0b1010, !0b1000 => __atomic_fetch_nand_8, __atomic_nand_fetch_8, __sync_fetch_and_nand_8, __sync_nand_and_fetch_8);
+        }
+    }
+
+    #[test]
+    fn test_fetch_min_max_all_sizes() {
+        // `old` is negative when signed, so signed and unsigned results differ
+        macro_rules! check {
+            ($t:ty, $atomic_max:ident, $sync_max:ident, $atomic_min:ident, $sync_min:ident,
+             $atomic_umax:ident, $sync_umax:ident, $atomic_umin:ident, $sync_umin:ident) => {{
+                let (old, val): ($t, $t) = (<$t>::MAX, 1);
+                let mut x = old;
+                assert_eq!($atomic_max(&mut x, val, SEQ_CST), old);
+                assert_eq!(x, val);
+                x = old;
+                assert_eq!($sync_max(&mut x, val), old);
+                assert_eq!(x, val);
+                x = val;
+                assert_eq!($atomic_min(&mut x, old, SEQ_CST), val);
+                assert_eq!(x, old);
+                x = val;
+                assert_eq!($sync_min(&mut x, old), val);
+                assert_eq!(x, old);
+                x = val;
+                assert_eq!($atomic_umax(&mut x, old, SEQ_CST), val);
+                assert_eq!(x, old);
+                x = val;
+                assert_eq!($sync_umax(&mut x, old), val);
+                assert_eq!(x, old);
+                x = old;
+                assert_eq!($atomic_umin(&mut x, val, SEQ_CST), old);
+                assert_eq!(x, val);
+                x = old;
+                assert_eq!($sync_umin(&mut x, val), old);
+                assert_eq!(x, val);
+            }};
+        }
+        unsafe {
+            check!(u8, __atomic_fetch_max_1, __sync_fetch_and_max_1, __atomic_fetch_min_1, __sync_fetch_and_min_1,
+                   __atomic_fetch_umax_1, __sync_fetch_and_umax_1, __atomic_fetch_umin_1, __sync_fetch_and_umin_1);
+            check!(u16, __atomic_fetch_max_2, __sync_fetch_and_max_2, __atomic_fetch_min_2, __sync_fetch_and_min_2,
+                   __atomic_fetch_umax_2, __sync_fetch_and_umax_2, __atomic_fetch_umin_2, __sync_fetch_and_umin_2);
+            check!(u32, __atomic_fetch_max_4, __sync_fetch_and_max_4, __atomic_fetch_min_4, __sync_fetch_and_min_4,
+                   __atomic_fetch_umax_4, __sync_fetch_and_umax_4, __atomic_fetch_umin_4, __sync_fetch_and_umin_4);
+            check!(u64, __atomic_fetch_max_8, __sync_fetch_and_max_8, __atomic_fetch_min_8, __sync_fetch_and_min_8,
+                   __atomic_fetch_umax_8, __sync_fetch_and_umax_8, __atomic_fetch_umin_8, __sync_fetch_and_umin_8);
+        }
+    }
+
+    #[test]
+    fn test_sync_lock_all_sizes() {
+        unsafe {
+            let (mut a, mut b, mut c, mut d) = (0u8, 0u16, 0u32, 0u64);
+            assert_eq!(__sync_lock_test_and_set_1(&mut a, 1), 0);
+            assert_eq!(__sync_lock_test_and_set_2(&mut b, 1), 0);
+            assert_eq!(__sync_lock_test_and_set_4(&mut c, 1), 0);
+            assert_eq!(__sync_lock_test_and_set_8(&mut d, 1), 0);
+            assert_eq!(__sync_lock_test_and_set_4(&mut c, 1), 1);
+            __sync_lock_release_1(&mut a);
+            __sync_lock_release_2(&mut b);
+            __sync_lock_release_4(&mut c);
+            __sync_lock_release_8(&mut d);
+            assert_eq!((a, b, c, d), (0, 0, 0, 0));
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Triple {
+        a: u32,
+        b: u32,
+        c: u32,
+    }
+
+    fn bytes_of<T>(val: &T) -> *const u8 {
+        val as *const T as *const u8
+    }
+
+    fn bytes_of_mut<T>(val: &mut T) -> *mut u8 {
+        val as *mut T as *mut u8
+    }
+
+    const SIZE: usize = core::mem::size_of::<Triple>();
+
+    #[test]
+    fn test_generic_load_store() {
+        unsafe {
+            let mut obj = Triple { a: 0, b: 0, c: 0 };
+            let src = Triple { a: 1, b: 2, c: 3 };
+            __atomic_store(SIZE, bytes_of_mut(&mut obj), bytes_of(&src), SEQ_CST);
+            assert_eq!(obj, src);
+
+            let mut dst = Triple { a: 0, b: 0, c: 0 };
+            __atomic_load(SIZE, bytes_of(&obj), bytes_of_mut(&mut dst), SEQ_CST);
+            assert_eq!(dst, src);
+
+            // Sized objects take the sized path
+            let mut word = 0u32;
+            __atomic_store(4, bytes_of_mut(&mut word), bytes_of(&7u32), SEQ_CST);
+            let mut out = 0u32;
+            __atomic_load(4, bytes_of(&word), bytes_of_mut(&mut out), SEQ_CST);
+            assert_eq!((word, out), (7, 7));
+        }
+    }
+
+    #[test]
+    fn test_generic_exchange() {
+        unsafe {
+            let mut obj = Triple { a: 1, b: 2, c: 3 };
+            let new = Triple { a: 4, b: 5, c: 6 };
+            let mut old = Triple { a: 0, b: 0, c: 0 };
+            __atomic_exchange(SIZE, bytes_of_mut(&mut obj), bytes_of(&new), bytes_of_mut(&mut old), SEQ_CST);
+            assert_eq!(obj, new);
+            assert_eq!(old, Triple { a: 1, b: 2, c: 3 });
+
+            let mut long = 1u64;
+            let mut prev = 0u64;
+            __atomic_exchange(8, bytes_of_mut(&mut long), bytes_of(&2u64), bytes_of_mut(&mut prev), SEQ_CST);
+            assert_eq!((long, prev), (2, 1));
+        }
+    }
+
+    #[test]
+    fn test_generic_compare_exchange() {
+        unsafe {
+            let mut obj = Triple { a: 1, b: 2, c: 3 };
+            let mut expected = obj;
+            let desired = Triple { a: 7, b: 8, c: 9 };
+            assert!(__atomic_compare_exchange(
+                SIZE,
+                bytes_of_mut(&mut obj),
+                bytes_of_mut(&mut expected),
+                bytes_of(&desired),
+                SEQ_CST,
+                SEQ_CST,
+            ));
+            assert_eq!(obj, desired);
+
+            // Mismatch copies the current value back into `expected`
+            assert!(!__atomic_compare_exchange(
+                SIZE,
+                bytes_of_mut(&mut obj),
+                bytes_of_mut(&mut expected),
+                bytes_of(&Triple { a: 0, b: 0, c: 0 }),
+                SEQ_CST,
+                SEQ_CST,
+            ));
+            assert_eq!(obj, desired);
+            assert_eq!(expected, desired);
+
+            let mut half = 5u16;
+            let mut want = 6u16;
+            assert!(!__atomic_compare_exchange(2, bytes_of_mut(&mut half), bytes_of_mut(&mut want), bytes_of(&9u16), SEQ_CST, SEQ_CST));
+            assert_eq!((half, want), (5, 5));
+        }
+    }
+
+    #[test]
+    fn test_is_lock_free() {
+        unsafe {
+            let native = cfg!(native_cas);
+            assert_eq!(__atomic_is_lock_free(1, core::ptr::null()), native);
+            assert_eq!(__atomic_is_lock_free(2, core::ptr::null()), native);
+            assert_eq!(__atomic_is_lock_free(4, core::ptr::null()), native);
+            assert!(!__atomic_is_lock_free(4, 2 as *const u8));
+            assert!(!__atomic_is_lock_free(8, core::ptr::null()));
+            assert!(!__atomic_is_lock_free(SIZE, core::ptr::null()));
+        }
+    }
+}
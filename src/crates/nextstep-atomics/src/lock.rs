@@ -0,0 +1,287 @@
+//! Spinlock table for 8 byte and generic atomics, and for 68000/68010
+//!
+//! Each lock is a byte taken with `TAS`, which every 680x0 implements as an
+//! indivisible read-modify-write bus cycle: the old "check then store"
+//! sequence could be interrupted between the two accesses and let two
+//! holders in. Addresses hash onto a fixed table of locks, each padded to
+//! its own cache line. Locks are only held inside a critical section, so a
+//! signal or interrupt handler can never spin on a lock its own thread holds.
+//!
+//! The table size comes from `NEXTSTEP_ATOMICS_LOCK_COUNT` at build time
+//! (default 64). The default hash maps each 16-byte block to one lock, so
+//! neighbouring fields share it; the `mixing-hash` feature spreads
+//! neighbouring words over different locks instead.
+
+use crate::critical_section;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{fence, Ordering};
+
+#[cfg(test)]
+extern crate std;
+
+// Scheduling points for the interleaving tests in `model`; no-ops otherwise
+#[cfg(test)]
+use crate::model as hooks;
+#[cfg(test)]
+pub(crate) use crate::model::preempt;
+
+#[cfg(not(test))]
+mod hooks {
+    #[inline(always)]
+    pub(crate) fn preempt() {}
+
+    #[inline(always)]
+    pub(crate) fn spin_wait(_lock: *const u8) {}
+
+    #[inline(always)]
+    pub(crate) fn acquired(_lock: *const u8) {}
+
+    #[inline(always)]
+    pub(crate) fn released(_lock: *const u8) {}
+}
+#[cfg(not(test))]
+pub(crate) use hooks::preempt;
+
+// Number of spinlocks (SPINLOCK_COUNT, a power of 2 = 1 << SPINLOCK_BITS)
+include!(concat!(env!("OUT_DIR"), "/lock_config.rs"));
+const SPINLOCK_MASK: usize = SPINLOCK_COUNT - 1;
+
+// Cache line size for padding (M68k typically 16 bytes)
+const CACHE_LINE_SIZE: usize = 16;
+
+// Value TAS leaves in a taken lock byte (bit 7 set)
+const LOCKED: u8 = 0x80;
+
+#[repr(C, align(16))]
+pub(crate) struct Spinlock {
+    locked: UnsafeCell<u8>,
+    _padding: [u8; CACHE_LINE_SIZE - 1],
+}
+
+// The lock byte is only modified through TAS and release stores
+unsafe impl Sync for Spinlock {}
+
+impl Spinlock {
+    pub(crate) const fn new() -> Spinlock {
+        Spinlock {
+            locked: UnsafeCell::new(0),
+            _padding: [0; CACHE_LINE_SIZE - 1],
+        }
+    }
+
+    /// Test-and-set the lock byte, returning true if the lock was free
+    #[cfg(target_arch = "m68k")]
+    #[inline(always)]
+    pub(crate) fn try_lock(&self) -> bool {
+        let was_set: u8;
+        unsafe {
+            core::arch::asm!(
+                "tas ({ptr})",
+                "smi {was_set}",
+                ptr = in(reg_addr) self.locked.get(),
+                was_set = out(reg_data) was_set,
+                options(nostack),
+            );
+        }
+        was_set == 0
+    }
+
+    /// Host stand-in for TAS so the lock logic can be tested off-target
+    #[cfg(not(target_arch = "m68k"))]
+    #[inline(always)]
+    pub(crate) fn try_lock(&self) -> bool {
+        let byte = unsafe { &*(self.locked.get() as *const core::sync::atomic::AtomicU8) };
+        byte.swap(LOCKED, Ordering::Acquire) & LOCKED == 0
+    }
+
+    /// Spin until the lock is acquired, returning how long it spun
+    #[inline(never)]
+    pub(crate) fn lock(&self) -> u32 {
+        let mut spins: u32 = 0;
+        while !self.try_lock() {
+            // Another holder must run to release the lock
+            while self.is_locked() {
+                spins = spins.saturating_add(1);
+                core::hint::spin_loop();
+                hooks::spin_wait(self.locked.get());
+            }
+        }
+        fence(Ordering::Acquire);
+        hooks::acquired(self.locked.get());
+        spins
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_locked(&self) -> bool {
+        unsafe { core::ptr::read_volatile(self.locked.get()) != 0 }
+    }
+
+    /// Release the lock
+    #[inline(never)]
+    pub(crate) fn unlock(&self) {
+        fence(Ordering::Release);
+        unsafe { core::ptr::write_volatile(self.locked.get(), 0) };
+        hooks::released(self.locked.get());
+    }
+}
+
+/// Fixed table of spinlocks indexed by address hash
+pub(crate) struct LockTable {
+    locks: [Spinlock; SPINLOCK_COUNT],
+    #[cfg(feature = "lock-stats")]
+    stats: crate::stats::StatsTable,
+}
+
+impl LockTable {
+    pub(crate) const fn new() -> LockTable {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const UNLOCKED: Spinlock = Spinlock::new();
+        LockTable {
+            locks: [UNLOCKED; SPINLOCK_COUNT],
+            #[cfg(feature = "lock-stats")]
+            stats: crate::stats::StatsTable::new(),
+        }
+    }
+
+    /// Lock covering `addr`
+    #[inline(always)]
+    pub(crate) fn lock_for(&self, addr: usize) -> &Spinlock {
+        &self.locks[addr_to_lock_idx(addr)]
+    }
+
+    /// Run `f` with the lock covering `addr` held
+    #[inline(always)]
+    pub(crate) fn with<R>(&self, addr: usize, f: impl FnOnce() -> R) -> R {
+        let lock = self.lock_for(addr);
+        hooks::preempt();
+        critical_section::with(|_| {
+            let _spins = lock.lock();
+            #[cfg(feature = "lock-stats")]
+            unsafe {
+                self.stats.record(addr_to_lock_idx(addr), addr, _spins)
+            };
+            let result = f();
+            lock.unlock();
+            result
+        })
+    }
+
+    /// Run `f` on the counters of `slot` with its lock held
+    #[cfg(feature = "lock-stats")]
+    pub(crate) fn with_stats<R>(&self, slot: usize, f: impl FnOnce(&mut crate::stats::SlotStats) -> R) -> R {
+        let lock = &self.locks[slot];
+        critical_section::with(|_| {
+            lock.lock();
+            let result = f(unsafe { self.stats.slot_mut(slot) });
+            lock.unlock();
+            result
+        })
+    }
+}
+
+// Global lock table
+pub(crate) static LOCKS: LockTable = LockTable::new();
+
+/// Name of the address hash, for reports
+#[cfg(feature = "lock-stats")]
+pub(crate) const HASH_NAME: &str = if cfg!(feature = "mixing-hash") { "mixing" } else { "shift" };
+
+// Hash function to map addresses to spinlock indices
+#[cfg(not(feature = "mixing-hash"))]
+#[inline(always)]
+pub(crate) fn addr_to_lock_idx(addr: usize) -> usize {
+    // Simple hash: use middle bits of address
+    (addr >> 4) & SPINLOCK_MASK
+}
+
+// Fibonacci hashing: the top bits of addr * 2^32/phi, so neighbouring
+// words land on different locks
+#[cfg(feature = "mixing-hash")]
+#[inline(always)]
+pub(crate) fn addr_to_lock_idx(addr: usize) -> usize {
+    if SPINLOCK_BITS == 0 {
+        return 0;
+    }
+    ((addr as u32).wrapping_mul(0x9e37_79b9) >> (32 - SPINLOCK_BITS)) as usize & SPINLOCK_MASK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::thread;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_try_lock_excludes() {
+        let lock = Spinlock::new();
+        assert!(lock.try_lock());
+        assert!(!lock.try_lock());
+        lock.unlock();
+        assert!(lock.try_lock());
+    }
+
+    #[test]
+    fn test_lock_counts_spins() {
+        let lock = Spinlock::new();
+        assert_eq!(lock.lock(), 0);
+        lock.unlock();
+    }
+
+    #[cfg(not(feature = "mixing-hash"))]
+    #[test]
+    fn test_shift_hash_shares_blocks() {
+        assert_eq!(addr_to_lock_idx(0x1000), addr_to_lock_idx(0x100c));
+        assert_ne!(addr_to_lock_idx(0x1000), addr_to_lock_idx(0x1010));
+    }
+
+    #[cfg(feature = "mixing-hash")]
+    #[test]
+    fn test_mixing_hash_spreads_neighbours() {
+        if SPINLOCK_COUNT == 1 {
+            return;
+        }
+        for base in [0x1000usize, 0x2_3450, 0x7fff_fff0] {
+            for offset in [4, 8, 12] {
+                assert_ne!(addr_to_lock_idx(base), addr_to_lock_idx(base + offset));
+            }
+            assert!(addr_to_lock_idx(base) < SPINLOCK_COUNT);
+        }
+    }
+
+    // Counter deliberately updated with plain, non-atomic accesses
+    struct Racy(UnsafeCell<u32>);
+    unsafe impl Sync for Racy {}
+
+    #[test]
+    fn test_stress_no_lost_updates() {
+        const THREADS: usize = 8;
+        const ITERATIONS: u32 = 5_000;
+
+        let table = Arc::new(LockTable::new());
+        let counter = Arc::new(Racy(UnsafeCell::new(0)));
+
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let table = Arc::clone(&table);
+                let counter = Arc::clone(&counter);
+                thread::spawn(move || {
+                    let addr = counter.0.get() as usize;
+                    for _ in 0..ITERATIONS {
+                        table.with(addr, || unsafe {
+                            let value = core::ptr::read_volatile(counter.0.get());
+                            thread::yield_now();
+                            core::ptr::write_volatile(counter.0.get(), value + 1);
+                        });
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(unsafe { *counter.0.get() }, THREADS as u32 * ITERATIONS);
+    }
+}
@@ -0,0 +1,561 @@
+//! Interleaving tests for the lock table on the host
+//!
+//! A small stateless model checker in the style of loom/CHESS. The threads
+//! of an execution are real host threads, but only one runs at a time: the
+//! lock table calls the hooks below at every point where another thread or
+//! a signal handler could get in (`preempt`), where a thread waits for a
+//! lock (`spin_wait`), and where locks change hands. At each point the
+//! scheduler picks who runs next, and `explore` repeats the execution until
+//! every schedule within the preemption bound has been tried.
+//!
+//! A simulated signal handler can be delivered to thread 0 at any point
+//! where its critical-section mask allows it, which checks that a handler
+//! never spins on a lock its own thread holds.
+//!
+//! Outside an execution the hooks do nothing, except that `spin_wait`
+//! yields so ordinary multi-threaded tests make progress on one CPU.
+
+extern crate std;
+
+use std::boxed::Box;
+use std::cell::RefCell;
+use std::format;
+use std::string::String;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::vec::Vec;
+
+/// Bounds for [`explore`]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Config {
+    pub(crate) threads: usize,
+    /// How many times a runnable thread may be switched away from
+    pub(crate) preemption_bound: usize,
+}
+
+// One decision of the schedule: which of `options` was taken
+#[derive(Debug, Clone, Copy)]
+struct Choice {
+    chosen: usize,
+    options: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Run(usize),
+    Signal,
+}
+
+type Handler = Box<dyn FnOnce() + Send>;
+
+struct Sched {
+    active: usize,
+    done: Vec<bool>,
+    // Waiting for a lock; runnable again once some lock is released
+    spinning: Vec<bool>,
+    in_handler: Vec<bool>,
+    // (lock byte address, holder)
+    owners: Vec<(usize, usize)>,
+    handler: Option<Handler>,
+    // Choices to replay from the previous execution, then new ones
+    path: Vec<Choice>,
+    pos: usize,
+    preemptions: usize,
+    bound: usize,
+    steps: usize,
+    failure: Option<String>,
+}
+
+impl Sched {
+    // Take the replayed choice, or the first option at a new decision
+    fn decide(&mut self, options: usize) -> usize {
+        let choice = if self.pos < self.path.len() {
+            let choice = self.path[self.pos];
+            assert_eq!(choice.options, options, "execution is not deterministic");
+            choice.chosen
+        } else {
+            self.path.push(Choice { chosen: 0, options });
+            0
+        };
+        self.pos += 1;
+        choice
+    }
+
+    fn owner(&self, lock: usize) -> Option<usize> {
+        self.owners.iter().find(|&&(addr, _)| addr == lock).map(|&(_, thread)| thread)
+    }
+}
+
+struct Execution {
+    sched: Mutex<Sched>,
+    wake: Condvar,
+}
+
+impl Execution {
+    fn lock(&self) -> MutexGuard<'_, Sched> {
+        self.sched.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Wait until the scheduler hands the CPU to `me`, or the execution failed
+    fn wait_turn<'a>(&'a self, mut sched: MutexGuard<'a, Sched>, me: usize) -> MutexGuard<'a, Sched> {
+        while sched.active != me && sched.failure.is_none() {
+            sched = self.wake.wait(sched).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        sched
+    }
+
+    // Record the first failure and let every thread run freely to its end
+    //
+    // The hooks run inside `extern "C"` libcalls, which cannot unwind, so a
+    // failure is reported by `explore` once the threads have finished.
+    // Locks are freed so that deadlocked threads can get through.
+    fn fail(&self, mut sched: MutexGuard<'_, Sched>, message: String) {
+        if sched.failure.is_none() {
+            sched.failure = Some(message);
+        }
+        for &(lock, _) in &sched.owners {
+            unsafe { core::ptr::write_volatile(lock as *mut u8, 0) };
+        }
+        sched.owners.clear();
+        drop(sched);
+        self.wake.notify_all();
+    }
+
+    // Pick the next step at a scheduling point of `me`
+    fn schedule(&self, me: usize, spinning_on: Option<usize>) {
+        let mut sched = self.lock();
+        if sched.failure.is_some() {
+            drop(sched);
+            thread::yield_now();
+            return;
+        }
+        sched.steps += 1;
+
+        if let Some(lock) = spinning_on {
+            if sched.in_handler[me] && sched.owner(lock) == Some(me) {
+                let message = format!("thread {} deadlocked: signal handler spins on a lock its thread holds", me);
+                return self.fail(sched, message);
+            }
+            sched.spinning[me] = true;
+        }
+
+        let runnable = |sched: &Sched, thread: usize| !sched.done[thread] && !sched.spinning[thread];
+        let me_runnable = runnable(&sched, me);
+        let mut options = Vec::new();
+        if me_runnable {
+            options.push(Step::Run(me));
+        }
+        if !me_runnable || sched.preemptions < sched.bound {
+            options.extend((0..sched.done.len()).filter(|&t| t != me && runnable(&sched, t)).map(Step::Run));
+        }
+        if me_runnable
+            && me == 0
+            && sched.handler.is_some()
+            && !sched.in_handler[me]
+            && !crate::critical_section::signals_blocked()
+        {
+            options.push(Step::Signal);
+        }
+        if options.is_empty() {
+            return self.fail(sched, String::from("deadlock: every thread is waiting for a lock"));
+        }
+
+        match options[sched.decide(options.len())] {
+            Step::Run(next) if next == me => {}
+            Step::Run(next) => {
+                if me_runnable {
+                    sched.preemptions += 1;
+                }
+                sched.active = next;
+                self.wake.notify_all();
+                drop(self.wait_turn(sched, me));
+            }
+            Step::Signal => {
+                let handler = sched.handler.take().unwrap();
+                self.run_handler(sched, me, handler);
+            }
+        }
+    }
+
+    fn run_handler(&self, mut sched: MutexGuard<'_, Sched>, me: usize, handler: Handler) {
+        sched.in_handler[me] = true;
+        drop(sched);
+        handler();
+        self.lock().in_handler[me] = false;
+    }
+
+    // `me` returned: deliver a signal still pending, then hand over the CPU
+    fn exit(&self, me: usize) {
+        let mut sched = self.lock();
+        if sched.failure.is_some() {
+            return;
+        }
+        if me == 0 {
+            if let Some(handler) = sched.handler.take() {
+                self.run_handler(sched, me, handler);
+                sched = self.lock();
+            }
+        }
+        sched.done[me] = true;
+        let waiting: Vec<usize> = (0..sched.done.len()).filter(|&t| !sched.done[t]).collect();
+        let runnable: Vec<usize> = waiting.iter().copied().filter(|&t| !sched.spinning[t]).collect();
+        if runnable.is_empty() {
+            if !waiting.is_empty() {
+                self.fail(sched, String::from("deadlock: every thread is waiting for a lock"));
+            }
+            return;
+        }
+        let next = runnable[sched.decide(runnable.len())];
+        sched.active = next;
+        drop(sched);
+        self.wake.notify_all();
+    }
+}
+
+std::thread_local! {
+    static CURRENT: RefCell<Option<(Arc<Execution>, usize)>> = const { RefCell::new(None) };
+}
+
+fn current() -> Option<(Arc<Execution>, usize)> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Point where another thread or a signal handler may run
+pub(crate) fn preempt() {
+    if let Some((execution, me)) = current() {
+        execution.schedule(me, None);
+    }
+}
+
+/// The lock at `lock` is held by someone else
+pub(crate) fn spin_wait(lock: *const u8) {
+    let Some((execution, me)) = current() else {
+        // Host test threads may share one CPU with the holder
+        thread::yield_now();
+        return;
+    };
+    // Held by a thread outside the execution (another test): just wait
+    if execution.lock().owner(lock as usize).is_none() {
+        thread::yield_now();
+        return;
+    }
+    execution.schedule(me, Some(lock as usize));
+}
+
+pub(crate) fn acquired(lock: *const u8) {
+    if let Some((execution, me)) = current() {
+        execution.lock().owners.push((lock as usize, me));
+    }
+}
+
+pub(crate) fn released(lock: *const u8) {
+    if let Some((execution, _)) = current() {
+        let mut sched = execution.lock();
+        sched.owners.retain(|&(addr, _)| addr != lock as usize);
+        // Any waiter may get the lock now
+        sched.spinning.iter_mut().for_each(|spinning| *spinning = false);
+    }
+}
+
+/// Logical time of the current execution, advanced at every scheduling point
+pub(crate) fn now() -> usize {
+    current().map_or(0, |(execution, _)| execution.lock().steps)
+}
+
+/// Run `body` on `config.threads` threads under every schedule within the
+/// preemption bound, calling `check` on the state after each execution
+///
+/// With a `handler`, a simulated signal runs it on thread 0 once per
+/// execution, at every point where signals are not blocked. Returns the
+/// number of executions explored.
+pub(crate) fn explore<S: Send + Sync + 'static>(
+    config: Config,
+    setup: impl Fn() -> S,
+    body: fn(&S, usize),
+    handler: Option<fn(&S)>,
+    check: impl Fn(&S),
+) -> usize {
+    // Executions share the global lock table, so run one at a time
+    static SERIAL: Mutex<()> = Mutex::new(());
+    let _serial = SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    let mut path = Vec::new();
+    let mut executions = 0;
+    loop {
+        let state = Arc::new(setup());
+        let execution = Arc::new(Execution {
+            sched: Mutex::new(Sched {
+                active: 0,
+                done: std::vec![false; config.threads],
+                spinning: std::vec![false; config.threads],
+                in_handler: std::vec![false; config.threads],
+                owners: Vec::new(),
+                handler: handler.map(|handler| {
+                    let state = Arc::clone(&state);
+                    Box::new(move || handler(&state)) as Handler
+                }),
+                path,
+                pos: 0,
+                preemptions: 0,
+                bound: config.preemption_bound,
+                steps: 0,
+                failure: None,
+            }),
+            wake: Condvar::new(),
+        });
+
+        let threads: Vec<_> = (0..config.threads)
+            .map(|me| {
+                let execution = Arc::clone(&execution);
+                let state = Arc::clone(&state);
+                thread::spawn(move || {
+                    CURRENT.with(|current| *current.borrow_mut() = Some((Arc::clone(&execution), me)));
+                    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        drop(execution.wait_turn(execution.lock(), me));
+                        body(&state, me);
+                        execution.exit(me);
+                    }));
+                    if let Err(payload) = result {
+                        let message = payload
+                            .downcast_ref::<&str>()
+                            .map(|s| String::from(*s))
+                            .or_else(|| payload.downcast_ref::<String>().cloned())
+                            .unwrap_or_else(|| String::from("thread panicked"));
+                        execution.fail(execution.lock(), format!("thread {}: {}", me, message));
+                    }
+                    CURRENT.with(|current| *current.borrow_mut() = None);
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        executions += 1;
+
+        let mut sched = execution.lock();
+        let schedule: Vec<usize> = sched.path.iter().map(|choice| choice.chosen).collect();
+        if let Some(failure) = sched.failure.take() {
+            panic!("{} (execution {}, schedule {:?})", failure, executions, schedule);
+        }
+        check(&state);
+
+        // Depth-first: advance the deepest choice with options left
+        path = core::mem::take(&mut sched.path);
+        while let Some(last) = path.last_mut() {
+            if last.chosen + 1 < last.options {
+                last.chosen += 1;
+                break;
+            }
+            path.pop();
+        }
+        if path.is_empty() {
+            return executions;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use core::cell::UnsafeCell;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Op {
+        Cas(u64, u64),
+        FetchAdd(u64),
+        Exchange(u64),
+    }
+
+    impl Op {
+        // Sequential specification: new value and result from `value`
+        fn apply(self, value: u64) -> (u64, u64) {
+            match self {
+                Op::Cas(old, new) => (if value == old { new } else { value }, value),
+                Op::FetchAdd(n) => (value.wrapping_add(n), value),
+                Op::Exchange(n) => (n, value),
+            }
+        }
+    }
+
+    // An operation as observed: logical invocation and response times
+    #[derive(Debug, Clone, Copy)]
+    struct Event {
+        op: Op,
+        result: u64,
+        invoked: usize,
+        returned: usize,
+    }
+
+    // Wing & Gong: is there an order of the events that respects real time
+    // (an event that returned before another was invoked comes first) and
+    // gives every event its observed result?
+    fn linearizable(value: u64, events: &[Event], taken: &mut Vec<bool>, last: u64) -> bool {
+        if taken.iter().all(|&t| t) {
+            return value == last;
+        }
+        for i in 0..events.len() {
+            if taken[i] {
+                continue;
+            }
+            let blocked = (0..events.len()).any(|j| !taken[j] && events[j].returned < events[i].invoked);
+            let (next, result) = events[i].op.apply(value);
+            if blocked || result != events[i].result {
+                continue;
+            }
+            taken[i] = true;
+            if linearizable(next, events, taken, last) {
+                return true;
+            }
+            taken[i] = false;
+        }
+        false
+    }
+
+    struct Shared<T> {
+        value: UnsafeCell<T>,
+        history: Mutex<Vec<Event>>,
+    }
+
+    unsafe impl<T> Sync for Shared<T> {}
+
+    impl<T> Shared<T> {
+        fn new(value: T) -> Shared<T> {
+            Shared { value: UnsafeCell::new(value), history: Mutex::new(Vec::new()) }
+        }
+
+        fn record(&self, op: Op, run: impl FnOnce() -> u64) {
+            let invoked = now();
+            let result = run();
+            let returned = now();
+            self.history.lock().unwrap().push(Event { op, result, invoked, returned });
+        }
+
+        fn check_linearizable(&self, initial: u64, last: u64) {
+            let events = self.history.lock().unwrap();
+            let mut taken = std::vec![false; events.len()];
+            assert!(linearizable(initial, &events, &mut taken, last), "not linearizable: {:?}", *events);
+        }
+    }
+
+    const PROGRAMS: [&[Op]; 3] = [
+        &[Op::Cas(0, 1), Op::FetchAdd(2)],
+        &[Op::Exchange(5), Op::Cas(5, 7)],
+        &[Op::FetchAdd(1)],
+    ];
+
+    fn run_4(shared: &Shared<u32>, me: usize) {
+        let ptr = shared.value.get();
+        for &op in PROGRAMS[me] {
+            shared.record(op, || unsafe {
+                match op {
+                    Op::Cas(old, new) => {
+                        let mut expected = old as u32;
+                        let ok = __atomic_compare_exchange_4(ptr, &mut expected, new as u32, ATOMIC_SEQ_CST, ATOMIC_SEQ_CST);
+                        assert_eq!(ok, expected == old as u32);
+                        expected as u64
+                    }
+                    Op::FetchAdd(n) => __atomic_fetch_add_4(ptr, n as u32, ATOMIC_SEQ_CST) as u64,
+                    Op::Exchange(n) => __atomic_exchange_4(ptr, n as u32, ATOMIC_SEQ_CST) as u64,
+                }
+            });
+        }
+    }
+
+    fn run_8(shared: &Shared<u64>, me: usize) {
+        let ptr = shared.value.get();
+        for &op in PROGRAMS[me] {
+            shared.record(op, || unsafe {
+                match op {
+                    Op::Cas(old, new) => __sync_val_compare_and_swap_8(ptr, old, new),
+                    Op::FetchAdd(n) => __atomic_fetch_add_8(ptr, n, ATOMIC_ACQ_REL),
+                    Op::Exchange(n) => __atomic_exchange_8(ptr, n, ATOMIC_ACQ_REL),
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn test_cas_fetch_add_exchange_linearizable() {
+        let config = Config { threads: 3, preemption_bound: 3 };
+        let executions = explore(
+            config,
+            || Shared::new(0u32),
+            run_4,
+            None,
+            |shared| shared.check_linearizable(0, unsafe { *shared.value.get() } as u64),
+        );
+        assert!(executions > 1000, "only {} executions explored", executions);
+    }
+
+    #[test]
+    fn test_8_byte_ops_linearizable() {
+        let config = Config { threads: 2, preemption_bound: 4 };
+        let executions = explore(
+            config,
+            || Shared::new(0u64),
+            run_8,
+            None,
+            |shared| shared.check_linearizable(0, unsafe { *shared.value.get() }),
+        );
+        assert!(executions > 50, "only {} executions explored", executions);
+    }
+
+    #[test]
+    fn test_checker_finds_unlocked_lost_update() {
+        // Read, preempt, write without a lock: some schedule must lose an update
+        let lost = Mutex::new(false);
+        explore(
+            Config { threads: 2, preemption_bound: 1 },
+            || Shared::new(0u32),
+            |shared, _| unsafe {
+                let value = *shared.value.get();
+                preempt();
+                *shared.value.get() = value + 1;
+            },
+            None,
+            |shared| *lost.lock().unwrap() |= unsafe { *shared.value.get() } != 2,
+        );
+        assert!(*lost.lock().unwrap());
+    }
+
+    #[test]
+    fn test_signal_handler_reentrancy() {
+        // The handler updates the same word (and so the same lock) as the
+        // interrupted thread, at every point the mask allows
+        let executions = explore(
+            Config { threads: 2, preemption_bound: 2 },
+            || Shared::new(0u32),
+            |shared, _| unsafe {
+                __atomic_fetch_add_4(shared.value.get(), 1, ATOMIC_SEQ_CST);
+                __sync_val_compare_and_swap_4(shared.value.get(), 100, 0);
+            },
+            Some(|shared: &Shared<u32>| unsafe {
+                __atomic_fetch_add_4(shared.value.get(), 10, ATOMIC_SEQ_CST);
+                __atomic_load_4(shared.value.get(), ATOMIC_SEQ_CST);
+            }),
+            |shared| assert_eq!(unsafe { *shared.value.get() }, 12),
+        );
+        assert!(executions > 50, "only {} executions explored", executions);
+    }
+
+    #[test]
+    #[should_panic(expected = "signal handler spins on a lock its thread holds")]
+    fn test_checker_finds_lock_held_without_critical_section() {
+        // What the table did before locks were held in critical sections
+        explore(
+            Config { threads: 1, preemption_bound: 0 },
+            || Shared::new(0u32),
+            |shared, _| {
+                let lock = lock::LOCKS.lock_for(shared.value.get() as usize);
+                lock.lock();
+                preempt();
+                lock.unlock();
+            },
+            Some(|shared: &Shared<u32>| unsafe {
+                __atomic_fetch_add_4(shared.value.get(), 1, ATOMIC_SEQ_CST);
+            }),
+            |_| {},
+        );
+    }
+}
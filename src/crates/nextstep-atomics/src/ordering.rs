@@ -0,0 +1,115 @@
+//! C11 memory orderings passed to the `__atomic_*` libcalls
+//!
+//! On a uniprocessor 680x0 the CPU never reorders memory accesses as seen
+//! by other threads or signal handlers, so orderings only constrain the
+//! compiler: each one maps to the fences it needs around the access.
+
+use core::sync::atomic::{fence, Ordering};
+
+// Values of C11 `memory_order` (`__ATOMIC_RELAXED` .. `__ATOMIC_SEQ_CST`)
+pub const ATOMIC_RELAXED: i32 = 0;
+pub const ATOMIC_CONSUME: i32 = 1;
+pub const ATOMIC_ACQUIRE: i32 = 2;
+pub const ATOMIC_RELEASE: i32 = 3;
+pub const ATOMIC_ACQ_REL: i32 = 4;
+pub const ATOMIC_SEQ_CST: i32 = 5;
+
+/// Kinds of atomic access, which differ in the orderings they accept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Access {
+    Load,
+    Store,
+    ReadModifyWrite,
+    /// The failure ordering of a compare-exchange, which performs no store
+    CompareFailure,
+}
+
+/// Decode a C11 memory order for `access`, or `None` if it is invalid
+pub(crate) fn decode_ordering(raw: i32, access: Access) -> Option<Ordering> {
+    let order = match raw {
+        ATOMIC_RELAXED => Ordering::Relaxed,
+        // Dependency ordering is not tracked, so consume is strengthened
+        ATOMIC_CONSUME | ATOMIC_ACQUIRE => Ordering::Acquire,
+        ATOMIC_RELEASE => Ordering::Release,
+        ATOMIC_ACQ_REL => Ordering::AcqRel,
+        ATOMIC_SEQ_CST => Ordering::SeqCst,
+        _ => return None,
+    };
+    let valid = match access {
+        Access::Load | Access::CompareFailure => !matches!(order, Ordering::Release | Ordering::AcqRel),
+        Access::Store => !matches!(order, Ordering::Acquire | Ordering::AcqRel),
+        Access::ReadModifyWrite => true,
+    };
+    if valid {
+        Some(order)
+    } else {
+        None
+    }
+}
+
+/// Decode a C11 memory order, panicking if `access` does not accept it
+#[inline(always)]
+pub(crate) fn checked_ordering(raw: i32, access: Access) -> Ordering {
+    match decode_ordering(raw, access) {
+        Some(order) => order,
+        None => invalid_ordering(raw, access),
+    }
+}
+
+#[cold]
+#[inline(never)]
+fn invalid_ordering(raw: i32, access: Access) -> ! {
+    panic!("invalid memory ordering {} for atomic {:?}", raw, access)
+}
+
+// Fence needed before an access with `order`
+#[inline(always)]
+pub(crate) fn fence_before(order: Ordering) {
+    match order {
+        Ordering::Release | Ordering::AcqRel => fence(Ordering::Release),
+        Ordering::SeqCst => fence(Ordering::SeqCst),
+        _ => {}
+    }
+}
+
+// Fence needed after an access with `order`
+#[inline(always)]
+pub(crate) fn fence_after(order: Ordering) {
+    match order {
+        Ordering::Acquire | Ordering::AcqRel => fence(Ordering::Acquire),
+        Ordering::SeqCst => fence(Ordering::SeqCst),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_valid_orderings() {
+        assert_eq!(decode_ordering(ATOMIC_RELAXED, Access::Load), Some(Ordering::Relaxed));
+        assert_eq!(decode_ordering(ATOMIC_CONSUME, Access::Load), Some(Ordering::Acquire));
+        assert_eq!(decode_ordering(ATOMIC_ACQUIRE, Access::Load), Some(Ordering::Acquire));
+        assert_eq!(decode_ordering(ATOMIC_SEQ_CST, Access::Load), Some(Ordering::SeqCst));
+        assert_eq!(decode_ordering(ATOMIC_RELAXED, Access::Store), Some(Ordering::Relaxed));
+        assert_eq!(decode_ordering(ATOMIC_RELEASE, Access::Store), Some(Ordering::Release));
+        assert_eq!(decode_ordering(ATOMIC_SEQ_CST, Access::Store), Some(Ordering::SeqCst));
+        for raw in ATOMIC_RELAXED..=ATOMIC_SEQ_CST {
+            assert!(decode_ordering(raw, Access::ReadModifyWrite).is_some());
+        }
+    }
+
+    #[test]
+    fn test_decode_rejects_invalid_orderings() {
+        assert_eq!(decode_ordering(ATOMIC_RELEASE, Access::Load), None);
+        assert_eq!(decode_ordering(ATOMIC_ACQ_REL, Access::Load), None);
+        assert_eq!(decode_ordering(ATOMIC_CONSUME, Access::Store), None);
+        assert_eq!(decode_ordering(ATOMIC_ACQUIRE, Access::Store), None);
+        assert_eq!(decode_ordering(ATOMIC_ACQ_REL, Access::Store), None);
+        assert_eq!(decode_ordering(ATOMIC_RELEASE, Access::CompareFailure), None);
+        assert_eq!(decode_ordering(ATOMIC_ACQ_REL, Access::CompareFailure), None);
+        assert_eq!(decode_ordering(-1, Access::ReadModifyWrite), None);
+        assert_eq!(decode_ordering(6, Access::ReadModifyWrite), None);
+    }
+}
@@ -0,0 +1,260 @@
+//! Contention statistics for the spinlock table (feature `lock-stats`)
+//!
+//! Each lock hold records one acquisition, and how long it spun, against
+//! its slot and the address it covered. The counters are written under the
+//! slot's own lock, so recording needs no extra synchronization. Slots with
+//! several busy addresses point at locks shared by unrelated data, which is
+//! what `NEXTSTEP_ATOMICS_LOCK_COUNT` and the `mixing-hash` feature tune.
+//!
+//! ```ignore
+//! let _ = nextstep_atomics::stats::report(&mut nextstep_io::stderr());
+//! ```
+
+use crate::lock::{HASH_NAME, LOCKS, SPINLOCK_COUNT};
+use core::cell::UnsafeCell;
+use core::fmt;
+
+/// Number of slots in the lock table
+pub const LOCK_COUNT: usize = SPINLOCK_COUNT;
+
+/// Addresses tracked per slot
+pub const ADDRS_PER_SLOT: usize = 4;
+
+/// Counters for one address
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AddrStats {
+    /// Address passed to the atomic operation (0 marks an unused entry)
+    pub addr: usize,
+    pub acquisitions: u32,
+    pub spins: u32,
+}
+
+/// Counters for one lock slot
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SlotStats {
+    pub slot: usize,
+    pub acquisitions: u32,
+    /// Acquisitions that found the lock taken
+    pub contended: u32,
+    pub spins: u32,
+    /// Busiest addresses seen on this slot
+    pub addresses: [AddrStats; ADDRS_PER_SLOT],
+}
+
+const EMPTY_ADDR: AddrStats = AddrStats { addr: 0, acquisitions: 0, spins: 0 };
+
+const EMPTY_SLOT: SlotStats = SlotStats {
+    slot: 0,
+    acquisitions: 0,
+    contended: 0,
+    spins: 0,
+    addresses: [EMPTY_ADDR; ADDRS_PER_SLOT],
+};
+
+impl SlotStats {
+    fn record(&mut self, addr: usize, spins: u32) {
+        self.acquisitions = self.acquisitions.saturating_add(1);
+        if spins > 0 {
+            self.contended = self.contended.saturating_add(1);
+        }
+        self.spins = self.spins.saturating_add(spins);
+
+        // Replace the least used entry when the address is new
+        let index = match self.addresses.iter().position(|entry| entry.addr == addr) {
+            Some(index) => index,
+            None => {
+                let mut index = 0;
+                for (i, entry) in self.addresses.iter().enumerate() {
+                    if entry.acquisitions < self.addresses[index].acquisitions {
+                        index = i;
+                    }
+                }
+                self.addresses[index] = AddrStats { addr, ..EMPTY_ADDR };
+                index
+            }
+        };
+        let entry = &mut self.addresses[index];
+        entry.acquisitions = entry.acquisitions.saturating_add(1);
+        entry.spins = entry.spins.saturating_add(spins);
+    }
+}
+
+/// Per-slot counters; a slot is only accessed with its lock held
+pub(crate) struct StatsTable {
+    slots: [UnsafeCell<SlotStats>; SPINLOCK_COUNT],
+}
+
+unsafe impl Sync for StatsTable {}
+
+impl StatsTable {
+    pub(crate) const fn new() -> StatsTable {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const EMPTY: UnsafeCell<SlotStats> = UnsafeCell::new(EMPTY_SLOT);
+        StatsTable {
+            slots: [EMPTY; SPINLOCK_COUNT],
+        }
+    }
+
+    /// Caller must hold the lock for `slot`
+    #[inline(always)]
+    pub(crate) unsafe fn record(&self, slot: usize, addr: usize, spins: u32) {
+        self.slot_mut(slot).record(addr, spins)
+    }
+
+    /// Caller must hold the lock for `slot`
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub(crate) unsafe fn slot_mut(&self, slot: usize) -> &mut SlotStats {
+        &mut *self.slots[slot].get()
+    }
+}
+
+/// Snapshot of the counters for `slot`
+pub fn slot(slot: usize) -> SlotStats {
+    LOCKS.with_stats(slot, |stats| SlotStats { slot, ..*stats })
+}
+
+/// Clear all counters
+pub fn reset() {
+    for slot in 0..SPINLOCK_COUNT {
+        LOCKS.with_stats(slot, |stats| *stats = EMPTY_SLOT);
+    }
+}
+
+// Insert `item` into `out[..*len]`, kept sorted by descending `key`
+fn insert_sorted<T: Copy>(out: &mut [T], len: &mut usize, item: T, key: impl Fn(&T) -> (u32, u32)) {
+    let mut index = *len;
+    while index > 0 && key(&out[index - 1]) < key(&item) {
+        index -= 1;
+    }
+    if index >= out.len() {
+        return;
+    }
+    let end = core::cmp::min(*len, out.len() - 1);
+    out.copy_within(index..end, index + 1);
+    out[index] = item;
+    *len = core::cmp::min(*len + 1, out.len());
+}
+
+/// Fill `out` with the busiest slots (by spins, then acquisitions)
+///
+/// Returns the number of entries written; idle slots are left out.
+pub fn hottest_slots(out: &mut [SlotStats]) -> usize {
+    let mut len = 0;
+    for index in 0..SPINLOCK_COUNT {
+        let stats = slot(index);
+        if stats.acquisitions > 0 {
+            insert_sorted(out, &mut len, stats, |s| (s.spins, s.acquisitions));
+        }
+    }
+    len
+}
+
+/// Fill `out` with the busiest addresses over all slots, paired with their slot
+///
+/// Returns the number of entries written.
+pub fn hottest_addresses(out: &mut [(usize, AddrStats)]) -> usize {
+    let mut len = 0;
+    for index in 0..SPINLOCK_COUNT {
+        for entry in slot(index).addresses {
+            if entry.acquisitions > 0 {
+                insert_sorted(out, &mut len, (index, entry), |(_, a)| (a.spins, a.acquisitions));
+            }
+        }
+    }
+    len
+}
+
+// Entries shown in each section of the report
+const REPORT_ENTRIES: usize = 8;
+
+/// Write a summary of the table and its hottest slots and addresses
+pub fn report(out: &mut impl fmt::Write) -> fmt::Result {
+    let (mut acquisitions, mut contended, mut spins) = (0u32, 0u32, 0u32);
+    for index in 0..SPINLOCK_COUNT {
+        let stats = slot(index);
+        acquisitions = acquisitions.saturating_add(stats.acquisitions);
+        contended = contended.saturating_add(stats.contended);
+        spins = spins.saturating_add(stats.spins);
+    }
+    writeln!(out, "lock table: {} slots, {} hash", SPINLOCK_COUNT, HASH_NAME)?;
+    writeln!(out, "total: {} acquisitions, {} contended, {} spins", acquisitions, contended, spins)?;
+
+    let mut slots = [EMPTY_SLOT; REPORT_ENTRIES];
+    let count = hottest_slots(&mut slots);
+    writeln!(out, "hottest slots:")?;
+    for stats in &slots[..count] {
+        let shared = stats.addresses.iter().filter(|a| a.acquisitions > 0).count();
+        writeln!(
+            out,
+            "  slot {:4}: {} acquisitions, {} contended, {} spins, {} addresses",
+            stats.slot, stats.acquisitions, stats.contended, stats.spins, shared
+        )?;
+    }
+
+    let mut addresses = [(0, EMPTY_ADDR); REPORT_ENTRIES];
+    let count = hottest_addresses(&mut addresses);
+    writeln!(out, "hottest addresses:")?;
+    for (slot, entry) in &addresses[..count] {
+        writeln!(
+            out,
+            "  {:#010x} (slot {}): {} acquisitions, {} spins",
+            entry.addr, slot, entry.acquisitions, entry.spins
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock::addr_to_lock_idx;
+
+    #[test]
+    fn test_record_tracks_addresses() {
+        let mut stats = EMPTY_SLOT;
+        stats.record(0x100, 0);
+        stats.record(0x100, 3);
+        stats.record(0x104, 0);
+        assert_eq!((stats.acquisitions, stats.contended, stats.spins), (3, 1, 3));
+        assert_eq!(stats.addresses[0], AddrStats { addr: 0x100, acquisitions: 2, spins: 3 });
+        assert_eq!(stats.addresses[1], AddrStats { addr: 0x104, acquisitions: 1, spins: 0 });
+
+        // A new address evicts the least used one
+        for addr in [0x108, 0x10c, 0x110] {
+            stats.record(addr, 0);
+        }
+        assert!(stats.addresses.iter().any(|a| a.addr == 0x100));
+        assert!(stats.addresses.iter().any(|a| a.addr == 0x110));
+    }
+
+    #[test]
+    fn test_insert_sorted_keeps_top_entries() {
+        let mut out = [0u32; 3];
+        let mut len = 0;
+        for value in [5, 1, 9, 7, 3] {
+            insert_sorted(&mut out, &mut len, value, |v| (*v, 0));
+        }
+        assert_eq!((len, out), (3, [9, 7, 5]));
+    }
+
+    #[test]
+    fn test_report_lists_sections() {
+        extern crate std;
+        let mut text = std::string::String::new();
+        report(&mut text).unwrap();
+        assert!(text.starts_with("lock table: "));
+        assert!(text.contains("hottest slots:\n"));
+        assert!(text.contains("hottest addresses:\n"));
+    }
+
+    #[test]
+    fn test_locked_ops_are_counted() {
+        let mut value: u64 = 0;
+        let addr = &value as *const u64 as usize;
+        let before = slot(addr_to_lock_idx(addr)).acquisitions;
+        unsafe { crate::__atomic_fetch_add_8(&mut value, 1, crate::ATOMIC_SEQ_CST) };
+        assert!(slot(addr_to_lock_idx(addr)).acquisitions > before);
+        assert!(slot(addr_to_lock_idx(addr)).addresses.iter().any(|a| a.addr == addr));
+    }
+}
@@ -0,0 +1,123 @@
+//! Rust legacy (`_ZN...E`) symbol demangling without allocation
+
+use core::fmt::{self, Write};
+
+/// Write the demangled form of `name` to `out`
+///
+/// Names that are not legacy-mangled Rust symbols (C functions, v0
+/// mangling) are written unchanged. The trailing `h<16 hex>` hash
+/// component is dropped, as `rustc-demangle` does in its alternate form.
+pub fn demangle(name: &str, out: &mut impl Write) -> fmt::Result {
+    let body = match legacy_body(name) {
+        Some(body) => body,
+        None => return out.write_str(name),
+    };
+
+    let mut first = true;
+    let mut components = Components(body).peekable();
+    while let Some(component) = components.next() {
+        if components.peek().is_none() && is_hash(component) {
+            break;
+        }
+        if !first {
+            out.write_str("::")?;
+        }
+        first = false;
+        write_component(component, out)?;
+    }
+    Ok(())
+}
+
+/// The `<len><ident>...` part of a well-formed legacy symbol
+fn legacy_body(name: &str) -> Option<&str> {
+    // Mach-O C symbols carry an extra leading underscore
+    let inner = name
+        .strip_prefix("__ZN")
+        .or_else(|| name.strip_prefix("_ZN"))
+        .or_else(|| name.strip_prefix("ZN"))?;
+
+    let bytes = inner.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() && bytes[i] != b'E' {
+        let start = i;
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        let len: usize = inner.get(start..i)?.parse().ok()?;
+        i = i.checked_add(len)?;
+        if len == 0 || i > bytes.len() {
+            return None;
+        }
+    }
+    if i == 0 || i >= bytes.len() {
+        return None;
+    }
+    // Anything after the closing `E` (e.g. `.llvm.1234`) is ignored
+    inner.get(..i)
+}
+
+/// Iterator over the length-prefixed path components of a legacy symbol
+struct Components<'a>(&'a str);
+
+impl<'a> Iterator for Components<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let digits = self.0.bytes().take_while(u8::is_ascii_digit).count();
+        let len: usize = self.0.get(..digits)?.parse().ok()?;
+        let component = self.0.get(digits..digits + len)?;
+        self.0 = &self.0[digits + len..];
+        Some(component)
+    }
+}
+
+fn is_hash(component: &str) -> bool {
+    component.len() == 17
+        && component.starts_with('h')
+        && component[1..].bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn write_component(component: &str, out: &mut impl Write) -> fmt::Result {
+    // Identifiers that would start with `$` are prefixed with `_`
+    let mut rest = match component.strip_prefix("_$") {
+        Some(_) => &component[1..],
+        None => component,
+    };
+
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("..") {
+            out.write_str("::")?;
+            rest = &rest[2..];
+            continue;
+        }
+        if c == '$' {
+            if let Some(end) = rest[1..].find('$') {
+                if let Some(unescaped) = unescape(&rest[1..1 + end]) {
+                    out.write_char(unescaped)?;
+                    rest = &rest[end + 2..];
+                    continue;
+                }
+            }
+        }
+        out.write_char(c)?;
+        rest = &rest[c.len_utf8()..];
+    }
+    Ok(())
+}
+
+fn unescape(escape: &str) -> Option<char> {
+    match escape {
+        "SP" => Some('@'),
+        "BP" => Some('*'),
+        "RF" => Some('&'),
+        "LT" => Some('<'),
+        "GT" => Some('>'),
+        "LP" => Some('('),
+        "RP" => Some(')'),
+        "C" => Some(','),
+        _ => {
+            let hex = escape.strip_prefix('u')?;
+            char::from_u32(u32::from_str_radix(hex, 16).ok()?)
+        }
+    }
+}
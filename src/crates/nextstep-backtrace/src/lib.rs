@@ -0,0 +1,209 @@
+//! nextstep-backtrace - Stack walking and symbolization for m68k NeXTSTEP
+//!
+//! Walks the `link a6` frame-pointer chain and maps the return addresses
+//! back to function names using the executable's Mach-O symbol table, with
+//! Rust demangling. The chain is only complete if every function sets up
+//! a6, which the target specs ensure with `"frame-pointer": "always"`;
+//! code built without it (e.g. C libraries) can end the walk early.
+//!
+//! Everything is `no_std` and allocation-free so it can run from panic and
+//! crash handlers:
+//!
+//! ```ignore
+//! nextstep_backtrace::set_executable_path(b"/me/bin/app\0");
+//! nextstep_backtrace::print();
+//! ```
+
+#![no_std]
+#![cfg_attr(target_arch = "m68k", feature(asm_experimental_arch))]
+
+use core::fmt::{self, Write};
+
+mod demangle;
+mod symbolize;
+
+pub use demangle::demangle;
+pub use symbolize::{Symbol, Symbolizer};
+
+/// Upper bound on frames yielded by [`Frames`]
+pub const MAX_FRAMES: usize = 64;
+
+// Longest symbol name printed before truncation
+const NAME_BUF_SIZE: usize = 256;
+
+// NUL-terminated path of the running executable, if known
+static mut EXECUTABLE_PATH: Option<&'static [u8]> = None;
+
+/// Register the path of the running executable for [`Symbolizer::open_self`]
+///
+/// `path` must be NUL-terminated. Runtimes typically pass `argv[0]`.
+pub fn set_executable_path(path: &'static [u8]) {
+    unsafe { EXECUTABLE_PATH = Some(path) };
+}
+
+/// Path registered with [`set_executable_path`]
+pub fn executable_path() -> Option<&'static [u8]> {
+    unsafe { EXECUTABLE_PATH }
+}
+
+/// Read the current frame pointer (a6)
+#[inline(always)]
+pub fn current_fp() -> usize {
+    #[cfg(target_arch = "m68k")]
+    {
+        let fp: usize;
+        unsafe { core::arch::asm!("move.l %a6, {0}", out(reg) fp, options(nomem, nostack)) };
+        fp
+    }
+    #[cfg(not(target_arch = "m68k"))]
+    {
+        0
+    }
+}
+
+/// Iterator over return addresses along the `link a6` frame chain
+///
+/// Each m68k frame holds the caller's a6 at `0(a6)` and the return address
+/// at `4(a6)`. The walk stops at a null or odd frame pointer, a null return
+/// address, after [`MAX_FRAMES`] frames, or when the chain stops moving
+/// toward the base of the stack.
+#[derive(Debug, Clone, Copy)]
+pub struct Frames {
+    fp: usize,
+    remaining: usize,
+}
+
+impl Frames {
+    /// Walk starting from the frame whose a6 is `fp`
+    pub const fn from_fp(fp: usize) -> Frames {
+        Frames { fp, remaining: MAX_FRAMES }
+    }
+
+    /// Walk starting from the caller's frame
+    #[inline(always)]
+    pub fn current() -> Frames {
+        Frames::from_fp(current_fp())
+    }
+}
+
+impl Iterator for Frames {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.remaining == 0 || self.fp == 0 || self.fp & 1 != 0 {
+            return None;
+        }
+        let (next, ret) = unsafe {
+            (
+                core::ptr::read_volatile(self.fp as *const usize),
+                core::ptr::read_volatile((self.fp + 4) as *const usize),
+            )
+        };
+        if ret == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.fp = if next > self.fp { next } else { 0 };
+        Some(ret)
+    }
+}
+
+/// Write one backtrace line: depth, address and, if found, the symbol
+///
+/// Return addresses point past the call instruction, so they are looked up
+/// one byte earlier to stay inside the calling function; pass
+/// `is_return_address = false` for a faulting PC.
+pub fn write_frame(
+    out: &mut impl Write,
+    depth: usize,
+    addr: usize,
+    is_return_address: bool,
+    symbolizer: Option<&Symbolizer>,
+) -> fmt::Result {
+    write!(out, "  #{:<2} {:#010x}", depth, addr as u32)?;
+
+    let lookup = if is_return_address { addr.wrapping_sub(1) } else { addr };
+    let mut name_buf = [0u8; NAME_BUF_SIZE];
+    if let Some(symbol) = symbolizer.and_then(|s| s.resolve(lookup, &mut name_buf)) {
+        out.write_str("  ")?;
+        demangle(symbol.name, out)?;
+        write!(out, " + {:#x}", addr - symbol.address)?;
+    }
+    writeln!(out)
+}
+
+/// Write every frame of `frames`, numbering from `first_depth`
+pub fn write_frames(
+    out: &mut impl Write,
+    frames: Frames,
+    first_depth: usize,
+    symbolizer: Option<&Symbolizer>,
+) -> fmt::Result {
+    for (i, addr) in frames.enumerate() {
+        write_frame(out, first_depth + i, addr, true, symbolizer)?;
+    }
+    Ok(())
+}
+
+/// Print a symbolized backtrace of the caller to stderr
+#[cfg(feature = "io")]
+#[inline(always)]
+pub fn print() {
+    let frames = Frames::current();
+    let symbolizer = Symbolizer::open_self().ok();
+    let mut err = nextstep_io::stderr();
+    let _ = writeln!(err, "backtrace:");
+    let _ = write_frames(&mut err, frames, 0, symbolizer.as_ref());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Buf {
+        data: [u8; 128],
+        len: usize,
+    }
+
+    impl Write for Buf {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.len + s.len();
+            self.data.get_mut(self.len..end).ok_or(fmt::Error)?.copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    fn demangled(name: &str) -> Buf {
+        let mut buf = Buf { data: [0; 128], len: 0 };
+        demangle(name, &mut buf).unwrap();
+        buf
+    }
+
+    fn as_str(buf: &Buf) -> &str {
+        core::str::from_utf8(&buf.data[..buf.len]).unwrap()
+    }
+
+    #[test]
+    fn test_demangle_legacy() {
+        let buf = demangled("_ZN4core9panicking5panic17h3a2f8b4c9d1e0f7aE");
+        assert_eq!(as_str(&buf), "core::panicking::panic");
+    }
+
+    #[test]
+    fn test_demangle_escapes() {
+        let buf = demangled("__ZN47_$LT$app..Point$u20$as$u20$core..fmt..Debug$GT$3fmt17h0123456789abcdefE");
+        assert_eq!(as_str(&buf), "<app::Point as core::fmt::Debug>::fmt");
+    }
+
+    #[test]
+    fn test_demangle_passthrough() {
+        assert_eq!(as_str(&demangled("_write")), "_write");
+        assert_eq!(as_str(&demangled("_ZN3foo")), "_ZN3foo");
+    }
+
+    #[test]
+    fn test_frames_stop_at_null() {
+        assert_eq!(Frames::from_fp(0).count(), 0);
+    }
+}
@@ -0,0 +1,149 @@
+//! Address-to-symbol lookup through the executable's Mach-O `LC_SYMTAB`
+//!
+//! NeXT `ld` does not map the symbol table into memory, so the lookup reads
+//! the executable file directly. Everything works on fixed-size stack
+//! buffers; no allocator is needed, which keeps it usable from panic and
+//! crash handlers.
+
+use nextstep_sys::*;
+
+// Mach-O constants (big-endian m68k layout)
+const MH_MAGIC: u32 = 0xfeed_face;
+const MACH_HEADER_SIZE: usize = 28;
+const LC_SYMTAB: u32 = 0x2;
+const SYMTAB_COMMAND_SIZE: usize = 24;
+const NLIST_SIZE: usize = 12;
+const N_STAB: u8 = 0xe0;
+const N_TYPE: u8 = 0x0e;
+const N_SECT: u8 = 0x0e;
+
+// Symbol table entries read per chunk
+const NLIST_CHUNK: usize = 32;
+
+/// A symbol covering a looked-up address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol<'a> {
+    /// Raw (mangled) symbol name
+    pub name: &'a str,
+    /// Address of the symbol
+    pub address: usize,
+    /// Distance from the symbol to the looked-up address
+    pub offset: usize,
+}
+
+/// Symbol table reader for a Mach-O executable on disk
+pub struct Symbolizer {
+    fd: c_int,
+    symoff: u32,
+    nsyms: u32,
+    stroff: u32,
+    strsize: u32,
+}
+
+#[inline]
+fn be32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+impl Symbolizer {
+    /// Open the executable at `path` (NUL-terminated) and locate its symbol table
+    pub fn open(path: &[u8]) -> Result<Symbolizer, i32> {
+        let fd = sys_open(path, O_RDONLY, 0)?;
+        let mut symbolizer = Symbolizer { fd, symoff: 0, nsyms: 0, stroff: 0, strsize: 0 };
+
+        let mut header = [0u8; MACH_HEADER_SIZE];
+        symbolizer.read_at(0, &mut header)?;
+        if be32(&header, 0) != MH_MAGIC {
+            return Err(ENOEXEC);
+        }
+
+        let ncmds = be32(&header, 16);
+        let mut offset = MACH_HEADER_SIZE as u32;
+        for _ in 0..ncmds {
+            let mut cmd = [0u8; SYMTAB_COMMAND_SIZE];
+            symbolizer.read_at(offset, &mut cmd[..8])?;
+            let (kind, size) = (be32(&cmd, 0), be32(&cmd, 4));
+            if kind == LC_SYMTAB {
+                symbolizer.read_at(offset, &mut cmd)?;
+                symbolizer.symoff = be32(&cmd, 8);
+                symbolizer.nsyms = be32(&cmd, 12);
+                symbolizer.stroff = be32(&cmd, 16);
+                symbolizer.strsize = be32(&cmd, 20);
+                return Ok(symbolizer);
+            }
+            if size < 8 {
+                return Err(ENOEXEC);
+            }
+            offset += size;
+        }
+        Err(ENOEXEC)
+    }
+
+    /// Open the running executable, as registered with [`crate::set_executable_path`]
+    pub fn open_self() -> Result<Symbolizer, i32> {
+        match crate::executable_path() {
+            Some(path) => Symbolizer::open(path),
+            None => Err(ENOENT),
+        }
+    }
+
+    /// Find the closest section symbol at or below `addr`
+    ///
+    /// The symbol name is copied into `name_buf` and truncated to fit.
+    pub fn resolve<'a>(&self, addr: usize, name_buf: &'a mut [u8]) -> Option<Symbol<'a>> {
+        let mut best: Option<(u32, u32)> = None;
+        let mut chunk = [0u8; NLIST_SIZE * NLIST_CHUNK];
+        let mut index = 0;
+
+        while index < self.nsyms {
+            let count = core::cmp::min(NLIST_CHUNK as u32, self.nsyms - index) as usize;
+            let entries = &mut chunk[..count * NLIST_SIZE];
+            self.read_at(self.symoff + index * NLIST_SIZE as u32, entries).ok()?;
+
+            for entry in entries.chunks_exact(NLIST_SIZE) {
+                let n_type = entry[4];
+                if n_type & N_STAB != 0 || n_type & N_TYPE != N_SECT {
+                    continue;
+                }
+                let value = be32(entry, 8);
+                if value as usize <= addr && !matches!(best, Some((v, _)) if v >= value) {
+                    best = Some((value, be32(entry, 0)));
+                }
+            }
+            index += count as u32;
+        }
+
+        let (value, strx) = best?;
+        let name = self.read_name(strx, name_buf)?;
+        Some(Symbol { name, address: value as usize, offset: addr - value as usize })
+    }
+
+    fn read_name<'a>(&self, strx: u32, buf: &'a mut [u8]) -> Option<&'a str> {
+        if strx >= self.strsize {
+            return None;
+        }
+        let len = core::cmp::min(buf.len(), (self.strsize - strx) as usize);
+        self.read_at(self.stroff + strx, &mut buf[..len]).ok()?;
+        let end = buf[..len].iter().position(|&b| b == 0).unwrap_or(len);
+        core::str::from_utf8(&buf[..end]).ok()
+    }
+
+    fn read_at(&self, offset: u32, mut buf: &mut [u8]) -> Result<(), i32> {
+        if unsafe { lseek(self.fd, offset as off_t, SEEK_SET) } < 0 {
+            return Err(EIO);
+        }
+        while !buf.is_empty() {
+            match sys_read(self.fd, buf)? {
+                0 => return Err(EIO),
+                n => buf = &mut buf[n..],
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Symbolizer {
+    fn drop(&mut self) {
+        let _ = sys_close(self.fd);
+    }
+}
@@ -0,0 +1,193 @@
+//! nextstep-crash - Opt-in crash reporter for NeXTSTEP
+//!
+//! Turns bus errors, segmentation faults and traps into a readable report
+//! on stderr instead of a silent "Bus error". Installs 4.3BSD `sigvec`
+//! handlers for the hardware fault signals and, when one fires, prints the
+//! signal code, the registers saved in the `sigcontext` and a symbolized
+//! frame-pointer backtrace (via `nextstep-backtrace`). It then re-raises
+//! the signal, so the parent, the shell and gdb see the process die of it
+//! and a core is written, as without the reporter.
+//!
+//! The 4.3BSD `sigcontext` only saves pc, sr, a7 and a6; the data and
+//! other address registers are not available to a signal handler.
+//!
+//! ```ignore
+//! #[no_mangle]
+//! pub extern "C" fn main() -> i32 {
+//!     let _ = nextstep_crash::install();
+//!     // ...
+//!     0
+//! }
+//! ```
+
+#![no_std]
+
+use core::fmt::{self, Write};
+use nextstep_backtrace::{write_frame, write_frames, Frames, Symbolizer};
+use nextstep_sys::*;
+
+/// Signals treated as crashes
+pub const CRASH_SIGNALS: [c_int; 6] = [SIGBUS, SIGSEGV, SIGILL, SIGFPE, SIGTRAP, SIGEMT];
+
+/// Base of the exit status used if re-raising a crash signal fails
+/// (`128 + signal`, as shells report it)
+pub const CRASH_EXIT_BASE: i32 = 128;
+
+// Alternate signal stack, so stack overflows can still be reported
+const ALT_STACK_SIZE: usize = 8192;
+
+#[repr(C, align(4))]
+struct AltStack([u8; ALT_STACK_SIZE]);
+
+static mut ALT_STACK: AltStack = AltStack([0; ALT_STACK_SIZE]);
+
+/// Unbuffered stderr writer that is safe to use inside a signal handler
+struct RawStderr;
+
+impl Write for RawStderr {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut buf = s.as_bytes();
+        while !buf.is_empty() {
+            match sys_write(STDERR_FILENO, buf) {
+                Ok(0) | Err(_) => return Err(fmt::Error),
+                Ok(n) => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Install the crash handler for every signal in [`CRASH_SIGNALS`]
+///
+/// The handler runs on a private alternate stack and is reset to the
+/// default action on entry, so a fault inside the reporter terminates
+/// the process instead of recursing.
+pub fn install() -> Result<(), i32> {
+    let stack = sigstack {
+        ss_sp: unsafe {
+            // Stacks grow down on m68k: hand the kernel the top address
+            core::ptr::addr_of_mut!(ALT_STACK.0)
+                .cast::<u8>()
+                .add(ALT_STACK_SIZE)
+                .cast()
+        },
+        ss_onstack: 0,
+    };
+    if unsafe { nextstep_sys::sigstack(&stack, core::ptr::null_mut()) } < 0 {
+        return Err(-1);
+    }
+
+    let mask = CRASH_SIGNALS.iter().fold(0, |m, &sig| m | sigmask(sig));
+    let handler: sig_handler_t = crash_handler;
+    let vec = nextstep_sys::sigvec {
+        sv_handler: handler as usize,
+        sv_mask: mask,
+        sv_flags: SV_ONSTACK | SV_RESETHAND,
+    };
+
+    for &sig in CRASH_SIGNALS.iter() {
+        if unsafe { nextstep_sys::sigvec(sig, &vec, core::ptr::null_mut()) } < 0 {
+            return Err(-1);
+        }
+    }
+    Ok(())
+}
+
+/// Short name of a crash signal
+pub fn signal_name(sig: c_int) -> &'static str {
+    match sig {
+        SIGBUS => "SIGBUS",
+        SIGSEGV => "SIGSEGV",
+        SIGILL => "SIGILL",
+        SIGFPE => "SIGFPE",
+        SIGTRAP => "SIGTRAP",
+        SIGEMT => "SIGEMT",
+        _ => "signal",
+    }
+}
+
+/// Human-readable description of a crash signal
+pub fn signal_description(sig: c_int) -> &'static str {
+    match sig {
+        SIGBUS => "bus error",
+        SIGSEGV => "segmentation fault",
+        SIGILL => "illegal instruction (unreachable code or bad jump)",
+        SIGFPE => "arithmetic exception",
+        SIGTRAP => "trace/breakpoint trap",
+        SIGEMT => "emulator trap",
+        _ => "unexpected signal",
+    }
+}
+
+fn report(out: &mut impl Write, sig: c_int, code: c_int, scp: Option<&sigcontext>) -> fmt::Result {
+    writeln!(out)?;
+    writeln!(out, "*** crash: {} ({}) ***", signal_name(sig), signal_description(sig))?;
+    writeln!(out, "code: {:#x}", code)?;
+
+    let scp = match scp {
+        Some(scp) => scp,
+        None => return Ok(()),
+    };
+
+    writeln!(out, "registers:")?;
+    writeln!(out, "  pc {:#010x}  sr {:#06x}", scp.sc_pc as u32, scp.sc_ps as u16)?;
+    writeln!(out, "  a7 {:#010x}  a6 {:#010x}", scp.sc_sp as u32, scp.sc_fp as u32)?;
+    writeln!(out, "  signal mask {:#010x}  onstack {}", scp.sc_mask as u32, scp.sc_onstack)?;
+
+    // Symbolized when the runtime registered the executable path
+    let symbolizer = Symbolizer::open_self().ok();
+    writeln!(out, "backtrace:")?;
+    write_frame(out, 0, scp.sc_pc as usize, false, symbolizer.as_ref())?;
+    write_frames(out, Frames::from_fp(scp.sc_fp as usize), 1, symbolizer.as_ref())
+}
+
+unsafe extern "C" fn crash_handler(sig: c_int, code: c_int, scp: *mut sigcontext) {
+    let _ = report(&mut RawStderr, sig, code, scp.as_ref());
+
+    // SV_RESETHAND restored the default action: deliver the signal again
+    // by unblocking it, bypassing exit handlers on a possibly corrupt heap
+    kill(getpid(), sig);
+    sigsetmask(sigblock(0) & !sigmask(sig));
+    sys_exit(CRASH_EXIT_BASE + sig);
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::String;
+
+    #[test]
+    fn test_report_without_context() {
+        let mut out = String::new();
+        report(&mut out, SIGILL, 3, None).unwrap();
+        assert_eq!(out, "\n*** crash: SIGILL (illegal instruction (unreachable code or bad jump)) ***\ncode: 0x3\n");
+    }
+
+    #[test]
+    fn test_report_registers() {
+        let scp = sigcontext {
+            sc_onstack: 1,
+            sc_mask: 0x400,
+            sc_sp: 0x0400_1f00,
+            sc_fp: 0,
+            sc_ap: 0,
+            sc_pc: 0x0000_2a4c,
+            sc_ps: 0x2704,
+        };
+        let mut out = String::new();
+        report(&mut out, SIGBUS, 0x10, Some(&scp)).unwrap();
+
+        let lines: std::vec::Vec<&str> = out.lines().collect();
+        assert_eq!(lines[1], "*** crash: SIGBUS (bus error) ***");
+        assert_eq!(lines[2], "code: 0x10");
+        assert_eq!(lines[4], "  pc 0x00002a4c  sr 0x2704");
+        assert_eq!(lines[5], "  a7 0x04001f00  a6 0x00000000");
+        assert_eq!(lines[6], "  signal mask 0x00000400  onstack 1");
+        assert_eq!(lines[7], "backtrace:");
+        // The faulting pc; a null a6 ends the walk there
+        assert!(lines[8].contains("0x00002a4c"), "{}", lines[8]);
+        assert_eq!(lines.len(), 9);
+    }
+}
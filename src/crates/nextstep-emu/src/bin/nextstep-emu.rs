@@ -0,0 +1,107 @@
+//! nextstep-emu - Run an m68k NeXTSTEP executable on the host
+//!
+//! Usage: nextstep-emu [OPTIONS] EXECUTABLE [ARGS...]
+//!
+//!   --timeout SECS         Give up after SECS (no limit)
+//!   --strace               Log every system call to stderr
+//!   -E VAR=VALUE           Set VAR in the program's environment
+//!   --clear-env            Start from an empty environment, not ours
+//!
+//! The program's stdin, stdout and stderr are ours, and its exit status
+//! is ours. Killed by a signal (a fault, an illegal instruction, `kill`)
+//! it exits 128 plus the signal number; 124 means it timed out, 125 that
+//! it could not be loaded.
+
+use nextstep_emu::{Exit, Options, Process};
+use std::os::unix::ffi::OsStringExt;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+const USAGE: &str = "usage: nextstep-emu [--timeout SECS] [--strace] [-E VAR=VALUE]... [--clear-env]
+                    EXECUTABLE [ARGS...]";
+
+const TIMED_OUT: u8 = 124;
+const LOAD_FAILURE: u8 = 125;
+
+struct Args {
+    options: Options,
+    timeout: Option<Duration>,
+    executable: PathBuf,
+}
+
+fn value(args: &mut impl Iterator<Item = String>) -> Result<String, String> {
+    args.next().ok_or_else(|| USAGE.to_string())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut options = Options::default();
+    let mut timeout = None;
+    let mut clear_env = false;
+    let mut set = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--timeout" => {
+                let secs = value(&mut args)?;
+                let secs: u64 = secs.parse().map_err(|_| format!("bad timeout {:?}\n{}", secs, USAGE))?;
+                timeout = Some(Duration::from_secs(secs));
+            }
+            "--strace" => options.strace = true,
+            "-E" => {
+                let var = value(&mut args)?;
+                if !var.contains('=') {
+                    return Err(format!("-E wants VAR=VALUE, not {:?}\n{}", var, USAGE));
+                }
+                set.push(var.into_bytes());
+            }
+            "--clear-env" => clear_env = true,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ => {
+                options.args = std::iter::once(arg.clone()).chain(args).map(String::into_bytes).collect();
+                if !clear_env {
+                    options.env = std::env::vars_os()
+                        .map(|(name, value)| [name.into_vec(), b"=".to_vec(), value.into_vec()].concat())
+                        .collect();
+                }
+                for var in set {
+                    let name = &var[..=var.iter().position(|&b| b == b'=').unwrap_or(0)];
+                    options.env.retain(|old| !old.starts_with(name));
+                    options.env.push(var);
+                }
+                return Ok(Args { options, timeout, executable: arg.into() });
+            }
+        }
+    }
+    Err(USAGE.to_string())
+}
+
+fn run(args: &Args) -> Result<Exit, String> {
+    let mut process = Process::open(&args.executable, &args.options).map_err(|e| e.to_string())?;
+    Ok(process.run(args.timeout))
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::from(2);
+        }
+    };
+    match run(&args) {
+        Ok(Exit::Exited(status)) => ExitCode::from(status as u8),
+        Ok(Exit::Signaled { signal, reason }) => {
+            eprintln!("nextstep-emu: {}: killed by signal {}: {}", args.executable.display(), signal, reason);
+            ExitCode::from(128 + signal as u8)
+        }
+        Ok(Exit::TimedOut) => {
+            eprintln!("nextstep-emu: {}: timed out", args.executable.display());
+            ExitCode::from(TIMED_OUT)
+        }
+        Err(message) => {
+            eprintln!("nextstep-emu: {}", message);
+            ExitCode::from(LOAD_FAILURE)
+        }
+    }
+}
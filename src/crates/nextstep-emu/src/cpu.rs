@@ -0,0 +1,1587 @@
+//! The 68030/68040 integer unit, in user mode
+//!
+//! Everything the compiler and hand-written assembly use is here: all
+//! addressing modes including the 68020 full extension formats, the
+//! integer, BCD, bit, bit-field, `MOVEM`, `CAS`/`CAS2`, `CHK2`/`CMP2`
+//! and 64-bit multiply and divide instructions. Supervisor instructions
+//! raise a privilege violation, as they would in a NeXTSTEP process; the
+//! FPU, MMU and `MOVE16` (line F) and line A are not emulated and raise
+//! their exceptions. The targets are soft-float, so code from rustc has
+//! no line F instructions.
+
+use crate::memory::{Fault, Memory};
+
+/// Why the CPU stopped before finishing an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    /// `TRAP #n`; the pc is past the instruction
+    Trap(u8),
+    /// `TRAPV` or `TRAPcc` with the condition true
+    TrapV,
+    /// `CHK` or `CHK2` out of bounds
+    Chk,
+    ZeroDivide,
+    /// An opcode the CPU does not have
+    Illegal(u16),
+    /// A supervisor instruction
+    Privilege(u16),
+    LineA(u16),
+    LineF(u16),
+    /// An instruction fetch from an odd address
+    AddressError(u32),
+    BusError(Fault),
+}
+
+impl From<Fault> for Exception {
+    fn from(fault: Fault) -> Exception {
+        Exception::BusError(fault)
+    }
+}
+
+type Step<T> = Result<T, Exception>;
+
+/// Operand size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Size {
+    Byte,
+    Word,
+    Long,
+}
+
+impl Size {
+    // The usual two-bit size field
+    fn from_bits(bits: u16) -> Option<Size> {
+        match bits & 3 {
+            0 => Some(Size::Byte),
+            1 => Some(Size::Word),
+            2 => Some(Size::Long),
+            _ => None,
+        }
+    }
+
+    pub fn bytes(self) -> u32 {
+        match self {
+            Size::Byte => 1,
+            Size::Word => 2,
+            Size::Long => 4,
+        }
+    }
+
+    fn mask(self) -> u32 {
+        match self {
+            Size::Byte => 0xff,
+            Size::Word => 0xffff,
+            Size::Long => 0xffff_ffff,
+        }
+    }
+
+    fn msb(self) -> u32 {
+        match self {
+            Size::Byte => 0x80,
+            Size::Word => 0x8000,
+            Size::Long => 0x8000_0000,
+        }
+    }
+
+    /// `value` sign-extended from this size to 32 bits
+    fn sext(self, value: u32) -> u32 {
+        match self {
+            Size::Byte => value as i8 as u32,
+            Size::Word => value as i16 as u32,
+            Size::Long => value,
+        }
+    }
+}
+
+// A decoded effective address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ea {
+    D(usize),
+    A(usize),
+    Mem(u32),
+    Imm(u32),
+}
+
+/// User-visible registers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cpu {
+    pub d: [u32; 8],
+    /// `a[7]` is the user stack pointer
+    pub a: [u32; 8],
+    pub pc: u32,
+    pub x: bool,
+    pub n: bool,
+    pub z: bool,
+    pub v: bool,
+    pub c: bool,
+    /// Address of the instruction being executed, or last executed
+    pub insn_pc: u32,
+}
+
+fn illegal<T>(op: u16) -> Step<T> {
+    Err(Exception::Illegal(op))
+}
+
+impl Cpu {
+    /// The condition code register
+    pub fn ccr(&self) -> u8 {
+        (self.x as u8) << 4 | (self.n as u8) << 3 | (self.z as u8) << 2 | (self.v as u8) << 1 | self.c as u8
+    }
+
+    pub fn set_ccr(&mut self, ccr: u8) {
+        self.x = ccr & 0x10 != 0;
+        self.n = ccr & 8 != 0;
+        self.z = ccr & 4 != 0;
+        self.v = ccr & 2 != 0;
+        self.c = ccr & 1 != 0;
+    }
+
+    pub fn push32(&mut self, mem: &mut Memory, value: u32) -> Result<(), Fault> {
+        self.a[7] = self.a[7].wrapping_sub(4);
+        mem.write_u32(self.a[7], value)
+    }
+
+    pub fn pop32(&mut self, mem: &Memory) -> Result<u32, Fault> {
+        let value = mem.read_u32(self.a[7])?;
+        self.a[7] = self.a[7].wrapping_add(4);
+        Ok(value)
+    }
+
+    fn fetch16(&mut self, mem: &Memory) -> Step<u16> {
+        let word = mem.read_u16(self.pc)?;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(word)
+    }
+
+    fn fetch32(&mut self, mem: &Memory) -> Step<u32> {
+        let long = mem.read_u32(self.pc)?;
+        self.pc = self.pc.wrapping_add(4);
+        Ok(long)
+    }
+
+    fn immediate(&mut self, mem: &Memory, size: Size) -> Step<u32> {
+        match size {
+            Size::Byte => Ok(self.fetch16(mem)? as u32 & 0xff),
+            Size::Word => Ok(self.fetch16(mem)? as u32),
+            Size::Long => self.fetch32(mem),
+        }
+    }
+
+    fn cond(&self, cc: u16) -> bool {
+        match cc & 15 {
+            0 => true,
+            1 => false,
+            2 => !self.c && !self.z,
+            3 => self.c || self.z,
+            4 => !self.c,
+            5 => self.c,
+            6 => !self.z,
+            7 => self.z,
+            8 => !self.v,
+            9 => self.v,
+            10 => !self.n,
+            11 => self.n,
+            12 => self.n == self.v,
+            13 => self.n != self.v,
+            14 => !self.z && self.n == self.v,
+            _ => self.z || self.n != self.v,
+        }
+    }
+
+    // Brief or full extension word addressing, `base` being An or the
+    // address of the extension word
+    fn indexed(&mut self, mem: &Memory, base: u32) -> Step<u32> {
+        let ext = self.fetch16(mem)?;
+        let reg = (ext >> 12) as usize & 7;
+        let mut index = if ext & 0x8000 != 0 { self.a[reg] } else { self.d[reg] };
+        if ext & 0x800 == 0 {
+            index = index as i16 as u32;
+        }
+        index = index.wrapping_shl((ext as u32 >> 9) & 3);
+        if ext & 0x100 == 0 {
+            return Ok(base.wrapping_add(index).wrapping_add(ext as i8 as u32));
+        }
+
+        let base = if ext & 0x80 != 0 { 0 } else { base };
+        let index = if ext & 0x40 != 0 { 0 } else { index };
+        let displacement = |cpu: &mut Cpu, size: u16| -> Step<u32> {
+            match size {
+                0 | 1 => Ok(0),
+                2 => Ok(cpu.fetch16(mem)? as i16 as u32),
+                _ => cpu.fetch32(mem),
+            }
+        };
+        let bd = displacement(self, (ext >> 4) & 3)?;
+        let iis = ext & 7;
+        if iis == 0 {
+            return Ok(base.wrapping_add(bd).wrapping_add(index));
+        }
+        if ext & 0x40 != 0 && iis & 4 != 0 {
+            return illegal(ext);
+        }
+        let od = displacement(self, iis & 3)?;
+        if iis & 4 == 0 {
+            // Preindexed
+            let pointer = mem.read_u32(base.wrapping_add(bd).wrapping_add(index))?;
+            Ok(pointer.wrapping_add(od))
+        } else {
+            // Postindexed
+            let pointer = mem.read_u32(base.wrapping_add(bd))?;
+            Ok(pointer.wrapping_add(index).wrapping_add(od))
+        }
+    }
+
+    fn ea(&mut self, mem: &Memory, mode: u16, reg: u16, size: Size) -> Step<Ea> {
+        let r = reg as usize & 7;
+        // Byte pushes and pops keep the stack pointer even
+        let step = if r == 7 && size == Size::Byte { 2 } else { size.bytes() };
+        Ok(match mode & 7 {
+            0 => Ea::D(r),
+            1 => Ea::A(r),
+            2 => Ea::Mem(self.a[r]),
+            3 => {
+                let address = self.a[r];
+                self.a[r] = address.wrapping_add(step);
+                Ea::Mem(address)
+            }
+            4 => {
+                self.a[r] = self.a[r].wrapping_sub(step);
+                Ea::Mem(self.a[r])
+            }
+            5 => Ea::Mem(self.a[r].wrapping_add(self.fetch16(mem)? as i16 as u32)),
+            6 => Ea::Mem(self.indexed(mem, self.a[r])?),
+            _ => match r {
+                0 => Ea::Mem(self.fetch16(mem)? as i16 as u32),
+                1 => Ea::Mem(self.fetch32(mem)?),
+                2 => {
+                    let base = self.pc;
+                    Ea::Mem(base.wrapping_add(self.fetch16(mem)? as i16 as u32))
+                }
+                3 => Ea::Mem(self.indexed(mem, self.pc)?),
+                4 => Ea::Imm(self.immediate(mem, size)?),
+                _ => return illegal(0),
+            },
+        })
+    }
+
+    // A control addressing mode: a memory address with no side effects
+    fn control_ea(&mut self, mem: &Memory, op: u16) -> Step<u32> {
+        let (mode, reg) = ((op >> 3) & 7, op & 7);
+        if matches!(mode, 0 | 1 | 3 | 4) || mode == 7 && reg > 3 {
+            return illegal(op);
+        }
+        match self.ea(mem, mode, reg, Size::Long)? {
+            Ea::Mem(address) => Ok(address),
+            _ => illegal(op),
+        }
+    }
+
+    fn read(&self, mem: &Memory, ea: Ea, size: Size) -> Step<u32> {
+        Ok(match ea {
+            Ea::D(r) => self.d[r] & size.mask(),
+            Ea::A(r) => self.a[r] & size.mask(),
+            Ea::Imm(value) => value & size.mask(),
+            Ea::Mem(address) => match size {
+                Size::Byte => mem.read_u8(address)? as u32,
+                Size::Word => mem.read_u16(address)? as u32,
+                Size::Long => mem.read_u32(address)?,
+            },
+        })
+    }
+
+    fn write(&mut self, mem: &mut Memory, ea: Ea, size: Size, value: u32) -> Step<()> {
+        match ea {
+            Ea::D(r) => self.d[r] = self.d[r] & !size.mask() | value & size.mask(),
+            Ea::A(r) => self.a[r] = size.sext(value & size.mask()),
+            Ea::Imm(_) => return illegal(0),
+            Ea::Mem(address) => match size {
+                Size::Byte => mem.write_u8(address, value as u8)?,
+                Size::Word => mem.write_u16(address, value as u16)?,
+                Size::Long => mem.write_u32(address, value)?,
+            },
+        }
+        Ok(())
+    }
+
+    fn logic_flags(&mut self, size: Size, result: u32) {
+        self.n = result & size.msb() != 0;
+        self.z = result & size.mask() == 0;
+        self.v = false;
+        self.c = false;
+    }
+
+    // `dst + src (+ X)`, setting every flag
+    fn add(&mut self, size: Size, src: u32, dst: u32, extend: bool) -> u32 {
+        let (src, dst) = (src & size.mask(), dst & size.mask());
+        let wide = src as u64 + dst as u64 + (extend && self.x) as u64;
+        let result = wide as u32 & size.mask();
+        self.c = wide > size.mask() as u64;
+        self.x = self.c;
+        self.v = (src ^ result) & (dst ^ result) & size.msb() != 0;
+        self.n = result & size.msb() != 0;
+        self.z = if extend { self.z && result == 0 } else { result == 0 };
+        result
+    }
+
+    // `dst - src (- X)`, setting every flag
+    fn sub(&mut self, size: Size, src: u32, dst: u32, extend: bool) -> u32 {
+        let (src, dst) = (src & size.mask(), dst & size.mask());
+        let borrow = (extend && self.x) as u64;
+        let result = (dst as u64).wrapping_sub(src as u64).wrapping_sub(borrow) as u32 & size.mask();
+        self.c = src as u64 + borrow > dst as u64;
+        self.x = self.c;
+        self.v = (src ^ dst) & (result ^ dst) & size.msb() != 0;
+        self.n = result & size.msb() != 0;
+        self.z = if extend { self.z && result == 0 } else { result == 0 };
+        result
+    }
+
+    fn cmp(&mut self, size: Size, src: u32, dst: u32) {
+        let x = self.x;
+        self.sub(size, src, dst, false);
+        self.x = x;
+    }
+
+    /// Execute one instruction
+    ///
+    /// On an exception other than a trap the pc is left at the
+    /// instruction, as the exception frame would have it.
+    pub fn step(&mut self, mem: &mut Memory) -> Result<(), Exception> {
+        self.insn_pc = self.pc;
+        if self.pc & 1 != 0 {
+            return Err(Exception::AddressError(self.pc));
+        }
+        let result = self.fetch16(mem).and_then(|op| match op >> 12 {
+            0x0 => self.line0(mem, op),
+            0x1..=0x3 => self.move_(mem, op),
+            0x4 => self.line4(mem, op),
+            0x5 => self.line5(mem, op),
+            0x6 => self.branch(mem, op),
+            0x7 if op & 0x100 == 0 => {
+                let value = op as i8 as u32;
+                self.d[(op >> 9) as usize & 7] = value;
+                self.logic_flags(Size::Long, value);
+                Ok(())
+            }
+            0x8 => self.line8(mem, op),
+            0x9 | 0xd => self.add_sub(mem, op),
+            0xb => self.line_b(mem, op),
+            0xc => self.line_c(mem, op),
+            0xe => self.line_e(mem, op),
+            0xa => Err(Exception::LineA(op)),
+            0xf => Err(Exception::LineF(op)),
+            _ => illegal(op),
+        });
+        if let Err(exception) = result {
+            if !matches!(exception, Exception::Trap(_) | Exception::TrapV | Exception::Chk | Exception::ZeroDivide) {
+                self.pc = self.insn_pc;
+            }
+            return Err(exception);
+        }
+        Ok(())
+    }
+
+    // Immediate arithmetic, bit operations, MOVEP, CAS, CAS2, CMP2, CHK2
+    fn line0(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let (mode, reg) = ((op >> 3) & 7, op & 7);
+        if op & 0x138 == 0x108 {
+            return self.movep(mem, op);
+        }
+        if op & 0x100 != 0 {
+            let bit = self.d[(op >> 9) as usize & 7];
+            return self.bit_op(mem, op, bit);
+        }
+        if op & 0xff00 == 0x0800 {
+            let bit = self.fetch16(mem)? as u32 & 0xff;
+            return self.bit_op(mem, op, bit);
+        }
+        let Some(size) = Size::from_bits(op >> 6) else {
+            return match op & 0xffc0 {
+                0x0ac0 | 0x0cc0 | 0x0ec0 if op & 0x3f == 0x3c => self.cas2(mem, op),
+                0x0ac0 | 0x0cc0 | 0x0ec0 => self.cas(mem, op),
+                0x00c0 | 0x02c0 | 0x04c0 => self.chk2(mem, op),
+                _ => illegal(op),
+            };
+        };
+        let kind = (op >> 9) & 7;
+        if kind == 7 {
+            return Err(Exception::Privilege(op));
+        }
+        if !matches!(kind, 0 | 1 | 2 | 3 | 5 | 6) {
+            return illegal(op);
+        }
+        if op & 0x3f == 0x3c {
+            // ORI, ANDI, EORI to CCR or SR
+            let value = self.fetch16(mem)? as u8;
+            return match (kind, size) {
+                (_, Size::Word) => Err(Exception::Privilege(op)),
+                (0, Size::Byte) => {
+                    self.set_ccr(self.ccr() | value);
+                    Ok(())
+                }
+                (1, Size::Byte) => {
+                    self.set_ccr(self.ccr() & value);
+                    Ok(())
+                }
+                (5, Size::Byte) => {
+                    self.set_ccr(self.ccr() ^ value);
+                    Ok(())
+                }
+                _ => illegal(op),
+            };
+        }
+        let imm = self.immediate(mem, size)?;
+        let ea = self.ea(mem, mode, reg, size)?;
+        if mode == 1 {
+            return illegal(op);
+        }
+        let dst = self.read(mem, ea, size)?;
+        let result = match kind {
+            0 => imm | dst,
+            1 => imm & dst,
+            2 => self.sub(size, imm, dst, false),
+            3 => self.add(size, imm, dst, false),
+            5 => imm ^ dst,
+            _ => {
+                self.cmp(size, imm, dst);
+                return Ok(());
+            }
+        };
+        if matches!(kind, 0 | 1 | 5) {
+            self.logic_flags(size, result);
+        }
+        self.write(mem, ea, size, result)
+    }
+
+    // BTST, BCHG, BCLR, BSET with the bit number in `bit`
+    fn bit_op(&mut self, mem: &mut Memory, op: u16, bit: u32) -> Step<()> {
+        let (mode, reg, kind) = ((op >> 3) & 7, op & 7, (op >> 6) & 3);
+        if mode == 0 {
+            let r = reg as usize;
+            let mask = 1 << (bit & 31);
+            self.z = self.d[r] & mask == 0;
+            match kind {
+                1 => self.d[r] ^= mask,
+                2 => self.d[r] &= !mask,
+                3 => self.d[r] |= mask,
+                _ => {}
+            }
+            return Ok(());
+        }
+        if mode == 1 {
+            return illegal(op);
+        }
+        let ea = self.ea(mem, mode, reg, Size::Byte)?;
+        let value = self.read(mem, ea, Size::Byte)?;
+        let mask = 1 << (bit & 7);
+        self.z = value & mask == 0;
+        let result = match kind {
+            0 => return Ok(()),
+            1 => value ^ mask,
+            2 => value & !mask,
+            _ => value | mask,
+        };
+        self.write(mem, ea, Size::Byte, result)
+    }
+
+    fn movep(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let r = (op >> 9) as usize & 7;
+        let address = self.a[op as usize & 7].wrapping_add(self.fetch16(mem)? as i16 as u32);
+        let count = if op & 0x40 != 0 { 4 } else { 2 };
+        if op & 0x80 != 0 {
+            for i in 0..count {
+                let byte = (self.d[r] >> (8 * (count - 1 - i))) as u8;
+                mem.write_u8(address.wrapping_add(2 * i), byte)?;
+            }
+        } else {
+            let mut value = 0;
+            for i in 0..count {
+                value = value << 8 | mem.read_u8(address.wrapping_add(2 * i))? as u32;
+            }
+            let size = if count == 4 { Size::Long } else { Size::Word };
+            self.write(mem, Ea::D(r), size, value)?;
+        }
+        Ok(())
+    }
+
+    fn cas(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let size = Size::from_bits(((op >> 9) & 3) - 1).ok_or(Exception::Illegal(op))?;
+        let ext = self.fetch16(mem)?;
+        let (dc, du) = (ext as usize & 7, (ext >> 6) as usize & 7);
+        let (mode, reg) = ((op >> 3) & 7, op & 7);
+        if mode < 2 {
+            return illegal(op);
+        }
+        let ea = self.ea(mem, mode, reg, size)?;
+        let value = self.read(mem, ea, size)?;
+        self.cmp(size, self.d[dc], value);
+        if self.z {
+            self.write(mem, ea, size, self.d[du])
+        } else {
+            self.write(mem, Ea::D(dc), size, value)
+        }
+    }
+
+    fn cas2(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let size = match op & 0xffc0 {
+            0x0cc0 => Size::Word,
+            0x0ec0 => Size::Long,
+            _ => return illegal(op),
+        };
+        let ext = [self.fetch16(mem)?, self.fetch16(mem)?];
+        let reg = |cpu: &Cpu, ext: u16| {
+            let r = (ext >> 12) as usize & 7;
+            if ext & 0x8000 != 0 {
+                cpu.a[r]
+            } else {
+                cpu.d[r]
+            }
+        };
+        let addresses = [reg(self, ext[0]), reg(self, ext[1])];
+        let values = [self.read(mem, Ea::Mem(addresses[0]), size)?, self.read(mem, Ea::Mem(addresses[1]), size)?];
+        self.cmp(size, self.d[ext[0] as usize & 7], values[0]);
+        if self.z {
+            self.cmp(size, self.d[ext[1] as usize & 7], values[1]);
+        }
+        if self.z {
+            for i in 0..2 {
+                let update = self.d[(ext[i] >> 6) as usize & 7];
+                self.write(mem, Ea::Mem(addresses[i]), size, update)?;
+            }
+        } else {
+            for i in 0..2 {
+                self.write(mem, Ea::D(ext[i] as usize & 7), size, values[i])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn chk2(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let size = Size::from_bits(op >> 9).ok_or(Exception::Illegal(op))?;
+        let ext = self.fetch16(mem)?;
+        let address = self.control_ea(mem, op)?;
+        let r = (ext >> 12) as usize & 7;
+        let lower = self.read(mem, Ea::Mem(address), size)?;
+        let upper = self.read(mem, Ea::Mem(address.wrapping_add(size.bytes())), size)?;
+        // Address registers compare all 32 bits against sign-extended bounds
+        let (value, lower, upper, size) = if ext & 0x8000 != 0 {
+            (self.a[r], size.sext(lower), size.sext(upper), Size::Long)
+        } else {
+            (self.d[r] & size.mask(), lower, upper, size)
+        };
+        // Bounds that are ordered as signed numbers are compared signed
+        let signed = |v: u32| size.sext(v) as i32;
+        let out = if signed(lower) <= signed(upper) {
+            signed(value) < signed(lower) || signed(value) > signed(upper)
+        } else {
+            value < lower || value > upper
+        };
+        self.z = value == lower || value == upper;
+        self.c = out;
+        if out && ext & 0x800 != 0 {
+            return Err(Exception::Chk);
+        }
+        Ok(())
+    }
+
+    // MOVE and MOVEA
+    fn move_(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let size = match op >> 12 {
+            1 => Size::Byte,
+            3 => Size::Word,
+            _ => Size::Long,
+        };
+        let src = self.ea(mem, (op >> 3) & 7, op & 7, size)?;
+        let value = self.read(mem, src, size)?;
+        let (mode, reg) = ((op >> 6) & 7, (op >> 9) & 7);
+        if mode == 1 {
+            if size == Size::Byte {
+                return illegal(op);
+            }
+            self.a[reg as usize] = size.sext(value);
+            return Ok(());
+        }
+        let dst = self.ea(mem, mode, reg, size)?;
+        if let Ea::Imm(_) = dst {
+            return illegal(op);
+        }
+        self.write(mem, dst, size, value)?;
+        self.logic_flags(size, value);
+        Ok(())
+    }
+
+    fn line4(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let (mode, reg) = ((op >> 3) & 7, op & 7);
+        let r = reg as usize;
+        match op {
+            0x4afc => return illegal(op),
+            0x4e70 | 0x4e72 | 0x4e73 | 0x4e7a | 0x4e7b => return Err(Exception::Privilege(op)),
+            0x4e71 => return Ok(()),
+            0x4e74 => {
+                let displacement = self.fetch16(mem)? as i16 as u32;
+                self.pc = self.pop32(mem)?;
+                self.a[7] = self.a[7].wrapping_add(displacement);
+                return Ok(());
+            }
+            0x4e75 => {
+                self.pc = self.pop32(mem)?;
+                return Ok(());
+            }
+            0x4e76 => return if self.v { Err(Exception::TrapV) } else { Ok(()) },
+            0x4e77 => {
+                let ccr = mem.read_u16(self.a[7])?;
+                self.a[7] = self.a[7].wrapping_add(2);
+                self.set_ccr(ccr as u8);
+                self.pc = self.pop32(mem)?;
+                return Ok(());
+            }
+            _ => {}
+        }
+        match op & 0xfff8 {
+            0x4e40 | 0x4e48 => return Err(Exception::Trap(op as u8 & 15)),
+            0x4e50 => {
+                let displacement = self.fetch16(mem)? as i16 as u32;
+                return self.link(mem, r, displacement);
+            }
+            0x4808 => {
+                let displacement = self.fetch32(mem)?;
+                return self.link(mem, r, displacement);
+            }
+            0x4e58 => {
+                self.a[7] = self.a[r];
+                self.a[r] = self.pop32(mem)?;
+                return Ok(());
+            }
+            0x4e60 | 0x4e68 => return Err(Exception::Privilege(op)),
+            0x4840 => {
+                self.d[r] = self.d[r].rotate_left(16);
+                self.logic_flags(Size::Long, self.d[r]);
+                return Ok(());
+            }
+            0x4848 => return illegal(op),
+            0x4880 => {
+                let value = self.d[r] as i8 as u16 as u32;
+                self.d[r] = self.d[r] & 0xffff_0000 | value;
+                self.logic_flags(Size::Word, value);
+                return Ok(());
+            }
+            0x48c0 => {
+                self.d[r] = self.d[r] as i16 as u32;
+                self.logic_flags(Size::Long, self.d[r]);
+                return Ok(());
+            }
+            0x49c0 => {
+                self.d[r] = self.d[r] as i8 as u32;
+                self.logic_flags(Size::Long, self.d[r]);
+                return Ok(());
+            }
+            _ => {}
+        }
+        match op & 0xffc0 {
+            0x4e80 => {
+                let target = self.control_ea(mem, op)?;
+                self.push32(mem, self.pc)?;
+                self.pc = target;
+                return Ok(());
+            }
+            0x4ec0 => {
+                self.pc = self.control_ea(mem, op)?;
+                return Ok(());
+            }
+            0x4840 => {
+                let address = self.control_ea(mem, op)?;
+                self.push32(mem, address)?;
+                return Ok(());
+            }
+            0x4800 => {
+                let ea = self.ea(mem, mode, reg, Size::Byte)?;
+                let value = self.read(mem, ea, Size::Byte)?;
+                let result = self.sbcd(value, 0);
+                return self.write(mem, ea, Size::Byte, result);
+            }
+            0x40c0 | 0x46c0 => return Err(Exception::Privilege(op)),
+            0x42c0 => {
+                let ea = self.ea(mem, mode, reg, Size::Word)?;
+                return self.write(mem, ea, Size::Word, self.ccr() as u32);
+            }
+            0x44c0 => {
+                let ea = self.ea(mem, mode, reg, Size::Word)?;
+                let value = self.read(mem, ea, Size::Word)?;
+                self.set_ccr(value as u8);
+                return Ok(());
+            }
+            0x4ac0 => {
+                let ea = self.ea(mem, mode, reg, Size::Byte)?;
+                let value = self.read(mem, ea, Size::Byte)?;
+                self.logic_flags(Size::Byte, value);
+                return self.write(mem, ea, Size::Byte, value | 0x80);
+            }
+            0x4c00 => return self.mul_long(mem, op),
+            0x4c40 => return self.div_long(mem, op),
+            _ => {}
+        }
+        if op & 0xfb80 == 0x4880 {
+            return self.movem(mem, op);
+        }
+        if op & 0xf1c0 == 0x41c0 {
+            self.a[(op >> 9) as usize & 7] = self.control_ea(mem, op)?;
+            return Ok(());
+        }
+        if op & 0xf140 == 0x4100 {
+            // CHK.W (0x4180) or CHK.L (0x4100)
+            let size = if op & 0x80 != 0 { Size::Word } else { Size::Long };
+            let ea = self.ea(mem, mode, reg, size)?;
+            let bound = size.sext(self.read(mem, ea, size)?) as i32;
+            let value = size.sext(self.d[(op >> 9) as usize & 7] & size.mask()) as i32;
+            if value < 0 || value > bound {
+                self.n = value < 0;
+                return Err(Exception::Chk);
+            }
+            return Ok(());
+        }
+        let Some(size) = Size::from_bits(op >> 6) else {
+            return illegal(op);
+        };
+        if mode == 1 && op & 0xff00 != 0x4a00 {
+            return illegal(op);
+        }
+        let ea = self.ea(mem, mode, reg, size)?;
+        match op & 0xff00 {
+            0x4000 => {
+                let value = self.read(mem, ea, size)?;
+                let result = self.sub(size, value, 0, true);
+                self.write(mem, ea, size, result)
+            }
+            0x4200 => {
+                self.logic_flags(size, 0);
+                self.write(mem, ea, size, 0)
+            }
+            0x4400 => {
+                let value = self.read(mem, ea, size)?;
+                let result = self.sub(size, value, 0, false);
+                self.write(mem, ea, size, result)
+            }
+            0x4600 => {
+                let result = !self.read(mem, ea, size)? & size.mask();
+                self.logic_flags(size, result);
+                self.write(mem, ea, size, result)
+            }
+            0x4a00 => {
+                let value = self.read(mem, ea, size)?;
+                self.logic_flags(size, value);
+                Ok(())
+            }
+            _ => illegal(op),
+        }
+    }
+
+    fn link(&mut self, mem: &mut Memory, r: usize, displacement: u32) -> Step<()> {
+        let value = self.a[r];
+        self.push32(mem, value)?;
+        self.a[r] = self.a[7];
+        self.a[7] = self.a[7].wrapping_add(displacement);
+        Ok(())
+    }
+
+    fn movem(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let size = if op & 0x40 != 0 { Size::Long } else { Size::Word };
+        let to_registers = op & 0x400 != 0;
+        let mask = self.fetch16(mem)?;
+        let (mode, r) = ((op >> 3) & 7, op as usize & 7);
+        let bytes = size.bytes();
+        if !to_registers && mode == 4 {
+            // Bit 0 is A7 and bit 15 D0; the 68020 and later store the
+            // decremented address register
+            let mut address = self.a[r];
+            for i in 0..16 {
+                if mask & 1 << i == 0 {
+                    continue;
+                }
+                let n = 15 - i;
+                let value = if n < 8 {
+                    self.d[n]
+                } else if n - 8 == r {
+                    self.a[r].wrapping_sub(bytes)
+                } else {
+                    self.a[n - 8]
+                };
+                address = address.wrapping_sub(bytes);
+                self.write(mem, Ea::Mem(address), size, value)?;
+            }
+            self.a[r] = address;
+            return Ok(());
+        }
+        if mode == 3 && to_registers {
+            let mut address = self.a[r];
+            for n in 0..16 {
+                if mask & 1 << n != 0 {
+                    let value = size.sext(self.read(mem, Ea::Mem(address), size)?);
+                    self.set_register(n, value);
+                    address = address.wrapping_add(bytes);
+                }
+            }
+            self.a[r] = address;
+            return Ok(());
+        }
+        if !to_registers && (mode == 7 && (op & 7) > 1) {
+            return illegal(op);
+        }
+        let mut address = self.control_ea(mem, op)?;
+        for n in 0..16 {
+            if mask & 1 << n == 0 {
+                continue;
+            }
+            if to_registers {
+                let value = size.sext(self.read(mem, Ea::Mem(address), size)?);
+                self.set_register(n, value);
+            } else {
+                let value = if n < 8 { self.d[n] } else { self.a[n - 8] };
+                self.write(mem, Ea::Mem(address), size, value)?;
+            }
+            address = address.wrapping_add(bytes);
+        }
+        Ok(())
+    }
+
+    // D0-D7 then A0-A7
+    fn set_register(&mut self, n: usize, value: u32) {
+        if n < 8 {
+            self.d[n] = value;
+        } else {
+            self.a[n - 8] = value;
+        }
+    }
+
+    fn mul_long(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let ext = self.fetch16(mem)?;
+        let ea = self.ea(mem, (op >> 3) & 7, op & 7, Size::Long)?;
+        let src = self.read(mem, ea, Size::Long)?;
+        let (dl, dh) = ((ext >> 12) as usize & 7, ext as usize & 7);
+        let product = if ext & 0x800 != 0 {
+            (self.d[dl] as i32 as i64).wrapping_mul(src as i32 as i64) as u64
+        } else {
+            self.d[dl] as u64 * src as u64
+        };
+        let low = product as u32;
+        self.c = false;
+        if ext & 0x400 != 0 {
+            self.d[dh] = (product >> 32) as u32;
+            self.d[dl] = low;
+            self.n = product >> 63 != 0;
+            self.z = product == 0;
+            self.v = false;
+        } else {
+            self.d[dl] = low;
+            self.n = low >> 31 != 0;
+            self.z = low == 0;
+            self.v = if ext & 0x800 != 0 { product as i64 != low as i32 as i64 } else { product >> 32 != 0 };
+        }
+        Ok(())
+    }
+
+    fn div_long(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let ext = self.fetch16(mem)?;
+        let ea = self.ea(mem, (op >> 3) & 7, op & 7, Size::Long)?;
+        let divisor = self.read(mem, ea, Size::Long)?;
+        let (dq, dr) = ((ext >> 12) as usize & 7, ext as usize & 7);
+        let quad = ext & 0x400 != 0;
+        self.c = false;
+        if divisor == 0 {
+            return Err(Exception::ZeroDivide);
+        }
+        let result = if ext & 0x800 != 0 {
+            let dividend = if quad { ((self.d[dr] as u64) << 32 | self.d[dq] as u64) as i64 } else { self.d[dq] as i32 as i64 };
+            let divisor = divisor as i32 as i64;
+            match (dividend.checked_div(divisor), dividend.checked_rem(divisor)) {
+                (Some(q), Some(r)) if q == q as i32 as i64 => Some((q as u32, r as u32)),
+                _ => None,
+            }
+        } else {
+            let dividend = if quad { (self.d[dr] as u64) << 32 | self.d[dq] as u64 } else { self.d[dq] as u64 };
+            let (q, r) = (dividend / divisor as u64, dividend % divisor as u64);
+            (q >> 32 == 0).then_some((q as u32, r as u32))
+        };
+        let Some((quotient, remainder)) = result else {
+            self.v = true;
+            return Ok(());
+        };
+        if quad || dr != dq {
+            self.d[dr] = remainder;
+        }
+        self.d[dq] = quotient;
+        self.logic_flags(Size::Long, quotient);
+        Ok(())
+    }
+
+    // ADDQ, SUBQ, Scc, DBcc, TRAPcc
+    fn line5(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let (mode, reg) = ((op >> 3) & 7, op & 7);
+        let Some(size) = Size::from_bits(op >> 6) else {
+            let cc = (op >> 8) & 15;
+            if mode == 1 {
+                let base = self.pc;
+                let displacement = self.fetch16(mem)? as i16 as u32;
+                if !self.cond(cc) {
+                    let r = reg as usize;
+                    let count = (self.d[r] as u16).wrapping_sub(1);
+                    self.d[r] = self.d[r] & 0xffff_0000 | count as u32;
+                    if count != 0xffff {
+                        self.pc = base.wrapping_add(displacement);
+                    }
+                }
+                return Ok(());
+            }
+            if mode == 7 && (2..=4).contains(&reg) {
+                match reg {
+                    2 => self.pc = self.pc.wrapping_add(2),
+                    3 => self.pc = self.pc.wrapping_add(4),
+                    _ => {}
+                }
+                return if self.cond(cc) { Err(Exception::TrapV) } else { Ok(()) };
+            }
+            let ea = self.ea(mem, mode, reg, Size::Byte)?;
+            let value = if self.cond(cc) { 0xff } else { 0 };
+            return self.write(mem, ea, Size::Byte, value);
+        };
+        let data = match (op >> 9) & 7 {
+            0 => 8,
+            n => n as u32,
+        };
+        let subtract = op & 0x100 != 0;
+        if mode == 1 {
+            if size == Size::Byte {
+                return illegal(op);
+            }
+            let r = reg as usize;
+            self.a[r] = if subtract { self.a[r].wrapping_sub(data) } else { self.a[r].wrapping_add(data) };
+            return Ok(());
+        }
+        let ea = self.ea(mem, mode, reg, size)?;
+        let value = self.read(mem, ea, size)?;
+        let result = if subtract { self.sub(size, data, value, false) } else { self.add(size, data, value, false) };
+        self.write(mem, ea, size, result)
+    }
+
+    // Bcc, BRA, BSR
+    fn branch(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let base = self.pc;
+        let displacement = match op & 0xff {
+            0 => self.fetch16(mem)? as i16 as u32,
+            0xff => self.fetch32(mem)?,
+            d => d as i8 as u32,
+        };
+        let cc = (op >> 8) & 15;
+        if cc == 1 {
+            self.push32(mem, self.pc)?;
+        } else if !self.cond(cc) {
+            return Ok(());
+        }
+        self.pc = base.wrapping_add(displacement);
+        Ok(())
+    }
+
+    // OR, DIVU.W, DIVS.W, SBCD
+    fn line8(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let mode = (op >> 3) & 7;
+        let opmode = (op >> 6) & 7;
+        match opmode {
+            3 | 7 => return self.div_word(mem, op, opmode == 7),
+            4 if mode < 2 => return self.bcd(mem, op, Cpu::sbcd),
+            5 | 6 if mode < 2 => return illegal(op),
+            _ => {}
+        }
+        self.logic(mem, op, |a, b| a | b)
+    }
+
+    // AND, MULU.W, MULS.W, ABCD, EXG
+    fn line_c(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let (mode, rx, ry) = ((op >> 3) & 7, (op >> 9) as usize & 7, op as usize & 7);
+        match ((op >> 6) & 7, mode) {
+            (3 | 7, _) => {
+                let ea = self.ea(mem, mode, op & 7, Size::Word)?;
+                let src = self.read(mem, ea, Size::Word)?;
+                let result = if op & 0x100 != 0 {
+                    (self.d[rx] as i16 as i32).wrapping_mul(src as i16 as i32) as u32
+                } else {
+                    (self.d[rx] & 0xffff) * src
+                };
+                self.d[rx] = result;
+                self.logic_flags(Size::Long, result);
+                Ok(())
+            }
+            (4, 0 | 1) => self.bcd(mem, op, Cpu::abcd),
+            (5, 0) => {
+                self.d.swap(rx, ry);
+                Ok(())
+            }
+            (5, 1) => {
+                self.a.swap(rx, ry);
+                Ok(())
+            }
+            (6, 1) => {
+                std::mem::swap(&mut self.d[rx], &mut self.a[ry]);
+                Ok(())
+            }
+            (5 | 6, _) if mode < 2 => illegal(op),
+            _ => self.logic(mem, op, |a, b| a & b),
+        }
+    }
+
+    // OR or AND between Dn and <ea>, either way round
+    fn logic(&mut self, mem: &mut Memory, op: u16, f: fn(u32, u32) -> u32) -> Step<()> {
+        let (mode, reg, r) = ((op >> 3) & 7, op & 7, (op >> 9) as usize & 7);
+        let size = Size::from_bits(op >> 6).ok_or(Exception::Illegal(op))?;
+        if mode == 1 {
+            return illegal(op);
+        }
+        let ea = self.ea(mem, mode, reg, size)?;
+        let value = self.read(mem, ea, size)?;
+        let result = f(value, self.d[r]) & size.mask();
+        self.logic_flags(size, result);
+        if op & 0x100 != 0 {
+            self.write(mem, ea, size, result)
+        } else {
+            self.write(mem, Ea::D(r), size, result)
+        }
+    }
+
+    fn div_word(&mut self, mem: &mut Memory, op: u16, signed: bool) -> Step<()> {
+        let ea = self.ea(mem, (op >> 3) & 7, op & 7, Size::Word)?;
+        let divisor = self.read(mem, ea, Size::Word)?;
+        let r = (op >> 9) as usize & 7;
+        self.c = false;
+        if divisor == 0 {
+            return Err(Exception::ZeroDivide);
+        }
+        let dividend = self.d[r];
+        let result = if signed {
+            let (dividend, divisor) = (dividend as i32, divisor as i16 as i32);
+            match (dividend.checked_div(divisor), dividend.checked_rem(divisor)) {
+                (Some(q), Some(rem)) if q == q as i16 as i32 => Some((q as u32, rem as u32)),
+                _ => None,
+            }
+        } else {
+            let q = dividend / divisor;
+            (q <= 0xffff).then_some((q, dividend % divisor))
+        };
+        let Some((quotient, remainder)) = result else {
+            self.v = true;
+            return Ok(());
+        };
+        self.d[r] = (remainder & 0xffff) << 16 | quotient & 0xffff;
+        self.logic_flags(Size::Word, quotient);
+        Ok(())
+    }
+
+    // ABCD or SBCD, register or predecrement form
+    fn bcd(&mut self, mem: &mut Memory, op: u16, f: fn(&mut Cpu, u32, u32) -> u32) -> Step<()> {
+        let (rx, ry) = ((op >> 9) & 7, op & 7);
+        if op & 8 == 0 {
+            let result = f(self, self.d[ry as usize] & 0xff, self.d[rx as usize] & 0xff);
+            return self.write(mem, Ea::D(rx as usize), Size::Byte, result);
+        }
+        let src = self.ea(mem, 4, ry, Size::Byte)?;
+        let src = self.read(mem, src, Size::Byte)?;
+        let dst = self.ea(mem, 4, rx, Size::Byte)?;
+        let value = self.read(mem, dst, Size::Byte)?;
+        let result = f(self, src, value);
+        self.write(mem, dst, Size::Byte, result)
+    }
+
+    fn abcd(&mut self, src: u32, dst: u32) -> u32 {
+        let mut result = (src & 0xf) + (dst & 0xf) + self.x as u32;
+        if result > 9 {
+            result += 6;
+        }
+        result += (src & 0xf0) + (dst & 0xf0);
+        self.c = result > 0x99;
+        if self.c {
+            result = result.wrapping_sub(0xa0);
+        }
+        self.bcd_flags(result)
+    }
+
+    fn sbcd(&mut self, src: u32, dst: u32) -> u32 {
+        let mut result = (dst & 0xf).wrapping_sub(src & 0xf).wrapping_sub(self.x as u32);
+        if result > 9 {
+            result = result.wrapping_sub(6);
+        }
+        result = result.wrapping_add(dst & 0xf0).wrapping_sub(src & 0xf0);
+        self.c = result > 0x99;
+        if self.c {
+            result = result.wrapping_add(0xa0);
+        }
+        self.bcd_flags(result)
+    }
+
+    fn bcd_flags(&mut self, result: u32) -> u32 {
+        let result = result & 0xff;
+        self.x = self.c;
+        self.n = result & 0x80 != 0;
+        self.v = false;
+        if result != 0 {
+            self.z = false;
+        }
+        result
+    }
+
+    // ADD, ADDA, ADDX (line D) and SUB, SUBA, SUBX (line 9)
+    fn add_sub(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let subtract = op >> 12 == 9;
+        let (mode, reg, r) = ((op >> 3) & 7, op & 7, (op >> 9) as usize & 7);
+        let opmode = (op >> 6) & 7;
+        if opmode == 3 || opmode == 7 {
+            let size = if opmode == 7 { Size::Long } else { Size::Word };
+            let ea = self.ea(mem, mode, reg, size)?;
+            let src = size.sext(self.read(mem, ea, size)?);
+            self.a[r] = if subtract { self.a[r].wrapping_sub(src) } else { self.a[r].wrapping_add(src) };
+            return Ok(());
+        }
+        let size = Size::from_bits(opmode).ok_or(Exception::Illegal(op))?;
+        if opmode >= 4 && mode < 2 {
+            // ADDX, SUBX
+            let (src, dst) = if mode == 0 {
+                (Ea::D(reg as usize), Ea::D(r))
+            } else {
+                let src = self.ea(mem, 4, reg, size)?;
+                (src, self.ea(mem, 4, r as u16, size)?)
+            };
+            let (a, b) = (self.read(mem, src, size)?, self.read(mem, dst, size)?);
+            let result = if subtract { self.sub(size, a, b, true) } else { self.add(size, a, b, true) };
+            return self.write(mem, dst, size, result);
+        }
+        if size == Size::Byte && mode == 1 {
+            return illegal(op);
+        }
+        let ea = self.ea(mem, mode, reg, size)?;
+        let value = self.read(mem, ea, size)?;
+        if opmode >= 4 {
+            let result = if subtract { self.sub(size, self.d[r], value, false) } else { self.add(size, self.d[r], value, false) };
+            self.write(mem, ea, size, result)
+        } else {
+            let result = if subtract { self.sub(size, value, self.d[r], false) } else { self.add(size, value, self.d[r], false) };
+            self.write(mem, Ea::D(r), size, result)
+        }
+    }
+
+    // CMP, CMPA, CMPM, EOR
+    fn line_b(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let (mode, reg, r) = ((op >> 3) & 7, op & 7, (op >> 9) as usize & 7);
+        let opmode = (op >> 6) & 7;
+        if opmode == 3 || opmode == 7 {
+            let size = if opmode == 7 { Size::Long } else { Size::Word };
+            let ea = self.ea(mem, mode, reg, size)?;
+            let src = size.sext(self.read(mem, ea, size)?);
+            self.cmp(Size::Long, src, self.a[r]);
+            return Ok(());
+        }
+        let size = Size::from_bits(opmode).ok_or(Exception::Illegal(op))?;
+        if opmode < 4 {
+            if size == Size::Byte && mode == 1 {
+                return illegal(op);
+            }
+            let ea = self.ea(mem, mode, reg, size)?;
+            let src = self.read(mem, ea, size)?;
+            self.cmp(size, src, self.d[r]);
+            return Ok(());
+        }
+        if mode == 1 {
+            let src = self.ea(mem, 3, reg, size)?;
+            let src = self.read(mem, src, size)?;
+            let dst = self.ea(mem, 3, r as u16, size)?;
+            let dst = self.read(mem, dst, size)?;
+            self.cmp(size, src, dst);
+            return Ok(());
+        }
+        let ea = self.ea(mem, mode, reg, size)?;
+        let result = (self.read(mem, ea, size)? ^ self.d[r]) & size.mask();
+        self.logic_flags(size, result);
+        self.write(mem, ea, size, result)
+    }
+
+    // Shifts, rotates and bit fields
+    fn line_e(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        if op & 0xf8c0 == 0xe8c0 {
+            return self.bitfield(mem, op);
+        }
+        let left = op & 0x100 != 0;
+        let Some(size) = Size::from_bits(op >> 6) else {
+            let ea = self.ea(mem, (op >> 3) & 7, op & 7, Size::Word)?;
+            if !matches!(ea, Ea::Mem(_)) {
+                return illegal(op);
+            }
+            let value = self.read(mem, ea, Size::Word)?;
+            let result = self.shift(Size::Word, (op >> 9) & 3, left, value, 1);
+            return self.write(mem, ea, Size::Word, result);
+        };
+        let count = if op & 0x20 != 0 {
+            self.d[(op >> 9) as usize & 7] & 63
+        } else {
+            match (op >> 9) & 7 {
+                0 => 8,
+                n => n as u32,
+            }
+        };
+        let r = op as usize & 7;
+        let result = self.shift(size, (op >> 3) & 3, left, self.d[r], count);
+        self.write(mem, Ea::D(r), size, result)
+    }
+
+    // One of ASx (0), LSx (1), ROXx (2), ROx (3), one bit at a time
+    fn shift(&mut self, size: Size, kind: u16, left: bool, value: u32, count: u32) -> u32 {
+        let (mask, msb) = (size.mask(), size.msb());
+        let mut value = value & mask;
+        self.v = false;
+        match kind {
+            0 | 1 => {
+                self.c = false;
+                for _ in 0..count {
+                    if left {
+                        let out = value & msb != 0;
+                        value = (value << 1) & mask;
+                        self.c = out;
+                        if kind == 0 && (value & msb != 0) != out {
+                            self.v = true;
+                        }
+                    } else {
+                        self.c = value & 1 != 0;
+                        value = if kind == 0 { value >> 1 | value & msb } else { value >> 1 };
+                    }
+                }
+                if count > 0 {
+                    self.x = self.c;
+                }
+            }
+            2 => {
+                for _ in 0..count {
+                    let out = if left { value & msb != 0 } else { value & 1 != 0 };
+                    value = if left {
+                        (value << 1 | self.x as u32) & mask
+                    } else {
+                        value >> 1 | if self.x { msb } else { 0 }
+                    };
+                    self.x = out;
+                }
+                self.c = self.x;
+            }
+            _ => {
+                self.c = false;
+                for _ in 0..count {
+                    if left {
+                        self.c = value & msb != 0;
+                        value = (value << 1 | self.c as u32) & mask;
+                    } else {
+                        self.c = value & 1 != 0;
+                        value = value >> 1 | if self.c { msb } else { 0 };
+                    }
+                }
+            }
+        }
+        self.n = value & msb != 0;
+        self.z = value == 0;
+        value
+    }
+
+    // BFTST, BFEXTU, BFCHG, BFEXTS, BFCLR, BFFFO, BFSET, BFINS
+    fn bitfield(&mut self, mem: &mut Memory, op: u16) -> Step<()> {
+        let kind = (op >> 8) & 7;
+        let ext = self.fetch16(mem)?;
+        let offset = if ext & 0x800 != 0 { self.d[(ext >> 6) as usize & 7] as i32 } else { (ext as i32 >> 6) & 31 };
+        let width = match if ext & 0x20 != 0 { self.d[ext as usize & 7] } else { ext as u32 } & 31 {
+            0 => 32,
+            w => w,
+        };
+        let field_mask = u32::MAX >> (32 - width);
+        let r = (ext >> 12) as usize & 7;
+        let (mode, reg) = ((op >> 3) & 7, op & 7);
+        if mode == 1 || matches!(mode, 3 | 4) || mode == 7 && reg > 3 {
+            return illegal(op);
+        }
+
+        // The field and where it lives: a data register rotated so the
+        // field is at the top, or up to five bytes of memory
+        let (field, location) = if mode == 0 {
+            let rotate = offset as u32 & 31;
+            let value = self.d[reg as usize].rotate_left(rotate);
+            (value >> (32 - width), None)
+        } else {
+            let address = self.control_ea(mem, op)?.wrapping_add((offset >> 3) as u32);
+            let bit = (offset & 7) as u32;
+            let count = (bit + width).div_ceil(8);
+            let mut raw = 0u64;
+            for i in 0..count {
+                raw |= (mem.read_u8(address.wrapping_add(i))? as u64) << (56 - 8 * i);
+            }
+            (((raw << bit) >> (64 - width)) as u32, Some((address, bit, count, raw)))
+        };
+        let inserted = self.d[r] & field_mask;
+        let flag_value = if kind == 7 { inserted } else { field };
+        self.n = flag_value >> (width - 1) & 1 != 0;
+        self.z = flag_value == 0;
+        self.v = false;
+        self.c = false;
+
+        let new_field = match kind {
+            1 => {
+                self.d[r] = field;
+                return Ok(());
+            }
+            3 => {
+                self.d[r] = ((field << (32 - width)) as i32 >> (32 - width)) as u32;
+                return Ok(());
+            }
+            5 => {
+                let zeros = (field << (32 - width)).leading_zeros().min(width);
+                self.d[r] = (offset as u32).wrapping_add(zeros);
+                return Ok(());
+            }
+            2 => !field & field_mask,
+            4 => 0,
+            6 => field_mask,
+            7 => inserted,
+            _ => return Ok(()),
+        };
+        match location {
+            None => {
+                let rotate = offset as u32 & 31;
+                let top_mask = field_mask << (32 - width);
+                let value = self.d[reg as usize].rotate_left(rotate);
+                let value = value & !top_mask | new_field << (32 - width);
+                self.d[reg as usize] = value.rotate_right(rotate);
+            }
+            Some((address, bit, count, raw)) => {
+                let shift = 64 - width - bit;
+                let raw = raw & !((field_mask as u64) << shift) | (new_field as u64) << shift;
+                for i in 0..count {
+                    mem.write_u8(address.wrapping_add(i), (raw >> (56 - 8 * i)) as u8)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use nextstep_macho::consts::{VM_PROT_READ, VM_PROT_WRITE};
+
+    pub(crate) const CODE: u32 = 0x2000;
+    pub(crate) const DATA: u32 = 0x10000;
+    pub(crate) const STACK: u32 = 0x20000;
+
+    /// A CPU about to run `code`, with data and stack pages
+    pub(crate) fn machine(code: &[u16]) -> (Cpu, Memory) {
+        let mut mem = Memory::new();
+        mem.map(CODE, 0x2000, VM_PROT_READ);
+        mem.map(DATA, 0x2000, VM_PROT_READ | VM_PROT_WRITE);
+        mem.map(STACK - 0x2000, 0x2000, VM_PROT_READ | VM_PROT_WRITE);
+        let bytes: Vec<u8> = code.iter().flat_map(|w| w.to_be_bytes()).collect();
+        mem.poke(CODE, &bytes).unwrap();
+        let cpu = Cpu { pc: CODE, a: [0, 0, 0, 0, 0, 0, 0, STACK], ..Cpu::default() };
+        (cpu, mem)
+    }
+
+    // Run until the pc leaves the code or an exception
+    fn run(cpu: &mut Cpu, mem: &mut Memory, end: u32) -> Result<(), Exception> {
+        while cpu.pc != end {
+            cpu.step(mem)?;
+        }
+        Ok(())
+    }
+
+    fn exec(code: &[u16], setup: impl FnOnce(&mut Cpu)) -> Cpu {
+        let (mut cpu, mut mem) = machine(code);
+        setup(&mut cpu);
+        run(&mut cpu, &mut mem, CODE + 2 * code.len() as u32).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn test_arithmetic_flags() {
+        // add.l d1,d0
+        let cpu = exec(&[0xd081], |c| (c.d[0], c.d[1]) = (0x7fff_ffff, 1));
+        assert_eq!((cpu.d[0], cpu.ccr()), (0x8000_0000, 0b01010));
+        // sub.b d1,d0: 0 - 1 borrows
+        let cpu = exec(&[0x9001], |c| (c.d[0], c.d[1]) = (0x1200, 1));
+        assert_eq!((cpu.d[0], cpu.ccr()), (0x12ff, 0b11001));
+        // addx.l d1,d0 with X set and Z kept only while the result is zero
+        let cpu = exec(&[0xd181], |c| {
+            (c.d[0], c.d[1]) = (0xffff_ffff, 0);
+            c.set_ccr(0x14);
+        });
+        assert_eq!((cpu.d[0], cpu.ccr()), (0, 0b10101));
+        // cmpi.l #5,d0; then bgt skips the moveq
+        let cpu = exec(&[0x0c80, 0, 5, 0x6e02, 0x7001], |c| c.d[0] = 6);
+        assert_eq!(cpu.d[0], 6);
+        // neg.w d0
+        let cpu = exec(&[0x4440], |c| c.d[0] = 0x8000);
+        assert_eq!((cpu.d[0], cpu.ccr()), (0x8000, 0b11011));
+        // abcd d1,d0: 0x19 + 0x28 = 0x47
+        let cpu = exec(&[0xc101], |c| (c.d[0], c.d[1]) = (0x19, 0x28));
+        assert_eq!(cpu.d[0], 0x47);
+    }
+
+    #[test]
+    fn test_multiply_divide() {
+        // muls.l d1,d3:d0 (64-bit)
+        let cpu = exec(&[0x4c01, 0x0c03], |c| (c.d[0], c.d[1]) = (-3i32 as u32, 0x4000_0000));
+        assert_eq!((cpu.d[3], cpu.d[0]), (0xffff_ffff, 0x4000_0000));
+        // mulu.l d1,d0 overflows 32 bits
+        let cpu = exec(&[0x4c01, 0x0000], |c| (c.d[0], c.d[1]) = (0x10000, 0x10000));
+        assert!(cpu.v && cpu.d[0] == 0);
+        // divs.l d1,d2:d0: -7 / 2 = -3 remainder -1
+        let cpu = exec(&[0x4c41, 0x0802], |c| (c.d[0], c.d[1]) = (-7i32 as u32, 2));
+        assert_eq!((cpu.d[0] as i32, cpu.d[2] as i32), (-3, -1));
+        // divu.w d1,d0
+        let cpu = exec(&[0x80c1], |c| (c.d[0], c.d[1]) = (100_003, 10));
+        assert_eq!(cpu.d[0], 3 << 16 | 10_000);
+        // divu.w overflow leaves the register alone
+        let cpu = exec(&[0x80c1], |c| (c.d[0], c.d[1]) = (0x10_0000, 1));
+        assert!(cpu.v && cpu.d[0] == 0x10_0000);
+        let (mut cpu, mut mem) = machine(&[0x80c1]);
+        assert_eq!(cpu.step(&mut mem), Err(Exception::ZeroDivide));
+    }
+
+    #[test]
+    fn test_shifts() {
+        // asl.b #1,d0: the sign changes
+        let cpu = exec(&[0xe300], |c| c.d[0] = 0x40);
+        assert_eq!((cpu.d[0], cpu.v, cpu.c), (0x80, true, false));
+        // asr.w #4,d0
+        let cpu = exec(&[0xe840], |c| c.d[0] = 0x8018);
+        assert_eq!((cpu.d[0], cpu.c, cpu.x), (0xf801, true, true));
+        // lsr.l d1,d0 with a count of 32 clears the register
+        let cpu = exec(&[0xe2a8], |c| (c.d[0], c.d[1]) = (0x8000_0001, 32));
+        assert_eq!((cpu.d[0], cpu.z, cpu.c), (0, true, true));
+        // roxl.l #1,d0 through X
+        let cpu = exec(&[0xe390], |c| {
+            c.d[0] = 0x8000_0000;
+            c.x = true;
+        });
+        assert_eq!((cpu.d[0], cpu.x, cpu.c), (1, true, true));
+        // ror.w #8,d0
+        let cpu = exec(&[0xe058], |c| c.d[0] = 0xaaaa_1234);
+        assert_eq!(cpu.d[0], 0xaaaa_3412);
+    }
+
+    #[test]
+    fn test_memory_operands() {
+        // movem.l d2-d3/a2,-(sp); clr.l d2; movem.l (sp)+,d2-d3/a2
+        let cpu = exec(&[0x48e7, 0x3020, 0x4282, 0x4cdf, 0x040c], |c| {
+            (c.d[2], c.d[3], c.a[2]) = (1, 2, 3);
+        });
+        assert_eq!((cpu.d[2], cpu.d[3], cpu.a[2], cpu.a[7]), (1, 2, 3, STACK));
+
+        // lea (8,a0,d1.l*4),a1 and move.l (2,a1,d1.w),d0 through the
+        // 68020 full format with a word base displacement
+        let (mut cpu, mut mem) = machine(&[0x43f0, 0x1c08, 0x2031, 0x1120, 0x0002]);
+        cpu.a[0] = DATA;
+        cpu.d[1] = 2;
+        mem.write_u32(DATA + 20, 0xdead_beef).unwrap();
+        run(&mut cpu, &mut mem, CODE + 10).unwrap();
+        assert_eq!((cpu.a[1], cpu.d[0]), (DATA + 16, 0xdead_beef));
+
+        // Memory indirect: ([4,a0],8)
+        let (mut cpu, mut mem) = machine(&[0x2030, 0x0162, 0x0004, 0x0008]);
+        cpu.a[0] = DATA;
+        mem.write_u32(DATA + 4, DATA + 0x100).unwrap();
+        mem.write_u32(DATA + 0x108, 42).unwrap();
+        run(&mut cpu, &mut mem, CODE + 8).unwrap();
+        assert_eq!(cpu.d[0], 42);
+
+        // A store into the code page faults, leaving the pc on the move
+        let (mut cpu, mut mem) = machine(&[0x2080]);
+        cpu.a[0] = CODE;
+        assert_eq!(cpu.step(&mut mem), Err(Exception::BusError(Fault { address: CODE, write: true })));
+        assert_eq!(cpu.pc, CODE);
+    }
+
+    #[test]
+    fn test_atomics_and_bitfields() {
+        // cas.l d0,d1,(a0) succeeds, then fails and loads the value
+        let (mut cpu, mut mem) = machine(&[0x0ed0, 0x0040, 0x0ed0, 0x0040]);
+        cpu.a[0] = DATA;
+        (cpu.d[0], cpu.d[1]) = (0, 7);
+        cpu.step(&mut mem).unwrap();
+        assert!(cpu.z && mem.read_u32(DATA).unwrap() == 7);
+        cpu.step(&mut mem).unwrap();
+        assert!(!cpu.z && cpu.d[0] == 7);
+
+        // tas (a0) sets the top bit and reports it clear
+        let (mut cpu, mut mem) = machine(&[0x4ad0, 0x4ad0]);
+        cpu.a[0] = DATA + 8;
+        cpu.step(&mut mem).unwrap();
+        assert!(cpu.z && !cpu.n);
+        cpu.step(&mut mem).unwrap();
+        assert!(cpu.n && mem.read_u8(DATA + 8).unwrap() == 0x80);
+
+        // bfextu (a0){4:12},d0 then bfins d0,(a0){20:12}
+        let (mut cpu, mut mem) = machine(&[0xe9d0, 0x010c, 0xefd0, 0x050c]);
+        cpu.a[0] = DATA;
+        mem.write_u32(DATA, 0x0abc_0000).unwrap();
+        run(&mut cpu, &mut mem, CODE + 8).unwrap();
+        assert_eq!((cpu.d[0], mem.read_u32(DATA).unwrap()), (0xabc, 0x0abc_0abc));
+        // bfffo d1{0:32},d0
+        let cpu = exec(&[0xedc1, 0x0000], |c| c.d[1] = 0x0010_0000);
+        assert_eq!(cpu.d[0], 11);
+    }
+
+    #[test]
+    fn test_control_flow() {
+        // bsr.s +2; bra.s +2; (sub) rts
+        let (mut cpu, mut mem) = machine(&[0x6102, 0x6002, 0x4e75]);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!((cpu.pc, mem.read_u32(STACK - 4).unwrap()), (CODE + 4, CODE + 2));
+        cpu.step(&mut mem).unwrap();
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.pc, CODE + 6);
+
+        // dbf d0 loop runs d0 + 1 times
+        let cpu = exec(&[0x5281, 0x51c8, 0xfffc], |c| c.d[0] = 4);
+        assert_eq!((cpu.d[1], cpu.d[0] & 0xffff), (5, 0xffff));
+
+        // link/unlk
+        let cpu = exec(&[0x4e56, 0xfff8, 0x4e5e], |c| c.a[6] = 0x1234);
+        assert_eq!((cpu.a[6], cpu.a[7]), (0x1234, STACK));
+
+        let (mut cpu, mut mem) = machine(&[0x4e40, 0x46fc, 0x2700, 0xffff]);
+        assert_eq!(cpu.step(&mut mem), Err(Exception::Trap(0)));
+        assert_eq!(cpu.pc, CODE + 2);
+        assert_eq!(cpu.step(&mut mem), Err(Exception::Privilege(0x46fc)));
+        assert_eq!(cpu.pc, CODE + 2);
+        cpu.pc = CODE + 6;
+        assert_eq!(cpu.step(&mut mem), Err(Exception::LineF(0xffff)));
+    }
+
+    #[test]
+    fn test_compiled_code() {
+        // rustc -C opt-level=1 -C relocation-model=pic -C target-cpu=M68040
+        // for m68k, of:
+        //   fn fib(n: u32) -> u32 { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }
+        //   fn collatz(mut n: u32) -> u32 { /* steps to reach 1 */ }
+        //   fn mix(a: i32, b: i32) -> i32 { ((a * b) >> 3) - (b ^ a) }
+        //   extern "C" fn entry(n: u32) -> u32 {
+        //       fib(n) + collatz(n + 27) + mix(n as i32 - 40, 12345) as u32
+        //   }
+        // with `entry` at 0xd0
+        let text: String = [
+            "9ffc0000000c48ef000c000424007600200290bc0000000265162002d0bcffffffff4ebaffdcd680d4bcfffffffe60e0",
+            "d48320024cef000c0004dffc0000000c4e759ffc0000000448ef00040000243c0000303922004c021800e6810a800000",
+            "3039928020014cef00040000dffc000000044e759ffc0000000448ef00040000220090bc00000001661070004cef0004",
+            "0000dffc000000044e757000601e2401e38ad481d4bc000000012202d0bc00000001240194bc0000000167d02401c4bc",
+            "000000010c820000000066d2e28960dc9ffc0000000c48ef001c0000242f001020024ebaff1c26002002d0bc0000001b",
+            "4ebaff822800d883d4bcffffffd820024ebaff40d88020044cef001c0000dffc0000000c4e75",
+        ]
+        .concat();
+        let code: Vec<u16> =
+            (0..text.len()).step_by(4).map(|i| u16::from_str_radix(&text[i..i + 4], 16).unwrap()).collect();
+        let (mut cpu, mut mem) = machine(&code);
+        cpu.pc = CODE + 0xd0;
+        cpu.push32(&mut mem, 20).unwrap();
+        cpu.push32(&mut mem, CODE + 0x1000).unwrap();
+        run(&mut cpu, &mut mem, CODE + 0x1000).unwrap();
+        // fib(20) + collatz(47) + (-30863 - -12331)
+        assert_eq!((cpu.d[0] as i32, cpu.a[7]), (6765 + 104 - 18532, STACK - 4));
+    }
+}
@@ -0,0 +1,42 @@
+//! Loader errors
+
+use std::fmt;
+
+/// Why an executable could not be loaded
+#[derive(Debug)]
+pub enum Error {
+    Io { path: String, error: std::io::Error },
+    Macho(nextstep_macho::Error),
+    /// A valid Mach-O file that is not something the emulator can run
+    NotExecutable(String),
+    /// A segment or the stack does not fit the address space
+    BadLayout(String),
+}
+
+impl Error {
+    pub(crate) fn io(path: impl AsRef<std::path::Path>, error: std::io::Error) -> Error {
+        Error::Io { path: path.as_ref().display().to_string(), error }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, error } => write!(f, "{}: {}", path, error),
+            Error::Macho(error) => error.fmt(f),
+            Error::NotExecutable(reason) => write!(f, "not an m68k NeXTSTEP executable: {}", reason),
+            Error::BadLayout(reason) => write!(f, "cannot lay out the program: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<nextstep_macho::Error> for Error {
+    fn from(error: nextstep_macho::Error) -> Error {
+        Error::Macho(error)
+    }
+}
+
+/// Result of loading
+pub type Result<T> = core::result::Result<T, Error>;
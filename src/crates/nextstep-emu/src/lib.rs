@@ -0,0 +1,32 @@
+//! nextstep-emu - User-mode m68k NeXTSTEP emulator for running target code in CI
+//!
+//! Runs static `m68k-next-nextstep` executables on the build host without
+//! a NeXT ROM, a boot disk or Previous: [`loader`] maps the Mach-O into a
+//! 32-bit address space ([`memory`]), [`cpu`] interprets the 68030/68040
+//! integer instruction set in user mode, and [`syscall`] carries out the
+//! BSD system calls and Mach traps `trap #0` asks for on the host.
+//! [`process`] ties them together and reports the exit status, or the
+//! signal a fault would have raised.
+//!
+//! It is for tests and smoke checks, not a NeXTSTEP: there is one thread,
+//! no FPU, no `fork` or `execve`, no signal delivery and no Mach IPC, so
+//! programs needing those still run under Previous through
+//! `nextstep-runner`. `nextstep-emu` is the command-line front end, and
+//! `nextstep-runner --mode user` uses it as a cargo runner.
+//!
+//! ```ignore
+//! let options = nextstep_emu::Options { capture: true, ..Default::default() };
+//! let mut process = nextstep_emu::Process::open("hello".as_ref(), &options)?;
+//! let exit = process.run(Some(std::time::Duration::from_secs(60)));
+//! print!("{}", String::from_utf8_lossy(process.output()));
+//! ```
+
+pub mod cpu;
+mod error;
+pub mod loader;
+pub mod memory;
+pub mod process;
+pub mod syscall;
+
+pub use error::{Error, Result};
+pub use process::{Exit, Options, Process};
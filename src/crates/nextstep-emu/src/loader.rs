@@ -0,0 +1,179 @@
+//! Loading an executable into guest memory
+//!
+//! Does what the NeXTSTEP kernel's `execve` does for a static m68k
+//! `MH_EXECUTE`: maps every segment with its initial protection (a
+//! `__PAGEZERO` with none stays unmapped, so null pointers fault), takes
+//! the registers from `LC_UNIXTHREAD`, and builds the initial stack the
+//! runtime's `_start` reads: `argc`, the NULL-terminated `argv` and
+//! `envp` pointer arrays, then the strings.
+
+use crate::error::{Error, Result};
+use crate::memory::{round_page, trunc_page, Memory};
+use nextstep_macho::consts::*;
+use nextstep_macho::{is_fat, Command, FatFile, MachO};
+
+/// Top of the user stack, where the NeXT kernel puts it
+pub const STACK_TOP: u32 = 0x0400_0000;
+/// Stack reserved below `STACK_TOP`
+pub const STACK_SIZE: u32 = 0x0080_0000;
+
+/// A loaded program, ready to start
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    /// D0-D7 then A0-A7 as `_start` sees them
+    pub registers: [u32; 16],
+    pub pc: u32,
+    /// End of the highest segment, where `sbrk` starts
+    pub brk: u32,
+}
+
+/// The m68k slice of `data`, thin or fat
+pub fn m68k_slice(data: &[u8]) -> Result<&[u8]> {
+    if !is_fat(data) {
+        return Ok(data);
+    }
+    let fat = FatFile::parse(data)?;
+    let (_, slice) = fat.find(CPU_TYPE_MC680X0).ok_or_else(|| Error::NotExecutable("no m68k slice".into()))?;
+    Ok(slice)
+}
+
+/// Map `data`, an m68k executable, into `mem` and build its stack
+pub fn load(data: &[u8], mem: &mut Memory, args: &[Vec<u8>], env: &[Vec<u8>]) -> Result<Image> {
+    let macho = MachO::parse(m68k_slice(data)?)?;
+    if macho.header.cputype != CPU_TYPE_MC680X0 {
+        return Err(Error::NotExecutable(format!("cputype {}", macho.header.cputype)));
+    }
+    if macho.header.filetype != MH_EXECUTE {
+        return Err(Error::NotExecutable(format!("file type {:#x}, not MH_EXECUTE", macho.header.filetype)));
+    }
+
+    let mut brk = 0;
+    for segment in macho.segments() {
+        let end = segment.vmaddr as u64 + segment.vmsize as u64;
+        let file_end = segment.fileoff as u64 + segment.filesize as u64;
+        if end > STACK_TOP as u64 - STACK_SIZE as u64 && segment.vmsize > 0 && segment.vmaddr < STACK_TOP {
+            return Err(Error::BadLayout(format!("segment {} overlaps the stack", segment.segname)));
+        }
+        if end > 1 << 32 || file_end > macho.data().len() as u64 || segment.filesize > segment.vmsize {
+            return Err(Error::BadLayout(format!("segment {} is out of range", segment.segname)));
+        }
+        if segment.initprot == 0 || segment.vmsize == 0 {
+            continue;
+        }
+        if !mem.is_free(trunc_page(segment.vmaddr), segment.vmsize) {
+            return Err(Error::BadLayout(format!("segment {} overlaps another", segment.segname)));
+        }
+        mem.map(segment.vmaddr, segment.vmsize, segment.initprot);
+        let contents = &macho.data()[segment.fileoff as usize..file_end as usize];
+        mem.poke(segment.vmaddr, contents).expect("segment is mapped");
+        brk = brk.max(end as u32);
+    }
+
+    let state = macho
+        .commands
+        .iter()
+        .filter(|lc| lc.cmd == LC_UNIXTHREAD)
+        .find_map(|lc| match &lc.command {
+            Command::Thread(states) => states.iter().find(|s| s.flavor == M68K_THREAD_STATE_REGS),
+            _ => None,
+        })
+        .filter(|s| s.state.len() > M68K_THREAD_STATE_PC)
+        .ok_or_else(|| Error::NotExecutable("no m68k LC_UNIXTHREAD".into()))?;
+    let mut registers = [0; 16];
+    registers.copy_from_slice(&state.state[..16]);
+    let pc = state.state[M68K_THREAD_STATE_PC];
+    if mem.prot(pc).is_none_or(|prot| prot & VM_PROT_EXECUTE == 0) {
+        return Err(Error::NotExecutable(format!("entry point {:#x} is not in executable memory", pc)));
+    }
+
+    mem.map(STACK_TOP - STACK_SIZE, STACK_SIZE, VM_PROT_READ | VM_PROT_WRITE);
+    registers[15] = build_stack(mem, args, env)?;
+    let brk = round_page(brk).ok_or_else(|| Error::BadLayout("no room for the heap".into()))?;
+    Ok(Image { registers, pc, brk })
+}
+
+// Strings at the top of the stack, pointers below them; the new stack
+// pointer, at argc
+fn build_stack(mem: &mut Memory, args: &[Vec<u8>], env: &[Vec<u8>]) -> Result<u32> {
+    let strings: usize = args.iter().chain(env).map(|s| s.len() + 1).sum();
+    let pointers = 4 * (args.len() + env.len() + 3);
+    if strings + pointers > STACK_SIZE as usize / 2 {
+        return Err(Error::BadLayout("arguments and environment too large".into()));
+    }
+    let mut at = STACK_TOP - strings as u32;
+    let mut addresses = Vec::with_capacity(args.len() + env.len());
+    for string in args.iter().chain(env) {
+        addresses.push(at);
+        mem.write(at, string).expect("stack is mapped");
+        mem.write_u8(at + string.len() as u32, 0).expect("stack is mapped");
+        at += string.len() as u32 + 1;
+    }
+    let sp = (STACK_TOP - strings as u32 - pointers as u32) & !3;
+    let (argv, envp) = addresses.split_at(args.len());
+    let mut words = vec![args.len() as u32];
+    words.extend(argv);
+    words.push(0);
+    words.extend(envp);
+    words.push(0);
+    for (i, word) in words.iter().enumerate() {
+        mem.write_u32(sp + 4 * i as u32, *word).expect("stack is mapped");
+    }
+    Ok(sp)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) const TEXT: u32 = 0x2000;
+
+    /// An executable with `__PAGEZERO`, a `__TEXT` of `code` starting at
+    /// the entry point after the load commands, and a `__DATA` page
+    pub(crate) fn executable(code: &[u16]) -> Vec<u8> {
+        let commands = [56u32, 56, 56, 16 + 4 * M68K_THREAD_STATE_REGS_COUNT];
+        let sizeofcmds: u32 = commands.iter().sum();
+        let entry = TEXT + 28 + sizeofcmds;
+        let mut words = vec![MH_MAGIC, CPU_TYPE_MC680X0 as u32, CPU_SUBTYPE_MC68040 as u32, MH_EXECUTE, 4, sizeofcmds, 0];
+        let mut segment = |name: &[u8], vmaddr: u32, vmsize: u32, fileoff: u32, filesize: u32, prot: u32| {
+            words.extend([LC_SEGMENT, 56]);
+            let mut segname = [0; 16];
+            segname[..name.len()].copy_from_slice(name);
+            words.extend(segname.chunks(4).map(|c| u32::from_be_bytes(c.try_into().unwrap())));
+            words.extend([vmaddr, vmsize, fileoff, filesize, prot, prot, 0, 0]);
+        };
+        let rx = VM_PROT_READ | VM_PROT_EXECUTE;
+        segment(b"__PAGEZERO", 0, TEXT, 0, 0, 0);
+        segment(b"__TEXT", TEXT, 0x2000, 0, 0x2000, rx);
+        segment(b"__DATA", TEXT + 0x2000, 0x2000, 0x2000, 0, VM_PROT_READ | VM_PROT_WRITE);
+        words.extend([LC_UNIXTHREAD, commands[3], M68K_THREAD_STATE_REGS, M68K_THREAD_STATE_REGS_COUNT]);
+        words.extend([0; 17]);
+        words.push(entry);
+        let mut data: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes()).collect();
+        data.extend(code.iter().flat_map(|w| w.to_be_bytes()));
+        data.resize(0x2000, 0);
+        data
+    }
+
+    #[test]
+    fn test_load() {
+        let mut mem = Memory::new();
+        let args = [b"prog".to_vec(), b"-v".to_vec()];
+        let image = load(&executable(&[0x4e71]), &mut mem, &args, &[b"HOME=/".to_vec()]).unwrap();
+        assert_eq!(mem.read_u16(image.pc).unwrap(), 0x4e71);
+        assert_eq!(image.brk, TEXT + 0x4000);
+        assert!(mem.prot(0).is_none());
+        assert_eq!(mem.prot(TEXT + 0x2000), Some(VM_PROT_READ | VM_PROT_WRITE));
+
+        let sp = image.registers[15];
+        assert_eq!(sp % 4, 0);
+        assert_eq!(mem.read_u32(sp).unwrap(), 2);
+        assert_eq!(mem.read_cstr(mem.read_u32(sp + 8).unwrap()).unwrap(), b"-v");
+        assert_eq!(mem.read_u32(sp + 12).unwrap(), 0);
+        assert_eq!(mem.read_cstr(mem.read_u32(sp + 16).unwrap()).unwrap(), b"HOME=/");
+        assert_eq!(mem.read_u32(sp + 20).unwrap(), 0);
+
+        let mut object = executable(&[]);
+        object[12..16].copy_from_slice(&MH_OBJECT.to_be_bytes());
+        assert!(matches!(load(&object, &mut Memory::new(), &[], &[]), Err(Error::NotExecutable(_))));
+    }
+}
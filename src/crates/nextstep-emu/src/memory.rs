@@ -0,0 +1,298 @@
+//! Guest memory
+//!
+//! The 32-bit guest address space is a table of 8 KB pages, the NeXT m68k
+//! page size, each with its own `VM_PROT_*` protection. Unmapped pages
+//! and accesses the protection forbids fault, which the emulator turns
+//! into `SIGSEGV`. Words and longs are big-endian and, as on the 68020
+//! and later, need no alignment.
+
+use nextstep_macho::consts::{VM_PROT_READ, VM_PROT_WRITE};
+
+/// `vm_page_size` on NeXT m68k machines
+pub const PAGE_SIZE: u32 = 0x2000;
+const PAGE_SHIFT: u32 = 13;
+const PAGE_COUNT: usize = 1 << (32 - PAGE_SHIFT);
+
+/// A refused access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    pub address: u32,
+    pub write: bool,
+}
+
+struct Page {
+    prot: u32,
+    data: [u8; PAGE_SIZE as usize],
+}
+
+/// The guest's address space
+pub struct Memory {
+    pages: Vec<Option<Box<Page>>>,
+}
+
+/// `address` rounded down to a page boundary
+pub fn trunc_page(address: u32) -> u32 {
+    address & !(PAGE_SIZE - 1)
+}
+
+/// `size` rounded up to whole pages, or `None` past 4 GB
+pub fn round_page(size: u32) -> Option<u32> {
+    size.checked_add(PAGE_SIZE - 1).map(trunc_page)
+}
+
+// Page numbers covering `size` bytes at `address`, stopping at 4 GB
+fn page_range(address: u32, size: u32) -> std::ops::Range<usize> {
+    let first = (address >> PAGE_SHIFT) as usize;
+    let end = (address as u64 + size as u64).div_ceil(PAGE_SIZE as u64) as usize;
+    first..end.clamp(first, PAGE_COUNT)
+}
+
+impl Default for Memory {
+    fn default() -> Memory {
+        Memory::new()
+    }
+}
+
+impl Memory {
+    pub fn new() -> Memory {
+        let mut pages = Vec::with_capacity(PAGE_COUNT);
+        pages.resize_with(PAGE_COUNT, || None);
+        Memory { pages }
+    }
+
+    /// Map zero-filled pages over `size` bytes at `address`, keeping the
+    /// contents of pages already mapped, and give them all `prot`
+    pub fn map(&mut self, address: u32, size: u32, prot: u32) {
+        for page in page_range(address, size) {
+            match &mut self.pages[page] {
+                Some(existing) => existing.prot = prot,
+                slot => *slot = Some(Box::new(Page { prot, data: [0; PAGE_SIZE as usize] })),
+            }
+        }
+    }
+
+    /// Unmap the pages over `size` bytes at `address`
+    pub fn unmap(&mut self, address: u32, size: u32) {
+        for page in page_range(address, size) {
+            self.pages[page] = None;
+        }
+    }
+
+    /// Change the protection of mapped pages; false if any is unmapped
+    pub fn protect(&mut self, address: u32, size: u32, prot: u32) -> bool {
+        if !self.is_mapped(address, size) {
+            return false;
+        }
+        for page in self.pages[page_range(address, size)].iter_mut().flatten() {
+            page.prot = prot;
+        }
+        true
+    }
+
+    /// Whether every page over `size` bytes at `address` is mapped
+    pub fn is_mapped(&self, address: u32, size: u32) -> bool {
+        page_range(address, size).all(|page| self.pages[page].is_some())
+    }
+
+    /// Whether no page over `size` bytes at `address` is mapped
+    pub fn is_free(&self, address: u32, size: u32) -> bool {
+        address as u64 + size as u64 <= 1 << 32 && page_range(address, size).all(|page| self.pages[page].is_none())
+    }
+
+    /// The first free page-aligned range of `size` bytes at or above `from`
+    pub fn find_free(&self, from: u32, size: u32) -> Option<u32> {
+        let pages = round_page(size)? >> PAGE_SHIFT;
+        let mut start = (round_page(from)? >> PAGE_SHIFT) as usize;
+        let mut run = 0;
+        let mut page = start;
+        while page < PAGE_COUNT {
+            if self.pages[page].is_some() {
+                run = 0;
+                start = page + 1;
+            } else {
+                run += 1;
+                if run == pages {
+                    return Some((start as u32) << PAGE_SHIFT);
+                }
+            }
+            page += 1;
+        }
+        None
+    }
+
+    /// Protection of the page holding `address`, if it is mapped
+    pub fn prot(&self, address: u32) -> Option<u32> {
+        self.pages[(address >> PAGE_SHIFT) as usize].as_ref().map(|page| page.prot)
+    }
+
+    /// The first run of mapped pages with one protection at or above
+    /// `address`: its start, size and protection, as `vm_region` reports
+    pub fn region(&self, address: u32) -> Option<(u32, u32, u32)> {
+        let first = (address >> PAGE_SHIFT) as usize;
+        let start = (first..PAGE_COUNT).find(|&page| self.pages[page].is_some())?;
+        let prot = self.pages[start].as_ref()?.prot;
+        let end = (start..PAGE_COUNT)
+            .find(|&page| self.pages[page].as_ref().is_none_or(|p| p.prot != prot))
+            .unwrap_or(PAGE_COUNT);
+        Some(((start as u32) << PAGE_SHIFT, ((end - start) as u64 * PAGE_SIZE as u64).min(u32::MAX as u64) as u32, prot))
+    }
+
+    fn page(&self, address: u32) -> Result<&Page, Fault> {
+        match &self.pages[(address >> PAGE_SHIFT) as usize] {
+            Some(page) if page.prot & VM_PROT_READ != 0 => Ok(page),
+            _ => Err(Fault { address, write: false }),
+        }
+    }
+
+    fn page_mut(&mut self, address: u32, force: bool) -> Result<&mut Page, Fault> {
+        match &mut self.pages[(address >> PAGE_SHIFT) as usize] {
+            Some(page) if force || page.prot & VM_PROT_WRITE != 0 => Ok(page),
+            _ => Err(Fault { address, write: true }),
+        }
+    }
+
+    pub fn read_u8(&self, address: u32) -> Result<u8, Fault> {
+        Ok(self.page(address)?.data[(address & (PAGE_SIZE - 1)) as usize])
+    }
+
+    pub fn read_u16(&self, address: u32) -> Result<u16, Fault> {
+        let offset = (address & (PAGE_SIZE - 1)) as usize;
+        if offset + 2 <= PAGE_SIZE as usize {
+            let data = &self.page(address)?.data;
+            return Ok(u16::from_be_bytes([data[offset], data[offset + 1]]));
+        }
+        Ok(u16::from_be_bytes([self.read_u8(address)?, self.read_u8(address.wrapping_add(1))?]))
+    }
+
+    pub fn read_u32(&self, address: u32) -> Result<u32, Fault> {
+        let offset = (address & (PAGE_SIZE - 1)) as usize;
+        if offset + 4 <= PAGE_SIZE as usize {
+            let data = &self.page(address)?.data;
+            return Ok(u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]));
+        }
+        let high = self.read_u16(address)? as u32;
+        Ok(high << 16 | self.read_u16(address.wrapping_add(2))? as u32)
+    }
+
+    pub fn write_u8(&mut self, address: u32, value: u8) -> Result<(), Fault> {
+        self.page_mut(address, false)?.data[(address & (PAGE_SIZE - 1)) as usize] = value;
+        Ok(())
+    }
+
+    pub fn write_u16(&mut self, address: u32, value: u16) -> Result<(), Fault> {
+        self.write(address, &value.to_be_bytes())
+    }
+
+    pub fn write_u32(&mut self, address: u32, value: u32) -> Result<(), Fault> {
+        self.write(address, &value.to_be_bytes())
+    }
+
+    /// Read `buf.len()` bytes at `address`
+    pub fn read(&self, address: u32, buf: &mut [u8]) -> Result<(), Fault> {
+        let mut done = 0;
+        while done < buf.len() {
+            let at = address.wrapping_add(done as u32);
+            let offset = (at & (PAGE_SIZE - 1)) as usize;
+            let len = (buf.len() - done).min(PAGE_SIZE as usize - offset);
+            buf[done..done + len].copy_from_slice(&self.page(at)?.data[offset..offset + len]);
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// `len` bytes at `address`
+    pub fn read_vec(&self, address: u32, len: u32) -> Result<Vec<u8>, Fault> {
+        // Check before allocating, so a bad length cannot exhaust the host
+        if len > 0 && !self.is_mapped(address, len) {
+            let first = page_range(address, len).find(|&page| self.pages[page].is_none()).unwrap_or(0);
+            return Err(Fault { address: ((first as u32) << PAGE_SHIFT).max(address), write: false });
+        }
+        let mut buf = vec![0; len as usize];
+        self.read(address, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// The NUL-terminated string at `address`, without the NUL
+    pub fn read_cstr(&self, address: u32) -> Result<Vec<u8>, Fault> {
+        let mut out = Vec::new();
+        let mut at = address;
+        loop {
+            match self.read_u8(at)? {
+                0 => return Ok(out),
+                byte => out.push(byte),
+            }
+            at = at.wrapping_add(1);
+        }
+    }
+
+    /// Write `data` at `address`
+    pub fn write(&mut self, address: u32, data: &[u8]) -> Result<(), Fault> {
+        self.copy_in(address, data, false)
+    }
+
+    /// Write `data` at `address` whatever the protection, as the loader
+    /// does; the pages must be mapped
+    pub fn poke(&mut self, address: u32, data: &[u8]) -> Result<(), Fault> {
+        self.copy_in(address, data, true)
+    }
+
+    fn copy_in(&mut self, address: u32, data: &[u8], force: bool) -> Result<(), Fault> {
+        // A write that faults part way leaves nothing written
+        let writable = page_range(address, data.len() as u32).all(|page| {
+            matches!(&self.pages[page], Some(p) if force || p.prot & VM_PROT_WRITE != 0)
+        });
+        if !writable {
+            let page = page_range(address, data.len() as u32)
+                .find(|&page| !matches!(&self.pages[page], Some(p) if force || p.prot & VM_PROT_WRITE != 0))
+                .unwrap_or(0);
+            return Err(Fault { address: ((page as u32) << PAGE_SHIFT).max(address), write: true });
+        }
+        let mut done = 0;
+        while done < data.len() {
+            let at = address.wrapping_add(done as u32);
+            let offset = (at & (PAGE_SIZE - 1)) as usize;
+            let len = (data.len() - done).min(PAGE_SIZE as usize - offset);
+            self.page_mut(at, force)?.data[offset..offset + len].copy_from_slice(&data[done..done + len]);
+            done += len;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nextstep_macho::consts::VM_PROT_READ;
+
+    #[test]
+    fn test_memory() {
+        let mut mem = Memory::new();
+        assert_eq!(mem.read_u8(0), Err(Fault { address: 0, write: false }));
+        mem.map(0x4000, 0x4000, VM_PROT_READ | VM_PROT_WRITE);
+        assert!(mem.is_mapped(0x4000, 0x4000) && !mem.is_free(0x5000, 1));
+        assert!(mem.is_free(0x8000, 0x2000));
+
+        // Longs straddling a page boundary
+        mem.write_u32(0x5ffe, 0x1234_5678).unwrap();
+        assert_eq!(mem.read_u32(0x5ffe).unwrap(), 0x1234_5678);
+        assert_eq!(mem.read_u16(0x5fff).unwrap(), 0x3456);
+        assert_eq!(mem.write_u32(0x7ffe, 1), Err(Fault { address: 0x8000, write: true }));
+        assert_eq!(mem.read_u8(0x7fff).unwrap(), 0);
+
+        mem.write(0x6000, b"hi\0").unwrap();
+        assert_eq!(mem.read_cstr(0x6000).unwrap(), b"hi");
+        assert_eq!(mem.read_vec(0x7fff, 2), Err(Fault { address: 0x8000, write: false }));
+
+        assert!(mem.protect(0x4000, 0x4000, VM_PROT_READ));
+        assert_eq!(mem.write_u8(0x4000, 1), Err(Fault { address: 0x4000, write: true }));
+        mem.poke(0x4000, &[7]).unwrap();
+        assert_eq!(mem.read_u8(0x4000).unwrap(), 7);
+
+        assert_eq!(mem.find_free(0x1000, 0x2001), Some(0x8000));
+        assert_eq!(mem.find_free(0, 0x4000), Some(0));
+        mem.unmap(0x4000, 0x2000);
+        assert_eq!(mem.find_free(0x3000, 0x2000), Some(0x4000));
+        assert_eq!(mem.region(0), Some((0x6000, 0x2000, VM_PROT_READ)));
+        assert_eq!(round_page(0xffff_ff00), None);
+    }
+}
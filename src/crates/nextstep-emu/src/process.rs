@@ -0,0 +1,176 @@
+//! A running guest process
+//!
+//! Ties the CPU, memory and system calls together: loads the executable,
+//! steps the CPU, hands `trap #0` to the system call layer, and turns
+//! every other exception into the signal the NeXTSTEP kernel would send.
+
+use crate::cpu::{Cpu, Exception};
+use crate::error::{Error, Result};
+use crate::loader::load;
+use crate::memory::Memory;
+use crate::syscall::{Stop, System, SIGBUS, SIGFPE, SIGILL, SIGSEGV, SIGSYS, SIGTRAP};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Instructions between checks of the clock
+const SLICE: u32 = 0x10000;
+
+/// How to start a process
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// `argv`, starting with the program name
+    pub args: Vec<Vec<u8>>,
+    /// `envp`, as `NAME=value` strings
+    pub env: Vec<Vec<u8>>,
+    /// Log every system call to stderr
+    pub strace: bool,
+    /// Collect stdout and stderr for [`Process::output`] and give the
+    /// guest an empty stdin, instead of using the host's
+    pub capture: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options { args: vec![b"program".to_vec()], env: Vec::new(), strace: false, capture: false }
+    }
+}
+
+/// How a process ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exit {
+    Exited(i32),
+    /// Killed by `signal`; `reason` says what the guest did
+    Signaled { signal: u32, reason: String },
+    TimedOut,
+}
+
+impl Exit {
+    /// The status a shell would report: the exit status, or 128 plus the
+    /// signal number
+    pub fn code(&self) -> i32 {
+        match self {
+            Exit::Exited(status) => *status,
+            Exit::Signaled { signal, .. } => 128 + *signal as i32,
+            Exit::TimedOut => 124,
+        }
+    }
+}
+
+pub struct Process {
+    pub cpu: Cpu,
+    pub memory: Memory,
+    system: System,
+    /// Instructions executed so far
+    pub steps: u64,
+}
+
+fn signal_for(exception: Exception) -> (u32, String) {
+    match exception {
+        Exception::Trap(15) => (SIGTRAP, "breakpoint trap #15".into()),
+        Exception::Trap(n) => (SIGILL, format!("unhandled trap #{}", n)),
+        Exception::TrapV => (SIGFPE, "TRAPV or TRAPcc".into()),
+        Exception::Chk => (SIGFPE, "CHK bounds check".into()),
+        Exception::ZeroDivide => (SIGFPE, "integer divide by zero".into()),
+        Exception::Illegal(op) => (SIGILL, format!("illegal instruction {:#06x}", op)),
+        Exception::Privilege(op) => (SIGILL, format!("privileged instruction {:#06x}", op)),
+        Exception::LineA(op) => (SIGILL, format!("line A instruction {:#06x}", op)),
+        Exception::LineF(op) => (SIGILL, format!("line F (FPU) instruction {:#06x}, not emulated", op)),
+        Exception::AddressError(pc) => (SIGBUS, format!("instruction fetch from odd address {:#x}", pc)),
+        Exception::BusError(fault) => {
+            let access = if fault.write { "write to" } else { "read of" };
+            (SIGSEGV, format!("{} {:#x}", access, fault.address))
+        }
+    }
+}
+
+impl Process {
+    /// Load `data`, an m68k `MH_EXECUTE`, thin or fat
+    pub fn load(data: &[u8], options: &Options) -> Result<Process> {
+        let mut memory = Memory::new();
+        let image = load(data, &mut memory, &options.args, &options.env)?;
+        let mut cpu = Cpu { pc: image.pc, ..Cpu::default() };
+        cpu.d.copy_from_slice(&image.registers[..8]);
+        cpu.a.copy_from_slice(&image.registers[8..]);
+        let system = System::new(options.strace, options.capture, image.brk);
+        Ok(Process { cpu, memory, system, steps: 0 })
+    }
+
+    /// Read and load the executable at `path`
+    pub fn open(path: &Path, options: &Options) -> Result<Process> {
+        let data = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+        Process::load(&data, options)
+    }
+
+    /// Run until the guest exits, is killed, or `timeout` passes
+    pub fn run(&mut self, timeout: Option<Duration>) -> Exit {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            for _ in 0..SLICE {
+                self.steps += 1;
+                let exception = match self.cpu.step(&mut self.memory) {
+                    Ok(()) => continue,
+                    Err(exception) => exception,
+                };
+                let stop = match exception {
+                    Exception::Trap(0) => match self.system.trap(&mut self.cpu, &mut self.memory) {
+                        None => continue,
+                        Some(stop) => stop,
+                    },
+                    _ => {
+                        let (signal, reason) = signal_for(exception);
+                        let reason = format!("{} at pc {:#x}", reason, self.cpu.insn_pc);
+                        return Exit::Signaled { signal, reason };
+                    }
+                };
+                return match stop {
+                    Stop::Exit(status) => Exit::Exited(status),
+                    Stop::Signal(SIGSYS) => {
+                        let reason = format!("bad system call {} at pc {:#x}", self.cpu.d[0] as i32, self.cpu.insn_pc);
+                        Exit::Signaled { signal: SIGSYS, reason }
+                    }
+                    Stop::Signal(signal) => Exit::Signaled { signal, reason: "kill".into() },
+                };
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Exit::TimedOut;
+            }
+        }
+    }
+
+    /// Captured stdout and stderr, interleaved as written
+    pub fn output(&self) -> &[u8] {
+        self.system.output.as_deref().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::tests::executable;
+
+    #[test]
+    fn test_run() {
+        // write(1, msg, 3) and exit(7), with the arguments pushed above a
+        // return address slot as examples/hello-simple.rs passes them
+        let code = [
+            0x4878, 0x0003, 0x487a, 0x0016, 0x4878, 0x0001, // pea 3.w; pea msg(pc); pea 1.w
+            0x42a7, 0x7004, 0x4e40, // clr.l -(sp); moveq #4,d0; trap #0
+            0x4878, 0x0007, 0x42a7, 0x7001, 0x4e40, // pea 7.w; clr.l -(sp); moveq #1,d0; trap #0
+            0x4f4b, 0x0a00, // "OK\n"
+        ];
+        let options = Options { capture: true, ..Options::default() };
+        let mut process = Process::load(&executable(&code), &options).unwrap();
+        assert_eq!(process.run(None), Exit::Exited(7));
+        assert_eq!(process.output(), b"OK\n");
+
+        // A store through a null pointer
+        let mut process = Process::load(&executable(&[0x4290, 0x4e71]), &options).unwrap();
+        let exit = process.run(None);
+        assert!(matches!(&exit, Exit::Signaled { signal: SIGSEGV, reason } if reason.starts_with("write to 0x0")));
+        assert_eq!(exit.code(), 139);
+
+        // bra.s to itself never ends
+        let mut process = Process::load(&executable(&[0x60fe]), &options).unwrap();
+        assert_eq!(process.run(Some(Duration::from_millis(10))), Exit::TimedOut);
+    }
+}
@@ -0,0 +1,1100 @@
+//! NeXTSTEP system calls and Mach traps, carried out on the host
+//!
+//! `trap #0` with a positive number in D0 is a BSD system call: the
+//! result goes in D0 (and D1 for `pipe`), or the carry flag is set and D0
+//! holds the errno. A negative number is a Mach trap returning a
+//! `kern_return_t`. Arguments are on the stack above the libsys stub's
+//! return address.
+//!
+//! Files and directories are the host's, seen from an emulated current
+//! directory; host errno values are translated to NeXTSTEP's. Signals
+//! are not delivered: one that is not ignored ends the process, as its
+//! default action would. Calls that cannot work in a single emulated
+//! process (`fork`, `execve`, `mount`, ...) fail with `EOPNOTSUPP`; an
+//! unknown call number raises `SIGSYS`.
+
+use crate::cpu::Cpu;
+use crate::memory::{round_page, trunc_page, Fault, Memory, PAGE_SIZE};
+use nextstep_macho::consts::{VM_PROT_READ, VM_PROT_WRITE};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::os::fd::AsFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// NeXTSTEP errno values
+const EPERM: u32 = 1;
+const ENOENT: u32 = 2;
+const EINTR: u32 = 4;
+const EIO: u32 = 5;
+const EBADF: u32 = 9;
+const ECHILD: u32 = 10;
+const EAGAIN: u32 = 11;
+const ENOMEM: u32 = 12;
+const EACCES: u32 = 13;
+const EFAULT: u32 = 14;
+const EISDIR: u32 = 21;
+const EINVAL: u32 = 22;
+const EMFILE: u32 = 24;
+const ENOTTY: u32 = 25;
+const ESPIPE: u32 = 29;
+const EOPNOTSUPP: u32 = 45;
+const ENAMETOOLONG: u32 = 63;
+const ENOSYS: u32 = 78;
+
+// Signals the emulator raises itself
+pub const SIGILL: u32 = 4;
+pub const SIGTRAP: u32 = 5;
+pub const SIGFPE: u32 = 8;
+pub const SIGBUS: u32 = 10;
+pub const SIGSEGV: u32 = 11;
+pub const SIGSYS: u32 = 12;
+
+// Mach return codes
+const KERN_SUCCESS: u32 = 0;
+const KERN_INVALID_ADDRESS: u32 = 1;
+const KERN_PROTECTION_FAILURE: u32 = 2;
+const KERN_NO_SPACE: u32 = 3;
+const KERN_INVALID_ARGUMENT: u32 = 4;
+const KERN_FAILURE: u32 = 5;
+
+// Port names handed out by the Mach traps
+const TASK_PORT: u32 = 1;
+const THREAD_PORT: u32 = 2;
+const REPLY_PORT: u32 = 3;
+const NOTIFY_PORT: u32 = 4;
+
+/// Where `vm_allocate` and `mmap` look for free space, above the stack
+pub const ALLOCATE_BASE: u32 = 0x0400_0000;
+/// Size of the descriptor table
+const OPEN_MAX: usize = 256;
+
+// (number, name, argument count) of every call handled, for `--strace`
+const CALLS: &[(i32, &str, usize)] = &[
+    (0, "syscall", 1),
+    (1, "exit", 1),
+    (2, "fork", 0),
+    (3, "read", 3),
+    (4, "write", 3),
+    (5, "open", 3),
+    (6, "close", 1),
+    (7, "wait4", 4),
+    (9, "link", 2),
+    (10, "unlink", 1),
+    (12, "chdir", 1),
+    (14, "mknod", 3),
+    (15, "chmod", 2),
+    (16, "chown", 3),
+    (17, "obreak", 1),
+    (19, "lseek", 3),
+    (20, "getpid", 0),
+    (21, "mount", 4),
+    (22, "umount", 1),
+    (23, "setuid", 1),
+    (24, "getuid", 0),
+    (25, "geteuid", 0),
+    (33, "access", 2),
+    (36, "sync", 0),
+    (37, "kill", 2),
+    (38, "stat", 2),
+    (39, "getppid", 0),
+    (40, "lstat", 2),
+    (41, "dup", 1),
+    (42, "pipe", 0),
+    (47, "getgid", 0),
+    (48, "getegid", 0),
+    (54, "ioctl", 3),
+    (57, "symlink", 2),
+    (58, "readlink", 3),
+    (59, "execve", 3),
+    (60, "umask", 1),
+    (61, "chroot", 1),
+    (62, "fstat", 2),
+    (64, "getpagesize", 0),
+    (66, "vfork", 0),
+    (69, "sbrk", 1),
+    (71, "mmap", 6),
+    (73, "munmap", 2),
+    (74, "mprotect", 3),
+    (89, "getdtablesize", 0),
+    (90, "dup2", 2),
+    (92, "fcntl", 3),
+    (108, "sigvec", 3),
+    (109, "sigblock", 1),
+    (110, "sigsetmask", 1),
+    (111, "sigpause", 1),
+    (112, "sigstack", 2),
+    (116, "gettimeofday", 2),
+    (117, "getrusage", 2),
+    (118, "getsockopt", 5),
+    (120, "readv", 3),
+    (121, "writev", 3),
+    (122, "settimeofday", 2),
+    (123, "fchown", 3),
+    (124, "fchmod", 2),
+    (128, "rename", 2),
+    (129, "truncate", 2),
+    (130, "ftruncate", 2),
+    (131, "flock", 2),
+    (136, "mkdir", 2),
+    (137, "rmdir", 1),
+    (138, "utimes", 2),
+    (156, "getdirentries", 4),
+    (-10, "task_self", 0),
+    (-11, "thread_reply", 0),
+    (-12, "task_notify", 0),
+    (-13, "thread_self", 0),
+    (-59, "swtch_pri", 1),
+    (-60, "swtch", 0),
+    (-61, "thread_switch", 3),
+    (-64, "vm_allocate", 4),
+    (-65, "vm_deallocate", 3),
+    (-66, "vm_protect", 5),
+    (-67, "vm_inherit", 4),
+    (-68, "vm_read", 5),
+    (-69, "vm_write", 4),
+    (-70, "vm_copy", 4),
+    (-71, "vm_region", 9),
+    (-72, "vm_statistics", 2),
+    (-168, "task_create", 3),
+];
+
+// Calls that exist on NeXTSTEP but not in one emulated process
+const UNSUPPORTED: &[i32] = &[2, 14, 21, 22, 59, 61, 66, 118, 122];
+
+/// A failed BSD call's errno
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Errno(pub u32);
+
+impl From<Fault> for Errno {
+    fn from(_: Fault) -> Errno {
+        Errno(EFAULT)
+    }
+}
+
+impl From<io::Error> for Errno {
+    fn from(error: io::Error) -> Errno {
+        Errno(error.raw_os_error().map_or(EIO, host_errno))
+    }
+}
+
+// A host errno as NeXTSTEP numbers it; the first 34 agree with Linux
+fn host_errno(errno: i32) -> u32 {
+    if !cfg!(target_os = "linux") {
+        return errno as u32;
+    }
+    match errno {
+        1..=34 => errno as u32,
+        35 => EAGAIN,
+        36 => ENAMETOOLONG,
+        38 => ENOSYS,
+        39 => 66,
+        40 => 62,
+        95 => EOPNOTSUPP,
+        110 => 60,
+        122 => 69,
+        _ => EIO,
+    }
+}
+
+/// Why the process stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stop {
+    Exit(i32),
+    Signal(u32),
+}
+
+// Both ends of an in-process pipe share the buffer; the read end sees
+// end of file once every write end is closed
+#[derive(Clone)]
+enum Handle {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(Rc<File>),
+    Directory(Rc<RefCell<Directory>>),
+    PipeRead(Rc<RefCell<VecDeque<u8>>>, Weak<()>),
+    PipeWrite { buffer: Rc<RefCell<VecDeque<u8>>>, _open: Rc<()> },
+}
+
+struct Directory {
+    file: File,
+    path: PathBuf,
+    // Inode and name of each entry, read on first use
+    entries: Option<Vec<(u32, Vec<u8>)>>,
+    position: usize,
+}
+
+#[derive(Clone)]
+struct Slot {
+    handle: Handle,
+    // The open flags, for F_GETFL
+    flags: u32,
+}
+
+/// The emulated kernel state of the process
+pub(crate) struct System {
+    strace: bool,
+    files: Vec<Option<Slot>>,
+    cwd: PathBuf,
+    umask: u32,
+    brk: u32,
+    /// Captured stdout and stderr, if they are captured
+    pub(crate) output: Option<Vec<u8>>,
+    handlers: [u32; 32],
+    signal_mask: u32,
+    stop: Option<Stop>,
+}
+
+fn words(mem: &mut Memory, address: u32, words: &[u32]) -> Result<(), Fault> {
+    let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes()).collect();
+    mem.write(address, &bytes)
+}
+
+// A NeXTSTEP `struct stat`
+fn stat_bytes(meta: &Metadata) -> Vec<u8> {
+    let mut out = Vec::with_capacity(68);
+    out.extend((meta.dev() as u32).to_be_bytes());
+    out.extend((meta.ino() as u32).to_be_bytes());
+    out.extend((meta.mode() as u16).to_be_bytes());
+    out.extend((meta.nlink().min(0xffff) as u16).to_be_bytes());
+    for word in [meta.uid(), meta.gid(), meta.rdev() as u32, meta.size().min(i32::MAX as u64) as u32] {
+        out.extend(word.to_be_bytes());
+    }
+    for time in [meta.atime(), meta.mtime(), meta.ctime()] {
+        out.extend((time as u32).to_be_bytes());
+        out.extend([0; 4]);
+    }
+    out.extend((meta.blksize() as u32).to_be_bytes());
+    out.extend((meta.blocks() as u32).to_be_bytes());
+    out.extend([0; 8]);
+    out
+}
+
+// A `struct stat` for a pipe or captured stream, which has no host file
+fn fifo_stat() -> Vec<u8> {
+    let mut out = vec![0; 68];
+    out[8..10].copy_from_slice(&0o010600u16.to_be_bytes());
+    out[11] = 1;
+    out[52..56].copy_from_slice(&PAGE_SIZE.to_be_bytes());
+    out
+}
+
+fn timeval(time: SystemTime) -> [u32; 2] {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    [since.as_secs() as u32, since.subsec_micros()]
+}
+
+// The owner, group and other permission check `access` does
+fn permitted(meta: &Metadata, mode: u32) -> bool {
+    let me = fs::metadata("/proc/self").map(|m| (m.uid(), m.gid())).unwrap_or((u32::MAX, u32::MAX));
+    let bits = meta.mode();
+    if me.0 == 0 {
+        return mode & 1 == 0 || bits & 0o111 != 0 || meta.is_dir();
+    }
+    let allowed = if meta.uid() == me.0 {
+        bits >> 6
+    } else if meta.gid() == me.1 {
+        bits >> 3
+    } else {
+        bits
+    };
+    mode & 7 & !allowed == 0
+}
+
+impl System {
+    pub(crate) fn new(strace: bool, capture: bool, brk: u32) -> System {
+        let slot = |handle| Some(Slot { handle, flags: 0 });
+        let mut files = vec![slot(Handle::Stdin), slot(Handle::Stdout), slot(Handle::Stderr)];
+        files[1].as_mut().unwrap().flags = 1;
+        files[2].as_mut().unwrap().flags = 1;
+        System {
+            strace,
+            files,
+            cwd: std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/")),
+            umask: 0o022,
+            brk,
+            output: capture.then(Vec::new),
+            handlers: [0; 32],
+            signal_mask: 0,
+            stop: None,
+        }
+    }
+
+    /// Run the call `trap #0` asks for, updating D0, D1 and the carry
+    pub(crate) fn trap(&mut self, cpu: &mut Cpu, mem: &mut Memory) -> Option<Stop> {
+        let mut number = cpu.d[0] as i32;
+        let mut args: Vec<u32> =
+            (0..10).map(|i| mem.read_u32(cpu.a[7].wrapping_add(4 + 4 * i)).unwrap_or(0)).collect();
+        if number == 0 {
+            number = args.remove(0) as i32;
+        }
+        let name = CALLS.iter().find(|c| c.0 == number);
+        if number < 0 {
+            let Some(result) = self.mach(number, &args, mem) else {
+                self.trace(number, &args, "unknown Mach trap".into());
+                return Some(Stop::Signal(SIGSYS));
+            };
+            cpu.d[0] = result;
+            self.trace(number, &args, format!("{}", result));
+            return None;
+        }
+        let result = if name.is_none() {
+            self.stop = Some(Stop::Signal(SIGSYS));
+            Err(Errno(ENOSYS))
+        } else if UNSUPPORTED.contains(&number) {
+            Err(Errno(EOPNOTSUPP))
+        } else {
+            self.bsd(number, &args, cpu, mem)
+        };
+        match result {
+            Ok(value) => {
+                cpu.d[0] = value;
+                cpu.c = false;
+                self.trace(number, &args, format!("{}", value as i32));
+            }
+            Err(Errno(errno)) => {
+                cpu.d[0] = errno;
+                cpu.c = true;
+                self.trace(number, &args, format!("-1 errno {}", errno));
+            }
+        }
+        self.stop.take()
+    }
+
+    fn trace(&self, number: i32, args: &[u32], result: String) {
+        if !self.strace {
+            return;
+        }
+        let (name, count) = CALLS
+            .iter()
+            .find(|c| c.0 == number)
+            .map_or_else(|| (format!("syscall_{}", number), 6), |c| (c.1.to_string(), c.2));
+        let args: Vec<String> = args
+            .iter()
+            .take(count)
+            .map(|&a| if a > 0xffff && (a as i32) > -4096 { format!("{:#x}", a) } else { (a as i32).to_string() })
+            .collect();
+        eprintln!("nextstep-emu: {}({}) = {}", name, args.join(", "), result);
+    }
+
+    fn path(&self, mem: &Memory, address: u32) -> Result<PathBuf, Errno> {
+        let bytes = mem.read_cstr(address)?;
+        if bytes.len() >= 1024 {
+            return Err(Errno(ENAMETOOLONG));
+        }
+        if bytes.is_empty() {
+            return Err(Errno(ENOENT));
+        }
+        Ok(self.cwd.join(std::ffi::OsStr::from_bytes(&bytes)))
+    }
+
+    fn slot(&self, fd: u32) -> Result<&Slot, Errno> {
+        self.files.get(fd as usize).and_then(Option::as_ref).ok_or(Errno(EBADF))
+    }
+
+    fn handle(&self, fd: u32) -> Result<Handle, Errno> {
+        Ok(self.slot(fd)?.handle.clone())
+    }
+
+    // The lowest free descriptor at or above `from`
+    fn install(&mut self, slot: Slot, from: usize) -> Result<u32, Errno> {
+        let fd = (from..OPEN_MAX).find(|&fd| self.files.get(fd).is_none_or(Option::is_none)).ok_or(Errno(EMFILE))?;
+        if fd >= self.files.len() {
+            self.files.resize(fd + 1, None);
+        }
+        self.files[fd] = Some(slot);
+        Ok(fd as u32)
+    }
+
+    fn read_fd(&mut self, fd: u32, len: u32) -> Result<Vec<u8>, Errno> {
+        let mut buf = vec![0; len.min(1 << 20) as usize];
+        let count = match self.handle(fd)? {
+            Handle::Stdin if self.output.is_some() => 0,
+            Handle::Stdin => io::stdin().read(&mut buf)?,
+            Handle::File(file) => (&*file).read(&mut buf)?,
+            Handle::Directory(_) => return Err(Errno(EISDIR)),
+            Handle::PipeRead(pipe, writers) => {
+                let mut pipe = pipe.borrow_mut();
+                if pipe.is_empty() && writers.strong_count() > 0 {
+                    // Nothing else could fill it
+                    return Err(Errno(EAGAIN));
+                }
+                let count = buf.len().min(pipe.len());
+                for (to, from) in buf.iter_mut().zip(pipe.drain(..count)) {
+                    *to = from;
+                }
+                count
+            }
+            Handle::Stdout | Handle::Stderr | Handle::PipeWrite { .. } => return Err(Errno(EBADF)),
+        };
+        buf.truncate(count);
+        Ok(buf)
+    }
+
+    fn write_fd(&mut self, fd: u32, data: &[u8]) -> Result<u32, Errno> {
+        match self.handle(fd)? {
+            Handle::Stdout | Handle::Stderr if self.output.is_some() => {
+                self.output.as_mut().unwrap().extend_from_slice(data);
+            }
+            Handle::Stdout => {
+                let mut out = io::stdout().lock();
+                out.write_all(data)?;
+                out.flush()?;
+            }
+            Handle::Stderr => io::stderr().write_all(data)?,
+            Handle::File(file) => return Ok((&*file).write(data)? as u32),
+            Handle::PipeWrite { buffer, .. } => buffer.borrow_mut().extend(data),
+            Handle::Directory(_) => return Err(Errno(EISDIR)),
+            Handle::Stdin | Handle::PipeRead(..) => return Err(Errno(EBADF)),
+        }
+        Ok(data.len() as u32)
+    }
+
+    fn open(&mut self, path: &Path, flags: u32, mode: u32) -> Result<u32, Errno> {
+        let mut options = OpenOptions::new();
+        match flags & 3 {
+            0 => options.read(true),
+            1 => options.write(true),
+            2 => options.read(true).write(true),
+            _ => return Err(Errno(EINVAL)),
+        };
+        options.append(flags & 0x8 != 0).truncate(flags & 0x400 != 0);
+        if flags & 0x200 != 0 {
+            options.create(true).mode(mode & !self.umask & 0o7777);
+            if flags & 0x800 != 0 {
+                options.create_new(true);
+            }
+        }
+        let file = options.open(path)?;
+        let handle = if file.metadata()?.is_dir() {
+            let directory = Directory { file, path: path.to_path_buf(), entries: None, position: 0 };
+            Handle::Directory(Rc::new(RefCell::new(directory)))
+        } else {
+            Handle::File(Rc::new(file))
+        };
+        self.install(Slot { handle, flags }, 0)
+    }
+
+    fn fstat(&self, fd: u32) -> Result<Vec<u8>, Errno> {
+        let host = |fd: std::os::fd::BorrowedFd| -> Result<Vec<u8>, Errno> {
+            Ok(stat_bytes(&File::from(fd.try_clone_to_owned()?).metadata()?))
+        };
+        let captured = self.output.is_some();
+        match self.handle(fd)? {
+            Handle::Stdin | Handle::Stdout | Handle::Stderr if captured => Ok(fifo_stat()),
+            Handle::Stdin => host(io::stdin().as_fd()),
+            Handle::Stdout => host(io::stdout().as_fd()),
+            Handle::Stderr => host(io::stderr().as_fd()),
+            Handle::File(file) => Ok(stat_bytes(&file.metadata()?)),
+            Handle::Directory(directory) => Ok(stat_bytes(&directory.borrow().file.metadata()?)),
+            Handle::PipeRead(..) | Handle::PipeWrite { .. } => Ok(fifo_stat()),
+        }
+    }
+
+    fn is_terminal(&self, fd: u32) -> Result<bool, Errno> {
+        Ok(self.output.is_none()
+            && match self.handle(fd)? {
+                Handle::Stdin => io::stdin().is_terminal(),
+                Handle::Stdout => io::stdout().is_terminal(),
+                Handle::Stderr => io::stderr().is_terminal(),
+                _ => false,
+            })
+    }
+
+    fn getdirentries(&mut self, fd: u32, buf: u32, size: u32, basep: u32, mem: &mut Memory) -> Result<u32, Errno> {
+        let Handle::Directory(directory) = self.handle(fd)? else {
+            return Err(Errno(EINVAL));
+        };
+        let mut directory = directory.borrow_mut();
+        if directory.entries.is_none() {
+            let mut entries = vec![(0, b".".to_vec()), (0, b"..".to_vec())];
+            for entry in fs::read_dir(&directory.path)? {
+                let entry = entry?;
+                let name = entry.file_name().as_bytes().to_vec();
+                entries.push((entry.metadata().map_or(0, |m| m.ino() as u32), name));
+            }
+            directory.entries = Some(entries);
+        }
+        if basep != 0 {
+            mem.write_u32(basep, directory.position as u32)?;
+        }
+        // struct direct: d_ino, d_reclen, d_type, d_namlen, then the name
+        let mut out = Vec::new();
+        let entries = directory.entries.as_ref().unwrap();
+        let mut position = directory.position;
+        while let Some((ino, name)) = entries.get(position) {
+            let name = &name[..name.len().min(255)];
+            let reclen = (8 + name.len() + 1).next_multiple_of(4);
+            if out.len() + reclen > size as usize {
+                if out.is_empty() {
+                    return Err(Errno(EINVAL));
+                }
+                break;
+            }
+            out.extend(ino.to_be_bytes());
+            out.extend((reclen as u16).to_be_bytes());
+            out.extend([0, name.len() as u8]);
+            out.extend(name);
+            out.resize(out.len() + reclen - 8 - name.len(), 0);
+            position += 1;
+        }
+        mem.write(buf, &out)?;
+        directory.position = position;
+        Ok(out.len() as u32)
+    }
+
+    // Fresh read-write pages at `address`, or anywhere free
+    fn allocate(&mut self, mem: &mut Memory, address: Option<u32>, size: u32) -> Option<u32> {
+        let size = round_page(size)?;
+        let address = match address {
+            Some(address) => mem.is_free(address, size).then_some(address)?,
+            None => mem.find_free(ALLOCATE_BASE, size)?,
+        };
+        mem.map(address, size, VM_PROT_READ | VM_PROT_WRITE);
+        Some(address)
+    }
+
+    fn bsd(&mut self, number: i32, a: &[u32], cpu: &mut Cpu, mem: &mut Memory) -> Result<u32, Errno> {
+        match number {
+            1 => {
+                self.stop = Some(Stop::Exit((a[0] & 0xff) as i32));
+                Ok(0)
+            }
+            3 => {
+                let data = self.read_fd(a[0], a[2])?;
+                mem.write(a[1], &data)?;
+                Ok(data.len() as u32)
+            }
+            4 => {
+                let data = mem.read_vec(a[1], a[2])?;
+                self.write_fd(a[0], &data)
+            }
+            5 => {
+                let path = self.path(mem, a[0])?;
+                self.open(&path, a[1], a[2])
+            }
+            6 => {
+                self.slot(a[0])?;
+                self.files[a[0] as usize] = None;
+                Ok(0)
+            }
+            7 => Err(Errno(ECHILD)),
+            9 => {
+                fs::hard_link(self.path(mem, a[0])?, self.path(mem, a[1])?)?;
+                Ok(0)
+            }
+            10 => {
+                fs::remove_file(self.path(mem, a[0])?)?;
+                Ok(0)
+            }
+            12 => {
+                let path = self.path(mem, a[0])?;
+                if !fs::metadata(&path)?.is_dir() {
+                    return Err(Errno(20));
+                }
+                self.cwd = path;
+                Ok(0)
+            }
+            15 => {
+                fs::set_permissions(self.path(mem, a[0])?, fs::Permissions::from_mode(a[1] & 0o7777))?;
+                Ok(0)
+            }
+            16 => {
+                let id = |v: u32| (v as i32 >= 0).then_some(v);
+                std::os::unix::fs::chown(self.path(mem, a[0])?, id(a[1]), id(a[2]))?;
+                Ok(0)
+            }
+            17 => {
+                // obreak: move the break to a[0]
+                let end = round_page(a[0]).ok_or(Errno(ENOMEM))?;
+                let current = round_page(self.brk).ok_or(Errno(ENOMEM))?;
+                if end > current {
+                    if !mem.is_free(current, end - current) {
+                        return Err(Errno(ENOMEM));
+                    }
+                    mem.map(current, end - current, VM_PROT_READ | VM_PROT_WRITE);
+                }
+                self.brk = a[0];
+                Ok(0)
+            }
+            19 => {
+                let offset = a[1] as i32 as i64;
+                let to = match a[2] {
+                    0 => SeekFrom::Start(offset.max(0) as u64),
+                    1 => SeekFrom::Current(offset),
+                    2 => SeekFrom::End(offset),
+                    _ => return Err(Errno(EINVAL)),
+                };
+                match self.handle(a[0])? {
+                    Handle::File(file) => {
+                        let position = (&*file).seek(to)?;
+                        i32::try_from(position).map(|p| p as u32).map_err(|_| Errno(EINVAL))
+                    }
+                    Handle::Directory(directory) if a[2] == 0 => {
+                        directory.borrow_mut().position = offset as usize;
+                        Ok(a[1])
+                    }
+                    _ => Err(Errno(ESPIPE)),
+                }
+            }
+            20 => Ok(std::process::id()),
+            23 => Err(Errno(EPERM)),
+            24 | 25 => Ok(fs::metadata("/proc/self").map_or(0, |m| m.uid())),
+            47 | 48 => Ok(fs::metadata("/proc/self").map_or(0, |m| m.gid())),
+            33 => {
+                let meta = fs::metadata(self.path(mem, a[0])?)?;
+                if permitted(&meta, a[1]) {
+                    Ok(0)
+                } else {
+                    Err(Errno(EACCES))
+                }
+            }
+            36 => Ok(0),
+            37 => {
+                if a[0] != 0 && a[0] != std::process::id() {
+                    return Err(Errno(EPERM));
+                }
+                let signal = a[1];
+                if signal >= 32 {
+                    return Err(Errno(EINVAL));
+                }
+                if signal != 0 && self.handlers[signal as usize] != 1 {
+                    self.stop = Some(Stop::Signal(signal));
+                }
+                Ok(0)
+            }
+            38 | 40 => {
+                let path = self.path(mem, a[0])?;
+                let meta = if number == 38 { fs::metadata(path)? } else { fs::symlink_metadata(path)? };
+                mem.write(a[1], &stat_bytes(&meta))?;
+                Ok(0)
+            }
+            62 => {
+                let stat = self.fstat(a[0])?;
+                mem.write(a[1], &stat)?;
+                Ok(0)
+            }
+            39 => Ok(std::os::unix::process::parent_id()),
+            41 => {
+                let slot = self.slot(a[0])?.clone();
+                self.install(slot, 0)
+            }
+            90 => {
+                let slot = self.slot(a[0])?.clone();
+                let to = a[1] as usize;
+                if to >= OPEN_MAX {
+                    return Err(Errno(EBADF));
+                }
+                if to >= self.files.len() {
+                    self.files.resize(to + 1, None);
+                }
+                self.files[to] = Some(slot);
+                Ok(a[1])
+            }
+            42 => {
+                let buffer = Rc::new(RefCell::new(VecDeque::new()));
+                let writer = Rc::new(());
+                let read = Handle::PipeRead(buffer.clone(), Rc::downgrade(&writer));
+                let read = self.install(Slot { handle: read, flags: 0 }, 0)?;
+                let write = self.install(Slot { handle: Handle::PipeWrite { buffer, _open: writer }, flags: 1 }, 0);
+                let write = write.inspect_err(|_| self.files[read as usize] = None)?;
+                cpu.d[1] = write;
+                Ok(read)
+            }
+            54 => {
+                if !self.is_terminal(a[0])? {
+                    return Err(Errno(ENOTTY));
+                }
+                match a[1] {
+                    // TIOCGETP: struct sgttyb, 9600 baud, echo and CRMOD
+                    0x4006_7408 => mem.write(a[2], &[13, 13, 0x7f, 0x15, 0, 0x18])?,
+                    // TIOCGWINSZ: 24 by 80
+                    0x4008_7468 => mem.write(a[2], &[0, 24, 0, 80, 0, 0, 0, 0])?,
+                    _ => return Err(Errno(ENOTTY)),
+                }
+                Ok(0)
+            }
+            57 => {
+                let target = mem.read_cstr(a[0])?;
+                std::os::unix::fs::symlink(std::ffi::OsStr::from_bytes(&target), self.path(mem, a[1])?)?;
+                Ok(0)
+            }
+            58 => {
+                let target = fs::read_link(self.path(mem, a[0])?)?;
+                let bytes = target.as_os_str().as_bytes();
+                let bytes = &bytes[..bytes.len().min(a[2] as usize)];
+                mem.write(a[1], bytes)?;
+                Ok(bytes.len() as u32)
+            }
+            60 => {
+                let old = self.umask;
+                self.umask = a[0] & 0o777;
+                Ok(old)
+            }
+            64 => Ok(PAGE_SIZE),
+            69 => {
+                // sbrk: grow the break by a[0], returning the old one
+                let old = self.brk;
+                let new = old.checked_add_signed(a[0] as i32).ok_or(Errno(ENOMEM))?;
+                self.bsd(17, &[new], cpu, mem)?;
+                Ok(old)
+            }
+            71 => self.mmap(a, mem),
+            73 => {
+                if trunc_page(a[0]) != a[0] {
+                    return Err(Errno(EINVAL));
+                }
+                mem.unmap(a[0], a[1]);
+                Ok(0)
+            }
+            74 => {
+                if trunc_page(a[0]) != a[0] || !mem.protect(a[0], a[1], a[2] & 7) {
+                    return Err(Errno(EINVAL));
+                }
+                Ok(0)
+            }
+            89 => Ok(OPEN_MAX as u32),
+            92 => {
+                let slot = self.slot(a[0])?.clone();
+                match a[1] {
+                    0 => self.install(slot, a[2] as usize),
+                    1 | 2 => Ok(0),
+                    3 => Ok(slot.flags),
+                    4 => {
+                        let flags = slot.flags & 3 | a[2] & 0xc;
+                        self.files[a[0] as usize].as_mut().unwrap().flags = flags;
+                        Ok(0)
+                    }
+                    _ => Err(Errno(EINVAL)),
+                }
+            }
+            108 => {
+                let signal = a[0] as usize;
+                if signal == 0 || signal >= 32 || signal == 9 || signal == 17 {
+                    return Err(Errno(EINVAL));
+                }
+                if a[2] != 0 {
+                    words(mem, a[2], &[self.handlers[signal], 0, 0])?;
+                }
+                if a[1] != 0 {
+                    self.handlers[signal] = mem.read_u32(a[1])?;
+                }
+                Ok(0)
+            }
+            109 | 110 => {
+                let old = self.signal_mask;
+                self.signal_mask = if number == 109 { old | a[0] } else { a[0] };
+                Ok(old)
+            }
+            111 => Err(Errno(EINTR)),
+            112 => {
+                if a[1] != 0 {
+                    words(mem, a[1], &[0, 0])?;
+                }
+                Ok(0)
+            }
+            116 => {
+                if a[0] != 0 {
+                    words(mem, a[0], &timeval(SystemTime::now()))?;
+                }
+                if a[1] != 0 {
+                    words(mem, a[1], &[0, 0])?;
+                }
+                Ok(0)
+            }
+            117 => {
+                mem.write(a[1], &[0; 72])?;
+                Ok(0)
+            }
+            120 | 121 => {
+                if a[2] > 1024 {
+                    return Err(Errno(EINVAL));
+                }
+                let mut total = 0;
+                for i in 0..a[2] {
+                    let base = mem.read_u32(a[1].wrapping_add(8 * i))?;
+                    let len = mem.read_u32(a[1].wrapping_add(8 * i + 4))?;
+                    let done = if number == 120 {
+                        let data = self.read_fd(a[0], len)?;
+                        mem.write(base, &data)?;
+                        data.len() as u32
+                    } else {
+                        let data = mem.read_vec(base, len)?;
+                        self.write_fd(a[0], &data)?
+                    };
+                    total += done;
+                    if done < len {
+                        break;
+                    }
+                }
+                Ok(total)
+            }
+            123 | 124 => {
+                let file = match self.handle(a[0])? {
+                    Handle::File(file) => file,
+                    Handle::Directory(directory) => Rc::new(directory.borrow().file.try_clone()?),
+                    _ => return Err(Errno(EINVAL)),
+                };
+                if number == 123 {
+                    let id = |v: u32| (v as i32 >= 0).then_some(v);
+                    std::os::unix::fs::fchown(&*file, id(a[1]), id(a[2]))?;
+                } else {
+                    file.set_permissions(fs::Permissions::from_mode(a[1] & 0o7777))?;
+                }
+                Ok(0)
+            }
+            128 => {
+                fs::rename(self.path(mem, a[0])?, self.path(mem, a[1])?)?;
+                Ok(0)
+            }
+            129 => {
+                OpenOptions::new().write(true).open(self.path(mem, a[0])?)?.set_len(a[1] as u64)?;
+                Ok(0)
+            }
+            130 => match self.handle(a[0])? {
+                Handle::File(file) => {
+                    file.set_len(a[1] as u64)?;
+                    Ok(0)
+                }
+                _ => Err(Errno(EINVAL)),
+            },
+            131 => {
+                self.handle(a[0])?;
+                Ok(0)
+            }
+            136 => {
+                fs::DirBuilder::new().mode(a[1] & !self.umask & 0o7777).create(self.path(mem, a[0])?)?;
+                Ok(0)
+            }
+            137 => {
+                fs::remove_dir(self.path(mem, a[0])?)?;
+                Ok(0)
+            }
+            138 => {
+                let path = self.path(mem, a[0])?;
+                let time = |at: u32| -> Result<SystemTime, Errno> {
+                    if a[1] == 0 {
+                        return Ok(SystemTime::now());
+                    }
+                    let (sec, usec) = (mem.read_u32(a[1] + at)?, mem.read_u32(a[1] + at + 4)?);
+                    Ok(UNIX_EPOCH + Duration::from_secs(sec as u64) + Duration::from_micros(usec as u64))
+                };
+                let times = fs::FileTimes::new().set_accessed(time(0)?).set_modified(time(8)?);
+                File::options().write(true).open(&path).or_else(|_| File::open(&path))?.set_times(times)?;
+                Ok(0)
+            }
+            156 => self.getdirentries(a[0], a[1], a[2], a[3], mem),
+            _ => Err(Errno(EOPNOTSUPP)),
+        }
+    }
+
+    // Anonymous memory, or a private copy of part of a file
+    fn mmap(&mut self, a: &[u32], mem: &mut Memory) -> Result<u32, Errno> {
+        let (address, len, prot, flags, fd, offset) = (a[0], a[1], a[2] & 7, a[3], a[4], a[5]);
+        let fixed = flags & 0x10 != 0;
+        if len == 0 || fixed && trunc_page(address) != address {
+            return Err(Errno(EINVAL));
+        }
+        let contents = if flags & 0x1000 != 0 {
+            Vec::new()
+        } else {
+            let Handle::File(file) = self.handle(fd)? else {
+                return Err(Errno(EINVAL));
+            };
+            if flags & 1 != 0 && prot & VM_PROT_WRITE != 0 {
+                // Writes to a shared mapping would never reach the file
+                return Err(Errno(EOPNOTSUPP));
+            }
+            let mut contents = vec![0; len as usize];
+            let count = std::os::unix::fs::FileExt::read_at(&*file, &mut contents, offset as u64)?;
+            contents.truncate(count);
+            contents
+        };
+        let size = round_page(len).ok_or(Errno(ENOMEM))?;
+        if fixed {
+            mem.unmap(address, size);
+        }
+        let at = self.allocate(mem, fixed.then_some(address), len).ok_or(Errno(ENOMEM))?;
+        mem.poke(at, &contents).expect("just mapped");
+        mem.protect(at, size, prot);
+        Ok(at)
+    }
+
+    // Mach traps; None for a trap the emulator does not know
+    fn mach(&mut self, number: i32, a: &[u32], mem: &mut Memory) -> Option<u32> {
+        let task = a[0] == TASK_PORT || a[0] == 0;
+        Some(match number {
+            -10 => TASK_PORT,
+            -11 => REPLY_PORT,
+            -12 => NOTIFY_PORT,
+            -13 => THREAD_PORT,
+            -59 | -60 => {
+                std::thread::yield_now();
+                0
+            }
+            -61 => {
+                std::thread::yield_now();
+                KERN_SUCCESS
+            }
+            _ if !task && number != -168 => KERN_INVALID_ARGUMENT,
+            -64 => {
+                let address = if a[3] != 0 {
+                    None
+                } else {
+                    match mem.read_u32(a[1]) {
+                        Ok(address) => Some(trunc_page(address)),
+                        Err(_) => return Some(KERN_INVALID_ADDRESS),
+                    }
+                };
+                if a[2] == 0 {
+                    return Some(KERN_SUCCESS);
+                }
+                match self.allocate(mem, address, a[2]) {
+                    Some(at) if mem.write_u32(a[1], at).is_ok() => KERN_SUCCESS,
+                    Some(at) => {
+                        mem.unmap(at, a[2]);
+                        KERN_INVALID_ADDRESS
+                    }
+                    None => KERN_NO_SPACE,
+                }
+            }
+            -65 => {
+                let start = trunc_page(a[1]);
+                match round_page(a[1].wrapping_add(a[2])) {
+                    Some(end) if end >= start => {
+                        mem.unmap(start, end - start);
+                        KERN_SUCCESS
+                    }
+                    _ => KERN_INVALID_ADDRESS,
+                }
+            }
+            -66 => {
+                let start = trunc_page(a[1]);
+                let size = a[1].wrapping_add(a[2]).wrapping_sub(start);
+                if a[3] != 0 || mem.protect(start, size, a[4] & 7) {
+                    KERN_SUCCESS
+                } else {
+                    KERN_INVALID_ADDRESS
+                }
+            }
+            -67 => {
+                if mem.is_mapped(trunc_page(a[1]), a[2]) {
+                    KERN_SUCCESS
+                } else {
+                    KERN_INVALID_ADDRESS
+                }
+            }
+            -68 => {
+                let Ok(data) = mem.read_vec(a[1], a[2]) else {
+                    return Some(KERN_INVALID_ADDRESS);
+                };
+                let Some(at) = self.allocate(mem, None, a[2].max(1)) else {
+                    return Some(KERN_NO_SPACE);
+                };
+                mem.write(at, &data).expect("just mapped");
+                if words(mem, a[3], &[at]).and_then(|_| words(mem, a[4], &[a[2]])).is_err() {
+                    return Some(KERN_INVALID_ADDRESS);
+                }
+                KERN_SUCCESS
+            }
+            -69 | -70 => {
+                let (from, to, count) = if number == -69 { (a[2], a[1], a[3]) } else { (a[1], a[3], a[2]) };
+                let Ok(data) = mem.read_vec(from, count) else {
+                    return Some(KERN_INVALID_ADDRESS);
+                };
+                match mem.write(to, &data) {
+                    Ok(()) => KERN_SUCCESS,
+                    Err(_) if mem.is_mapped(to, count) => KERN_PROTECTION_FAILURE,
+                    Err(_) => KERN_INVALID_ADDRESS,
+                }
+            }
+            -71 => {
+                let Ok(address) = mem.read_u32(a[1]) else {
+                    return Some(KERN_INVALID_ADDRESS);
+                };
+                let Some((start, size, prot)) = mem.region(address) else {
+                    return Some(KERN_NO_SPACE);
+                };
+                // address, size, protection, max protection, inheritance,
+                // shared, object name, offset
+                let values = [start, size, prot, 7, 1, 0, 0, 0];
+                for (pointer, value) in a[1..9].iter().zip(values) {
+                    if *pointer != 0 && mem.write_u32(*pointer, value).is_err() {
+                        return Some(KERN_INVALID_ADDRESS);
+                    }
+                }
+                KERN_SUCCESS
+            }
+            -72 => {
+                // struct vm_statistics: page size, then counts
+                let mut values = [0; 13];
+                values[0] = PAGE_SIZE;
+                values[1] = 4096;
+                if words(mem, a[1], &values).is_err() {
+                    return Some(KERN_INVALID_ADDRESS);
+                }
+                KERN_SUCCESS
+            }
+            -168 => KERN_FAILURE,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::tests::{machine, DATA};
+
+    #[test]
+    fn test_syscalls() {
+        let (mut cpu, mut mem) = machine(&[]);
+        let mut system = System::new(false, true, DATA + 0x2000);
+        let mut call = |cpu: &mut Cpu, mem: &mut Memory, number: i32, args: &[u32]| {
+            cpu.a[7] = crate::cpu::tests::STACK - 4 - 4 * args.len() as u32;
+            words(mem, cpu.a[7] + 4, args).unwrap();
+            cpu.d[0] = number as u32;
+            let stop = system.trap(cpu, mem);
+            (stop, cpu.d[0], cpu.c)
+        };
+
+        mem.write(DATA, b"hi\n").unwrap();
+        assert_eq!(call(&mut cpu, &mut mem, 4, &[1, DATA, 3]), (None, 3, false));
+        assert_eq!(call(&mut cpu, &mut mem, 4, &[1, 0, 3]), (None, EFAULT, true));
+        assert_eq!(call(&mut cpu, &mut mem, 6, &[9]), (None, EBADF, true));
+
+        // A pipe, through the indirect syscall
+        let (_, read, _) = call(&mut cpu, &mut mem, 0, &[42]);
+        let write = cpu.d[1];
+        call(&mut cpu, &mut mem, 4, &[write, DATA, 2]);
+        assert_eq!(call(&mut cpu, &mut mem, 3, &[read, DATA + 0x100, 10]), (None, 2, false));
+        assert_eq!(mem.read_u16(DATA + 0x100).unwrap(), u16::from_be_bytes(*b"hi"));
+        call(&mut cpu, &mut mem, 6, &[write]);
+        assert_eq!(call(&mut cpu, &mut mem, 3, &[read, DATA + 0x100, 10]), (None, 0, false));
+
+        // sbrk and vm_allocate
+        assert_eq!(call(&mut cpu, &mut mem, 69, &[0x100]), (None, DATA + 0x2000, false));
+        assert!(mem.write_u8(DATA + 0x20ff, 1).is_ok());
+        assert_eq!(call(&mut cpu, &mut mem, -64, &[TASK_PORT, DATA + 8, 0x3000, 1]).1, KERN_SUCCESS);
+        let allocated = mem.read_u32(DATA + 8).unwrap();
+        assert!(allocated >= ALLOCATE_BASE && mem.is_mapped(allocated, 0x3000));
+        assert_eq!(call(&mut cpu, &mut mem, -65, &[TASK_PORT, allocated, 0x3000]).1, KERN_SUCCESS);
+        assert!(mem.is_free(allocated, 0x3000));
+
+        // stat of a host file in the nextstep-sys layout
+        mem.write(DATA + 0x200, b"/\0").unwrap();
+        assert_eq!(call(&mut cpu, &mut mem, 38, &[DATA + 0x200, DATA + 0x300]), (None, 0, false));
+        assert_eq!(mem.read_u16(DATA + 0x308).unwrap() as u32 & 0o170000, 0o040000);
+
+        assert_eq!(call(&mut cpu, &mut mem, 59, &[0, 0, 0]), (None, EOPNOTSUPP, true));
+        assert_eq!(call(&mut cpu, &mut mem, 250, &[]), (Some(Stop::Signal(SIGSYS)), ENOSYS, true));
+        assert_eq!(call(&mut cpu, &mut mem, 1, &[3]).0, Some(Stop::Exit(3)));
+        assert_eq!(system.output.as_deref(), Some(&b"hi\n"[..]));
+    }
+}
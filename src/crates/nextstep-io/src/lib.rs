@@ -8,9 +8,11 @@
 extern crate alloc;
 
 use core::fmt;
+use nextstep_atomics::critical_section;
+use nextstep_sync::Mutex;
 use nextstep_sys::*;
 
-/// Standard output handle
+/// Standard output handle (line buffered)
 pub struct Stdout;
 
 /// Standard error handle  
@@ -86,10 +88,95 @@ pub trait Read {
     fn read_exact(&mut self, buf: &mut [u8]) -> Result<()>;
 }
 
+// Size of the stdout line buffer
+const STDOUT_BUF_SIZE: usize = 1024;
+
+// Line buffer shared by every Stdout handle
+struct StdoutBuf {
+    data: [u8; STDOUT_BUF_SIZE],
+    len: usize,
+    flush_registered: bool,
+}
+
+impl StdoutBuf {
+    const fn new() -> StdoutBuf {
+        StdoutBuf { data: [0; STDOUT_BUF_SIZE], len: 0, flush_registered: false }
+    }
+
+    // Buffer `buf`, passing full lines and overflow on to `sink`
+    fn write(&mut self, buf: &[u8], sink: &mut impl FnMut(&[u8]) -> Result<usize>) -> Result<usize> {
+        // Too large to buffer: write through
+        if buf.len() > STDOUT_BUF_SIZE - self.len {
+            self.flush(sink)?;
+            if buf.len() >= STDOUT_BUF_SIZE {
+                return sink(buf);
+            }
+        }
+
+        let start = self.len;
+        self.data[start..start + buf.len()].copy_from_slice(buf);
+        self.len += buf.len();
+
+        // Line buffered, like C stdio on a terminal
+        if buf.contains(&b'\n') {
+            self.flush(sink)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self, sink: &mut impl FnMut(&[u8]) -> Result<usize>) -> Result<()> {
+        let mut pending = &self.data[..self.len];
+        while !pending.is_empty() {
+            match sink(pending)? {
+                0 => return Err(IoError { kind: IoErrorKind::WriteZero }),
+                n => pending = &pending[n..],
+            }
+        }
+        self.len = 0;
+        Ok(())
+    }
+}
+
+fn write_stdout(buf: &[u8]) -> Result<usize> {
+    sys_write(STDOUT_FILENO, buf).map_err(|_| IoError { kind: IoErrorKind::Other })
+}
+
+// Locked by cthreads sharing stdout, and only taken inside a critical
+// section so a signal handler that prints cannot deadlock on it
+static STDOUT_BUF: Mutex<StdoutBuf> = Mutex::new(StdoutBuf::new());
+
+/// Write out any buffered stdout data
+///
+/// Registered as an exit handler the first time stdout is written, so
+/// output is not lost when the process exits. Does nothing if the buffer
+/// is locked, e.g. when a crash report exits in the middle of a write.
+pub fn flush_stdout() {
+    let _ = Stdout.try_flush();
+}
+
+impl Stdout {
+    /// Flush unless the buffer is locked, failing with `WouldBlock` then
+    ///
+    /// For panic and signal handlers, which may have interrupted a write
+    /// on their own thread: [`Write::flush`] would wait forever there.
+    pub fn try_flush(&mut self) -> Result<()> {
+        critical_section::with(|_| match STDOUT_BUF.try_lock() {
+            Some(mut out) => out.flush(&mut write_stdout),
+            None => Err(IoError { kind: IoErrorKind::WouldBlock }),
+        })
+    }
+}
+
 impl Write for Stdout {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        sys_write(STDOUT_FILENO, buf)
-            .map_err(|_| IoError { kind: IoErrorKind::Other })
+        critical_section::with(|_| {
+            let mut out = STDOUT_BUF.lock();
+            if !out.flush_registered {
+                out.flush_registered = true;
+                let _ = sys_atexit(flush_stdout);
+            }
+            out.write(buf, &mut write_stdout)
+        })
     }
 
     fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
@@ -104,8 +191,7 @@ impl Write for Stdout {
     }
 
     fn flush(&mut self) -> Result<()> {
-        // No buffering, always flushed
-        Ok(())
+        critical_section::with(|_| STDOUT_BUF.lock().flush(&mut write_stdout))
     }
 }
 
@@ -292,4 +378,69 @@ impl Drop for File {
     fn drop(&mut self) {
         let _ = sys_close(self.fd);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    // Collects what the buffer passes on, one entry per write
+    fn sink(writes: &mut Vec<Vec<u8>>) -> impl FnMut(&[u8]) -> Result<usize> + '_ {
+        |buf| {
+            writes.push(buf.to_vec());
+            Ok(buf.len())
+        }
+    }
+
+    #[test]
+    fn test_stdout_flushes_on_newline() {
+        let mut buf = StdoutBuf::new();
+        let mut writes = Vec::new();
+        buf.write(b"partial", &mut sink(&mut writes)).unwrap();
+        assert!(writes.is_empty());
+        buf.write(b" line\nmore", &mut sink(&mut writes)).unwrap();
+        assert_eq!(writes, [b"partial line\nmore".to_vec()]);
+        assert_eq!(buf.len, 0);
+    }
+
+    #[test]
+    fn test_stdout_flushes_when_full() {
+        let mut buf = StdoutBuf::new();
+        let mut writes = Vec::new();
+        let chunk = [b'x'; STDOUT_BUF_SIZE - 10];
+        buf.write(&chunk, &mut sink(&mut writes)).unwrap();
+        assert!(writes.is_empty());
+
+        // Does not fit: the buffered data goes out first
+        buf.write(&[b'y'; 20], &mut sink(&mut writes)).unwrap();
+        assert_eq!(writes, [chunk.to_vec()]);
+        assert_eq!(buf.len, 20);
+
+        // Larger than the buffer: written through after a flush
+        let big = [b'z'; STDOUT_BUF_SIZE];
+        buf.write(&big, &mut sink(&mut writes)).unwrap();
+        assert_eq!(writes[1..], [[b'y'; 20].to_vec(), big.to_vec()]);
+        assert_eq!(buf.len, 0);
+    }
+
+    #[test]
+    fn test_stdout_short_and_zero_writes() {
+        let mut buf = StdoutBuf::new();
+        let mut out = Vec::new();
+        // A sink taking 3 bytes at a time
+        buf.write(b"abcdefg\n", &mut |b: &[u8]| {
+            let n = b.len().min(3);
+            out.extend_from_slice(&b[..n]);
+            Ok(n)
+        })
+        .unwrap();
+        assert_eq!(out, b"abcdefg\n");
+
+        buf.write(b"stuck", &mut |_: &[u8]| Ok(1)).unwrap();
+        let err = buf.flush(&mut |_: &[u8]| Ok(0)).unwrap_err();
+        assert_eq!(err.kind, IoErrorKind::WriteZero);
+    }
+}
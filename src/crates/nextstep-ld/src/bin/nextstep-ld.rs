@@ -0,0 +1,187 @@
+//! nextstep-ld - Link m68k Mach-O objects into a NeXTSTEP executable
+//!
+//! Usage: nextstep-ld [options] FILE... -o OUTPUT
+//!
+//!   -o FILE             Output executable (default a.out)
+//!   -e SYMBOL           Entry point (default _start, then start)
+//!   -L DIR, -l NAME     Search DIR for libNAME.a
+//!   -seg1addr ADDR      Address of __TEXT (hex; default 0x2000)
+//!   -all_load           Link every member of the archives that follow
+//!   -s, -S, -x          Strip all symbols, debug entries, or locals
+//!
+//! Also accepts what rustc and cc pass a gcc-style linker: `-Wl,`
+//! wrapped options, `@file` response files, `--whole-archive`, and a set
+//! of options that do not apply to a static NeXTSTEP link, which are
+//! ignored.
+
+use nextstep_ld::{Linker, Options, Strip};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+// Options that take a value which does not matter here
+const IGNORED_WITH_VALUE: &[&str] = &["-arch", "-target", "-z", "-m", "-framework", "-install_name"];
+
+// Input file and whether it is linked whole
+struct Input {
+    path: PathBuf,
+    whole: bool,
+}
+
+struct Args {
+    options: Options,
+    output: PathBuf,
+    inputs: Vec<Input>,
+}
+
+fn ignored(arg: &str) -> bool {
+    matches!(
+        arg,
+        "-static"
+            | "-nostdlib"
+            | "-nodefaultlibs"
+            | "-nostartfiles"
+            | "-Bstatic"
+            | "-Bdynamic"
+            | "--as-needed"
+            | "--no-as-needed"
+            | "--gc-sections"
+            | "--no-gc-sections"
+            | "-dead_strip"
+            | "--eh-frame-hdr"
+            | "-no-pie"
+            | "-nopie"
+            | "-g"
+            | "--strip-debug"
+            | "--strip-all"
+    ) || arg.starts_with("-fuse-ld=")
+        || arg.starts_with("-march=")
+        || arg.starts_with("-mcpu=")
+        || arg.starts_with("-O")
+        || arg.starts_with("--target=")
+}
+
+// Response file: one argument per line, with `\` escaping as rustc writes it
+fn response_file(path: &str) -> Result<Vec<String>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(text
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut arg = String::new();
+            let mut chars = line.chars();
+            while let Some(c) = chars.next() {
+                arg.push(if c == '\\' { chars.next().unwrap_or('\\') } else { c });
+            }
+            arg
+        })
+        .collect())
+}
+
+fn find_library(name: &str, dirs: &[PathBuf]) -> Result<PathBuf, String> {
+    let file = format!("lib{}.a", name);
+    dirs.iter()
+        .map(|dir| dir.join(&file))
+        .find(|path| path.is_file())
+        .ok_or_else(|| format!("library not found for -l{}", name))
+}
+
+fn parse_hex(value: &str) -> Option<u32> {
+    u32::from_str_radix(value.trim_start_matches("0x").trim_start_matches("0X"), 16).ok()
+}
+
+fn parse_args(raw: Vec<String>) -> Result<Args, String> {
+    // Expand response files and -Wl, first
+    let mut args = Vec::new();
+    for arg in raw {
+        if let Some(path) = arg.strip_prefix('@') {
+            args.extend(response_file(path)?);
+        } else if let Some(list) = arg.strip_prefix("-Wl,") {
+            args.extend(list.split(',').filter(|a| !a.is_empty()).map(str::to_string));
+        } else {
+            args.push(arg);
+        }
+    }
+
+    let mut parsed = Args { options: Options::default(), output: PathBuf::from("a.out"), inputs: Vec::new() };
+    let mut search: Vec<PathBuf> = Vec::new();
+    let mut libraries: Vec<(usize, String, bool)> = Vec::new();
+    let mut whole = false;
+    let mut args = args.into_iter();
+    let value = |args: &mut std::vec::IntoIter<String>, option: &str| {
+        args.next().ok_or_else(|| format!("{} needs a value", option))
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => parsed.output = PathBuf::from(value(&mut args, "-o")?),
+            "-e" | "--entry" => parsed.options.entry = Some(value(&mut args, "-e")?),
+            "-seg1addr" => {
+                let addr = value(&mut args, "-seg1addr")?;
+                parsed.options.seg1addr = parse_hex(&addr).ok_or_else(|| format!("bad -seg1addr {}", addr))?;
+            }
+            "-L" => search.push(PathBuf::from(value(&mut args, "-L")?)),
+            "-l" => libraries.push((parsed.inputs.len(), value(&mut args, "-l")?, whole)),
+            "-all_load" | "--whole-archive" => whole = true,
+            "--no-whole-archive" => whole = false,
+            "-s" => parsed.options.strip = Strip::All,
+            "-S" => parsed.options.strip = Strip::Debug,
+            "-x" => parsed.options.strip = Strip::Locals,
+            _ if IGNORED_WITH_VALUE.contains(&arg.as_str()) => {
+                value(&mut args, &arg)?;
+            }
+            _ if ignored(&arg) => {}
+            _ if arg.starts_with("-L") => search.push(PathBuf::from(&arg[2..])),
+            _ if arg.starts_with("-l") => libraries.push((parsed.inputs.len(), arg[2..].to_string(), whole)),
+            _ if arg.starts_with("-e") && arg.len() > 2 => parsed.options.entry = Some(arg[2..].to_string()),
+            _ if arg.starts_with('-') => eprintln!("nextstep-ld: warning: ignoring option {}", arg),
+            _ => parsed.inputs.push(Input { path: PathBuf::from(arg), whole }),
+        }
+    }
+
+    // -l is searched for with all -L directories known, but keeps its place
+    for (position, name, whole) in libraries.into_iter().rev() {
+        let path = find_library(&name, &search)?;
+        parsed.inputs.insert(position, Input { path, whole });
+    }
+    if parsed.inputs.is_empty() {
+        return Err("no input files".to_string());
+    }
+    if parsed.options.seg1addr & (nextstep_macho::validate::NEXT_PAGE_SIZE - 1) != 0 {
+        return Err(format!("-seg1addr {:#x} is not page aligned", parsed.options.seg1addr));
+    }
+    Ok(parsed)
+}
+
+fn write_executable(path: &Path, image: &[u8]) -> std::io::Result<()> {
+    std::fs::write(path, image)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let files = args
+        .inputs
+        .iter()
+        .map(|input| std::fs::read(&input.path).map_err(|e| format!("{}: {}", input.path.display(), e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut linker = Linker::new(args.options);
+    for (input, data) in args.inputs.iter().zip(&files) {
+        linker.add_file(&input.path.display().to_string(), data, input.whole).map_err(|e| e.to_string())?;
+    }
+    let image = linker.link().map_err(|e| e.to_string())?;
+    write_executable(&args.output, &image).map_err(|e| format!("{}: {}", args.output.display(), e))
+}
+
+fn main() -> ExitCode {
+    let result = parse_args(std::env::args().skip(1).collect()).and_then(run);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("nextstep-ld: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
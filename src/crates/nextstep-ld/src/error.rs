@@ -0,0 +1,61 @@
+//! Link errors
+
+use std::fmt;
+
+/// Why a link failed
+#[derive(Debug)]
+pub enum Error {
+    /// An input could not be read as Mach-O
+    Parse { file: String, error: nextstep_macho::Error },
+    /// An input archive is malformed
+    BadArchive { file: String, error: nextstep_ar::Error },
+    /// An input uses something this linker does not handle
+    Unsupported { file: String, what: String },
+    /// Two inputs define the same global symbol
+    Duplicate { symbol: String, first: String, second: String },
+    /// Symbols nobody defines, with the first file referencing each
+    Undefined(Vec<(String, String)>),
+    /// The entry point symbol is not defined
+    NoEntry(String),
+    /// Inputs built for different CPUs (68030-only and 68040)
+    CpuMismatch { file: String },
+    /// A relocation entry does not make sense
+    BadRelocation { file: String, section: String, address: u32, reason: String },
+    /// A relocated value does not fit its item
+    Overflow { file: String, section: String, address: u32, value: i64 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse { file, error } => write!(f, "{}: {}", file, error),
+            Error::BadArchive { file, error } => write!(f, "{}: bad archive: {}", file, error),
+            Error::Unsupported { file, what } => write!(f, "{}: unsupported: {}", file, what),
+            Error::Duplicate { symbol, first, second } => {
+                write!(f, "duplicate symbol {} in {} and {}", symbol, first, second)
+            }
+            Error::Undefined(symbols) => {
+                f.write_str("undefined symbols:")?;
+                for (symbol, file) in symbols {
+                    write!(f, "\n  {} (referenced from {})", symbol, file)?;
+                }
+                Ok(())
+            }
+            Error::NoEntry(symbol) => write!(f, "entry point {} is not defined", symbol),
+            Error::CpuMismatch { file } => {
+                write!(f, "{}: cpusubtype conflicts with earlier inputs (68030-only vs 68040)", file)
+            }
+            Error::BadRelocation { file, section, address, reason } => {
+                write!(f, "{}: {} relocation at {:#x}: {}", file, section, address, reason)
+            }
+            Error::Overflow { file, section, address, value } => {
+                write!(f, "{}: {} relocation at {:#x}: value {:#x} does not fit", file, section, address, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Result of linking
+pub type Result<T> = core::result::Result<T, Error>;
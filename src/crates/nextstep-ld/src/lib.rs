@@ -0,0 +1,30 @@
+//! nextstep-ld - Static linker for NeXTSTEP m68k Mach-O executables
+//!
+//! Links `MH_OBJECT` files and `ar` archives (BSD, as the target's
+//! `archive-format: bsd` writes them, or GNU) into an `MH_EXECUTE` that
+//! NeXTSTEP 3.3 loads directly, without NeXT's `ld` or any other external
+//! toolchain:
+//!
+//! - symbols are resolved across objects; archive members are linked when
+//!   they define something still undefined, and common symbols are placed
+//!   in `__DATA,__common`
+//! - `M68K_RELOC_VANILLA` (plain or scattered, absolute or pc-relative)
+//!   and `SECTDIFF`/`PAIR` relocations are applied
+//! - `__PAGEZERO`, `__TEXT` (holding the Mach header, visible as
+//!   `__mh_execute_header`) and `__DATA` are laid out on 8 KB page
+//!   boundaries, followed by any other segments the inputs name
+//! - `LC_UNIXTHREAD` starts the program at `_start` (or `start`)
+//!
+//! The `nextstep-ld` binary takes the arguments rustc passes a gcc-flavor
+//! linker, so a target spec can use it directly:
+//!
+//! ```json
+//! "linker-flavor": "gcc",
+//! "linker": "nextstep-ld",
+//! ```
+
+mod error;
+mod link;
+
+pub use error::{Error, Result};
+pub use link::{Linker, Options, Strip, MH_EXECUTE_HEADER};
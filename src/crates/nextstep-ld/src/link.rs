@@ -0,0 +1,1093 @@
+//! Symbol resolution, layout, relocation and output
+
+use crate::error::{Error, Result};
+use nextstep_ar::Archive;
+use nextstep_macho::consts::*;
+use nextstep_macho::validate::NEXT_PAGE_SIZE;
+use nextstep_macho::{MachO, Nlist, RelocTarget, Relocation, Section};
+use std::collections::HashMap;
+
+/// Symbol the kernel-visible Mach header is given, as NeXT `ld` names it
+pub const MH_EXECUTE_HEADER: &str = "__mh_execute_header";
+
+// The same header under the name nextstep-rt links against
+const MH_EXECUTE_HEADER_RT: &str = "_mh_execute_header";
+
+// Entry point symbols tried when none is given: nextstep-rt's, then crt0's
+const DEFAULT_ENTRIES: [&str; 2] = ["_start", "start"];
+
+/// Which symbols to leave out of the output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strip {
+    /// Keep everything
+    #[default]
+    None,
+    /// Drop debugger (STABS) entries (`-S`)
+    Debug,
+    /// Drop debug entries and local symbols (`-x`)
+    Locals,
+    /// Write no symbol table entries at all (`-s`)
+    All,
+}
+
+/// How to link
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Entry point symbol; `_start`, then `start`, when None
+    pub entry: Option<String>,
+    /// Address of `__TEXT`; a `__PAGEZERO` segment covers everything below
+    pub seg1addr: u32,
+    pub strip: Strip,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options { entry: None, seg1addr: NEXT_PAGE_SIZE, strip: Strip::None }
+    }
+}
+
+// An input object loaded into the link
+struct Object<'a> {
+    name: String,
+    macho: MachO<'a>,
+    // In ordinal order; `n_sect` k is sections[k - 1]
+    sections: Vec<Section>,
+    symbols: Vec<Nlist<'a>>,
+}
+
+struct ArchiveMember<'a> {
+    name: String,
+    data: &'a [u8],
+    // External symbols the member defines
+    defines: Vec<&'a [u8]>,
+    loaded: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Definition {
+    Symbol { object: usize, symbol: usize },
+    Common { size: u32 },
+    Header,
+}
+
+struct Global {
+    definition: Option<Definition>,
+    // First object referencing the symbol, for undefined symbol errors
+    referenced_by: Option<usize>,
+}
+
+/// A static link in progress
+///
+/// Objects are always linked; archive members only when they define a
+/// symbol that is still undefined, searching every archive again until
+/// nothing changes, so archive order does not matter.
+pub struct Linker<'a> {
+    options: Options,
+    objects: Vec<Object<'a>>,
+    archives: Vec<(String, Vec<ArchiveMember<'a>>)>,
+    globals: HashMap<&'a [u8], Global>,
+    // Global names in the order they were first seen
+    order: Vec<&'a [u8]>,
+    cpusubtype: i32,
+}
+
+fn lossy(name: &[u8]) -> String {
+    String::from_utf8_lossy(name).into_owned()
+}
+
+fn align_up(value: u32, align: u32) -> u32 {
+    (value + align - 1) & !(align - 1)
+}
+
+fn round_page(value: u32) -> u32 {
+    align_up(value, NEXT_PAGE_SIZE)
+}
+
+fn parse_object<'a>(name: &str, data: &'a [u8]) -> Result<(MachO<'a>, Vec<Nlist<'a>>)> {
+    let parse_error = |error| Error::Parse { file: name.to_string(), error };
+    let macho = MachO::parse(data).map_err(parse_error)?;
+    let unsupported = |what: String| Error::Unsupported { file: name.to_string(), what };
+    if macho.header.cputype != CPU_TYPE_MC680X0 {
+        return Err(unsupported(format!("cputype {} is not MC680x0", macho.header.cputype)));
+    }
+    if macho.header.filetype != MH_OBJECT {
+        return Err(unsupported(format!("filetype {:#x} is not MH_OBJECT", macho.header.filetype)));
+    }
+    let symbols = macho.symbols().map_err(parse_error)?;
+    Ok((macho, symbols))
+}
+
+// External symbols an object defines
+fn defined_externals<'a>(symbols: &[Nlist<'a>]) -> Vec<&'a [u8]> {
+    symbols
+        .iter()
+        .filter(|s| s.is_external() && matches!(s.kind(), Some(N_SECT) | Some(N_ABS)))
+        .map(|s| s.name)
+        .collect()
+}
+
+impl<'a> Linker<'a> {
+    pub fn new(options: Options) -> Linker<'a> {
+        Linker {
+            options,
+            objects: Vec::new(),
+            archives: Vec::new(),
+            globals: HashMap::new(),
+            order: Vec::new(),
+            cpusubtype: CPU_SUBTYPE_MC680X0_ALL,
+        }
+    }
+
+    /// Add an object file or archive, told apart by their contents
+    ///
+    /// With `whole`, every object in an archive is linked.
+    pub fn add_file(&mut self, name: &str, data: &'a [u8], whole: bool) -> Result<()> {
+        if nextstep_ar::is_archive(data) {
+            self.add_archive(name, data, whole)
+        } else {
+            self.add_object(name, data)
+        }
+    }
+
+    /// Add an object file
+    pub fn add_object(&mut self, name: &str, data: &'a [u8]) -> Result<()> {
+        let (macho, symbols) = parse_object(name, data)?;
+        self.load(name.to_string(), macho, symbols)
+    }
+
+    /// Add an archive whose members are linked as needed (or all, with `whole`)
+    ///
+    /// Members that are not Mach-O objects, like the metadata in an rlib,
+    /// are ignored.
+    pub fn add_archive(&mut self, name: &str, data: &'a [u8], whole: bool) -> Result<()> {
+        let mut members = Vec::new();
+        let archive = Archive::parse(data).map_err(|error| Error::BadArchive { file: name.to_string(), error })?;
+        for member in archive.members {
+            let member_name = format!("{}({})", name, member.name);
+            let Ok((macho, symbols)) = parse_object(&member_name, member.data) else {
+                continue;
+            };
+            if whole {
+                self.load(member_name, macho, symbols)?;
+            } else {
+                members.push(ArchiveMember {
+                    name: member_name,
+                    data: member.data,
+                    defines: defined_externals(&symbols),
+                    loaded: false,
+                });
+            }
+        }
+        self.archives.push((name.to_string(), members));
+        Ok(())
+    }
+
+    fn global(&mut self, name: &'a [u8]) -> &mut Global {
+        let order = &mut self.order;
+        self.globals.entry(name).or_insert_with(|| {
+            order.push(name);
+            Global { definition: None, referenced_by: None }
+        })
+    }
+
+    fn load(&mut self, name: String, macho: MachO<'a>, symbols: Vec<Nlist<'a>>) -> Result<()> {
+        let index = self.objects.len();
+
+        self.cpusubtype = match (self.cpusubtype, macho.header.cpusubtype) {
+            (current, CPU_SUBTYPE_MC680X0_ALL) => current,
+            (CPU_SUBTYPE_MC680X0_ALL, subtype @ (CPU_SUBTYPE_MC68040 | CPU_SUBTYPE_MC68030_ONLY)) => subtype,
+            (current, subtype) if current == subtype => current,
+            (_, CPU_SUBTYPE_MC68040 | CPU_SUBTYPE_MC68030_ONLY) => return Err(Error::CpuMismatch { file: name }),
+            (_, subtype) => return Err(Error::Unsupported { file: name, what: format!("cpusubtype {}", subtype) }),
+        };
+
+        let sections: Vec<_> = macho.sections().cloned().collect();
+        // Relocation and symbol addresses index the section table by n_sect
+        if let Some(symbol) = symbols
+            .iter()
+            .find(|s| s.kind() == Some(N_SECT) && s.n_sect != NO_SECT && s.n_sect as usize > sections.len())
+        {
+            let what = format!("symbol {} is in section {} of {}", lossy(symbol.name), symbol.n_sect, sections.len());
+            return Err(Error::Unsupported { file: name, what });
+        }
+
+        for (i, symbol) in symbols.iter().enumerate() {
+            if !symbol.is_external() {
+                continue;
+            }
+            let existing = self.global(symbol.name).definition;
+            let definition = match (symbol.kind(), existing) {
+                // Common: the largest size wins, any real definition beats it
+                (Some(N_UNDF), Some(Definition::Common { size, .. })) if symbol.n_value > size => {
+                    Definition::Common { size: symbol.n_value }
+                }
+                (Some(N_UNDF), None) if symbol.n_value != 0 => Definition::Common { size: symbol.n_value },
+                (Some(N_UNDF), _) => {
+                    self.global(symbol.name).referenced_by.get_or_insert(index);
+                    continue;
+                }
+                (Some(N_SECT) | Some(N_ABS), Some(Definition::Symbol { object, .. })) => {
+                    return Err(Error::Duplicate {
+                        symbol: lossy(symbol.name),
+                        first: self.objects[object].name.clone(),
+                        second: name,
+                    })
+                }
+                (Some(N_SECT) | Some(N_ABS), _) => Definition::Symbol { object: index, symbol: i },
+                _ => {
+                    let what = format!("symbol {} has n_type {:#x}", lossy(symbol.name), symbol.n_type);
+                    return Err(Error::Unsupported { file: name, what });
+                }
+            };
+            self.global(symbol.name).definition = Some(definition);
+        }
+
+        self.objects.push(Object { name, macho, sections, symbols });
+        Ok(())
+    }
+
+    fn undefined(&self, name: &[u8]) -> bool {
+        self.globals.get(name).is_some_and(|g| g.definition.is_none())
+    }
+
+    // Pull in archive members until no member defines an undefined symbol
+    fn load_archive_members(&mut self) -> Result<()> {
+        loop {
+            let mut pending = None;
+            'search: for (a, (_, members)) in self.archives.iter().enumerate() {
+                for (m, member) in members.iter().enumerate() {
+                    if !member.loaded && member.defines.iter().any(|name| self.undefined(name)) {
+                        pending = Some((a, m));
+                        break 'search;
+                    }
+                }
+            }
+            let Some((a, m)) = pending else {
+                return Ok(());
+            };
+            let member = &mut self.archives[a].1[m];
+            member.loaded = true;
+            let (name, data) = (member.name.clone(), member.data);
+            let (macho, symbols) = parse_object(&name, data)?;
+            self.load(name, macho, symbols)?;
+        }
+    }
+
+    /// Resolve symbols, lay out the image and return the executable's bytes
+    pub fn link(mut self) -> Result<Vec<u8>> {
+        self.load_archive_members()?;
+
+        for name in [MH_EXECUTE_HEADER, MH_EXECUTE_HEADER_RT] {
+            let referenced = self.undefined(name.as_bytes());
+            if referenced || name == MH_EXECUTE_HEADER {
+                let global = self.global(name.as_bytes());
+                global.definition.get_or_insert(Definition::Header);
+            }
+        }
+
+        let mut undefined: Vec<(String, String)> = self
+            .globals
+            .iter()
+            .filter(|(_, g)| g.definition.is_none())
+            .map(|(name, g)| {
+                let from = g.referenced_by.map_or_else(String::new, |o| self.objects[o].name.clone());
+                (lossy(name), from)
+            })
+            .collect();
+        if !undefined.is_empty() {
+            undefined.sort();
+            return Err(Error::Undefined(undefined));
+        }
+
+        let entry = match &self.options.entry {
+            Some(entry) => entry.clone(),
+            None => DEFAULT_ENTRIES
+                .iter()
+                .find(|name| self.globals.contains_key(name.as_bytes()))
+                .unwrap_or(&DEFAULT_ENTRIES[0])
+                .to_string(),
+        };
+        if !self.globals.contains_key(entry.as_bytes()) {
+            return Err(Error::NoEntry(entry));
+        }
+
+        let layout = Layout::new(&self);
+        Writer::new(&self, &layout, entry.as_bytes()).write()
+    }
+}
+
+// An output section and the input sections it is made of
+struct OutSection {
+    segname: String,
+    sectname: String,
+    flags: u32,
+    align: u32,
+    addr: u32,
+    size: u32,
+    offset: u32,
+}
+
+struct OutSegment {
+    name: String,
+    // Indices into Layout::sections, in address order
+    sections: Vec<usize>,
+    vmaddr: u32,
+    vmsize: u32,
+    fileoff: u32,
+    filesize: u32,
+}
+
+struct Layout {
+    sections: Vec<OutSection>,
+    segments: Vec<OutSegment>,
+    // Per object, per input section: (output section, offset in it)
+    placement: Vec<Vec<(usize, u32)>>,
+    // Common symbol name -> address
+    commons: HashMap<Vec<u8>, u32>,
+    // Output section index -> section ordinal in the executable
+    ordinals: Vec<u8>,
+    header_size: u32,
+    text_vmaddr: u32,
+    // File offset just past the last segment
+    end_of_segments: u32,
+}
+
+const THREAD_COMMAND_SIZE: u32 = 16 + 4 * M68K_THREAD_STATE_REGS_COUNT;
+
+impl Layout {
+    fn new(linker: &Linker) -> Layout {
+        let mut sections: Vec<OutSection> = Vec::new();
+        let mut placement = Vec::new();
+        let find = |sections: &mut Vec<OutSection>, segname: &str, sectname: &str, flags: u32| {
+            match sections.iter().position(|s| s.segname == segname && s.sectname == sectname) {
+                Some(index) => index,
+                None => {
+                    sections.push(OutSection {
+                        segname: segname.to_string(),
+                        sectname: sectname.to_string(),
+                        // Nothing is left to relocate in the output
+                        flags: flags & !(S_ATTR_EXT_RELOC | S_ATTR_LOC_RELOC),
+                        align: 0,
+                        addr: 0,
+                        size: 0,
+                        offset: 0,
+                    });
+                    sections.len() - 1
+                }
+            }
+        };
+
+        for object in &linker.objects {
+            let mut places = Vec::new();
+            for section in &object.sections {
+                let out = find(&mut sections, &section.segname, &section.sectname, section.flags);
+                let target = &mut sections[out];
+                let offset = align_up(target.size, 1 << section.align.min(15));
+                target.size = offset + section.size;
+                target.align = target.align.max(section.align);
+                places.push((out, offset));
+            }
+            placement.push(places);
+        }
+
+        let mut commons = HashMap::new();
+        for name in &linker.order {
+            if let Some(Definition::Common { size, .. }) = linker.globals[name].definition {
+                let out = find(&mut sections, "__DATA", "__common", S_ZEROFILL);
+                let target = &mut sections[out];
+                let align = if size >= 4 { 2 } else if size >= 2 { 1 } else { 0 };
+                let offset = align_up(target.size, 1 << align);
+                target.size = offset + size;
+                target.align = target.align.max(align);
+                commons.insert(name.to_vec(), offset);
+            }
+        }
+
+        // __TEXT first (it holds the header), then __DATA, then the rest
+        let mut names: Vec<&str> = vec!["__TEXT", "__DATA"];
+        for section in &sections {
+            if !names.contains(&section.segname.as_str()) {
+                names.push(&section.segname);
+            }
+        }
+        let is_zerofill = |section: &OutSection| section.flags & SECTION_TYPE == S_ZEROFILL;
+        let mut segments = Vec::new();
+        for name in names {
+            // Zerofill sections go last so the file part stays contiguous
+            let mut members: Vec<usize> = (0..sections.len()).filter(|&i| sections[i].segname == name).collect();
+            members.sort_by_key(|&i| is_zerofill(&sections[i]));
+            if name == "__TEXT" || !members.is_empty() {
+                segments.push(OutSegment {
+                    name: name.to_string(),
+                    sections: members,
+                    vmaddr: 0,
+                    vmsize: 0,
+                    fileoff: 0,
+                    filesize: 0,
+                });
+            }
+        }
+
+        let mut ordinals = vec![0u8; sections.len()];
+        for (ordinal, &index) in segments.iter().flat_map(|s| s.sections.iter()).enumerate() {
+            ordinals[index] = (ordinal + 1) as u8;
+        }
+
+        let pagezero = (linker.options.seg1addr != 0) as u32;
+        let header_size = MACH_HEADER_SIZE as u32
+            + pagezero * SEGMENT_COMMAND_SIZE as u32
+            + segments
+                .iter()
+                .map(|s| (SEGMENT_COMMAND_SIZE + s.sections.len() * SECTION_SIZE) as u32)
+                .sum::<u32>()
+            + SYMTAB_COMMAND_SIZE as u32
+            + THREAD_COMMAND_SIZE;
+
+        let mut vmaddr = linker.options.seg1addr;
+        let mut fileoff = 0;
+        for (i, segment) in segments.iter_mut().enumerate() {
+            segment.vmaddr = vmaddr;
+            segment.fileoff = fileoff;
+            let mut cursor = vmaddr + if i == 0 { header_size } else { 0 };
+            let mut file_end = cursor;
+            for &index in &segment.sections {
+                let section = &mut sections[index];
+                cursor = align_up(cursor, 1 << section.align.min(15));
+                section.addr = cursor;
+                cursor += section.size;
+                if section.flags & SECTION_TYPE != S_ZEROFILL {
+                    section.offset = fileoff + (section.addr - vmaddr);
+                    file_end = cursor;
+                }
+            }
+            segment.filesize = if file_end > vmaddr { round_page(file_end - vmaddr) } else { 0 };
+            segment.vmsize = round_page(cursor - vmaddr).max(segment.filesize);
+            vmaddr += segment.vmsize;
+            fileoff += segment.filesize;
+        }
+
+        Layout {
+            sections,
+            segments,
+            placement,
+            commons,
+            ordinals,
+            header_size,
+            text_vmaddr: linker.options.seg1addr,
+            end_of_segments: fileoff,
+        }
+    }
+
+    // Linked address of an object's input section (0-based index)
+    fn section_addr(&self, object: usize, section: usize) -> u32 {
+        let (out, offset) = self.placement[object][section];
+        self.sections[out].addr + offset
+    }
+}
+
+struct Writer<'l, 'a> {
+    linker: &'l Linker<'a>,
+    layout: &'l Layout,
+    entry: &'l [u8],
+    image: Vec<u8>,
+}
+
+fn put32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_name(out: &mut Vec<u8>, name: &str) {
+    let mut field = [0u8; 16];
+    let len = name.len().min(16);
+    field[..len].copy_from_slice(&name.as_bytes()[..len]);
+    out.extend_from_slice(&field);
+}
+
+fn read_item(bytes: &[u8], size: u32) -> i64 {
+    match size {
+        1 => bytes[0] as i8 as i64,
+        2 => i16::from_be_bytes([bytes[0], bytes[1]]) as i64,
+        _ => i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64,
+    }
+}
+
+impl<'l, 'a> Writer<'l, 'a> {
+    fn new(linker: &'l Linker<'a>, layout: &'l Layout, entry: &'l [u8]) -> Writer<'l, 'a> {
+        Writer { linker, layout, entry, image: Vec::new() }
+    }
+
+    // Linked address of a symbol defined in `object`
+    fn symbol_addr(&self, object: usize, symbol: &Nlist) -> u32 {
+        match symbol.kind() {
+            Some(N_SECT) if symbol.n_sect != NO_SECT => {
+                let section = symbol.n_sect as usize - 1;
+                let old = self.linker.objects[object].sections[section].addr;
+                self.layout.section_addr(object, section).wrapping_add(symbol.n_value.wrapping_sub(old))
+            }
+            _ => symbol.n_value,
+        }
+    }
+
+    fn global_addr(&self, name: &[u8]) -> u32 {
+        match self.linker.globals[name].definition {
+            Some(Definition::Symbol { object, symbol }) => {
+                self.symbol_addr(object, &self.linker.objects[object].symbols[symbol])
+            }
+            Some(Definition::Common { .. }) => {
+                let common = self.layout.sections.iter().find(|s| s.segname == "__DATA" && s.sectname == "__common");
+                common.map_or(0, |s| s.addr) + self.layout.commons[name]
+            }
+            Some(Definition::Header) => self.layout.text_vmaddr,
+            None => 0,
+        }
+    }
+
+    // How far the input section of `object` holding `address` moved
+    fn displacement_at(&self, object: usize, address: u32) -> Option<i64> {
+        let sections = &self.linker.objects[object].sections;
+        let index = sections
+            .iter()
+            .position(|s| address >= s.addr && address - s.addr < s.size)
+            .or_else(|| sections.iter().position(|s| address >= s.addr && address - s.addr <= s.size))?;
+        Some(self.layout.section_addr(object, index) as i64 - sections[index].addr as i64)
+    }
+
+    // How much the value an item refers to moved
+    fn target_displacement(&self, object: usize, reloc: &Relocation) -> std::result::Result<i64, String> {
+        let obj = &self.linker.objects[object];
+        match reloc.target {
+            RelocTarget::Symbol(index) => {
+                let symbol = obj.symbols.get(index as usize).ok_or(format!("symbol index {} out of range", index))?;
+                // External items hold only the addend
+                if symbol.is_external() {
+                    Ok(self.global_addr(symbol.name) as i64)
+                } else {
+                    Ok(self.symbol_addr(object, symbol) as i64)
+                }
+            }
+            RelocTarget::Section(R_ABS) => Ok(0),
+            RelocTarget::Section(n) => {
+                let index = (n as usize).checked_sub(1).filter(|&i| i < obj.sections.len());
+                let index = index.ok_or(format!("section ordinal {} out of range", n))?;
+                Ok(self.layout.section_addr(object, index) as i64 - obj.sections[index].addr as i64)
+            }
+            RelocTarget::Address(value) => self
+                .displacement_at(object, value)
+                .ok_or(format!("scattered address {:#x} is outside every section", value)),
+        }
+    }
+
+    fn relocate_section(&mut self, object: usize, section: usize) -> Result<()> {
+        let obj = &self.linker.objects[object];
+        let input = &obj.sections[section];
+        let name = format!("{},{}", input.segname, input.sectname);
+        let relocs = obj.macho.relocations(input).map_err(|error| Error::Parse { file: obj.name.clone(), error })?;
+        let base = self.layout.sections[self.layout.placement[object][section].0].offset as usize
+            + self.layout.placement[object][section].1 as usize;
+        let pc_displacement = self.layout.section_addr(object, section) as i64 - input.addr as i64;
+        let bad = |reloc: &Relocation, reason: String| Error::BadRelocation {
+            file: obj.name.clone(),
+            section: name.clone(),
+            address: reloc.address,
+            reason,
+        };
+
+        let mut i = 0;
+        while i < relocs.len() {
+            let reloc = relocs[i];
+            let size = reloc.size();
+            if reloc.length > 2 || reloc.address as u64 + size as u64 > input.size as u64 {
+                return Err(bad(&reloc, "item is outside the section".to_string()));
+            }
+            let at = base + reloc.address as usize;
+            let old = read_item(&self.image[at..], size);
+            let value = match reloc.r_type {
+                M68K_RELOC_VANILLA => {
+                    let mut displacement = self.target_displacement(object, &reloc).map_err(|e| bad(&reloc, e))?;
+                    if reloc.pcrel {
+                        displacement -= pc_displacement;
+                    }
+                    old + displacement
+                }
+                M68K_RELOC_SECTDIFF | M68K_RELOC_LOCAL_SECTDIFF => {
+                    let pair = relocs.get(i + 1).filter(|r| r.is_pair());
+                    let (RelocTarget::Address(a), Some(&Relocation { target: RelocTarget::Address(b), .. })) =
+                        (reloc.target, pair)
+                    else {
+                        return Err(bad(&reloc, "SECTDIFF needs a scattered entry and a PAIR".to_string()));
+                    };
+                    i += 1;
+                    let minuend = self.displacement_at(object, a);
+                    let subtrahend = self.displacement_at(object, b);
+                    let (Some(minuend), Some(subtrahend)) = (minuend, subtrahend) else {
+                        return Err(bad(&reloc, "SECTDIFF address outside every section".to_string()));
+                    };
+                    old + minuend - subtrahend
+                }
+                M68K_RELOC_PAIR => return Err(bad(&reloc, "PAIR without a SECTDIFF".to_string())),
+                other => {
+                    return Err(Error::Unsupported {
+                        file: obj.name.clone(),
+                        what: format!("relocation type {} in {}", reloc_type_name(other).unwrap_or("?"), name),
+                    })
+                }
+            };
+            let bits = 8 * size;
+            if value < -(1i64 << (bits - 1)) || value >= 1i64 << bits {
+                return Err(Error::Overflow { file: obj.name.clone(), section: name, address: reloc.address, value });
+            }
+            let bytes = (value as u32).to_be_bytes();
+            self.image[at..at + size as usize].copy_from_slice(&bytes[4 - size as usize..]);
+            i += 1;
+        }
+        Ok(())
+    }
+
+    fn write_commands(&mut self, nsyms: u32, symoff: u32, stroff: u32, strsize: u32) -> Result<()> {
+        let layout = self.layout;
+        let out = &mut self.image;
+        let pagezero = self.linker.options.seg1addr != 0;
+        let ncmds = layout.segments.len() as u32 + pagezero as u32 + 2;
+        for word in [
+            MH_MAGIC,
+            CPU_TYPE_MC680X0 as u32,
+            self.linker.cpusubtype as u32,
+            MH_EXECUTE,
+            ncmds,
+            layout.header_size - MACH_HEADER_SIZE as u32,
+            MH_NOUNDEFS,
+        ] {
+            put32(out, word);
+        }
+        if pagezero {
+            put32(out, LC_SEGMENT);
+            put32(out, SEGMENT_COMMAND_SIZE as u32);
+            put_name(out, "__PAGEZERO");
+            for word in [0, self.linker.options.seg1addr, 0, 0, 0, 0, 0, 0] {
+                put32(out, word);
+            }
+        }
+        for segment in &layout.segments {
+            let text = segment.name == "__TEXT";
+            let initprot = if text { VM_PROT_READ | VM_PROT_EXECUTE } else { VM_PROT_READ | VM_PROT_WRITE };
+            put32(out, LC_SEGMENT);
+            put32(out, (SEGMENT_COMMAND_SIZE + segment.sections.len() * SECTION_SIZE) as u32);
+            put_name(out, &segment.name);
+            let all = VM_PROT_READ | VM_PROT_WRITE | VM_PROT_EXECUTE;
+            for word in [
+                segment.vmaddr,
+                segment.vmsize,
+                segment.fileoff,
+                segment.filesize,
+                all,
+                initprot,
+                segment.sections.len() as u32,
+                0,
+            ] {
+                put32(out, word);
+            }
+            for &index in &segment.sections {
+                let section = &layout.sections[index];
+                put_name(out, &section.sectname);
+                put_name(out, &section.segname);
+                for word in [section.addr, section.size, section.offset, section.align, 0, 0, section.flags, 0, 0] {
+                    put32(out, word);
+                }
+            }
+        }
+        for word in [LC_SYMTAB, SYMTAB_COMMAND_SIZE as u32, symoff, nsyms, stroff, strsize] {
+            put32(out, word);
+        }
+        let entry = self.global_addr_checked()?;
+        for word in [LC_UNIXTHREAD, THREAD_COMMAND_SIZE, M68K_THREAD_STATE_REGS, M68K_THREAD_STATE_REGS_COUNT] {
+            put32(&mut self.image, word);
+        }
+        for i in 0..M68K_THREAD_STATE_REGS_COUNT as usize {
+            put32(&mut self.image, if i == M68K_THREAD_STATE_PC { entry } else { 0 });
+        }
+        Ok(())
+    }
+
+    fn global_addr_checked(&self) -> Result<u32> {
+        match self.linker.globals.get(self.entry) {
+            Some(Global { definition: Some(_), .. }) => Ok(self.global_addr(self.entry)),
+            _ => Err(Error::NoEntry(lossy(self.entry))),
+        }
+    }
+
+    // The output symbol table: each object's stabs and locals in input
+    // order, then the globals
+    fn symbols(&self) -> (Vec<u8>, Vec<u8>, u32) {
+        let strip = self.linker.options.strip;
+        let mut table = Vec::new();
+        let mut strings = vec![0u8];
+        let mut count = 0;
+        let mut push = |name: &[u8], n_type: u8, n_sect: u8, n_desc: u16, n_value: u32| {
+            let n_strx = if name.is_empty() {
+                0
+            } else {
+                strings.extend_from_slice(name);
+                strings.push(0);
+                (strings.len() - name.len() - 1) as u32
+            };
+            let entry = Nlist { name: &[], n_strx, n_type, n_sect, n_desc, n_value };
+            table.extend_from_slice(&entry.to_bytes());
+            count += 1;
+        };
+        let out_sect = |object: usize, n_sect: u8| -> u8 {
+            match (n_sect as usize).checked_sub(1) {
+                Some(index) if index < self.layout.placement[object].len() => {
+                    self.layout.ordinals[self.layout.placement[object][index].0]
+                }
+                _ => NO_SECT,
+            }
+        };
+
+        if strip != Strip::All {
+            for (object, obj) in self.linker.objects.iter().enumerate() {
+                for symbol in &obj.symbols {
+                    if symbol.is_stab() {
+                        if strip != Strip::None {
+                            continue;
+                        }
+                        let (n_sect, n_value) = if symbol.n_sect == NO_SECT {
+                            (NO_SECT, symbol.n_value)
+                        } else {
+                            let index = symbol.n_sect as usize - 1;
+                            let moved = obj.sections.get(index).map_or(0, |s| {
+                                self.layout.section_addr(object, index).wrapping_sub(s.addr)
+                            });
+                            (out_sect(object, symbol.n_sect), symbol.n_value.wrapping_add(moved))
+                        };
+                        push(symbol.name, symbol.n_type, n_sect, symbol.n_desc, n_value);
+                        continue;
+                    }
+                    // Assembler-local labels never reach the output
+                    let local = !symbol.is_external() && matches!(symbol.kind(), Some(N_SECT) | Some(N_ABS));
+                    if !local || strip == Strip::Locals || symbol.name.is_empty() || symbol.name[0] == b'L' {
+                        continue;
+                    }
+                    let n_sect = if symbol.kind() == Some(N_SECT) { out_sect(object, symbol.n_sect) } else { NO_SECT };
+                    push(symbol.name, symbol.n_type, n_sect, symbol.n_desc, self.symbol_addr(object, symbol));
+                }
+            }
+
+            let common_sect = self
+                .layout
+                .sections
+                .iter()
+                .position(|s| s.segname == "__DATA" && s.sectname == "__common")
+                .map_or(NO_SECT, |i| self.layout.ordinals[i]);
+            for name in &self.linker.order {
+                let address = self.global_addr(name);
+                match self.linker.globals[name].definition {
+                    Some(Definition::Symbol { object, symbol }) => {
+                        let symbol = &self.linker.objects[object].symbols[symbol];
+                        let n_sect = if symbol.kind() == Some(N_SECT) { out_sect(object, symbol.n_sect) } else { NO_SECT };
+                        push(name, (symbol.n_type & N_TYPE) | N_EXT, n_sect, symbol.n_desc, address);
+                    }
+                    Some(Definition::Common { .. }) => push(name, N_SECT | N_EXT, common_sect, 0, address),
+                    Some(Definition::Header) => {
+                        let (n_type, n_sect) = if self.layout.ordinals.is_empty() { (N_ABS, NO_SECT) } else { (N_SECT, 1) };
+                        push(name, n_type | N_EXT, n_sect, 0, address)
+                    }
+                    None => {}
+                }
+            }
+        }
+        while strings.len() % 4 != 0 {
+            strings.push(0);
+        }
+        (table, strings, count)
+    }
+
+    fn write(mut self) -> Result<Vec<u8>> {
+        let (table, strings, nsyms) = self.symbols();
+        let symoff = self.layout.end_of_segments;
+        let stroff = symoff + table.len() as u32;
+        self.write_commands(nsyms, symoff, stroff, strings.len() as u32)?;
+        debug_assert_eq!(self.image.len() as u32, self.layout.header_size);
+        self.image.resize(self.layout.end_of_segments as usize, 0);
+
+        let linker = self.linker;
+        for (object, obj) in linker.objects.iter().enumerate() {
+            for (index, section) in obj.sections.iter().enumerate() {
+                if section.is_zerofill() {
+                    continue;
+                }
+                let data = obj.macho.section_data(section).map_err(|error| Error::Parse { file: obj.name.clone(), error })?;
+                let (out, offset) = self.layout.placement[object][index];
+                let at = (self.layout.sections[out].offset + offset) as usize;
+                self.image[at..at + data.len()].copy_from_slice(data);
+            }
+        }
+        for (object, obj) in linker.objects.iter().enumerate() {
+            for (index, section) in obj.sections.iter().enumerate() {
+                if !section.is_zerofill() && section.nreloc != 0 {
+                    self.relocate_section(object, index)?;
+                }
+            }
+        }
+
+        self.image.extend_from_slice(&table);
+        self.image.extend_from_slice(&strings);
+        Ok(self.image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nextstep_macho::validate::{validate, Severity};
+
+    struct Sect<'s> {
+        seg: &'s str,
+        sect: &'s str,
+        addr: u32,
+        data: &'s [u8],
+        align: u32,
+        zerofill: bool,
+        relocs: Vec<Relocation>,
+    }
+
+    fn sect<'s>(seg: &'s str, sect: &'s str, addr: u32, data: &'s [u8]) -> Sect<'s> {
+        Sect { seg, sect, addr, data, align: 1, zerofill: false, relocs: Vec::new() }
+    }
+
+    // (name, n_type, n_sect, n_value)
+    type Sym<'s> = (&'s str, u8, u8, u32);
+
+    // A relocatable MH_OBJECT like LLVM writes
+    fn object(sections: &[Sect], symbols: &[Sym]) -> Vec<u8> {
+        let sizeofcmds = SEGMENT_COMMAND_SIZE + sections.len() * SECTION_SIZE + SYMTAB_COMMAND_SIZE;
+        let mut at = (MACH_HEADER_SIZE + sizeofcmds) as u32;
+        let mut out = Vec::new();
+        for word in [MH_MAGIC, 6, 1, MH_OBJECT, 2, sizeofcmds as u32, 0] {
+            put32(&mut out, word);
+        }
+        let vmsize = sections.iter().map(|s| s.addr + s.data.len() as u32).max().unwrap_or(0);
+        put32(&mut out, LC_SEGMENT);
+        put32(&mut out, (SEGMENT_COMMAND_SIZE + sections.len() * SECTION_SIZE) as u32);
+        put_name(&mut out, "");
+        let filesize: u32 = sections.iter().filter(|s| !s.zerofill).map(|s| s.data.len() as u32).sum();
+        for word in [0, vmsize, at, filesize, 7, 7, sections.len() as u32, 0] {
+            put32(&mut out, word);
+        }
+        let mut reloff = at + filesize;
+        for s in sections {
+            put_name(&mut out, s.sect);
+            put_name(&mut out, s.seg);
+            let (offset, flags) = if s.zerofill { (0, S_ZEROFILL) } else { (at, 0) };
+            for word in [s.addr, s.data.len() as u32, offset, s.align, reloff, s.relocs.len() as u32, flags, 0, 0] {
+                put32(&mut out, word);
+            }
+            if !s.zerofill {
+                at += s.data.len() as u32;
+            }
+            reloff += (s.relocs.len() * RELOCATION_INFO_SIZE) as u32;
+        }
+        let symoff = reloff;
+        let mut strings = vec![0u8];
+        let mut table = Vec::new();
+        for &(name, n_type, n_sect, n_value) in symbols {
+            let n_strx = strings.len() as u32;
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+            table.extend_from_slice(&Nlist { name: &[], n_strx, n_type, n_sect, n_desc: 0, n_value }.to_bytes());
+        }
+        for word in [LC_SYMTAB, SYMTAB_COMMAND_SIZE as u32, symoff, symbols.len() as u32, symoff + table.len() as u32, strings.len() as u32] {
+            put32(&mut out, word);
+        }
+        for s in sections.iter().filter(|s| !s.zerofill) {
+            out.extend_from_slice(s.data);
+        }
+        for s in sections {
+            for reloc in &s.relocs {
+                out.extend_from_slice(&reloc.to_bytes());
+            }
+        }
+        out.extend_from_slice(&table);
+        out.extend_from_slice(&strings);
+        out
+    }
+
+    fn extern_reloc(address: u32, symbol: u32, pcrel: bool, length: u8) -> Relocation {
+        Relocation { address, pcrel, length, r_type: M68K_RELOC_VANILLA, target: RelocTarget::Symbol(symbol) }
+    }
+
+    fn scattered(address: u32, r_type: u8, value: u32) -> Relocation {
+        Relocation { address, pcrel: false, length: 2, r_type, target: RelocTarget::Address(value) }
+    }
+
+    const TEXT: u8 = N_SECT | N_EXT;
+    const UNDEF: u8 = N_UNDF | N_EXT;
+
+    // _start: jsr _helper; lea _counter,%a0; rts -- plus a 16-byte common
+    fn main_object() -> Vec<u8> {
+        let code = [0x4e, 0xb9, 0, 0, 0, 0, 0x41, 0xf9, 0, 0, 0, 0, 0x4e, 0x75];
+        let mut text = sect("__TEXT", "__text", 0, &code);
+        text.relocs = vec![extern_reloc(2, 1, false, 2), extern_reloc(8, 2, false, 2)];
+        object(&[text], &[("_start", TEXT, 1, 0), ("_helper", UNDEF, 0, 0), ("_counter", UNDEF, 0, 0), ("_buf", UNDEF, 0, 16)])
+    }
+
+    // _helper: rts; _counter: .long 42
+    fn helper_object() -> Vec<u8> {
+        let text = sect("__TEXT", "__text", 0, &[0x4e, 0x75]);
+        let data = sect("__DATA", "__data", 4, &[0, 0, 0, 42]);
+        object(&[text, data], &[("_helper", TEXT, 1, 0), ("_counter", TEXT, 2, 4)])
+    }
+
+    fn symbol_value(macho: &MachO, name: &str) -> u32 {
+        macho.symbols().unwrap().iter().find(|s| s.name == name.as_bytes()).unwrap().n_value
+    }
+
+    fn word_at(macho: &MachO, address: u32) -> u32 {
+        let section = macho.sections().find(|s| address >= s.addr && address < s.addr + s.size).unwrap();
+        let data = macho.section_data(section).unwrap();
+        let at = (address - section.addr) as usize;
+        u32::from_be_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+    }
+
+    fn archive(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = nextstep_ar::Builder::new();
+        for (name, data) in members {
+            builder.insert(nextstep_ar::NewMember::new(name, data.to_vec()));
+        }
+        builder.write().unwrap()
+    }
+
+    fn link(inputs: &[(&str, &[u8])]) -> Result<Vec<u8>> {
+        let mut linker = Linker::new(Options::default());
+        for (name, data) in inputs {
+            linker.add_file(name, data, false)?;
+        }
+        linker.link()
+    }
+
+    #[test]
+    fn test_link_executable() {
+        let (main, helper) = (main_object(), helper_object());
+        let image = link(&[("main.o", &main), ("helper.o", &helper)]).unwrap();
+        let macho = MachO::parse(&image).unwrap();
+
+        let errors: Vec<_> = validate(&macho, &Default::default()).into_iter().filter(|d| d.severity == Severity::Error).collect();
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(macho.header.filetype, MH_EXECUTE);
+        let names: Vec<&str> = macho.segments().map(|s| s.segname.as_str()).collect();
+        assert_eq!(names, ["__PAGEZERO", "__TEXT", "__DATA"]);
+
+        let start = symbol_value(&macho, "_start");
+        let helper = symbol_value(&macho, "_helper");
+        let counter = symbol_value(&macho, "_counter");
+        assert_eq!(macho.entry_point(), Some(start));
+        assert_eq!(word_at(&macho, start + 2), helper);
+        assert_eq!(word_at(&macho, start + 8), counter);
+        assert_eq!(word_at(&macho, counter), 42);
+        // helper.o's text follows main.o's, 2-byte aligned
+        assert_eq!(helper, start + 14);
+
+        let buf = macho.symbols().unwrap().into_iter().find(|s| s.name == b"_buf").unwrap();
+        let common = macho.section(buf.n_sect as u32).unwrap();
+        assert_eq!((common.sectname.as_str(), common.is_zerofill()), ("__common", true));
+        assert_eq!(symbol_value(&macho, MH_EXECUTE_HEADER), NEXT_PAGE_SIZE);
+    }
+
+    #[test]
+    fn test_symbol_section_out_of_range() {
+        let main = main_object();
+        // _helper claims section 3 of a 1-section object
+        let helper = object(&[sect("__TEXT", "__text", 0, &[0x4e, 0x75])], &[("_helper", TEXT, 3, 0), ("Llocal", N_SECT, 2, 0)]);
+        match link(&[("main.o", &main), ("helper.o", &helper)]) {
+            Err(Error::Unsupported { file, what }) => {
+                assert_eq!(file, "helper.o");
+                assert_eq!(what, "symbol _helper is in section 3 of 1");
+            }
+            other => panic!("expected Unsupported, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_pcrel_section_and_sectdiff_relocations() {
+        // bsr.w _far; .long data - text (SECTDIFF); lea data (section-relative)
+        let code = [0x61, 0, 0xff, 0xfe, 0, 0, 0, 0x10, 0x41, 0xf9, 0, 0, 0, 0x10];
+        let mut text = sect("__TEXT", "__text", 0, &code);
+        text.relocs = vec![
+            extern_reloc(2, 2, true, 1),
+            scattered(4, M68K_RELOC_SECTDIFF, 0x10),
+            scattered(0, M68K_RELOC_PAIR, 0),
+            Relocation { address: 10, pcrel: false, length: 2, r_type: M68K_RELOC_VANILLA, target: RelocTarget::Section(2) },
+        ];
+        let data = sect("__DATA", "__data", 0x10, &[1, 2, 3, 4]);
+        let first = object(&[text, data], &[("_start", TEXT, 1, 0), ("Ldata", N_SECT, 2, 0x10), ("_far", UNDEF, 0, 0)]);
+        let second = object(&[sect("__TEXT", "__text", 0, &[0x4e, 0x71, 0x4e, 0x75])], &[("_far", TEXT, 1, 2)]);
+
+        let image = link(&[("first.o", &first), ("second.o", &second)]).unwrap();
+        let macho = MachO::parse(&image).unwrap();
+        let start = symbol_value(&macho, "_start");
+        let far = symbol_value(&macho, "_far");
+        let data = macho.sections().find(|s| s.sectname == "__data").unwrap().addr;
+
+        let text = macho.section(1).unwrap();
+        let code = &macho.section_data(text).unwrap()[(start - text.addr) as usize..];
+        assert_eq!(i16::from_be_bytes([code[2], code[3]]) as i64, far as i64 - (start as i64 + 2));
+        assert_eq!(word_at(&macho, start + 4), data - start);
+        assert_eq!(word_at(&macho, start + 10), data);
+        // Assembler-local labels are dropped
+        assert!(macho.symbols().unwrap().iter().all(|s| s.name != b"Ldata"));
+    }
+
+    #[test]
+    fn test_undefined_and_duplicate_symbols() {
+        let main = main_object();
+        match link(&[("main.o", &main)]) {
+            Err(Error::Undefined(symbols)) => assert_eq!(
+                symbols,
+                [("_counter".to_string(), "main.o".to_string()), ("_helper".to_string(), "main.o".to_string())]
+            ),
+            other => panic!("expected undefined symbols, got {:?}", other.map(|_| ())),
+        }
+        let helper = helper_object();
+        assert!(matches!(
+            link(&[("main.o", &main), ("a.o", &helper), ("b.o", &helper)]),
+            Err(Error::Duplicate { symbol, .. }) if symbol == "_helper"
+        ));
+    }
+
+    #[test]
+    fn test_archive_members_linked_on_demand() {
+        let main = main_object();
+        let helper = helper_object();
+        let unused = object(&[sect("__TEXT", "__text", 0, &[0x4e, 0x75])], &[("_unused", TEXT, 1, 0)]);
+        let archive = archive(&[("unused.o", &unused), ("helper.o", &helper), ("lib.rmeta", b"not an object")]);
+
+        let image = link(&[("libhelper.a", &archive), ("main.o", &main)]).unwrap();
+        let macho = MachO::parse(&image).unwrap();
+        let symbols = macho.symbols().unwrap();
+        assert!(symbols.iter().any(|s| s.name == b"_helper"));
+        assert!(symbols.iter().all(|s| s.name != b"_unused"));
+
+        let mut linker = Linker::new(Options::default());
+        linker.add_file("main.o", &main, false).unwrap();
+        linker.add_file("libhelper.a", &archive, true).unwrap();
+        let image = linker.link().unwrap();
+        assert!(MachO::parse(&image).unwrap().symbols().unwrap().iter().any(|s| s.name == b"_unused"));
+    }
+
+    #[test]
+    fn test_entry_point_and_strip() {
+        let (main, helper) = (main_object(), helper_object());
+        let mut linker = Linker::new(Options { entry: Some("_nope".to_string()), ..Options::default() });
+        linker.add_object("main.o", &main).unwrap();
+        linker.add_object("helper.o", &helper).unwrap();
+        assert!(matches!(linker.link(), Err(Error::NoEntry(name)) if name == "_nope"));
+
+        let mut linker = Linker::new(Options { strip: Strip::All, seg1addr: 0, ..Options::default() });
+        linker.add_object("main.o", &main).unwrap();
+        linker.add_object("helper.o", &helper).unwrap();
+        let image = linker.link().unwrap();
+        let macho = MachO::parse(&image).unwrap();
+        assert!(macho.symbols().unwrap().is_empty());
+        assert_eq!(macho.segments().next().unwrap().segname, "__TEXT");
+        assert_eq!(macho.entry_point(), Some(MACH_HEADER_SIZE as u32 + macho.header.sizeofcmds));
+    }
+}
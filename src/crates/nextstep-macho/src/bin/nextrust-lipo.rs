@@ -0,0 +1,204 @@
+//! nextrust-lipo - Create and take apart fat NeXTSTEP binaries
+//!
+//! Usage: nextrust-lipo -create FILE... -output OUTPUT
+//!        nextrust-lipo -thin ARCH FILE -output OUTPUT
+//!        nextrust-lipo -remove ARCH FILE -output OUTPUT
+//!        nextrust-lipo -replace ARCH NEWFILE FILE -output OUTPUT
+//!        nextrust-lipo -info FILE...
+//!        nextrust-lipo -detailed_info FILE...
+//!
+//! ARCH is m68k, i386, hppa or sparc (m68030/m68040 name the same slice
+//! as m68k). Inputs to -create may themselves be fat; every slice is
+//! taken, and each CPU may appear only once. Slices are aligned to their
+//! CPU's page size, as NeXT's `lipo` does, so the kernel can map them.
+
+use nextstep_macho::consts::{arch_from_name, arch_name};
+use nextstep_macho::fat::{build, thin_arch};
+use nextstep_macho::{is_fat, FatFile};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: nextrust-lipo -create FILE... -output OUTPUT
+       nextrust-lipo -thin|-remove ARCH FILE -output OUTPUT
+       nextrust-lipo -replace ARCH NEWFILE FILE -output OUTPUT
+       nextrust-lipo -info|-detailed_info FILE...";
+
+enum Operation {
+    Create,
+    Thin(i32),
+    Remove(i32),
+    Replace(i32, String),
+    Info,
+    DetailedInfo,
+}
+
+// One slice: its CPU type and bytes
+struct Slice {
+    cputype: i32,
+    data: Vec<u8>,
+}
+
+fn arch(name: Option<String>) -> Result<i32, String> {
+    let name = name.ok_or_else(|| USAGE.to_string())?;
+    arch_from_name(&name).map(|(cputype, _)| cputype).ok_or_else(|| format!("unknown architecture {}", name))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(Operation, Vec<String>, Option<String>), String> {
+    let mut operation = None;
+    let mut files = Vec::new();
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        let op = match arg.as_str() {
+            "-create" => Operation::Create,
+            "-thin" => Operation::Thin(arch(args.next())?),
+            "-remove" => Operation::Remove(arch(args.next())?),
+            "-replace" => {
+                let cputype = arch(args.next())?;
+                Operation::Replace(cputype, args.next().ok_or_else(|| USAGE.to_string())?)
+            }
+            "-info" => Operation::Info,
+            "-detailed_info" => Operation::DetailedInfo,
+            "-output" | "-o" => {
+                output = Some(args.next().ok_or_else(|| USAGE.to_string())?);
+                continue;
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ => {
+                files.push(arg);
+                continue;
+            }
+        };
+        if operation.replace(op).is_some() {
+            return Err(format!("only one operation at a time\n{}", USAGE));
+        }
+    }
+    let operation = operation.ok_or_else(|| USAGE.to_string())?;
+    let writes = !matches!(operation, Operation::Info | Operation::DetailedInfo);
+    if files.is_empty() || writes && output.is_none() {
+        return Err(USAGE.to_string());
+    }
+    if writes && !matches!(operation, Operation::Create) && files.len() != 1 {
+        return Err(format!("exactly one input file needed\n{}", USAGE));
+    }
+    Ok((operation, files, output))
+}
+
+fn read(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+// The slices of a fat file, or the file itself if it is thin
+fn slices(path: &str) -> Result<Vec<Slice>, String> {
+    let data = read(path)?;
+    if is_fat(&data) {
+        let fat = FatFile::parse(&data).map_err(|e| format!("{}: {}", path, e))?;
+        return Ok(fat.arches.iter().map(|a| Slice { cputype: a.cputype, data: fat.slice(a).to_vec() }).collect());
+    }
+    let (cputype, _) = thin_arch(&data).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(vec![Slice { cputype, data }])
+}
+
+fn name(cputype: i32, cpusubtype: i32) -> String {
+    arch_name(cputype, cpusubtype)
+        .map_or_else(|| format!("cputype {} cpusubtype {}", cputype, cpusubtype), str::to_string)
+}
+
+fn info(path: &str, detailed: bool) -> Result<(), String> {
+    let data = read(path)?;
+    if !is_fat(&data) {
+        let (cputype, cpusubtype) = thin_arch(&data).map_err(|e| format!("{}: {}", path, e))?;
+        println!("Non-fat file: {} is architecture: {}", path, name(cputype, cpusubtype));
+        return Ok(());
+    }
+    let fat = FatFile::parse(&data).map_err(|e| format!("{}: {}", path, e))?;
+    if !detailed {
+        let names: Vec<String> = fat.arches.iter().map(|a| name(a.cputype, a.cpusubtype)).collect();
+        println!("Architectures in the fat file: {} are: {}", path, names.join(" "));
+        return Ok(());
+    }
+    println!("Fat header in: {}", path);
+    println!("nfat_arch {}", fat.arches.len());
+    for arch in &fat.arches {
+        println!("architecture {}", name(arch.cputype, arch.cpusubtype));
+        println!("    cputype {}", arch.cputype);
+        println!("    cpusubtype {}", arch.cpusubtype);
+        println!("    offset {}", arch.offset);
+        println!("    size {}", arch.size);
+        println!("    align 2^{} ({})", arch.align, 1u32 << arch.align);
+    }
+    Ok(())
+}
+
+fn write_output(path: &str, data: &[u8], like: &str) -> Result<(), String> {
+    std::fs::write(path, data).map_err(|e| format!("{}: {}", path, e))?;
+    // Keep the execute bits of the (first) input
+    if let Ok(metadata) = std::fs::metadata(like) {
+        std::fs::set_permissions(path, metadata.permissions()).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+fn fat(slices: &[Slice]) -> Result<Vec<u8>, String> {
+    let data: Vec<&[u8]> = slices.iter().map(|s| s.data.as_slice()).collect();
+    build(&data).map_err(|e| e.to_string())
+}
+
+fn run(operation: Operation, files: &[String], output: Option<String>) -> Result<(), String> {
+    let input = &files[0];
+    let missing = |cputype: i32| format!("{} does not contain cputype {}", input, cputype);
+    let data = match operation {
+        Operation::Info | Operation::DetailedInfo => {
+            let detailed = matches!(operation, Operation::DetailedInfo);
+            return files.iter().try_for_each(|path| info(path, detailed));
+        }
+        Operation::Create => {
+            let mut all = Vec::new();
+            for path in files {
+                all.extend(slices(path)?);
+            }
+            fat(&all)?
+        }
+        Operation::Thin(cputype) => {
+            let slice = slices(input)?.into_iter().find(|s| s.cputype == cputype).ok_or_else(|| missing(cputype))?;
+            slice.data
+        }
+        Operation::Remove(cputype) => {
+            let mut all = slices(input)?;
+            let before = all.len();
+            all.retain(|s| s.cputype != cputype);
+            if all.len() == before {
+                return Err(missing(cputype));
+            }
+            fat(&all)?
+        }
+        Operation::Replace(cputype, new_file) => {
+            let mut all = slices(input)?;
+            let data = read(&new_file)?;
+            let (new_type, _) = thin_arch(&data).map_err(|e| format!("{}: {}", new_file, e))?;
+            if new_type != cputype {
+                return Err(format!("{} is not a thin file for cputype {}", new_file, cputype));
+            }
+            let slot = all.iter_mut().find(|s| s.cputype == cputype).ok_or_else(|| missing(cputype))?;
+            slot.data = data;
+            fat(&all)?
+        }
+    };
+    let output = output.unwrap_or_default();
+    write_output(&output, &data, input)
+}
+
+fn main() -> ExitCode {
+    let (operation, files, output) = match parse_args(std::env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::from(2);
+        }
+    };
+    match run(operation, &files, output) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("nextrust-lipo: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! nextrust-macho-validate - Check Mach-O files against NeXTSTEP 3.3's rules
+//!
+//! Usage: nextrust-macho-validate [--cpu 68030|68040] [--werror] [-q] FILE...
+//!
+//! Prints one line per violation as `FILE: error[rule]: message`. Exits 1
+//! if any file has errors (or warnings with --werror), 2 on bad usage.
+//! Fat files are checked through their m68k slice.
+
+use nextstep_macho::validate::{validate, validate_fat, Cpu, Options, Severity};
+use nextstep_macho::{is_fat, FatFile, MachO};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: nextrust-macho-validate [--cpu 68030|68040] [--werror] [-q] FILE...";
+
+struct Args {
+    options: Options,
+    werror: bool,
+    quiet: bool,
+    files: Vec<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args { options: Options::default(), werror: false, quiet: false, files: Vec::new() };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--cpu" => {
+                parsed.options.cpu = Some(match args.next().as_deref() {
+                    Some("68030") => Cpu::M68030,
+                    Some("68040") => Cpu::M68040,
+                    _ => return Err(format!("--cpu takes 68030 or 68040\n{}", USAGE)),
+                })
+            }
+            "--werror" => parsed.werror = true,
+            "-q" | "--quiet" => parsed.quiet = true,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ => parsed.files.push(arg),
+        }
+    }
+    if parsed.files.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    Ok(parsed)
+}
+
+// Whether the file passed
+fn check(path: &str, args: &Args) -> bool {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) => {
+            println!("{}: error[io]: {}", path, e);
+            return false;
+        }
+    };
+    let parsed = if is_fat(&data) {
+        FatFile::parse(&data).map(|fat| validate_fat(&fat, &args.options))
+    } else {
+        MachO::parse(&data).map(|macho| validate(&macho, &args.options))
+    };
+    let diagnostics = match parsed {
+        Ok(diagnostics) => diagnostics,
+        Err(e) => {
+            println!("{}: error[parse]: {}", path, e);
+            return false;
+        }
+    };
+    let failing = if args.werror { Severity::Warning } else { Severity::Error };
+    let mut passed = true;
+    for diagnostic in &diagnostics {
+        if diagnostic.severity >= failing {
+            passed = false;
+        }
+        if !args.quiet || diagnostic.severity == Severity::Error {
+            println!("{}: {}", path, diagnostic);
+        }
+    }
+    if passed && !args.quiet {
+        println!("{}: ok", path);
+    }
+    passed
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::from(2);
+        }
+    };
+    let mut passed = true;
+    for path in &args.files {
+        passed &= check(path, &args);
+    }
+    if passed {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
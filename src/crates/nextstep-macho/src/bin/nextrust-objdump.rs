@@ -0,0 +1,372 @@
+//! nextrust-objdump - Print what is inside an m68k Mach-O file
+//!
+//! Usage: nextrust-objdump [-f] [-p] [-h] [-t] [-r] [-s] [-x] FILE...
+//!
+//!   -f, --file-headers     Mach header
+//!   -p, --private-headers  Load commands
+//!   -h, --section-headers  Sections
+//!   -t, --syms             Symbol table
+//!   -r, --reloc            Relocation entries
+//!   -s, --full-contents    Hex dump of every section
+//!   -x, --all-headers      -f -p -h -t -r (the default)
+//!
+//! For a fat file, -f also prints the fat header, and the m68k slice is
+//! dumped; other architectures are only listed.
+
+use nextstep_macho::consts::*;
+use nextstep_macho::{is_fat, Command, FatFile, MachO, Nlist, RelocTarget, Relocation, Section};
+use std::process::ExitCode;
+
+#[derive(Default)]
+struct Options {
+    file_header: bool,
+    private_headers: bool,
+    section_headers: bool,
+    syms: bool,
+    reloc: bool,
+    contents: bool,
+}
+
+const USAGE: &str = "usage: nextrust-objdump [-f] [-p] [-h] [-t] [-r] [-s] [-x] FILE...";
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<(Options, Vec<String>), String> {
+    let mut options = Options::default();
+    let mut files = Vec::new();
+    for arg in args {
+        let flags: Vec<char> = match arg.as_str() {
+            "--file-headers" => vec!['f'],
+            "--private-headers" => vec!['p'],
+            "--section-headers" => vec!['h'],
+            "--syms" => vec!['t'],
+            "--reloc" => vec!['r'],
+            "--full-contents" => vec!['s'],
+            "--all-headers" => vec!['x'],
+            "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ if arg.starts_with('-') && arg.len() > 1 => arg[1..].chars().collect(),
+            _ => {
+                files.push(arg);
+                continue;
+            }
+        };
+        for flag in flags {
+            match flag {
+                'f' => options.file_header = true,
+                'p' => options.private_headers = true,
+                'h' => options.section_headers = true,
+                't' => options.syms = true,
+                'r' => options.reloc = true,
+                's' => options.contents = true,
+                'x' => {
+                    options.file_header = true;
+                    options.private_headers = true;
+                    options.section_headers = true;
+                    options.syms = true;
+                    options.reloc = true;
+                }
+                _ => return Err(format!("unknown option -{}\n{}", flag, USAGE)),
+            }
+        }
+    }
+    if files.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    let any = options.file_header
+        || options.private_headers
+        || options.section_headers
+        || options.syms
+        || options.reloc
+        || options.contents;
+    if !any {
+        options = Options { file_header: true, private_headers: true, section_headers: true, syms: true, reloc: true, contents: false };
+    }
+    Ok((options, files))
+}
+
+// Hex for known constants that have no name
+fn named(name: Option<&'static str>, value: u32) -> String {
+    match name {
+        Some(name) => name.to_string(),
+        None => format!("{:#x}", value),
+    }
+}
+
+fn prot(prot: u32) -> String {
+    let bit = |mask, c| if prot & mask != 0 { c } else { '-' };
+    [bit(VM_PROT_READ, 'r'), bit(VM_PROT_WRITE, 'w'), bit(VM_PROT_EXECUTE, 'x')].iter().collect()
+}
+
+fn print_file_header(macho: &MachO) {
+    let h = &macho.header;
+    let cpu = if h.cputype == CPU_TYPE_MC680X0 {
+        format!("MC680x0 ({})", named(cpu_subtype_name(h.cpusubtype), h.cpusubtype as u32))
+    } else {
+        format!("{} (subtype {})", h.cputype, h.cpusubtype)
+    };
+    println!("Mach header");
+    println!("  magic      {:#010x}", h.magic);
+    println!("  cputype    {}", cpu);
+    println!("  filetype   {}", named(filetype_name(h.filetype), h.filetype));
+    println!("  ncmds      {}", h.ncmds);
+    println!("  sizeofcmds {}", h.sizeofcmds);
+    println!("  flags      {:#x}", h.flags);
+    if let Some(entry) = macho.entry_point() {
+        println!("  entry      {:#010x}", entry);
+    }
+    println!();
+}
+
+fn print_load_commands(macho: &MachO) {
+    for (index, lc) in macho.commands.iter().enumerate() {
+        println!("Load command {} at {:#x}", index, lc.offset);
+        println!("      cmd {}", named(load_command_name(lc.cmd), lc.cmd));
+        println!("  cmdsize {}", lc.cmdsize);
+        match &lc.command {
+            Command::Segment(seg) => {
+                println!("  segname {}", seg.segname);
+                println!("   vmaddr {:#010x}  vmsize {:#010x}", seg.vmaddr, seg.vmsize);
+                println!("  fileoff {:#x}  filesize {:#x}", seg.fileoff, seg.filesize);
+                println!("  maxprot {}  initprot {}", prot(seg.maxprot), prot(seg.initprot));
+                println!("   nsects {}  flags {:#x}", seg.sections.len(), seg.flags);
+            }
+            Command::Symtab(symtab) => {
+                println!("   symoff {}  nsyms {}", symtab.symoff, symtab.nsyms);
+                println!("   stroff {}  strsize {}", symtab.stroff, symtab.strsize);
+            }
+            Command::Dysymtab(d) => {
+                println!("  ilocalsym {}  nlocalsym {}", d.ilocalsym, d.nlocalsym);
+                println!("  iextdefsym {}  nextdefsym {}", d.iextdefsym, d.nextdefsym);
+                println!("  iundefsym {}  nundefsym {}", d.iundefsym, d.nundefsym);
+                println!("  tocoff {}  ntoc {}", d.tocoff, d.ntoc);
+                println!("  modtaboff {}  nmodtab {}", d.modtaboff, d.nmodtab);
+                println!("  extrefsymoff {}  nextrefsyms {}", d.extrefsymoff, d.nextrefsyms);
+                println!("  indirectsymoff {}  nindirectsyms {}", d.indirectsymoff, d.nindirectsyms);
+                println!("  extreloff {}  nextrel {}", d.extreloff, d.nextrel);
+                println!("  locreloff {}  nlocrel {}", d.locreloff, d.nlocrel);
+            }
+            Command::Thread(states) => {
+                for state in states {
+                    println!("   flavor {}  count {}", state.flavor, state.state.len());
+                    if state.flavor == M68K_THREAD_STATE_REGS && state.state.len() as u32 == M68K_THREAD_STATE_REGS_COUNT {
+                        let regs = |base: usize, prefix: char| {
+                            (0..8).map(|i| format!("{}{} {:08x}", prefix, i, state.state[base + i])).collect::<Vec<_>>()
+                        };
+                        println!("    {}", regs(0, 'd').join(" "));
+                        println!("    {}", regs(8, 'a').join(" "));
+                        println!("    sr {:04x} pc {:08x}", state.state[16] & 0xffff, state.state[M68K_THREAD_STATE_PC]);
+                    } else {
+                        let words: Vec<String> = state.state.iter().map(|w| format!("{:08x}", w)).collect();
+                        println!("    {}", words.join(" "));
+                    }
+                }
+            }
+            Command::Dylib { name, timestamp, current_version, compatibility_version } => {
+                println!("     name {}", name);
+                println!("  timestamp {}  current {:#x}  compatibility {:#x}", timestamp, current_version, compatibility_version);
+            }
+            Command::Dylinker(name) => println!("     name {}", name),
+            Command::Fvmlib { name, minor_version, header_addr } => {
+                println!("     name {}", name);
+                println!("  minor version {}  header addr {:#010x}", minor_version, header_addr);
+            }
+            Command::Ident(strings) => {
+                for string in strings {
+                    println!("    {}", string);
+                }
+            }
+            Command::Other(_) => {}
+        }
+    }
+    println!();
+}
+
+fn print_section_headers(macho: &MachO) {
+    println!("Sections:");
+    println!("Idx Name             Size     Address  Offset   Align Relocs Type");
+    for (index, section) in macho.sections().enumerate() {
+        println!(
+            "{:3} {:16} {:08x} {:08x} {:08x} 2**{:<2} {:6} {:#x}",
+            index + 1,
+            format!("{},{}", section.segname, section.sectname),
+            section.size,
+            section.addr,
+            section.offset,
+            section.align,
+            section.nreloc,
+            section.flags,
+        );
+    }
+    println!();
+}
+
+fn symbol_kind(symbol: &Nlist) -> String {
+    if symbol.is_stab() {
+        return match stab_name(symbol.n_type) {
+            Some(name) => format!("stab {}", name),
+            None => format!("stab {:#04x}", symbol.n_type),
+        };
+    }
+    let kind = match symbol.n_type & N_TYPE {
+        N_UNDF if symbol.is_common() => "common",
+        N_UNDF => "undef",
+        N_ABS => "abs",
+        N_SECT => "sect",
+        N_PBUD => "pbud",
+        N_INDR => "indr",
+        _ => "?",
+    };
+    let scope = if symbol.is_external() { "ext" } else if symbol.is_private_external() { "pext" } else { "" };
+    format!("{} {}", kind, scope).trim_end().to_string()
+}
+
+fn print_symbols(macho: &MachO) -> nextstep_macho::Result<()> {
+    println!("Symbol table:");
+    for (index, symbol) in macho.symbols()?.iter().enumerate() {
+        println!(
+            "{:5} {:08x} {:10} sect {:2} desc {:04x} {}",
+            index,
+            symbol.n_value,
+            symbol_kind(symbol),
+            symbol.n_sect,
+            symbol.n_desc,
+            symbol.name_lossy()
+        );
+    }
+    println!();
+    Ok(())
+}
+
+fn reloc_target(macho: &MachO, symbols: &[Nlist], reloc: &Relocation) -> String {
+    match reloc.target {
+        RelocTarget::Symbol(index) => match symbols.get(index as usize) {
+            Some(symbol) => symbol.name_lossy().into_owned(),
+            None => format!("<bad symbol {}>", index),
+        },
+        RelocTarget::Section(R_ABS) => "absolute".to_string(),
+        RelocTarget::Section(n) => match macho.section(n) {
+            Some(section) => format!("{},{}", section.segname, section.sectname),
+            None => format!("<bad section {}>", n),
+        },
+        RelocTarget::Address(value) => format!("{:#010x}", value),
+    }
+}
+
+fn print_relocations(macho: &MachO, symbols: &[Nlist], section: &Section, relocs: &[Relocation]) {
+    println!("Relocations for {},{} ({} entries):", section.segname, section.sectname, relocs.len());
+    println!("Address  Sc Pc Len Type                       Target");
+    for reloc in relocs {
+        println!(
+            "{:08x} {:2} {:2} {:3} {:26} {}",
+            reloc.address,
+            if reloc.is_scattered() { "S" } else { "" },
+            if reloc.pcrel { "P" } else { "" },
+            reloc.size(),
+            named(reloc_type_name(reloc.r_type), reloc.r_type as u32),
+            reloc_target(macho, symbols, reloc),
+        );
+    }
+    println!();
+}
+
+fn print_all_relocations(macho: &MachO) -> nextstep_macho::Result<()> {
+    let symbols = macho.symbols()?;
+    for section in macho.sections().filter(|s| s.nreloc != 0) {
+        print_relocations(macho, &symbols, section, &macho.relocations(section)?);
+    }
+    if let Some(d) = macho.dysymtab() {
+        let mut pseudo = Section { segname: "(dysymtab)".to_string(), ..Section::default() };
+        for (name, offset, count) in [("extrel", d.extreloff, d.nextrel), ("locrel", d.locreloff, d.nlocrel)] {
+            if count != 0 {
+                pseudo.sectname = name.to_string();
+                print_relocations(macho, &symbols, &pseudo, &macho.relocations_at(offset, count)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_contents(macho: &MachO) -> nextstep_macho::Result<()> {
+    for section in macho.sections() {
+        println!("Contents of section {},{}:", section.segname, section.sectname);
+        if section.is_zerofill() {
+            println!(" (zerofill, {} bytes)", section.size);
+            continue;
+        }
+        for (row, chunk) in macho.section_data(section)?.chunks(16).enumerate() {
+            let hex: Vec<String> = chunk.chunks(4).map(|word| word.iter().map(|b| format!("{:02x}", b)).collect()).collect();
+            let text: String = chunk.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect();
+            println!(" {:08x} {:35}  {}", section.addr as usize + row * 16, hex.join(" "), text);
+        }
+    }
+    println!();
+    Ok(())
+}
+
+fn print_fat_header(fat: &FatFile) {
+    println!("Fat header");
+    println!("  nfat_arch  {}", fat.arches.len());
+    for arch in &fat.arches {
+        let name = arch_name(arch.cputype, arch.cpusubtype).unwrap_or("?");
+        println!(
+            "  {:<8} cputype {:<3} cpusubtype {:<3} offset {:#010x} size {:#010x} align 2^{}",
+            name, arch.cputype, arch.cpusubtype, arch.offset, arch.size, arch.align
+        );
+    }
+    println!();
+}
+
+fn dump(path: &str, options: &Options) -> Result<(), String> {
+    let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    println!("{}:", path);
+    if !is_fat(&data) {
+        return dump_macho(&data, options).map_err(|e| format!("{}: {}", path, e));
+    }
+    let fat = FatFile::parse(&data).map_err(|e| format!("{}: {}", path, e))?;
+    if options.file_header {
+        print_fat_header(&fat);
+    }
+    let (_, slice) = fat.find(CPU_TYPE_MC680X0).ok_or_else(|| format!("{}: no m68k slice", path))?;
+    println!("{} (architecture m68k):", path);
+    dump_macho(slice, options).map_err(|e| format!("{} (m68k): {}", path, e))
+}
+
+fn dump_macho(data: &[u8], options: &Options) -> nextstep_macho::Result<()> {
+    let macho = MachO::parse(data)?;
+    if options.file_header {
+        print_file_header(&macho);
+    }
+    if options.private_headers {
+        print_load_commands(&macho);
+    }
+    if options.section_headers {
+        print_section_headers(&macho);
+    }
+    if options.syms {
+        print_symbols(&macho)?;
+    }
+    if options.reloc {
+        print_all_relocations(&macho)?;
+    }
+    if options.contents {
+        print_contents(&macho)?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let (options, files) = match parse_args(std::env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::from(2);
+        }
+    };
+    let mut status = ExitCode::SUCCESS;
+    for path in &files {
+        if let Err(message) = dump(path, &options) {
+            eprintln!("nextrust-objdump: {}", message);
+            status = ExitCode::FAILURE;
+        }
+    }
+    status
+}
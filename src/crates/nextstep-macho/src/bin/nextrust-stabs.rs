@@ -0,0 +1,166 @@
+//! nextrust-stabs - List and check the STABS debugging entries of a Mach-O file
+//!
+//! Usage: nextrust-stabs [-s] FILE
+//!        nextrust-stabs --check [--function NAME]... [--type NAME]... FILE
+//!
+//!   (default)        Every stab, with continued strings joined
+//!   -s, --summary    Source files, functions with their line tables, types
+//!   --check          Fail unless the stabs are usable for source debugging:
+//!                    source files, functions with line entries inside them,
+//!                    and definitions for the types they use
+//!   --function NAME  With --check, also require this function
+//!   --type NAME      With --check, also require this type
+//!
+//! Fat files are read through their m68k slice. --check exits 1 when
+//! something is missing, 2 on bad usage.
+
+use nextstep_macho::consts::*;
+use nextstep_macho::stabs::{stabs, DebugInfo};
+use nextstep_macho::{is_fat, FatFile, MachO, Nlist};
+use std::process::ExitCode;
+
+const USAGE: &str =
+    "usage: nextrust-stabs [-s] FILE\n       nextrust-stabs --check [--function NAME]... [--type NAME]... FILE";
+
+#[derive(Default)]
+struct Args {
+    summary: bool,
+    check: bool,
+    functions: Vec<String>,
+    types: Vec<String>,
+    file: String,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut files = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-s" | "--summary" => parsed.summary = true,
+            "--check" => parsed.check = true,
+            "--function" => parsed.functions.push(args.next().ok_or(USAGE)?),
+            "--type" => parsed.types.push(args.next().ok_or(USAGE)?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ => files.push(arg),
+        }
+    }
+    let requires = !parsed.functions.is_empty() || !parsed.types.is_empty();
+    if files.len() != 1 || requires && !parsed.check {
+        return Err(USAGE.to_string());
+    }
+    parsed.file = files.remove(0);
+    Ok(parsed)
+}
+
+fn print_stabs(symbols: &[Nlist]) {
+    println!("{:<6} {:<8} {:<4} {:<6} {:<10} string", "index", "type", "sect", "desc", "value");
+    for (index, (stab, string)) in stabs(symbols).iter().enumerate() {
+        let kind = stab_name(stab.n_type).map_or_else(|| format!("{:#04x}", stab.n_type), str::to_string);
+        println!(
+            "{:<6} {:<8} {:<4} {:<6} {:#010x} {}",
+            index, kind, stab.n_sect, stab.n_desc, stab.n_value, string
+        );
+    }
+}
+
+fn print_summary(info: &DebugInfo) {
+    println!("Source files");
+    for file in &info.files {
+        println!("  {}", file);
+    }
+    println!("Functions");
+    for function in &info.functions {
+        let end = info.function_end(function).map_or_else(|| "?".to_string(), |end| format!("{:#010x}", end));
+        println!(
+            "  {:#010x}-{} {}({}) {} lines",
+            function.address,
+            end,
+            function.name,
+            function.params.join(", "),
+            function.lines.len()
+        );
+        for line in &function.lines {
+            println!("    {:#010x} {}:{}", line.address, info.files[line.file], line.line);
+        }
+    }
+    println!("Types");
+    for def in &info.types {
+        let kind = if def.tag { "tag" } else { "type" };
+        println!("  ({},{}) {} {}", def.id.file, def.id.number, kind, def.name);
+    }
+    println!("Variables");
+    for variable in &info.variables {
+        let address = variable.address.map_or_else(|| "global".to_string(), |a| format!("{:#010x}", a));
+        println!("  {} {}", address, variable.name);
+    }
+}
+
+// Whether the check passed
+fn check(info: &DebugInfo, args: &Args) -> bool {
+    let mut problems = info.problems();
+    for name in &args.functions {
+        if info.function(name).is_none() {
+            problems.push(format!("no N_FUN stab for function {}", name));
+        }
+    }
+    for name in &args.types {
+        if info.type_named(name).is_none() {
+            problems.push(format!("no type stab for {}", name));
+        }
+    }
+    for problem in &problems {
+        println!("{}: error: {}", args.file, problem);
+    }
+    if problems.is_empty() {
+        println!(
+            "{}: ok ({} files, {} functions, {} types)",
+            args.file,
+            info.files.len(),
+            info.functions.len(),
+            info.types.len()
+        );
+    }
+    problems.is_empty()
+}
+
+fn run(args: &Args) -> Result<bool, String> {
+    let path = &args.file;
+    let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let error = |e: nextstep_macho::Error| format!("{}: {}", path, e);
+    let slice = if is_fat(&data) {
+        let fat = FatFile::parse(&data).map_err(error)?;
+        fat.find(CPU_TYPE_MC680X0).ok_or_else(|| format!("{}: no m68k slice", path))?.1
+    } else {
+        &data
+    };
+    let macho = MachO::parse(slice).map_err(error)?;
+    let symbols = macho.symbols().map_err(error)?;
+    let info = DebugInfo::from_symbols(&symbols);
+    if args.check {
+        return Ok(check(&info, args));
+    }
+    if args.summary {
+        print_summary(&info);
+    } else {
+        print_stabs(&symbols);
+    }
+    Ok(true)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::from(2);
+        }
+    };
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(message) => {
+            eprintln!("nextrust-stabs: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
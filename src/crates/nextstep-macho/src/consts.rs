@@ -0,0 +1,265 @@
+//! Mach-O constants from NeXTSTEP 3.3's `<mach-o/loader.h>`, `nlist.h` and
+//! `reloc.h`
+
+// Header magic; m68k Mach-O is big-endian on disk
+pub const MH_MAGIC: u32 = 0xfeed_face;
+pub const MH_CIGAM: u32 = 0xcefa_edfe;
+pub const FAT_MAGIC: u32 = 0xcafe_babe;
+pub const FAT_CIGAM: u32 = 0xbeba_feca;
+
+// CPU types
+pub const CPU_TYPE_MC680X0: i32 = 6;
+pub const CPU_SUBTYPE_MC680X0_ALL: i32 = 1;
+pub const CPU_SUBTYPE_MC68040: i32 = 2;
+pub const CPU_SUBTYPE_MC68030_ONLY: i32 = 3;
+// The other architectures of a NeXTSTEP 3.x quad-fat binary
+pub const CPU_TYPE_I386: i32 = 7;
+pub const CPU_SUBTYPE_I386_ALL: i32 = 3;
+pub const CPU_TYPE_HPPA: i32 = 11;
+pub const CPU_SUBTYPE_HPPA_ALL: i32 = 0;
+pub const CPU_TYPE_SPARC: i32 = 14;
+pub const CPU_SUBTYPE_SPARC_ALL: i32 = 0;
+
+// File types
+pub const MH_OBJECT: u32 = 0x1;
+pub const MH_EXECUTE: u32 = 0x2;
+pub const MH_FVMLIB: u32 = 0x3;
+pub const MH_CORE: u32 = 0x4;
+pub const MH_PRELOAD: u32 = 0x5;
+pub const MH_DYLIB: u32 = 0x6;
+pub const MH_DYLINKER: u32 = 0x7;
+pub const MH_BUNDLE: u32 = 0x8;
+
+// Header flags
+pub const MH_NOUNDEFS: u32 = 0x1;
+pub const MH_INCRLINK: u32 = 0x2;
+pub const MH_DYLDLINK: u32 = 0x4;
+pub const MH_BINDATLOAD: u32 = 0x8;
+pub const MH_PREBOUND: u32 = 0x10;
+
+// Load commands
+pub const LC_SEGMENT: u32 = 0x1;
+pub const LC_SYMTAB: u32 = 0x2;
+pub const LC_SYMSEG: u32 = 0x3;
+pub const LC_THREAD: u32 = 0x4;
+pub const LC_UNIXTHREAD: u32 = 0x5;
+pub const LC_LOADFVMLIB: u32 = 0x6;
+pub const LC_IDFVMLIB: u32 = 0x7;
+pub const LC_IDENT: u32 = 0x8;
+pub const LC_FVMFILE: u32 = 0x9;
+pub const LC_PREPAGE: u32 = 0xa;
+pub const LC_DYSYMTAB: u32 = 0xb;
+pub const LC_LOAD_DYLIB: u32 = 0xc;
+pub const LC_ID_DYLIB: u32 = 0xd;
+pub const LC_LOAD_DYLINKER: u32 = 0xe;
+pub const LC_ID_DYLINKER: u32 = 0xf;
+
+// Fixed structure sizes
+pub const MACH_HEADER_SIZE: usize = 28;
+pub const SEGMENT_COMMAND_SIZE: usize = 56;
+pub const SECTION_SIZE: usize = 68;
+pub const SYMTAB_COMMAND_SIZE: usize = 24;
+pub const DYSYMTAB_COMMAND_SIZE: usize = 80;
+pub const NLIST_SIZE: usize = 12;
+pub const RELOCATION_INFO_SIZE: usize = 8;
+pub const FAT_HEADER_SIZE: usize = 8;
+pub const FAT_ARCH_SIZE: usize = 20;
+
+// Section flags: the low byte is the type, the rest attributes
+pub const SECTION_TYPE: u32 = 0x0000_00ff;
+pub const SECTION_ATTRIBUTES: u32 = 0xffff_ff00;
+pub const S_REGULAR: u32 = 0x0;
+pub const S_ZEROFILL: u32 = 0x1;
+pub const S_CSTRING_LITERALS: u32 = 0x2;
+pub const S_4BYTE_LITERALS: u32 = 0x3;
+pub const S_8BYTE_LITERALS: u32 = 0x4;
+pub const S_LITERAL_POINTERS: u32 = 0x5;
+pub const S_NON_LAZY_SYMBOL_POINTERS: u32 = 0x6;
+pub const S_LAZY_SYMBOL_POINTERS: u32 = 0x7;
+pub const S_SYMBOL_STUBS: u32 = 0x8;
+pub const S_MOD_INIT_FUNC_POINTERS: u32 = 0x9;
+pub const S_ATTR_PURE_INSTRUCTIONS: u32 = 0x8000_0000;
+pub const S_ATTR_SOME_INSTRUCTIONS: u32 = 0x0000_0400;
+pub const S_ATTR_EXT_RELOC: u32 = 0x0000_0200;
+pub const S_ATTR_LOC_RELOC: u32 = 0x0000_0100;
+
+// Segment flags
+pub const SG_HIGHVM: u32 = 0x1;
+pub const SG_FVMLIB: u32 = 0x2;
+pub const SG_NORELOC: u32 = 0x4;
+
+// nlist n_type
+pub const N_STAB: u8 = 0xe0;
+pub const N_PEXT: u8 = 0x10;
+pub const N_TYPE: u8 = 0x0e;
+pub const N_EXT: u8 = 0x01;
+pub const N_UNDF: u8 = 0x0;
+pub const N_ABS: u8 = 0x2;
+pub const N_SECT: u8 = 0xe;
+pub const N_PBUD: u8 = 0xc;
+pub const N_INDR: u8 = 0xa;
+
+// Stab types (`<stab.h>`): the whole n_type when any N_STAB bit is set
+pub const N_GSYM: u8 = 0x20;
+pub const N_FNAME: u8 = 0x22;
+pub const N_FUN: u8 = 0x24;
+pub const N_STSYM: u8 = 0x26;
+pub const N_LCSYM: u8 = 0x28;
+pub const N_OPT: u8 = 0x3c;
+pub const N_RSYM: u8 = 0x40;
+pub const N_SLINE: u8 = 0x44;
+pub const N_SSYM: u8 = 0x60;
+pub const N_SO: u8 = 0x64;
+pub const N_LSYM: u8 = 0x80;
+pub const N_BINCL: u8 = 0x82;
+pub const N_SOL: u8 = 0x84;
+pub const N_PSYM: u8 = 0xa0;
+pub const N_EINCL: u8 = 0xa2;
+pub const N_ENTRY: u8 = 0xa4;
+pub const N_LBRAC: u8 = 0xc0;
+pub const N_EXCL: u8 = 0xc2;
+pub const N_RBRAC: u8 = 0xe0;
+pub const N_BCOMM: u8 = 0xe2;
+pub const N_ECOMM: u8 = 0xe4;
+pub const N_ECOML: u8 = 0xe8;
+pub const N_LENG: u8 = 0xfe;
+pub const NO_SECT: u8 = 0;
+
+// Indirect symbol table markers
+pub const INDIRECT_SYMBOL_LOCAL: u32 = 0x8000_0000;
+pub const INDIRECT_SYMBOL_ABS: u32 = 0x4000_0000;
+
+// Relocations; m68k uses the generic relocation types
+pub const R_SCATTERED: u32 = 0x8000_0000;
+pub const R_ABS: u32 = 0;
+pub const M68K_RELOC_VANILLA: u8 = 0;
+pub const M68K_RELOC_PAIR: u8 = 1;
+pub const M68K_RELOC_SECTDIFF: u8 = 2;
+pub const M68K_RELOC_PB_LA_PTR: u8 = 3;
+pub const M68K_RELOC_LOCAL_SECTDIFF: u8 = 4;
+
+// Thread state flavors (`<mach/m68k/thread_status.h>`)
+pub const M68K_THREAD_STATE_REGS: u32 = 1;
+pub const M68K_THREAD_STATE_REGS_COUNT: u32 = 18;
+// Index of the pc word in m68k_thread_state_regs
+pub const M68K_THREAD_STATE_PC: usize = 17;
+
+// VM protections
+pub const VM_PROT_READ: u32 = 0x1;
+pub const VM_PROT_WRITE: u32 = 0x2;
+pub const VM_PROT_EXECUTE: u32 = 0x4;
+
+/// Name of a file type, e.g. `"MH_OBJECT"`
+pub fn filetype_name(filetype: u32) -> Option<&'static str> {
+    Some(match filetype {
+        MH_OBJECT => "MH_OBJECT",
+        MH_EXECUTE => "MH_EXECUTE",
+        MH_FVMLIB => "MH_FVMLIB",
+        MH_CORE => "MH_CORE",
+        MH_PRELOAD => "MH_PRELOAD",
+        MH_DYLIB => "MH_DYLIB",
+        MH_DYLINKER => "MH_DYLINKER",
+        MH_BUNDLE => "MH_BUNDLE",
+        _ => return None,
+    })
+}
+
+/// Name of a load command, e.g. `"LC_SEGMENT"`
+pub fn load_command_name(cmd: u32) -> Option<&'static str> {
+    Some(match cmd {
+        LC_SEGMENT => "LC_SEGMENT",
+        LC_SYMTAB => "LC_SYMTAB",
+        LC_SYMSEG => "LC_SYMSEG",
+        LC_THREAD => "LC_THREAD",
+        LC_UNIXTHREAD => "LC_UNIXTHREAD",
+        LC_LOADFVMLIB => "LC_LOADFVMLIB",
+        LC_IDFVMLIB => "LC_IDFVMLIB",
+        LC_IDENT => "LC_IDENT",
+        LC_FVMFILE => "LC_FVMFILE",
+        LC_PREPAGE => "LC_PREPAGE",
+        LC_DYSYMTAB => "LC_DYSYMTAB",
+        LC_LOAD_DYLIB => "LC_LOAD_DYLIB",
+        LC_ID_DYLIB => "LC_ID_DYLIB",
+        LC_LOAD_DYLINKER => "LC_LOAD_DYLINKER",
+        LC_ID_DYLINKER => "LC_ID_DYLINKER",
+        _ => return None,
+    })
+}
+
+/// Name of an m68k relocation type, e.g. `"M68K_RELOC_SECTDIFF"`
+pub fn reloc_type_name(r_type: u8) -> Option<&'static str> {
+    Some(match r_type {
+        M68K_RELOC_VANILLA => "M68K_RELOC_VANILLA",
+        M68K_RELOC_PAIR => "M68K_RELOC_PAIR",
+        M68K_RELOC_SECTDIFF => "M68K_RELOC_SECTDIFF",
+        M68K_RELOC_PB_LA_PTR => "M68K_RELOC_PB_LA_PTR",
+        M68K_RELOC_LOCAL_SECTDIFF => "M68K_RELOC_LOCAL_SECTDIFF",
+        _ => return None,
+    })
+}
+
+/// Name of a stab type, e.g. `"N_FUN"`
+pub fn stab_name(n_type: u8) -> Option<&'static str> {
+    Some(match n_type {
+        N_GSYM => "N_GSYM",
+        N_FNAME => "N_FNAME",
+        N_FUN => "N_FUN",
+        N_STSYM => "N_STSYM",
+        N_LCSYM => "N_LCSYM",
+        N_OPT => "N_OPT",
+        N_RSYM => "N_RSYM",
+        N_SLINE => "N_SLINE",
+        N_SSYM => "N_SSYM",
+        N_SO => "N_SO",
+        N_LSYM => "N_LSYM",
+        N_BINCL => "N_BINCL",
+        N_SOL => "N_SOL",
+        N_PSYM => "N_PSYM",
+        N_EINCL => "N_EINCL",
+        N_ENTRY => "N_ENTRY",
+        N_LBRAC => "N_LBRAC",
+        N_EXCL => "N_EXCL",
+        N_RBRAC => "N_RBRAC",
+        N_BCOMM => "N_BCOMM",
+        N_ECOMM => "N_ECOMM",
+        N_ECOML => "N_ECOML",
+        N_LENG => "N_LENG",
+        _ => return None,
+    })
+}
+
+/// Name of a CPU subtype for `CPU_TYPE_MC680x0`
+pub fn cpu_subtype_name(cpusubtype: i32) -> Option<&'static str> {
+    Some(match cpusubtype {
+        CPU_SUBTYPE_MC680X0_ALL => "MC680x0_ALL",
+        CPU_SUBTYPE_MC68040 => "MC68040",
+        CPU_SUBTYPE_MC68030_ONLY => "MC68030_ONLY",
+        _ => return None,
+    })
+}
+
+/// `lipo`-style name of an architecture, e.g. `"m68040"` or `"i386"`
+pub fn arch_name(cputype: i32, cpusubtype: i32) -> Option<&'static str> {
+    Some(match (cputype, cpusubtype) {
+        (CPU_TYPE_MC680X0, CPU_SUBTYPE_MC680X0_ALL) => "m68k",
+        (CPU_TYPE_MC680X0, CPU_SUBTYPE_MC68040) => "m68040",
+        (CPU_TYPE_MC680X0, CPU_SUBTYPE_MC68030_ONLY) => "m68030",
+        (CPU_TYPE_I386, _) => "i386",
+        (CPU_TYPE_HPPA, _) => "hppa",
+        (CPU_TYPE_SPARC, _) => "sparc",
+        _ => return None,
+    })
+}
+
+/// CPU type and subtype for an [`arch_name`]
+pub fn arch_from_name(name: &str) -> Option<(i32, i32)> {
+    Some(match name {
+        "m68k" => (CPU_TYPE_MC680X0, CPU_SUBTYPE_MC680X0_ALL),
+        "m68040" => (CPU_TYPE_MC680X0, CPU_SUBTYPE_MC68040),
+        "m68030" => (CPU_TYPE_MC680X0, CPU_SUBTYPE_MC68030_ONLY),
+        "i386" => (CPU_TYPE_I386, CPU_SUBTYPE_I386_ALL),
+        "hppa" => (CPU_TYPE_HPPA, CPU_SUBTYPE_HPPA_ALL),
+        "sparc" => (CPU_TYPE_SPARC, CPU_SUBTYPE_SPARC_ALL),
+        _ => return None,
+    })
+}
@@ -0,0 +1,45 @@
+//! Parse errors
+
+use std::fmt;
+
+/// Why a file could not be read as Mach-O
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The first word is not `MH_MAGIC`
+    BadMagic(u32),
+    /// A little-endian (`MH_CIGAM`) file; only big-endian m68k is handled
+    WrongEndian,
+    /// A structure runs past the end of the file
+    Truncated { what: &'static str, offset: usize },
+    /// A load command's size is too small, unaligned or overruns `sizeofcmds`
+    BadLoadCommand { index: u32, cmdsize: u32 },
+    /// A string table offset points outside the string table
+    BadStringIndex { symbol: u32, strx: u32 },
+    /// A fat file's `fat_arch` entry is out of range or misaligned
+    BadFatArch { index: u32, reason: &'static str },
+    /// Two slices of a fat file have the same CPU type
+    DuplicateArch { cputype: i32 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadMagic(magic) => write!(f, "not a Mach-O file (magic {:#010x})", magic),
+            Error::WrongEndian => f.write_str("little-endian Mach-O; expected big-endian m68k"),
+            Error::Truncated { what, offset } => write!(f, "truncated {} at offset {:#x}", what, offset),
+            Error::BadLoadCommand { index, cmdsize } => {
+                write!(f, "load command {} has bad cmdsize {}", index, cmdsize)
+            }
+            Error::BadStringIndex { symbol, strx } => {
+                write!(f, "symbol {} has string index {:#x} outside the string table", symbol, strx)
+            }
+            Error::BadFatArch { index, reason } => write!(f, "fat_arch {}: {}", index, reason),
+            Error::DuplicateArch { cputype } => write!(f, "more than one slice for cputype {}", cputype),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Result of parsing
+pub type Result<T> = core::result::Result<T, Error>;
@@ -0,0 +1,207 @@
+//! Fat (multi-architecture) files
+//!
+//! NeXTSTEP 3.1 and later run "fat" binaries: a big-endian `fat_header`
+//! and `fat_arch` table followed by complete Mach-O files, one per CPU,
+//! each at an offset aligned to `1 << align`. The kernel and `ld` use the
+//! slice for the running CPU. Only the header of the other slices is
+//! read here, in whichever byte order it is (i386 is little-endian).
+
+use crate::consts::*;
+use crate::error::{Error, Result};
+use crate::read::{be32, bytes};
+use crate::validate::MAX_SECT_ALIGN;
+
+/// `struct fat_arch`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FatArch {
+    pub cputype: i32,
+    pub cpusubtype: i32,
+    pub offset: u32,
+    pub size: u32,
+    /// log2 of the slice's alignment in the file
+    pub align: u32,
+}
+
+impl FatArch {
+    /// `lipo` name of the slice's architecture, e.g. `"m68k"`
+    pub fn name(&self) -> Option<&'static str> {
+        arch_name(self.cputype, self.cpusubtype)
+    }
+
+    pub fn to_bytes(&self) -> [u8; FAT_ARCH_SIZE] {
+        let mut out = [0; FAT_ARCH_SIZE];
+        let fields = [self.cputype as u32, self.cpusubtype as u32, self.offset, self.size, self.align];
+        for (chunk, field) in out.chunks_exact_mut(4).zip(fields) {
+            chunk.copy_from_slice(&field.to_be_bytes());
+        }
+        out
+    }
+}
+
+/// A parsed fat file
+#[derive(Debug, Clone)]
+pub struct FatFile<'a> {
+    data: &'a [u8],
+    pub arches: Vec<FatArch>,
+}
+
+/// Whether `data` starts with `FAT_MAGIC`
+pub fn is_fat(data: &[u8]) -> bool {
+    data.len() >= 4 && be32(data, 0) == FAT_MAGIC
+}
+
+impl<'a> FatFile<'a> {
+    /// Parse the fat header and check every slice lies inside `data`
+    pub fn parse(data: &'a [u8]) -> Result<FatFile<'a>> {
+        let header = bytes(data, 0, FAT_HEADER_SIZE, "fat header")?;
+        let magic = be32(header, 0);
+        if magic != FAT_MAGIC {
+            return Err(Error::BadMagic(magic));
+        }
+        let nfat_arch = be32(header, 4);
+        let table_end = FAT_HEADER_SIZE as u64 + nfat_arch as u64 * FAT_ARCH_SIZE as u64;
+        let mut arches: Vec<FatArch> = Vec::new();
+        for index in 0..nfat_arch {
+            let at = FAT_HEADER_SIZE + index as usize * FAT_ARCH_SIZE;
+            let entry = bytes(data, at, FAT_ARCH_SIZE, "fat_arch")?;
+            let arch = FatArch {
+                cputype: be32(entry, 0) as i32,
+                cpusubtype: be32(entry, 4) as i32,
+                offset: be32(entry, 8),
+                size: be32(entry, 12),
+                align: be32(entry, 16),
+            };
+            let bad = |reason| Err(Error::BadFatArch { index, reason });
+            if arch.align > MAX_SECT_ALIGN {
+                return bad("alignment above 2^15");
+            }
+            if arch.offset & ((1 << arch.align) - 1) != 0 {
+                return bad("offset is not aligned to 2^align");
+            }
+            if (arch.offset as u64) < table_end {
+                return bad("slice overlaps the fat_arch table");
+            }
+            if arch.offset as u64 + arch.size as u64 > data.len() as u64 {
+                return bad("slice runs past end of file");
+            }
+            if arches.iter().any(|a| a.cputype == arch.cputype) {
+                return Err(Error::DuplicateArch { cputype: arch.cputype });
+            }
+            arches.push(arch);
+        }
+        Ok(FatFile { data, arches })
+    }
+
+    /// The bytes of one slice
+    pub fn slice(&self, arch: &FatArch) -> &'a [u8] {
+        &self.data[arch.offset as usize..(arch.offset + arch.size) as usize]
+    }
+
+    /// The slice for `cputype`, if there is one
+    pub fn find(&self, cputype: i32) -> Option<(&FatArch, &'a [u8])> {
+        self.arches.iter().find(|a| a.cputype == cputype).map(|a| (a, self.slice(a)))
+    }
+}
+
+/// CPU type and subtype from a thin Mach-O header of either byte order
+pub fn thin_arch(data: &[u8]) -> Result<(i32, i32)> {
+    let header = bytes(data, 0, 12, "mach header")?;
+    let word = |at: usize, big: bool| {
+        let b = [header[at], header[at + 1], header[at + 2], header[at + 3]];
+        if big {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    };
+    let big = match be32(header, 0) {
+        MH_MAGIC => true,
+        MH_CIGAM => false,
+        magic => return Err(Error::BadMagic(magic)),
+    };
+    Ok((word(4, big) as i32, word(8, big) as i32))
+}
+
+/// Alignment (log2) `lipo` gives a slice: the CPU's page size
+pub fn default_align(cputype: i32) -> u32 {
+    match cputype {
+        CPU_TYPE_MC680X0 | CPU_TYPE_SPARC => 13,
+        _ => 12,
+    }
+}
+
+/// A fat file holding the thin Mach-O files `slices`, in order
+pub fn build(slices: &[&[u8]]) -> Result<Vec<u8>> {
+    let mut arches: Vec<FatArch> = Vec::with_capacity(slices.len());
+    let mut offset = (FAT_HEADER_SIZE + slices.len() * FAT_ARCH_SIZE) as u32;
+    for slice in slices {
+        let (cputype, cpusubtype) = thin_arch(slice)?;
+        if arches.iter().any(|a| a.cputype == cputype) {
+            return Err(Error::DuplicateArch { cputype });
+        }
+        let align = default_align(cputype);
+        offset = (offset + (1 << align) - 1) & !((1 << align) - 1);
+        arches.push(FatArch { cputype, cpusubtype, offset, size: slice.len() as u32, align });
+        offset += slice.len() as u32;
+    }
+
+    let mut out = Vec::with_capacity(offset as usize);
+    out.extend_from_slice(&FAT_MAGIC.to_be_bytes());
+    out.extend_from_slice(&(arches.len() as u32).to_be_bytes());
+    for arch in &arches {
+        out.extend_from_slice(&arch.to_bytes());
+    }
+    for (arch, slice) in arches.iter().zip(slices) {
+        out.resize(arch.offset as usize, 0);
+        out.extend_from_slice(slice);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::file::tests::sample_object;
+
+    /// Header of a little-endian i386 object, enough to be a slice
+    pub(crate) fn i386_object() -> Vec<u8> {
+        let words = [MH_MAGIC, CPU_TYPE_I386 as u32, CPU_SUBTYPE_I386_ALL as u32, MH_OBJECT, 0, 0, 0];
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_build_and_parse() {
+        let m68k = sample_object();
+        let i386 = i386_object();
+        let data = build(&[&m68k, &i386]).unwrap();
+        assert!(is_fat(&data));
+
+        let fat = FatFile::parse(&data).unwrap();
+        let names: Vec<_> = fat.arches.iter().map(|a| a.name()).collect();
+        assert_eq!(names, [Some("m68040"), Some("i386")]);
+        assert_eq!((fat.arches[0].offset, fat.arches[0].align), (0x2000, 13));
+        assert_eq!(fat.arches[1].offset & 0xfff, 0);
+        assert_eq!(fat.find(CPU_TYPE_MC680X0).unwrap().1, &m68k[..]);
+        assert_eq!(fat.find(CPU_TYPE_I386).unwrap().1, &i386[..]);
+        assert!(fat.find(CPU_TYPE_SPARC).is_none());
+        assert_eq!(thin_arch(&i386).unwrap(), (CPU_TYPE_I386, CPU_SUBTYPE_I386_ALL));
+    }
+
+    #[test]
+    fn test_bad_fat_files() {
+        let m68k = sample_object();
+        assert_eq!(build(&[&m68k, &m68k]).unwrap_err(), Error::DuplicateArch { cputype: CPU_TYPE_MC680X0 });
+
+        let mut data = build(&[&m68k]).unwrap();
+        // Offset 0x2000 with align 2^14
+        data[FAT_HEADER_SIZE + 19] = 14;
+        assert!(matches!(FatFile::parse(&data), Err(Error::BadFatArch { index: 0, .. })));
+        data[FAT_HEADER_SIZE + 19] = 13;
+        data.truncate(data.len() - 1);
+        assert_eq!(
+            FatFile::parse(&data).unwrap_err(),
+            Error::BadFatArch { index: 0, reason: "slice runs past end of file" }
+        );
+        assert_eq!(FatFile::parse(&m68k).unwrap_err(), Error::BadMagic(MH_MAGIC));
+    }
+}
@@ -0,0 +1,569 @@
+//! Mach-O header and load commands
+
+use crate::consts::*;
+use crate::error::{Error, Result};
+use crate::read::{be32, bytes, c_str, fixed_name};
+use crate::reloc::Relocation;
+use crate::symbol::Nlist;
+
+/// `struct mach_header`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub magic: u32,
+    pub cputype: i32,
+    pub cpusubtype: i32,
+    pub filetype: u32,
+    pub ncmds: u32,
+    pub sizeofcmds: u32,
+    pub flags: u32,
+}
+
+/// `struct section`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Section {
+    pub sectname: String,
+    pub segname: String,
+    pub addr: u32,
+    pub size: u32,
+    pub offset: u32,
+    /// log2 of the alignment
+    pub align: u32,
+    pub reloff: u32,
+    pub nreloc: u32,
+    pub flags: u32,
+    pub reserved1: u32,
+    pub reserved2: u32,
+}
+
+impl Section {
+    /// The `SECTION_TYPE` bits of `flags`
+    pub fn section_type(&self) -> u32 {
+        self.flags & SECTION_TYPE
+    }
+
+    /// Section occupies no file space (`S_ZEROFILL`)
+    pub fn is_zerofill(&self) -> bool {
+        self.section_type() == S_ZEROFILL
+    }
+}
+
+/// `struct segment_command` with its sections
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// Empty in object files, which put every section in one segment
+    pub segname: String,
+    pub vmaddr: u32,
+    pub vmsize: u32,
+    pub fileoff: u32,
+    pub filesize: u32,
+    pub maxprot: u32,
+    pub initprot: u32,
+    pub flags: u32,
+    pub sections: Vec<Section>,
+}
+
+/// `struct symtab_command`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symtab {
+    pub symoff: u32,
+    pub nsyms: u32,
+    pub stroff: u32,
+    pub strsize: u32,
+}
+
+/// `struct dysymtab_command`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Dysymtab {
+    pub ilocalsym: u32,
+    pub nlocalsym: u32,
+    pub iextdefsym: u32,
+    pub nextdefsym: u32,
+    pub iundefsym: u32,
+    pub nundefsym: u32,
+    pub tocoff: u32,
+    pub ntoc: u32,
+    pub modtaboff: u32,
+    pub nmodtab: u32,
+    pub extrefsymoff: u32,
+    pub nextrefsyms: u32,
+    pub indirectsymoff: u32,
+    pub nindirectsyms: u32,
+    pub extreloff: u32,
+    pub nextrel: u32,
+    pub locreloff: u32,
+    pub nlocrel: u32,
+}
+
+impl Dysymtab {
+    fn parse(cmd: &[u8]) -> Dysymtab {
+        let field = |i: usize| be32(cmd, 8 + 4 * i);
+        Dysymtab {
+            ilocalsym: field(0),
+            nlocalsym: field(1),
+            iextdefsym: field(2),
+            nextdefsym: field(3),
+            iundefsym: field(4),
+            nundefsym: field(5),
+            tocoff: field(6),
+            ntoc: field(7),
+            modtaboff: field(8),
+            nmodtab: field(9),
+            extrefsymoff: field(10),
+            nextrefsyms: field(11),
+            indirectsymoff: field(12),
+            nindirectsyms: field(13),
+            extreloff: field(14),
+            nextrel: field(15),
+            locreloff: field(16),
+            nlocrel: field(17),
+        }
+    }
+}
+
+/// One flavor of register state in `LC_THREAD` / `LC_UNIXTHREAD`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadState {
+    pub flavor: u32,
+    pub state: Vec<u32>,
+}
+
+/// A decoded load command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command<'a> {
+    Segment(Segment),
+    Symtab(Symtab),
+    Dysymtab(Dysymtab),
+    /// `LC_THREAD` or `LC_UNIXTHREAD`
+    Thread(Vec<ThreadState>),
+    /// `LC_LOAD_DYLIB` or `LC_ID_DYLIB`
+    Dylib { name: String, timestamp: u32, current_version: u32, compatibility_version: u32 },
+    /// `LC_LOAD_DYLINKER` or `LC_ID_DYLINKER`
+    Dylinker(String),
+    /// `LC_LOADFVMLIB` or `LC_IDFVMLIB`
+    Fvmlib { name: String, minor_version: u32, header_addr: u32 },
+    Ident(Vec<String>),
+    /// Anything else, as raw bytes after `cmd` and `cmdsize`
+    Other(&'a [u8]),
+}
+
+/// A load command and where it sits in the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadCommand<'a> {
+    pub cmd: u32,
+    pub cmdsize: u32,
+    /// File offset of the command
+    pub offset: usize,
+    pub command: Command<'a>,
+}
+
+/// A parsed Mach-O file, borrowing its bytes
+#[derive(Debug, Clone)]
+pub struct MachO<'a> {
+    data: &'a [u8],
+    pub header: Header,
+    pub commands: Vec<LoadCommand<'a>>,
+}
+
+// A `union lc_str` inside a command: an offset from the command's start
+fn lc_str(cmd: &[u8], field: usize) -> String {
+    let offset = be32(cmd, field) as usize;
+    match cmd.get(offset..) {
+        Some(rest) => fixed_name(rest),
+        None => String::new(),
+    }
+}
+
+fn parse_segment(cmd: &[u8], offset: usize) -> Result<Segment> {
+    if cmd.len() < SEGMENT_COMMAND_SIZE {
+        return Err(Error::Truncated { what: "segment command", offset });
+    }
+    let nsects = be32(cmd, 48) as usize;
+    let mut sections = Vec::with_capacity(nsects.min(256));
+    for i in 0..nsects {
+        let start = SEGMENT_COMMAND_SIZE + i * SECTION_SIZE;
+        let sect = bytes(cmd, start, SECTION_SIZE, "section header").map_err(|_| Error::Truncated {
+            what: "section header",
+            offset: offset + start,
+        })?;
+        sections.push(Section {
+            sectname: fixed_name(&sect[0..16]),
+            segname: fixed_name(&sect[16..32]),
+            addr: be32(sect, 32),
+            size: be32(sect, 36),
+            offset: be32(sect, 40),
+            align: be32(sect, 44),
+            reloff: be32(sect, 48),
+            nreloc: be32(sect, 52),
+            flags: be32(sect, 56),
+            reserved1: be32(sect, 60),
+            reserved2: be32(sect, 64),
+        });
+    }
+    Ok(Segment {
+        segname: fixed_name(&cmd[8..24]),
+        vmaddr: be32(cmd, 24),
+        vmsize: be32(cmd, 28),
+        fileoff: be32(cmd, 32),
+        filesize: be32(cmd, 36),
+        maxprot: be32(cmd, 40),
+        initprot: be32(cmd, 44),
+        flags: be32(cmd, 52),
+        sections,
+    })
+}
+
+fn parse_thread(cmd: &[u8], offset: usize) -> Result<Vec<ThreadState>> {
+    let mut states = Vec::new();
+    let mut at = 8;
+    while at + 8 <= cmd.len() {
+        let flavor = be32(cmd, at);
+        let count = be32(cmd, at + 4) as usize;
+        let words = bytes(cmd, at + 8, count * 4, "thread state")
+            .map_err(|_| Error::Truncated { what: "thread state", offset: offset + at })?;
+        states.push(ThreadState { flavor, state: words.chunks_exact(4).map(|w| be32(w, 0)).collect() });
+        at += 8 + count * 4;
+    }
+    Ok(states)
+}
+
+fn parse_command(cmd: u32, body: &[u8], offset: usize) -> Result<Command<'_>> {
+    let need = |size: usize, what: &'static str| {
+        if body.len() < size {
+            Err(Error::Truncated { what, offset })
+        } else {
+            Ok(())
+        }
+    };
+    Ok(match cmd {
+        LC_SEGMENT => Command::Segment(parse_segment(body, offset)?),
+        LC_SYMTAB => {
+            need(SYMTAB_COMMAND_SIZE, "symtab command")?;
+            Command::Symtab(Symtab {
+                symoff: be32(body, 8),
+                nsyms: be32(body, 12),
+                stroff: be32(body, 16),
+                strsize: be32(body, 20),
+            })
+        }
+        LC_DYSYMTAB => {
+            need(DYSYMTAB_COMMAND_SIZE, "dysymtab command")?;
+            Command::Dysymtab(Dysymtab::parse(body))
+        }
+        LC_THREAD | LC_UNIXTHREAD => Command::Thread(parse_thread(body, offset)?),
+        LC_LOAD_DYLIB | LC_ID_DYLIB => {
+            need(24, "dylib command")?;
+            Command::Dylib {
+                name: lc_str(body, 8),
+                timestamp: be32(body, 12),
+                current_version: be32(body, 16),
+                compatibility_version: be32(body, 20),
+            }
+        }
+        LC_LOAD_DYLINKER | LC_ID_DYLINKER => {
+            need(12, "dylinker command")?;
+            Command::Dylinker(lc_str(body, 8))
+        }
+        LC_LOADFVMLIB | LC_IDFVMLIB => {
+            need(20, "fvmlib command")?;
+            Command::Fvmlib { name: lc_str(body, 8), minor_version: be32(body, 12), header_addr: be32(body, 16) }
+        }
+        LC_IDENT => Command::Ident(
+            body[8..].split(|&b| b == 0).filter(|s| !s.is_empty()).map(fixed_name).collect(),
+        ),
+        _ => Command::Other(&body[8..]),
+    })
+}
+
+impl<'a> MachO<'a> {
+    /// Parse the header and load commands of a big-endian Mach-O file
+    pub fn parse(data: &'a [u8]) -> Result<MachO<'a>> {
+        let raw = bytes(data, 0, MACH_HEADER_SIZE, "mach header")?;
+        let magic = be32(raw, 0);
+        match magic {
+            MH_MAGIC => {}
+            MH_CIGAM => return Err(Error::WrongEndian),
+            _ => return Err(Error::BadMagic(magic)),
+        }
+        let header = Header {
+            magic,
+            cputype: be32(raw, 4) as i32,
+            cpusubtype: be32(raw, 8) as i32,
+            filetype: be32(raw, 12),
+            ncmds: be32(raw, 16),
+            sizeofcmds: be32(raw, 20),
+            flags: be32(raw, 24),
+        };
+
+        let cmds = bytes(data, MACH_HEADER_SIZE, header.sizeofcmds as usize, "load commands")?;
+        let mut commands = Vec::with_capacity(header.ncmds.min(256) as usize);
+        let mut at = 0;
+        for index in 0..header.ncmds {
+            let head = bytes(cmds, at, 8, "load command")
+                .map_err(|_| Error::Truncated { what: "load command", offset: MACH_HEADER_SIZE + at })?;
+            let (cmd, cmdsize) = (be32(head, 0), be32(head, 4));
+            if cmdsize < 8 || cmdsize % 4 != 0 || at + cmdsize as usize > cmds.len() {
+                return Err(Error::BadLoadCommand { index, cmdsize });
+            }
+            let body = &cmds[at..at + cmdsize as usize];
+            let offset = MACH_HEADER_SIZE + at;
+            commands.push(LoadCommand { cmd, cmdsize, offset, command: parse_command(cmd, body, offset)? });
+            at += cmdsize as usize;
+        }
+        Ok(MachO { data, header, commands })
+    }
+
+    /// The whole file
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Segments in load command order
+    pub fn segments(&self) -> impl Iterator<Item = &Segment> {
+        self.commands.iter().filter_map(|lc| match &lc.command {
+            Command::Segment(segment) => Some(segment),
+            _ => None,
+        })
+    }
+
+    /// Sections in ordinal order; `n_sect` and `r_symbolnum` count from 1
+    pub fn sections(&self) -> impl Iterator<Item = &Section> {
+        self.segments().flat_map(|segment| segment.sections.iter())
+    }
+
+    /// Section with ordinal `n_sect` (from 1)
+    pub fn section(&self, n_sect: u32) -> Option<&Section> {
+        self.sections().nth((n_sect as usize).checked_sub(1)?)
+    }
+
+    /// The `LC_SYMTAB` command, if any
+    pub fn symtab(&self) -> Option<Symtab> {
+        self.commands.iter().find_map(|lc| match lc.command {
+            Command::Symtab(symtab) => Some(symtab),
+            _ => None,
+        })
+    }
+
+    /// The `LC_DYSYMTAB` command, if any
+    pub fn dysymtab(&self) -> Option<Dysymtab> {
+        self.commands.iter().find_map(|lc| match lc.command {
+            Command::Dysymtab(dysymtab) => Some(dysymtab),
+            _ => None,
+        })
+    }
+
+    /// Entry point from the m68k register state in `LC_UNIXTHREAD`
+    pub fn entry_point(&self) -> Option<u32> {
+        self.commands.iter().filter(|lc| lc.cmd == LC_UNIXTHREAD).find_map(|lc| match &lc.command {
+            Command::Thread(states) => states
+                .iter()
+                .find(|s| s.flavor == M68K_THREAD_STATE_REGS)
+                .and_then(|s| s.state.get(M68K_THREAD_STATE_PC).copied()),
+            _ => None,
+        })
+    }
+
+    /// File contents of a section; empty for zerofill sections
+    pub fn section_data(&self, section: &Section) -> Result<&'a [u8]> {
+        if section.is_zerofill() {
+            return Ok(&[]);
+        }
+        bytes(self.data, section.offset as usize, section.size as usize, "section contents")
+    }
+
+    /// Relocation entries of a section
+    pub fn relocations(&self, section: &Section) -> Result<Vec<Relocation>> {
+        self.relocations_at(section.reloff, section.nreloc)
+    }
+
+    /// `count` relocation entries starting at file offset `offset`, as
+    /// referenced from sections and `LC_DYSYMTAB`
+    pub fn relocations_at(&self, offset: u32, count: u32) -> Result<Vec<Relocation>> {
+        let table = bytes(
+            self.data,
+            offset as usize,
+            count as usize * RELOCATION_INFO_SIZE,
+            "relocation entries",
+        )?;
+        Ok(table.chunks_exact(RELOCATION_INFO_SIZE).map(Relocation::parse).collect())
+    }
+
+    /// The symbol table with names resolved, or empty without `LC_SYMTAB`
+    pub fn symbols(&self) -> Result<Vec<Nlist<'a>>> {
+        let Some(symtab) = self.symtab() else {
+            return Ok(Vec::new());
+        };
+        let table = bytes(self.data, symtab.symoff as usize, symtab.nsyms as usize * NLIST_SIZE, "symbol table")?;
+        let strings = bytes(self.data, symtab.stroff as usize, symtab.strsize as usize, "string table")?;
+        table
+            .chunks_exact(NLIST_SIZE)
+            .enumerate()
+            .map(|(index, entry)| {
+                let mut symbol = Nlist::parse(entry);
+                if symbol.n_strx != 0 {
+                    symbol.name = c_str(strings, symbol.n_strx as usize)
+                        .ok_or(Error::BadStringIndex { symbol: index as u32, strx: symbol.n_strx })?;
+                }
+                Ok(symbol)
+            })
+            .collect()
+    }
+
+    /// The indirect symbol table from `LC_DYSYMTAB`
+    pub fn indirect_symbols(&self) -> Result<Vec<u32>> {
+        let Some(dysymtab) = self.dysymtab() else {
+            return Ok(Vec::new());
+        };
+        let table = bytes(
+            self.data,
+            dysymtab.indirectsymoff as usize,
+            dysymtab.nindirectsyms as usize * 4,
+            "indirect symbol table",
+        )?;
+        Ok(table.chunks_exact(4).map(|entry| be32(entry, 0)).collect())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::reloc::RelocTarget;
+
+    fn push32(out: &mut Vec<u8>, value: u32) {
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn name16(out: &mut Vec<u8>, name: &str) {
+        let mut field = [0u8; 16];
+        field[..name.len()].copy_from_slice(name.as_bytes());
+        out.extend_from_slice(&field);
+    }
+
+    // An MH_OBJECT like LLVM emits for a function that loads a local
+    // label difference and calls an external
+    pub(crate) fn sample_object() -> Vec<u8> {
+        let text = [0x4e, 0xb9, 0, 0, 0, 0, 0x20, 0x3c, 0, 0, 0, 0x04, 0x4e, 0x75, 0x4e, 0x71];
+        let ncmds = 3;
+        let sizeofcmds = (SEGMENT_COMMAND_SIZE + SECTION_SIZE + SYMTAB_COMMAND_SIZE + DYSYMTAB_COMMAND_SIZE) as u32;
+        let text_off = MACH_HEADER_SIZE as u32 + sizeofcmds;
+        let reloff = text_off + text.len() as u32;
+        let relocs = [
+            Relocation { address: 2, pcrel: false, length: 2, r_type: M68K_RELOC_VANILLA, target: RelocTarget::Symbol(1) },
+            Relocation { address: 8, pcrel: false, length: 2, r_type: M68K_RELOC_SECTDIFF, target: RelocTarget::Address(0xc) },
+            Relocation { address: 0, pcrel: false, length: 2, r_type: M68K_RELOC_PAIR, target: RelocTarget::Address(0x8) },
+        ];
+        let symoff = reloff + (relocs.len() * RELOCATION_INFO_SIZE) as u32;
+        let strings = b"\0_main\0_puts\0";
+        let stroff = symoff + 2 * NLIST_SIZE as u32;
+
+        let mut out = Vec::new();
+        for word in [MH_MAGIC, CPU_TYPE_MC680X0 as u32, CPU_SUBTYPE_MC68040 as u32, MH_OBJECT, ncmds, sizeofcmds, 0] {
+            push32(&mut out, word);
+        }
+        // LC_SEGMENT with one section
+        push32(&mut out, LC_SEGMENT);
+        push32(&mut out, (SEGMENT_COMMAND_SIZE + SECTION_SIZE) as u32);
+        name16(&mut out, "");
+        for word in [0, text.len() as u32, text_off, text.len() as u32, 7, 7, 1, 0] {
+            push32(&mut out, word);
+        }
+        name16(&mut out, "__text");
+        name16(&mut out, "__TEXT");
+        let flags = S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS | S_ATTR_EXT_RELOC | S_ATTR_LOC_RELOC;
+        for word in [0, text.len() as u32, text_off, 1, reloff, relocs.len() as u32, flags, 0, 0] {
+            push32(&mut out, word);
+        }
+        // LC_SYMTAB
+        for word in [LC_SYMTAB, SYMTAB_COMMAND_SIZE as u32, symoff, 2, stroff, strings.len() as u32] {
+            push32(&mut out, word);
+        }
+        // LC_DYSYMTAB: no locals, _main defined, _puts undefined
+        push32(&mut out, LC_DYSYMTAB);
+        push32(&mut out, DYSYMTAB_COMMAND_SIZE as u32);
+        for word in [0, 0, 0, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0] {
+            push32(&mut out, word);
+        }
+        assert_eq!(out.len() as u32, text_off);
+        out.extend_from_slice(&text);
+        for reloc in &relocs {
+            out.extend_from_slice(&reloc.to_bytes());
+        }
+        let main = Nlist { name: &[], n_strx: 1, n_type: N_SECT | N_EXT, n_sect: 1, n_desc: 0, n_value: 0 };
+        let puts = Nlist { name: &[], n_strx: 7, n_type: N_UNDF | N_EXT, n_sect: NO_SECT, n_desc: 0, n_value: 0 };
+        out.extend_from_slice(&main.to_bytes());
+        out.extend_from_slice(&puts.to_bytes());
+        out.extend_from_slice(strings);
+        out
+    }
+
+    #[test]
+    fn test_parse_object() {
+        let data = sample_object();
+        let macho = MachO::parse(&data).unwrap();
+        assert_eq!(macho.header.cputype, CPU_TYPE_MC680X0);
+        assert_eq!(macho.header.cpusubtype, CPU_SUBTYPE_MC68040);
+        assert_eq!(macho.header.filetype, MH_OBJECT);
+        assert_eq!(macho.commands.len(), 3);
+
+        let text = macho.section(1).unwrap();
+        assert_eq!((text.segname.as_str(), text.sectname.as_str()), ("__TEXT", "__text"));
+        assert_eq!(macho.section_data(text).unwrap()[..2], [0x4e, 0xb9]);
+        assert!(macho.section(2).is_none());
+
+        let relocs = macho.relocations(text).unwrap();
+        assert_eq!(relocs.len(), 3);
+        assert_eq!(relocs[0].target, RelocTarget::Symbol(1));
+        assert!(relocs[1].is_scattered());
+        assert_eq!(relocs[1].r_type, M68K_RELOC_SECTDIFF);
+        assert!(relocs[2].is_pair());
+
+        let symbols = macho.symbols().unwrap();
+        assert_eq!(symbols[0].name, b"_main");
+        assert!(symbols[0].is_section() && symbols[0].is_external());
+        assert_eq!(symbols[1].name, b"_puts");
+        assert!(symbols[1].is_undefined() && !symbols[1].is_common());
+
+        let dysymtab = macho.dysymtab().unwrap();
+        assert_eq!((dysymtab.iundefsym, dysymtab.nundefsym), (1, 1));
+        assert!(macho.indirect_symbols().unwrap().is_empty());
+        assert_eq!(macho.entry_point(), None);
+    }
+
+    #[test]
+    fn test_unixthread_entry_point() {
+        let mut data = Vec::new();
+        let cmdsize = 16 + 4 * M68K_THREAD_STATE_REGS_COUNT;
+        for word in [MH_MAGIC, CPU_TYPE_MC680X0 as u32, CPU_SUBTYPE_MC680X0_ALL as u32, MH_EXECUTE, 1, cmdsize, MH_NOUNDEFS] {
+            push32(&mut data, word);
+        }
+        for word in [LC_UNIXTHREAD, cmdsize, M68K_THREAD_STATE_REGS, M68K_THREAD_STATE_REGS_COUNT] {
+            push32(&mut data, word);
+        }
+        for i in 0..M68K_THREAD_STATE_REGS_COUNT as usize {
+            push32(&mut data, if i == M68K_THREAD_STATE_PC { 0x4000 } else { 0 });
+        }
+        let macho = MachO::parse(&data).unwrap();
+        assert_eq!(macho.entry_point(), Some(0x4000));
+    }
+
+    #[test]
+    fn test_rejects_bad_input() {
+        assert_eq!(MachO::parse(&[0; 4]).unwrap_err(), Error::Truncated { what: "mach header", offset: 0 });
+        let mut data = sample_object();
+        data[..4].copy_from_slice(&MH_CIGAM.to_be_bytes());
+        assert_eq!(MachO::parse(&data).unwrap_err(), Error::WrongEndian);
+        data[..4].copy_from_slice(&0x7f45_4c46u32.to_be_bytes());
+        assert_eq!(MachO::parse(&data).unwrap_err(), Error::BadMagic(0x7f45_4c46));
+
+        // First command's cmdsize overruns sizeofcmds
+        let mut data = sample_object();
+        data[MACH_HEADER_SIZE + 4..MACH_HEADER_SIZE + 8].copy_from_slice(&0x1000u32.to_be_bytes());
+        assert_eq!(MachO::parse(&data).unwrap_err(), Error::BadLoadCommand { index: 0, cmdsize: 0x1000 });
+
+        // String index past the string table
+        let mut data = sample_object();
+        let symoff = data.len() - 13 - 2 * NLIST_SIZE;
+        data[symoff..symoff + 4].copy_from_slice(&0x100u32.to_be_bytes());
+        let macho = MachO::parse(&data).unwrap();
+        assert_eq!(macho.symbols().unwrap_err(), Error::BadStringIndex { symbol: 0, strx: 0x100 });
+    }
+}
@@ -0,0 +1,41 @@
+//! nextstep-macho - Mach-O reader for NeXTSTEP m68k objects and executables
+//!
+//! Parses the big-endian Mach-O that the m68k backend and NeXT `ld` produce:
+//! the header, load commands, segments and sections, `LC_SYMTAB`,
+//! `LC_DYSYMTAB` and relocation entries, plain and scattered. It runs on
+//! the build host, so output can be checked on Linux without a NeXT box;
+//! `nextrust-objdump` prints everything it finds.
+//!
+//! [`validate`] checks a file against what NeXTSTEP 3.3's `ld` and kernel
+//! accept; `nextrust-macho-validate` runs it from the command line and CI.
+//!
+//! [`fat`] reads and writes multi-architecture files, so the m68k output
+//! can ship next to an i386 build in one binary; `nextrust-lipo` is the
+//! command-line front end, like NeXT's `lipo`.
+//!
+//! [`stabs`] reads the STABS debugging entries NeXTSTEP's gdb uses;
+//! `nextrust-stabs` lists them and checks they are usable.
+//!
+//! ```ignore
+//! let data = std::fs::read("hello.o")?;
+//! let macho = nextstep_macho::MachO::parse(&data)?;
+//! for section in macho.sections() {
+//!     println!("{},{} {} relocs", section.segname, section.sectname, section.nreloc);
+//! }
+//! ```
+
+pub mod consts;
+mod error;
+pub mod fat;
+mod file;
+mod read;
+mod reloc;
+pub mod stabs;
+mod symbol;
+pub mod validate;
+
+pub use error::{Error, Result};
+pub use fat::{is_fat, FatArch, FatFile};
+pub use file::{Command, Dysymtab, Header, LoadCommand, MachO, Section, Segment, Symtab, ThreadState};
+pub use reloc::{RelocTarget, Relocation};
+pub use symbol::Nlist;
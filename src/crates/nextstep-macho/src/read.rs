@@ -0,0 +1,34 @@
+//! Big-endian field access with bounds checks
+
+use crate::error::{Error, Result};
+
+/// `len` bytes at `offset`, or a truncation error naming `what`
+pub(crate) fn bytes<'a>(data: &'a [u8], offset: usize, len: usize, what: &'static str) -> Result<&'a [u8]> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or(Error::Truncated { what, offset })
+}
+
+#[inline]
+pub(crate) fn be16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+#[inline]
+pub(crate) fn be32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+// A NUL-padded fixed-size name such as segname or sectname
+pub(crate) fn fixed_name(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+// A NUL-terminated string starting at `offset`, or None if unterminated
+pub(crate) fn c_str(bytes: &[u8], offset: usize) -> Option<&[u8]> {
+    let rest = bytes.get(offset..)?;
+    let end = rest.iter().position(|&b| b == 0)?;
+    Some(&rest[..end])
+}
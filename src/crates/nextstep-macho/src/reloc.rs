@@ -0,0 +1,138 @@
+//! Relocation entries, plain and scattered
+//!
+//! A plain entry is `r_address` followed by
+//! `r_symbolnum:24 r_pcrel:1 r_length:2 r_extern:1 r_type:4`, packed from
+//! the most significant bit down. A scattered entry sets `R_SCATTERED` in
+//! its first word, which then holds
+//! `r_scattered:1 r_pcrel:1 r_length:2 r_type:4 r_address:24`, and carries
+//! the target address (`r_value`) in the second word. `SECTDIFF` and
+//! `LOCAL_SECTDIFF` entries are always scattered and followed by a `PAIR`.
+
+use crate::consts::*;
+use crate::read::be32;
+
+/// What a relocation refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocTarget {
+    /// Symbol table index (`r_extern` set)
+    Symbol(u32),
+    /// Section ordinal from 1, or `R_ABS` (`r_extern` clear)
+    Section(u32),
+    /// Address the relocated item refers to (scattered entries)
+    Address(u32),
+}
+
+/// One `relocation_info` or `scattered_relocation_info`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Relocation {
+    /// Offset of the item from the start of its section
+    pub address: u32,
+    /// Item is relative to the pc
+    pub pcrel: bool,
+    /// log2 of the item's size in bytes
+    pub length: u8,
+    /// `M68K_RELOC_*` type
+    pub r_type: u8,
+    pub target: RelocTarget,
+}
+
+impl Relocation {
+    /// Decode an 8-byte entry
+    pub fn parse(entry: &[u8]) -> Relocation {
+        let word0 = be32(entry, 0);
+        let word1 = be32(entry, 4);
+        if word0 & R_SCATTERED != 0 {
+            Relocation {
+                address: word0 & 0x00ff_ffff,
+                pcrel: word0 & 0x4000_0000 != 0,
+                length: ((word0 >> 28) & 0x3) as u8,
+                r_type: ((word0 >> 24) & 0xf) as u8,
+                target: RelocTarget::Address(word1),
+            }
+        } else {
+            let index = word1 >> 8;
+            Relocation {
+                address: word0,
+                pcrel: word1 & 0x80 != 0,
+                length: ((word1 >> 5) & 0x3) as u8,
+                r_type: (word1 & 0xf) as u8,
+                target: if word1 & 0x10 != 0 {
+                    RelocTarget::Symbol(index)
+                } else {
+                    RelocTarget::Section(index)
+                },
+            }
+        }
+    }
+
+    /// Encode as an 8-byte entry
+    pub fn to_bytes(&self) -> [u8; RELOCATION_INFO_SIZE] {
+        // pcrel and length sit side by side in both layouts
+        let flags = (self.pcrel as u32) << 2 | (self.length as u32 & 0x3);
+        let r_type = self.r_type as u32 & 0xf;
+        let (word0, word1) = match self.target {
+            RelocTarget::Address(value) => {
+                (R_SCATTERED | flags << 28 | r_type << 24 | (self.address & 0x00ff_ffff), value)
+            }
+            RelocTarget::Symbol(index) => (self.address, index << 8 | flags << 5 | 0x10 | r_type),
+            RelocTarget::Section(index) => (self.address, index << 8 | flags << 5 | r_type),
+        };
+        let mut bytes = [0; RELOCATION_INFO_SIZE];
+        bytes[..4].copy_from_slice(&word0.to_be_bytes());
+        bytes[4..].copy_from_slice(&word1.to_be_bytes());
+        bytes
+    }
+
+    /// Entry is a `scattered_relocation_info`
+    pub fn is_scattered(&self) -> bool {
+        matches!(self.target, RelocTarget::Address(_))
+    }
+
+    /// Size of the relocated item in bytes
+    pub fn size(&self) -> u32 {
+        1 << self.length
+    }
+
+    /// Entry is the second half of a `SECTDIFF`-style pair
+    pub fn is_pair(&self) -> bool {
+        self.r_type == M68K_RELOC_PAIR
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_extern_layout() {
+        // symbolnum 5, pcrel, long, extern, VANILLA at offset 0x12
+        let entry = [0, 0, 0, 0x12, 0, 0, 0x05, 0xd0];
+        let reloc = Relocation::parse(&entry);
+        assert_eq!(
+            reloc,
+            Relocation { address: 0x12, pcrel: true, length: 2, r_type: M68K_RELOC_VANILLA, target: RelocTarget::Symbol(5) }
+        );
+        assert_eq!(reloc.size(), 4);
+        assert_eq!(reloc.to_bytes(), entry);
+    }
+
+    #[test]
+    fn test_scattered_layout() {
+        // scattered, not pcrel, long, SECTDIFF at 0x20, r_value 0x1000
+        let entry = [0xa2, 0, 0, 0x20, 0, 0, 0x10, 0];
+        let reloc = Relocation::parse(&entry);
+        assert!(reloc.is_scattered());
+        assert_eq!(reloc.r_type, M68K_RELOC_SECTDIFF);
+        assert_eq!(reloc.length, 2);
+        assert!(!reloc.pcrel);
+        assert_eq!(reloc.address, 0x20);
+        assert_eq!(reloc.target, RelocTarget::Address(0x1000));
+        assert_eq!(reloc.to_bytes(), entry);
+    }
+
+    #[test]
+    fn test_section_target_round_trip() {
+        let reloc = Relocation { address: 4, pcrel: false, length: 1, r_type: 0, target: RelocTarget::Section(2) };
+        assert_eq!(Relocation::parse(&reloc.to_bytes()), reloc);
+    }
+}
@@ -0,0 +1,416 @@
+//! STABS debugging information
+//!
+//! NeXTSTEP's gdb reads STABS from the symbol table, not DWARF. Each stab
+//! is an `nlist` whose `n_type` has an `N_STAB` bit set; its string has the
+//! form `name:<descriptor><type>`, where a type is a number or a
+//! `(file,number)` pair, optionally followed by `=definition`. Strings
+//! ending in `\` continue in the next stab.
+//!
+//! [`DebugInfo::from_symbols`] collects what a debugger needs to step
+//! through source: source files (`N_SO`, `N_SOL`), functions (`N_FUN`)
+//! with their parameters and line table (`N_PSYM`, `N_SLINE`), type
+//! definitions (`N_LSYM` with `t`/`T`) and variables. Line addresses are
+//! absolute, as on Mach-O.
+
+use crate::consts::*;
+use crate::symbol::Nlist;
+
+/// A stab type number; plain `N` is `(0,N)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TypeId {
+    pub file: u32,
+    pub number: u32,
+}
+
+/// The parts of a stab string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StabString<'s> {
+    pub name: &'s str,
+    /// Symbol descriptor such as `F` (global function) or `T` (tag);
+    /// None for a local variable, whose type follows the colon directly
+    pub descriptor: Option<char>,
+    pub type_id: Option<TypeId>,
+    /// What follows `=`, for a type defined here
+    pub definition: Option<&'s str>,
+}
+
+// A type number at the start of `s`, and the rest
+fn type_id(s: &str) -> Option<(TypeId, &str)> {
+    fn number(s: &str) -> Option<(u32, &str)> {
+        let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        Some((s[..end].parse().ok()?, &s[end..]))
+    }
+    if let Some(rest) = s.strip_prefix('(') {
+        let (file, rest) = number(rest)?;
+        let (number, rest) = number(rest.strip_prefix(',')?)?;
+        return Some((TypeId { file, number }, rest.strip_prefix(')')?));
+    }
+    let (number, rest) = number(s)?;
+    Some((TypeId { file: 0, number }, rest))
+}
+
+impl<'s> StabString<'s> {
+    /// Split a stab string; one without a `:` is all name (`N_SO`, `N_SOL`)
+    pub fn parse(string: &'s str) -> StabString<'s> {
+        // C++ and Rust names may contain "::"; the separator is the first
+        // lone colon
+        let mut split = None;
+        let bytes = string.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b':' {
+                if bytes.get(i + 1) == Some(&b':') {
+                    i += 2;
+                    continue;
+                }
+                split = Some(i);
+                break;
+            }
+            i += 1;
+        }
+        let Some(split) = split else {
+            return StabString { name: string, descriptor: None, type_id: None, definition: None };
+        };
+        let name = &string[..split];
+        let mut rest = &string[split + 1..];
+        let descriptor = rest.chars().next().filter(|c| c.is_ascii_alphabetic());
+        if let Some(c) = descriptor {
+            rest = &rest[c.len_utf8()..];
+        }
+        let (type_id, definition) = match type_id(rest) {
+            Some((id, after)) => (Some(id), after.strip_prefix('=')),
+            None => (None, None),
+        };
+        StabString { name, descriptor, type_id, definition }
+    }
+}
+
+/// One line table entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line {
+    pub line: u16,
+    pub address: u32,
+    /// Index into [`DebugInfo::files`]
+    pub file: usize,
+}
+
+/// A function from its `N_FUN` stab
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    /// `F` rather than `f`
+    pub global: bool,
+    pub address: u32,
+    /// End address, from an empty-named `N_FUN` giving the size, if present
+    pub end: Option<u32>,
+    pub return_type: Option<TypeId>,
+    /// Index into [`DebugInfo::files`]
+    pub file: Option<usize>,
+    pub params: Vec<String>,
+    pub lines: Vec<Line>,
+}
+
+/// A named type (`t`) or struct/union/enum tag (`T`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeDef {
+    pub name: String,
+    pub tag: bool,
+    pub id: TypeId,
+    /// Definition text after `=`, if the stab defines the type
+    pub definition: Option<String>,
+}
+
+/// A global (`N_GSYM`) or static (`N_STSYM`, `N_LCSYM`) variable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub global: bool,
+    /// None for `N_GSYM`, which gdb looks up in the symbol table
+    pub address: Option<u32>,
+    pub type_id: Option<TypeId>,
+}
+
+/// Debugging information from a symbol table's stabs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    /// Source files, from `N_SO` (with its directory) and `N_SOL`
+    pub files: Vec<String>,
+    pub functions: Vec<Function>,
+    pub types: Vec<TypeDef>,
+    pub variables: Vec<Variable>,
+}
+
+/// The stabs of a symbol table, with continued strings joined
+///
+/// Each item is the first entry of a stab and its full string.
+pub fn stabs<'a>(symbols: &[Nlist<'a>]) -> Vec<(Nlist<'a>, String)> {
+    let mut out: Vec<(Nlist<'a>, String)> = Vec::new();
+    let mut continued = false;
+    for symbol in symbols.iter().filter(|s| s.is_stab()) {
+        let text = symbol.name_lossy();
+        match out.last_mut() {
+            Some((_, string)) if continued => string.push_str(&text),
+            _ => out.push((*symbol, text.into_owned())),
+        }
+        if let Some((_, string)) = out.last_mut() {
+            continued = string.ends_with('\\');
+            if continued {
+                string.pop();
+            }
+        }
+    }
+    out
+}
+
+impl DebugInfo {
+    /// Collect the stabs in `symbols`
+    pub fn from_symbols(symbols: &[Nlist]) -> DebugInfo {
+        let mut info = DebugInfo::default();
+        let mut directory = String::new();
+        let mut file: Option<usize> = None;
+        let mut current: Option<usize> = None;
+        for (stab, string) in stabs(symbols) {
+            let parsed = StabString::parse(&string);
+            match stab.n_type {
+                N_SO if string.is_empty() => {
+                    directory.clear();
+                    file = None;
+                    current = None;
+                }
+                N_SO if string.ends_with('/') => directory = string.clone(),
+                N_SO | N_SOL => {
+                    let path = if string.starts_with('/') {
+                        string.clone()
+                    } else {
+                        format!("{}{}", directory, string)
+                    };
+                    let index = info.files.iter().position(|f| *f == path).unwrap_or_else(|| {
+                        info.files.push(path);
+                        info.files.len() - 1
+                    });
+                    file = Some(index);
+                }
+                N_FUN if string.is_empty() => {
+                    // End of the current function: n_value is its size
+                    if let Some(function) = current.and_then(|f| info.functions.get_mut(f)) {
+                        function.end = Some(function.address.wrapping_add(stab.n_value));
+                    }
+                    current = None;
+                }
+                N_FUN => {
+                    info.functions.push(Function {
+                        name: parsed.name.to_string(),
+                        global: parsed.descriptor == Some('F'),
+                        address: stab.n_value,
+                        end: None,
+                        return_type: parsed.type_id,
+                        file,
+                        params: Vec::new(),
+                        lines: Vec::new(),
+                    });
+                    current = Some(info.functions.len() - 1);
+                }
+                N_SLINE => {
+                    if let (Some(function), Some(file)) = (current.and_then(|f| info.functions.get_mut(f)), file) {
+                        function.lines.push(Line { line: stab.n_desc, address: stab.n_value, file });
+                    }
+                }
+                N_PSYM => {
+                    if let Some(function) = current.and_then(|f| info.functions.get_mut(f)) {
+                        function.params.push(parsed.name.to_string());
+                    }
+                }
+                N_LSYM if matches!(parsed.descriptor, Some('t') | Some('T')) => {
+                    if let Some(id) = parsed.type_id {
+                        info.types.push(TypeDef {
+                            name: parsed.name.to_string(),
+                            tag: parsed.descriptor == Some('T'),
+                            id,
+                            definition: parsed.definition.map(str::to_string),
+                        });
+                    }
+                }
+                N_GSYM | N_STSYM | N_LCSYM => info.variables.push(Variable {
+                    name: parsed.name.to_string(),
+                    global: stab.n_type == N_GSYM,
+                    address: (stab.n_type != N_GSYM).then_some(stab.n_value),
+                    type_id: parsed.type_id,
+                }),
+                _ => {}
+            }
+        }
+        info
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+
+    pub fn type_named(&self, name: &str) -> Option<&TypeDef> {
+        self.types.iter().find(|t| t.name == name)
+    }
+
+    /// End of `function`: its own end, else the next function's start
+    pub fn function_end(&self, function: &Function) -> Option<u32> {
+        function.end.or_else(|| {
+            self.functions
+                .iter()
+                .map(|f| f.address)
+                .filter(|&a| a > function.address)
+                .min()
+        })
+    }
+
+    /// The function whose code holds `address`
+    pub fn function_at(&self, address: u32) -> Option<&Function> {
+        self.functions
+            .iter()
+            .filter(|f| f.address <= address && address < self.function_end(f).unwrap_or(u32::MAX))
+            .max_by_key(|f| f.address)
+    }
+
+    /// Source file and line of `address`: the last line entry at or before
+    /// it in the enclosing function
+    pub fn line_at(&self, address: u32) -> Option<(&str, u16)> {
+        let function = self.function_at(address)?;
+        let line = function.lines.iter().filter(|l| l.address <= address).max_by_key(|l| l.address)?;
+        Some((self.files[line.file].as_str(), line.line))
+    }
+
+    /// What keeps these stabs from being usable for source debugging
+    ///
+    /// Every function needs line entries inside its own code, and type
+    /// numbers functions and variables use should be defined somewhere.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.files.is_empty() {
+            problems.push("no source file (N_SO) stabs".to_string());
+        }
+        if self.functions.is_empty() {
+            problems.push("no function (N_FUN) stabs".to_string());
+        }
+        for function in &self.functions {
+            if function.lines.is_empty() {
+                problems.push(format!("function {} has no line (N_SLINE) stabs", function.name));
+            }
+            let end = self.function_end(function);
+            for line in &function.lines {
+                if line.address < function.address || end.is_some_and(|end| line.address >= end) {
+                    problems.push(format!(
+                        "line {} of {} at {:#x} is outside the function",
+                        line.line, function.name, line.address
+                    ));
+                }
+            }
+        }
+        let used = self
+            .functions
+            .iter()
+            .map(|f| (&f.name, f.return_type))
+            .chain(self.variables.iter().map(|v| (&v.name, v.type_id)));
+        for (name, id) in used {
+            if let Some(id) = id {
+                if !self.types.iter().any(|t| t.id == id) {
+                    problems.push(format!("{} uses type ({},{}), which no stab defines", name, id.file, id.number));
+                }
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stab(name: &'static str, n_type: u8, n_desc: u16, n_value: u32) -> Nlist<'static> {
+        let n_sect = if matches!(n_type, N_FUN | N_SLINE | N_SO) && !name.is_empty() { 1 } else { NO_SECT };
+        Nlist { name: name.as_bytes(), n_strx: 1, n_type, n_sect, n_desc, n_value }
+    }
+
+    // What a compiler emits for a small file: two functions, a struct
+    // and a static, with the struct definition continued over two stabs
+    fn sample() -> Vec<Nlist<'static>> {
+        vec![
+            stab("/src/probe/", N_SO, 0, 0x2000),
+            stab("main.rs", N_SO, 0, 0x2000),
+            stab("u32:t(0,1)=r(0,1);0;4294967295;", N_LSYM, 0, 0),
+            stab("Point:T(0,2)=s8x:(0,1),0,32;\\", N_LSYM, 0, 0),
+            stab("y:(0,1),32,32;;", N_LSYM, 0, 0),
+            stab("add:F(0,1)", N_FUN, 3, 0x2000),
+            stab("a:p(0,1)", N_PSYM, 0, 8),
+            stab("b:p(0,1)", N_PSYM, 0, 12),
+            stab("", N_SLINE, 3, 0x2000),
+            stab("", N_SLINE, 4, 0x2004),
+            stab("", N_FUN, 0, 0x10),
+            stab("helper:f(0,1)", N_FUN, 8, 0x2010),
+            stab("", N_SLINE, 8, 0x2010),
+            stab("", N_SLINE, 9, 0x2018),
+            stab("COUNTER:S(0,1)", N_STSYM, 0, 0x4000),
+            stab("", N_SO, 0, 0x2020),
+            // An ordinary symbol in between does not disturb anything
+            Nlist { name: b"_add", n_strx: 1, n_type: N_SECT | N_EXT, n_sect: 1, n_desc: 0, n_value: 0x2000 },
+        ]
+    }
+
+    #[test]
+    fn test_stab_strings() {
+        let parsed = StabString::parse("Point:T(0,2)=s8x:(0,1),0,32;");
+        assert_eq!(parsed.name, "Point");
+        assert_eq!(parsed.descriptor, Some('T'));
+        assert_eq!(parsed.type_id, Some(TypeId { file: 0, number: 2 }));
+        assert_eq!(parsed.definition, Some("s8x:(0,1),0,32;"));
+
+        let local = StabString::parse("count:12");
+        assert_eq!((local.descriptor, local.type_id), (None, Some(TypeId { file: 0, number: 12 })));
+
+        let path = StabString::parse("probe::Point:T(1,3)");
+        assert_eq!((path.name, path.type_id), ("probe::Point", Some(TypeId { file: 1, number: 3 })));
+        assert_eq!(StabString::parse("main.rs").name, "main.rs");
+    }
+
+    #[test]
+    fn test_debug_info() {
+        let info = DebugInfo::from_symbols(&sample());
+        assert_eq!(info.files, ["/src/probe/main.rs"]);
+
+        let add = info.function("add").unwrap();
+        assert!(add.global);
+        assert_eq!((add.address, add.end, add.file), (0x2000, Some(0x2010), Some(0)));
+        assert_eq!(add.params, ["a", "b"]);
+        assert_eq!(add.lines.len(), 2);
+        let helper = info.function("helper").unwrap();
+        assert!(!helper.global);
+        assert_eq!(info.function_end(helper), None);
+
+        let point = info.type_named("Point").unwrap();
+        assert!(point.tag);
+        assert_eq!(point.definition.as_deref(), Some("s8x:(0,1),0,32;y:(0,1),32,32;;"));
+        assert_eq!(info.variables[0].address, Some(0x4000));
+
+        assert_eq!(info.line_at(0x2006), Some(("/src/probe/main.rs", 4)));
+        assert_eq!(info.line_at(0x2014), Some(("/src/probe/main.rs", 8)));
+        assert_eq!(info.function_at(0x200c).unwrap().name, "add");
+        assert_eq!(info.line_at(0x1000), None);
+        assert_eq!(info.problems(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_problems() {
+        let mut symbols = sample();
+        // Drop helper's lines and the u32 definition
+        let helper_line = |s: &Nlist| s.n_type == N_SLINE && s.n_value >= 0x2010;
+        symbols.retain(|s| !helper_line(s) && !s.name.starts_with(b"u32"));
+        let problems = DebugInfo::from_symbols(&symbols).problems();
+        assert_eq!(
+            problems,
+            [
+                "function helper has no line (N_SLINE) stabs",
+                "add uses type (0,1), which no stab defines",
+                "helper uses type (0,1), which no stab defines",
+                "COUNTER uses type (0,1), which no stab defines",
+            ]
+        );
+        assert_eq!(DebugInfo::from_symbols(&[]).problems().len(), 2);
+    }
+}
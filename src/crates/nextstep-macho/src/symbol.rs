@@ -0,0 +1,86 @@
+//! Symbol table entries (`struct nlist`)
+
+use crate::consts::*;
+use crate::read::{be16, be32};
+
+/// One symbol table entry with its name resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nlist<'a> {
+    /// Name from the string table; empty for `n_strx` 0
+    pub name: &'a [u8],
+    pub n_strx: u32,
+    pub n_type: u8,
+    /// Section ordinal from 1, or `NO_SECT`
+    pub n_sect: u8,
+    pub n_desc: u16,
+    pub n_value: u32,
+}
+
+impl<'a> Nlist<'a> {
+    // Decode an entry; the caller resolves the name
+    pub(crate) fn parse(entry: &[u8]) -> Nlist<'a> {
+        Nlist {
+            name: &[],
+            n_strx: be32(entry, 0),
+            n_type: entry[4],
+            n_sect: entry[5],
+            n_desc: be16(entry, 6),
+            n_value: be32(entry, 8),
+        }
+    }
+
+    /// Name as text, with invalid UTF-8 replaced
+    pub fn name_lossy(&self) -> std::borrow::Cow<'a, str> {
+        String::from_utf8_lossy(self.name)
+    }
+
+    /// Debugger (STABS) entry
+    pub fn is_stab(&self) -> bool {
+        self.n_type & N_STAB != 0
+    }
+
+    /// Visible to other object files
+    pub fn is_external(&self) -> bool {
+        !self.is_stab() && self.n_type & N_EXT != 0
+    }
+
+    /// Private external (`N_PEXT`)
+    pub fn is_private_external(&self) -> bool {
+        !self.is_stab() && self.n_type & N_PEXT != 0
+    }
+
+    /// The `N_TYPE` bits, or None for stabs
+    pub fn kind(&self) -> Option<u8> {
+        if self.is_stab() {
+            None
+        } else {
+            Some(self.n_type & N_TYPE)
+        }
+    }
+
+    /// Undefined reference (common symbols included)
+    pub fn is_undefined(&self) -> bool {
+        self.kind() == Some(N_UNDF)
+    }
+
+    /// Common symbol: undefined external with a size in `n_value`
+    pub fn is_common(&self) -> bool {
+        self.is_undefined() && self.is_external() && self.n_value != 0
+    }
+
+    /// Defined in a section
+    pub fn is_section(&self) -> bool {
+        self.kind() == Some(N_SECT)
+    }
+
+    /// Encode as a 12-byte entry
+    pub fn to_bytes(&self) -> [u8; NLIST_SIZE] {
+        let mut bytes = [0; NLIST_SIZE];
+        bytes[0..4].copy_from_slice(&self.n_strx.to_be_bytes());
+        bytes[4] = self.n_type;
+        bytes[5] = self.n_sect;
+        bytes[6..8].copy_from_slice(&self.n_desc.to_be_bytes());
+        bytes[8..12].copy_from_slice(&self.n_value.to_be_bytes());
+        bytes
+    }
+}
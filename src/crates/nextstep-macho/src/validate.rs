@@ -0,0 +1,561 @@
+//! Conformance checks against what NeXTSTEP 3.3's `ld` and kernel accept
+//!
+//! 3.3 predates dyld: dylibs, bundles, symbol stubs, lazy pointers and
+//! prebinding all arrived with OPENSTEP 4.0 and are rejected here. The
+//! checks are structural; they do not disassemble anything.
+
+use crate::consts::*;
+use crate::fat::FatFile;
+use crate::file::{Command, MachO, Section, Segment};
+use crate::reloc::{RelocTarget, Relocation};
+use crate::symbol::Nlist;
+use std::fmt;
+
+/// VM page size on NeXT m68k hardware
+pub const NEXT_PAGE_SIZE: u32 = 0x2000;
+
+/// Largest section alignment (log2) NeXT `ld` handles (`MAXSECTALIGN`)
+pub const MAX_SECT_ALIGN: u32 = 15;
+
+/// CPU the binary has to run on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cpu {
+    M68030,
+    M68040,
+}
+
+/// What to check beyond the format itself
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    /// Require a `cpusubtype` this CPU's kernel will run
+    pub cpu: Option<Cpu>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Accepted, but likely not what was meant
+    Warning,
+    /// `ld` or the kernel rejects the file
+    Error,
+}
+
+/// One violated rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Short rule name, e.g. `"section-align"`
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}[{}]: {}", severity, self.rule, self.message)
+    }
+}
+
+// Whether a symbol belongs in a dysymtab group
+type SymbolFilter = fn(&Nlist) -> bool;
+
+struct Checker<'m, 'a> {
+    macho: &'m MachO<'a>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+fn section_name(section: &Section) -> String {
+    format!("{},{}", section.segname, section.sectname)
+}
+
+// `[offset, offset + size)` lies within `len`
+fn in_file(offset: u32, size: u64, len: usize) -> bool {
+    offset as u64 + size <= len as u64
+}
+
+impl<'m, 'a> Checker<'m, 'a> {
+    fn error(&mut self, rule: &'static str, message: String) {
+        self.diagnostics.push(Diagnostic { severity: Severity::Error, rule, message });
+    }
+
+    fn warning(&mut self, rule: &'static str, message: String) {
+        self.diagnostics.push(Diagnostic { severity: Severity::Warning, rule, message });
+    }
+
+    fn is_object(&self) -> bool {
+        self.macho.header.filetype == MH_OBJECT
+    }
+
+    fn check_header(&mut self, options: &Options) {
+        let header = self.macho.header;
+        if header.cputype != CPU_TYPE_MC680X0 {
+            self.error("cpu-type", format!("cputype {} is not CPU_TYPE_MC680x0 (6)", header.cputype));
+        } else {
+            match (cpu_subtype_name(header.cpusubtype), options.cpu) {
+                (None, _) => self.error("cpu-subtype", format!("unknown MC680x0 cpusubtype {}", header.cpusubtype)),
+                (Some(name), Some(Cpu::M68040)) if header.cpusubtype == CPU_SUBTYPE_MC68030_ONLY => {
+                    self.error("cpu-subtype", format!("cpusubtype {} will not run on a 68040", name))
+                }
+                (Some(name), Some(Cpu::M68030)) if header.cpusubtype == CPU_SUBTYPE_MC68040 => {
+                    self.error("cpu-subtype", format!("cpusubtype {} will not run on a 68030", name))
+                }
+                _ => {}
+            }
+        }
+
+        match header.filetype {
+            MH_OBJECT | MH_EXECUTE | MH_FVMLIB | MH_CORE | MH_PRELOAD => {}
+            MH_DYLIB | MH_DYLINKER | MH_BUNDLE => self.error(
+                "filetype",
+                format!("{} needs dyld (OPENSTEP 4.0 or later)", filetype_name(header.filetype).unwrap_or("?")),
+            ),
+            other => self.error("filetype", format!("unknown filetype {:#x}", other)),
+        }
+
+        if header.flags & (MH_DYLDLINK | MH_BINDATLOAD | MH_PREBOUND) != 0 {
+            self.error("header-flags", format!("flags {:#x} set dyld-only bits", header.flags));
+        }
+        let known = MH_NOUNDEFS | MH_INCRLINK | MH_DYLDLINK | MH_BINDATLOAD | MH_PREBOUND;
+        if header.flags & !known != 0 {
+            self.error("header-flags", format!("unknown flag bits {:#x}", header.flags & !known));
+        }
+    }
+
+    fn check_load_commands(&mut self) {
+        let mut threads = 0;
+        for (index, lc) in self.macho.commands.iter().enumerate() {
+            let name = load_command_name(lc.cmd).unwrap_or("?");
+            match lc.cmd {
+                LC_SEGMENT | LC_SYMTAB | LC_SYMSEG | LC_THREAD | LC_LOADFVMLIB | LC_IDFVMLIB | LC_IDENT
+                | LC_FVMFILE | LC_PREPAGE => {}
+                LC_UNIXTHREAD => threads += 1,
+                // LLVM writes one into every object; NeXT ld reads only LC_SYMTAB
+                LC_DYSYMTAB => self.warning(
+                    "load-command",
+                    format!("load command {}: LC_DYSYMTAB is ignored before OPENSTEP 4.0", index),
+                ),
+                LC_LOAD_DYLIB | LC_ID_DYLIB | LC_LOAD_DYLINKER | LC_ID_DYLINKER => self.error(
+                    "load-command",
+                    format!("load command {}: {} needs dyld (OPENSTEP 4.0 or later)", index, name),
+                ),
+                other => self.error("load-command", format!("load command {}: unknown cmd {:#x}", index, other)),
+            }
+        }
+
+        if self.macho.header.filetype == MH_EXECUTE {
+            if threads != 1 {
+                self.error("thread-state", format!("executable has {} LC_UNIXTHREAD commands; needs exactly one", threads));
+            }
+            self.check_entry_point();
+        } else if threads != 0 && self.is_object() {
+            self.error("thread-state", "object file has an LC_UNIXTHREAD command".to_string());
+        }
+    }
+
+    fn check_entry_point(&mut self) {
+        let Some(pc) = self.macho.entry_point() else {
+            if self.macho.commands.iter().any(|lc| lc.cmd == LC_UNIXTHREAD) {
+                self.error("thread-state", "LC_UNIXTHREAD has no M68K_THREAD_STATE_REGS state".to_string());
+            }
+            return;
+        };
+        let executable = self.macho.segments().any(|seg| {
+            seg.initprot & VM_PROT_EXECUTE != 0 && pc >= seg.vmaddr && pc - seg.vmaddr < seg.vmsize
+        });
+        if !executable {
+            self.error("thread-state", format!("entry point {:#010x} is not in an executable segment", pc));
+        }
+        if pc & 1 != 0 {
+            self.error("thread-state", format!("entry point {:#010x} is odd", pc));
+        }
+    }
+
+    fn check_segment(&mut self, segment: &Segment) {
+        let len = self.macho.data().len();
+        let name = if segment.segname.is_empty() { "(unnamed)" } else { segment.segname.as_str() };
+        if segment.filesize > segment.vmsize {
+            self.error(
+                "segment-layout",
+                format!("segment {}: filesize {:#x} exceeds vmsize {:#x}", name, segment.filesize, segment.vmsize),
+            );
+        }
+        if !in_file(segment.fileoff, segment.filesize as u64, len) {
+            self.error("file-range", format!("segment {}: file range runs past end of file", name));
+        }
+        if segment.initprot & !segment.maxprot != 0 {
+            self.error(
+                "segment-layout",
+                format!("segment {}: initprot {:#x} exceeds maxprot {:#x}", name, segment.initprot, segment.maxprot),
+            );
+        }
+        // The kernel maps executables and fixed shared libraries page by page
+        let paged = matches!(self.macho.header.filetype, MH_EXECUTE | MH_FVMLIB);
+        if paged && ((segment.vmaddr | segment.fileoff) & (NEXT_PAGE_SIZE - 1) != 0) {
+            self.error(
+                "segment-layout",
+                format!(
+                    "segment {}: vmaddr {:#x} and fileoff {:#x} must be multiples of the {:#x} page size",
+                    name, segment.vmaddr, segment.fileoff, NEXT_PAGE_SIZE
+                ),
+            );
+        }
+        if !self.is_object() && segment.segname.is_empty() {
+            self.error("segment-layout", "unnamed segment outside an object file".to_string());
+        }
+        for section in &segment.sections {
+            self.check_section(segment, section);
+        }
+    }
+
+    fn check_section(&mut self, segment: &Segment, section: &Section) {
+        let name = section_name(section);
+        if section.align > MAX_SECT_ALIGN {
+            self.error(
+                "section-align",
+                format!("section {}: alignment 2**{} exceeds 2**{}", name, section.align, MAX_SECT_ALIGN),
+            );
+        } else if section.addr & ((1 << section.align) - 1) != 0 {
+            self.error(
+                "section-align",
+                format!("section {}: address {:#x} is not 2**{} aligned", name, section.addr, section.align),
+            );
+        }
+
+        match section.section_type() {
+            S_REGULAR | S_ZEROFILL | S_CSTRING_LITERALS | S_4BYTE_LITERALS | S_8BYTE_LITERALS
+            | S_LITERAL_POINTERS => {}
+            S_NON_LAZY_SYMBOL_POINTERS | S_LAZY_SYMBOL_POINTERS | S_SYMBOL_STUBS | S_MOD_INIT_FUNC_POINTERS => self.error(
+                "section-type",
+                format!("section {}: type {:#x} needs dyld (OPENSTEP 4.0 or later)", name, section.section_type()),
+            ),
+            other => self.error("section-type", format!("section {}: unknown section type {:#x}", name, other)),
+        }
+
+        let (start, end) = (section.addr as u64, section.addr as u64 + section.size as u64);
+        if start < segment.vmaddr as u64 || end > segment.vmaddr as u64 + segment.vmsize as u64 {
+            self.error("section-layout", format!("section {}: address range is outside its segment", name));
+        }
+        if !self.is_object() && section.segname != segment.segname {
+            self.error(
+                "section-layout",
+                format!("section {}: listed in segment {}", name, segment.segname),
+            );
+        }
+        if !section.is_zerofill() {
+            if !in_file(section.offset, section.size as u64, self.macho.data().len()) {
+                self.error("file-range", format!("section {}: contents run past end of file", name));
+            } else if !self.is_object()
+                && section.offset.wrapping_sub(segment.fileoff) != section.addr.wrapping_sub(segment.vmaddr)
+            {
+                self.error(
+                    "section-layout",
+                    format!("section {}: file offset does not match its address within the segment", name),
+                );
+            }
+        }
+
+        if section.nreloc != 0 {
+            if !self.is_object() {
+                self.warning("reloc", format!("section {}: relocation entries in a linked file", name));
+            }
+            match self.macho.relocations(section) {
+                Ok(relocs) => self.check_relocations(section, &relocs),
+                Err(_) => self.error("file-range", format!("section {}: relocation entries run past end of file", name)),
+            }
+        }
+    }
+
+    fn check_relocations(&mut self, section: &Section, relocs: &[Relocation]) {
+        let name = section_name(section);
+        let nsyms = self.macho.symtab().map_or(0, |symtab| symtab.nsyms);
+        let nsects = self.macho.sections().count() as u32;
+        let mut prev: Option<&Relocation> = None;
+        for (index, reloc) in relocs.iter().enumerate() {
+            let at = format!("section {}: relocation {} at {:#x}", name, index, reloc.address);
+            let needs_pair = prev.is_some_and(|p| p.r_type == M68K_RELOC_SECTDIFF);
+            match reloc.r_type {
+                M68K_RELOC_VANILLA => {}
+                M68K_RELOC_PAIR if needs_pair => {}
+                M68K_RELOC_PAIR => self.error("reloc-pair", format!("{}: PAIR does not follow a SECTDIFF", at)),
+                M68K_RELOC_SECTDIFF => {
+                    if !reloc.is_scattered() {
+                        self.error("reloc-scattered", format!("{}: SECTDIFF must be a scattered entry", at));
+                    }
+                }
+                M68K_RELOC_PB_LA_PTR | M68K_RELOC_LOCAL_SECTDIFF => self.error(
+                    "reloc-type",
+                    format!(
+                        "{}: {} is not understood by NeXTSTEP 3.3 ld",
+                        at,
+                        reloc_type_name(reloc.r_type).unwrap_or("?")
+                    ),
+                ),
+                other => self.error("reloc-type", format!("{}: unknown relocation type {}", at, other)),
+            }
+            if needs_pair && reloc.r_type != M68K_RELOC_PAIR {
+                self.error("reloc-pair", format!("{}: SECTDIFF is not followed by a PAIR", at));
+            }
+            if reloc.r_type == M68K_RELOC_PAIR {
+                // The address field of a PAIR is unused; nothing else to check
+                prev = Some(reloc);
+                continue;
+            }
+            if reloc.length > 2 {
+                self.error("reloc-range", format!("{}: length {} is wider than 4 bytes", at, reloc.length));
+            } else if reloc.address as u64 + reloc.size() as u64 > section.size as u64 {
+                self.error("reloc-range", format!("{}: item runs past the end of the section", at));
+            }
+            match reloc.target {
+                RelocTarget::Symbol(symbol) if symbol >= nsyms => {
+                    self.error("reloc-range", format!("{}: symbol index {} out of range", at, symbol))
+                }
+                RelocTarget::Section(n) if n != R_ABS && n > nsects => {
+                    self.error("reloc-range", format!("{}: section ordinal {} out of range", at, n))
+                }
+                RelocTarget::Address(value) if self.is_object() && !self.in_any_section(value) => self.warning(
+                    "reloc-scattered",
+                    format!("{}: scattered value {:#x} is not inside any section", at, value),
+                ),
+                _ => {}
+            }
+            prev = Some(reloc);
+        }
+        if prev.is_some_and(|p| p.r_type == M68K_RELOC_SECTDIFF) {
+            self.error("reloc-pair", format!("section {}: last SECTDIFF is not followed by a PAIR", name));
+        }
+    }
+
+    fn in_any_section(&self, address: u32) -> bool {
+        self.macho
+            .sections()
+            .any(|s| address >= s.addr && (address - s.addr) <= s.size)
+    }
+
+    fn check_symbols(&mut self) {
+        let Some(symtab) = self.macho.symtab() else {
+            return;
+        };
+        let len = self.macho.data().len();
+        if !in_file(symtab.symoff, symtab.nsyms as u64 * NLIST_SIZE as u64, len) {
+            self.error("file-range", "symbol table runs past end of file".to_string());
+            return;
+        }
+        if !in_file(symtab.stroff, symtab.strsize as u64, len) {
+            self.error("file-range", "string table runs past end of file".to_string());
+            return;
+        }
+        let symbols = match self.macho.symbols() {
+            Ok(symbols) => symbols,
+            Err(e) => {
+                self.error("symbol", e.to_string());
+                return;
+            }
+        };
+        let nsects = self.macho.sections().count() as u32;
+        for (index, symbol) in symbols.iter().enumerate() {
+            if symbol.is_stab() {
+                continue;
+            }
+            let what = format!("symbol {} ({})", index, symbol.name_lossy());
+            match symbol.n_type & N_TYPE {
+                N_SECT if symbol.n_sect == NO_SECT || symbol.n_sect as u32 > nsects => {
+                    self.error("symbol", format!("{}: section ordinal {} out of range", what, symbol.n_sect))
+                }
+                N_SECT | N_UNDF | N_ABS | N_INDR => {}
+                N_PBUD => self.error("symbol", format!("{}: N_PBUD needs dyld (OPENSTEP 4.0 or later)", what)),
+                other => self.error("symbol", format!("{}: unknown n_type {:#x}", what, other)),
+            }
+            if symbol.n_type & N_TYPE != N_SECT && symbol.n_sect != NO_SECT {
+                self.warning("symbol", format!("{}: n_sect {} on a symbol not defined in a section", what, symbol.n_sect));
+            }
+        }
+        if self.macho.header.filetype == MH_EXECUTE && self.macho.header.flags & MH_NOUNDEFS != 0 {
+            if let Some(undef) = symbols.iter().find(|s| s.is_undefined() && s.is_external()) {
+                self.error(
+                    "symbol",
+                    format!("MH_NOUNDEFS is set but {} is undefined", undef.name_lossy()),
+                );
+            }
+        }
+        self.check_dysymtab(&symbols);
+    }
+
+    fn check_dysymtab(&mut self, symbols: &[Nlist]) {
+        let Some(d) = self.macho.dysymtab() else {
+            return;
+        };
+        let nsyms = symbols.len() as u64;
+        let groups: [(&str, u32, u32, SymbolFilter); 3] = [
+            ("local", d.ilocalsym, d.nlocalsym, |s| s.is_stab() || !s.is_external()),
+            ("extdef", d.iextdefsym, d.nextdefsym, |s| s.is_external() && !s.is_undefined()),
+            ("undef", d.iundefsym, d.nundefsym, |s| s.is_external() && s.is_undefined()),
+        ];
+        for (name, first, count, belongs) in groups {
+            if first as u64 + count as u64 > nsyms {
+                self.error("dysymtab", format!("{} symbols {}..{} exceed nsyms {}", name, first, first as u64 + count as u64, nsyms));
+                continue;
+            }
+            let range = &symbols[first as usize..(first + count) as usize];
+            if let Some(stray) = range.iter().position(|s| !belongs(s)) {
+                self.error(
+                    "dysymtab",
+                    format!("symbol {} ({}) is in the {} group", first as usize + stray, range[stray].name_lossy(), name),
+                );
+            }
+        }
+        let len = self.macho.data().len();
+        let tables = [
+            ("indirect symbol table", d.indirectsymoff, d.nindirectsyms as u64 * 4),
+            ("external relocations", d.extreloff, d.nextrel as u64 * RELOCATION_INFO_SIZE as u64),
+            ("local relocations", d.locreloff, d.nlocrel as u64 * RELOCATION_INFO_SIZE as u64),
+        ];
+        for (name, offset, size) in tables {
+            if size != 0 && !in_file(offset, size, len) {
+                self.error("file-range", format!("{} runs past end of file", name));
+            }
+        }
+    }
+}
+
+/// Check a parsed file; diagnostics come out in file order, by area
+pub fn validate(macho: &MachO, options: &Options) -> Vec<Diagnostic> {
+    let mut checker = Checker { macho, diagnostics: Vec::new() };
+    checker.check_header(options);
+    checker.check_load_commands();
+    for lc in &macho.commands {
+        if let Command::Segment(segment) = &lc.command {
+            checker.check_segment(segment);
+        }
+    }
+    checker.check_symbols();
+    checker.diagnostics
+}
+
+/// Check a fat file's m68k slice, and that the kernel can map it
+///
+/// The other slices are not looked at beyond the `fat_arch` table.
+pub fn validate_fat(fat: &FatFile, options: &Options) -> Vec<Diagnostic> {
+    let error = |rule, message| vec![Diagnostic { severity: Severity::Error, rule, message }];
+    let Some((arch, slice)) = fat.find(CPU_TYPE_MC680X0) else {
+        return error("fat", "no m68k slice".to_string());
+    };
+    let macho = match MachO::parse(slice) {
+        Ok(macho) => macho,
+        Err(e) => return error("parse", format!("m68k slice: {}", e)),
+    };
+    let mut diagnostics = Vec::new();
+    if macho.header.filetype == MH_EXECUTE && arch.offset & (NEXT_PAGE_SIZE - 1) != 0 {
+        diagnostics.extend(error("fat", format!("m68k slice at {:#x} is not page aligned", arch.offset)));
+    }
+    diagnostics.extend(validate(&macho, options));
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::tests::sample_object;
+
+    fn rules(data: &[u8], options: &Options) -> Vec<(Severity, &'static str)> {
+        let macho = MachO::parse(data).unwrap();
+        validate(&macho, options).iter().map(|d| (d.severity, d.rule)).collect()
+    }
+
+    fn set32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    // Offsets into sample_object()
+    const SECTION: usize = MACH_HEADER_SIZE + SEGMENT_COMMAND_SIZE;
+    const DYSYMTAB: usize = SECTION + SECTION_SIZE + SYMTAB_COMMAND_SIZE;
+
+    #[test]
+    fn test_llvm_object_passes() {
+        // Only the informational LC_DYSYMTAB warning
+        let data = sample_object();
+        assert_eq!(rules(&data, &Options { cpu: Some(Cpu::M68040) }), [(Severity::Warning, "load-command")]);
+    }
+
+    #[test]
+    fn test_cpu_subtype_for_target() {
+        let mut data = sample_object();
+        set32(&mut data, 8, CPU_SUBTYPE_MC68030_ONLY as u32);
+        assert!(rules(&data, &Options::default()).iter().all(|&(_, rule)| rule != "cpu-subtype"));
+        assert!(rules(&data, &Options { cpu: Some(Cpu::M68040) }).contains(&(Severity::Error, "cpu-subtype")));
+        set32(&mut data, 4, 7);
+        assert!(rules(&data, &Options::default()).contains(&(Severity::Error, "cpu-type")));
+    }
+
+    #[test]
+    fn test_section_alignment() {
+        let mut data = sample_object();
+        // 2**16 is past MAXSECTALIGN
+        set32(&mut data, SECTION + 44, 16);
+        assert!(rules(&data, &Options::default()).contains(&(Severity::Error, "section-align")));
+        // Address 2 with 2**2 alignment
+        set32(&mut data, SECTION + 44, 2);
+        set32(&mut data, SECTION + 32, 2);
+        let macho = MachO::parse(&data).unwrap();
+        let diagnostics = validate(&macho, &Options::default());
+        let align = diagnostics.iter().find(|d| d.rule == "section-align").unwrap();
+        assert_eq!(align.to_string(), "error[section-align]: section __TEXT,__text: address 0x2 is not 2**2 aligned");
+    }
+
+    #[test]
+    fn test_dyld_only_features() {
+        let mut data = sample_object();
+        set32(&mut data, 12, MH_BUNDLE);
+        set32(&mut data, SECTION + 56, S_SYMBOL_STUBS);
+        let found = rules(&data, &Options::default());
+        assert!(found.contains(&(Severity::Error, "filetype")));
+        assert!(found.contains(&(Severity::Error, "section-type")));
+    }
+
+    #[test]
+    fn test_scattered_relocation_misuse() {
+        let data = sample_object();
+        let reloff = {
+            let macho = MachO::parse(&data).unwrap();
+            macho.section(1).unwrap().reloff as usize
+        };
+
+        // SECTDIFF without its PAIR: turn the PAIR into a VANILLA
+        let mut unpaired = data.clone();
+        unpaired[reloff + 16] &= 0xf0;
+        assert!(rules(&unpaired, &Options::default()).contains(&(Severity::Error, "reloc-pair")));
+
+        // SECTDIFF written as a plain entry
+        let mut plain = data.clone();
+        let reloc = Relocation { address: 8, pcrel: false, length: 2, r_type: M68K_RELOC_SECTDIFF, target: RelocTarget::Section(1) };
+        plain[reloff + 8..reloff + 16].copy_from_slice(&reloc.to_bytes());
+        assert!(rules(&plain, &Options::default()).contains(&(Severity::Error, "reloc-scattered")));
+
+        // LOCAL_SECTDIFF postdates 3.3
+        let mut local = data;
+        local[reloff + 8] = (local[reloff + 8] & 0xf0) | M68K_RELOC_LOCAL_SECTDIFF;
+        assert!(rules(&local, &Options::default()).contains(&(Severity::Error, "reloc-type")));
+    }
+
+    #[test]
+    fn test_fat_m68k_slice() {
+        use crate::fat::{build, tests::i386_object};
+        let data = build(&[&i386_object(), &sample_object()]).unwrap();
+        let fat = FatFile::parse(&data).unwrap();
+        let found: Vec<_> = validate_fat(&fat, &Options::default()).iter().map(|d| (d.severity, d.rule)).collect();
+        assert_eq!(found, [(Severity::Warning, "load-command")]);
+
+        let data = build(&[&i386_object()]).unwrap();
+        let fat = FatFile::parse(&data).unwrap();
+        assert_eq!(validate_fat(&fat, &Options::default())[0].rule, "fat");
+    }
+
+    #[test]
+    fn test_dysymtab_groups() {
+        let mut data = sample_object();
+        // Claim _puts (undefined) is an extdef
+        set32(&mut data, DYSYMTAB + 20, 2);
+        assert!(rules(&data, &Options::default()).contains(&(Severity::Error, "dysymtab")));
+    }
+}
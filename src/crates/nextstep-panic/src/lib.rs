@@ -0,0 +1,135 @@
+//! nextstep-panic - Standard panic runtime for no_std NeXTSTEP binaries
+//!
+//! Provides the `#[panic_handler]` so examples and applications don't need
+//! to carry their own. On panic the handler flushes stdout, runs the
+//! installed hook (by default: print the location and message to stderr,
+//! plus a backtrace with the `backtrace` feature) and exits with
+//! [`PANIC_EXIT_CODE`].
+//!
+//! Link it in with `extern crate nextstep_panic;`. Crates that only want
+//! the hook API can disable the default `handler` feature.
+
+#![no_std]
+
+use core::fmt;
+use core::panic::{Location, PanicInfo};
+
+/// Exit status of a process that panicked (the same value std uses)
+pub const PANIC_EXIT_CODE: i32 = 101;
+
+/// Signature of a custom panic hook
+pub type PanicHook = fn(&PanicInfo<'_>);
+
+// Installed hook; `None` means `default_hook`
+static mut HOOK: Option<PanicHook> = None;
+
+// Set while the handler runs, to catch a hook that panics itself
+static mut PANICKING: bool = false;
+
+/// Replace the panic hook
+///
+/// The hook runs after stdout has been flushed and before the process
+/// exits. It replaces the default report entirely; call [`default_hook`]
+/// from it to keep the standard output.
+pub fn set_hook(hook: PanicHook) {
+    unsafe { HOOK = Some(hook) };
+}
+
+/// Remove the custom panic hook, returning it
+///
+/// After this call panics are reported by [`default_hook`] again.
+pub fn take_hook() -> Option<PanicHook> {
+    unsafe { core::ptr::replace(core::ptr::addr_of_mut!(HOOK), None) }
+}
+
+/// The standard panic report: location and message on stderr
+pub fn default_hook(info: &PanicInfo<'_>) {
+    let _ = write_report(&mut nextstep_io::stderr(), info.location(), info.message());
+
+    #[cfg(feature = "backtrace")]
+    nextstep_backtrace::print();
+}
+
+// The report line(s) default_hook prints, in the format of `PanicInfo`'s
+// Display
+fn write_report(out: &mut impl fmt::Write, location: Option<&Location<'_>>, message: impl fmt::Display) -> fmt::Result {
+    match location {
+        Some(location) => writeln!(out, "PANIC: panicked at {}:\n{}", location, message),
+        None => writeln!(out, "PANIC: panicked:\n{}", message),
+    }
+}
+
+// Mark this process as panicking, returning false if it already was
+fn enter_panic() -> bool {
+    unsafe { !core::mem::replace(&mut *core::ptr::addr_of_mut!(PANICKING), true) }
+}
+
+/// Run the panic machinery and exit
+///
+/// This is what the provided `#[panic_handler]` calls; custom handlers can
+/// call it too.
+pub fn handle_panic(info: &PanicInfo<'_>) -> ! {
+    if !enter_panic() {
+        let _ = nextstep_sys::sys_write(
+            nextstep_sys::STDERR_FILENO,
+            b"PANIC: panicked while processing panic\n",
+        );
+        nextstep_sys::sys_exit(PANIC_EXIT_CODE);
+    }
+
+    // try_flush: the panic may have interrupted a write on this thread
+    let _ = nextstep_io::stdout().try_flush();
+
+    match unsafe { HOOK } {
+        Some(hook) => hook(info),
+        None => default_hook(info),
+    }
+
+    nextstep_sys::sys_process_exit(PANIC_EXIT_CODE);
+}
+
+#[cfg(all(feature = "handler", not(test)))]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    handle_panic(info)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::format;
+    use std::string::String;
+
+    fn quiet(_: &PanicInfo<'_>) {}
+
+    #[test]
+    fn test_set_and_take_hook() {
+        assert!(take_hook().is_none());
+        set_hook(quiet);
+        assert_eq!(take_hook().map(|hook| hook as usize), Some(quiet as PanicHook as usize));
+        assert!(take_hook().is_none());
+    }
+
+    #[test]
+    fn test_report_format() {
+        let location = Location::caller();
+        let mut out = String::new();
+        write_report(&mut out, Some(location), format_args!("index {} out of range", 4)).unwrap();
+        assert_eq!(
+            out,
+            format!("PANIC: panicked at {}:{}:{}:\nindex 4 out of range\n", file!(), location.line(), location.column())
+        );
+
+        out.clear();
+        write_report(&mut out, None, "no location").unwrap();
+        assert_eq!(out, "PANIC: panicked:\nno location\n");
+    }
+
+    #[test]
+    fn test_nested_panic_detected() {
+        assert!(enter_panic());
+        assert!(!enter_panic());
+    }
+}
@@ -0,0 +1,81 @@
+//! nextstep-rt-macros - Attribute macros for nextstep-rt
+//!
+//! Implemented directly on `proc_macro` token trees to keep the toolchain
+//! free of external dependencies.
+
+use proc_macro::{Delimiter, TokenStream, TokenTree};
+
+/// Mark the program's entry function
+///
+/// ```ignore
+/// #![no_std]
+/// #![no_main]
+///
+/// #[nextstep_rt::main]
+/// fn main() -> Result<(), nextstep_io::IoError> {
+///     nextstep_io::println!("Hello from NeXTSTEP!");
+///     Ok(())
+/// }
+/// ```
+///
+/// The function must take no arguments and return a type implementing
+/// `nextstep_rt::Termination`. The macro keeps the function as written and
+/// adds the `__nextstep_rt_main` symbol that the runtime's `_start` calls.
+#[proc_macro_attribute]
+pub fn main(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return compile_error("`#[nextstep_rt::main]` takes no arguments");
+    }
+
+    let name = match entry_name(&item) {
+        Ok(name) => name,
+        Err(message) => return compile_error(message),
+    };
+
+    let entry: TokenStream = format!(
+        "#[doc(hidden)]
+        #[no_mangle]
+        pub extern \"Rust\" fn __nextstep_rt_main() -> i32 {{
+            ::nextstep_rt::Termination::report({}())
+        }}",
+        name
+    )
+    .parse()
+    .unwrap();
+
+    let mut output = item;
+    output.extend(entry);
+    output
+}
+
+/// Name of the annotated function, checking that it takes no arguments
+fn entry_name(item: &TokenStream) -> Result<String, &'static str> {
+    let mut tokens = item.clone().into_iter();
+    while let Some(token) = tokens.next() {
+        if let TokenTree::Ident(ident) = &token {
+            if ident.to_string() == "fn" {
+                let name = match tokens.next() {
+                    Some(TokenTree::Ident(name)) => name.to_string(),
+                    _ => return Err("expected a function name"),
+                };
+                return match tokens.next() {
+                    Some(TokenTree::Group(params))
+                        if params.delimiter() == Delimiter::Parenthesis =>
+                    {
+                        if params.stream().is_empty() {
+                            Ok(name)
+                        } else {
+                            Err("`#[nextstep_rt::main]` function must take no arguments")
+                        }
+                    }
+                    _ => Err("`#[nextstep_rt::main]` function cannot be generic"),
+                };
+            }
+        }
+    }
+    Err("`#[nextstep_rt::main]` can only be applied to a function")
+}
+
+fn compile_error(message: &str) -> TokenStream {
+    format!("compile_error!({:?});", message).parse().unwrap()
+}
@@ -0,0 +1,64 @@
+//! Process arguments and environment captured at startup
+
+use core::ffi::CStr;
+
+static mut ARGC: usize = 0;
+static mut ARGV: *const *const u8 = core::ptr::null();
+static mut ENVP: *const *const u8 = core::ptr::null();
+
+/// Record the vectors found on the initial stack
+pub(crate) unsafe fn init(argc: usize, argv: *const *const u8, envp: *const *const u8) {
+    ARGC = argc;
+    ARGV = argv;
+    ENVP = envp;
+}
+
+/// Iterator over a NULL-terminated vector of C strings
+#[derive(Debug, Clone, Copy)]
+pub struct CStrVec {
+    next: *const *const u8,
+}
+
+impl Iterator for CStrVec {
+    type Item = &'static CStr;
+
+    fn next(&mut self) -> Option<&'static CStr> {
+        if self.next.is_null() {
+            return None;
+        }
+        unsafe {
+            let s = *self.next;
+            if s.is_null() {
+                return None;
+            }
+            self.next = self.next.add(1);
+            Some(CStr::from_ptr(s.cast()))
+        }
+    }
+}
+
+/// Number of command-line arguments, including the program name
+pub fn argc() -> usize {
+    unsafe { ARGC }
+}
+
+/// Command-line arguments, starting with the program name
+pub fn args() -> CStrVec {
+    CStrVec { next: unsafe { ARGV } }
+}
+
+/// Environment entries in `NAME=value` form
+pub fn env() -> CStrVec {
+    CStrVec { next: unsafe { ENVP } }
+}
+
+/// Value of the environment variable `name`
+pub fn var(name: &str) -> Option<&'static [u8]> {
+    env().find_map(|entry| {
+        let entry = entry.to_bytes();
+        match entry.strip_prefix(name.as_bytes()) {
+            Some([b'=', value @ ..]) => Some(value),
+            _ => None,
+        }
+    })
+}
@@ -0,0 +1,93 @@
+//! Static constructors from the executable's `__DATA,__mod_init_func` section
+//!
+//! NeXT `ld` has no `section$start` symbols, so the section is located by
+//! walking the load commands of the in-memory Mach-O header that the
+//! linker places at the start of `__TEXT` as `_mh_execute_header`.
+
+// Mach-O constants (native big-endian layout in memory)
+const MACH_HEADER_SIZE: usize = 28;
+const LC_SEGMENT: u32 = 0x1;
+const SEGMENT_COMMAND_SIZE: usize = 56;
+const SECTION_SIZE: usize = 68;
+
+/// Section holding constructor function pointers
+pub const INIT_SECTION: &str = "__mod_init_func";
+
+/// Signature of a static constructor
+pub type InitFunc = extern "C" fn();
+
+extern "C" {
+    #[link_name = "_mh_execute_header"]
+    static MH_EXECUTE_HEADER: u8;
+}
+
+/// Register a function to run before `main`
+///
+/// ```ignore
+/// extern "C" fn setup() { /* ... */ }
+/// nextstep_rt::init_func!(setup);
+/// ```
+#[macro_export]
+macro_rules! init_func {
+    ($f:path) => {
+        const _: () = {
+            #[used]
+            #[link_section = "__DATA,__mod_init_func"]
+            static INIT: $crate::InitFunc = $f;
+        };
+    };
+}
+
+#[inline]
+unsafe fn read_u32(p: *const u8) -> u32 {
+    core::ptr::read_unaligned(p as *const u32)
+}
+
+/// Compare a fixed 16-byte Mach-O name field with `name`
+fn name_eq(field: &[u8], name: &str) -> bool {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    &field[..end] == name.as_bytes()
+}
+
+/// Address and size of `__DATA,<sectname>` in the running image
+pub(crate) unsafe fn find_data_section(sectname: &str) -> Option<(usize, usize)> {
+    let header = core::ptr::addr_of!(MH_EXECUTE_HEADER);
+    let ncmds = read_u32(header.add(16));
+    let mut cmd = header.add(MACH_HEADER_SIZE);
+
+    for _ in 0..ncmds {
+        let kind = read_u32(cmd);
+        let size = read_u32(cmd.add(4)) as usize;
+        if kind == LC_SEGMENT {
+            let segname = core::slice::from_raw_parts(cmd.add(8), 16);
+            if name_eq(segname, "__DATA") {
+                let nsects = read_u32(cmd.add(48));
+                let mut sect = cmd.add(SEGMENT_COMMAND_SIZE);
+                for _ in 0..nsects {
+                    let name = core::slice::from_raw_parts(sect, 16);
+                    if name_eq(name, sectname) {
+                        let addr = read_u32(sect.add(32)) as usize;
+                        let size = read_u32(sect.add(36)) as usize;
+                        return Some((addr, size));
+                    }
+                    sect = sect.add(SECTION_SIZE);
+                }
+            }
+        }
+        if size == 0 {
+            break;
+        }
+        cmd = cmd.add(size);
+    }
+    None
+}
+
+/// Run the function pointers stored in `__DATA,<sectname>`, in order
+pub(crate) unsafe fn run_section(sectname: &str) {
+    if let Some((addr, size)) = find_data_section(sectname) {
+        let funcs = core::slice::from_raw_parts(addr as *const InitFunc, size / core::mem::size_of::<InitFunc>());
+        for f in funcs {
+            f();
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! nextstep-rt - Process runtime for static NeXTSTEP binaries
+//!
+//! Replaces the platform crt0 that `-nostdlib -static` leaves out: `_start`
+//! picks up argc, argv and envp from the initial stack, runs static
+//! constructors, calls the Rust entry function and shuts down through
+//! [`exit`], which runs the registered exit handlers in LIFO order.
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! extern crate nextstep_alloc;
+//! extern crate nextstep_panic;
+//!
+//! #[nextstep_rt::main]
+//! fn main() -> Result<(), nextstep_io::IoError> {
+//!     for arg in nextstep_rt::args() {
+//!         nextstep_io::println!("{:?}", arg);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+#![no_std]
+#![cfg_attr(all(feature = "start", target_arch = "m68k"), feature(asm_experimental_arch))]
+
+use core::fmt::{self, Write as _};
+
+mod args;
+mod init;
+#[cfg(doctest)]
+mod macro_tests;
+
+pub use args::{argc, args, env, var, CStrVec};
+pub use init::{InitFunc, INIT_SECTION};
+pub use nextstep_rt_macros::main;
+pub use nextstep_sys::{sys_atexit as atexit, ATEXIT_MAX};
+
+/// Return types allowed for the `#[nextstep_rt::main]` function
+pub trait Termination {
+    /// Convert the value into a process exit status
+    fn report(self) -> i32;
+}
+
+impl Termination for () {
+    fn report(self) -> i32 {
+        0
+    }
+}
+
+impl Termination for i32 {
+    fn report(self) -> i32 {
+        self
+    }
+}
+
+impl<T: Termination, E: fmt::Debug> Termination for Result<T, E> {
+    fn report(self) -> i32 {
+        match self {
+            Ok(value) => value.report(),
+            Err(err) => {
+                let _ = writeln!(nextstep_io::stderr(), "Error: {:?}", err);
+                1
+            }
+        }
+    }
+}
+
+/// Terminate the process in an orderly way
+///
+/// Runs the handlers registered with [`atexit`] (most recent first, which
+/// includes nextstep-io's stdout flush), then exits through the C
+/// library's `exit()` so C atexit handlers and stdio run as well. This is
+/// also the path taken when `main` returns.
+pub fn exit(code: i32) -> ! {
+    nextstep_sys::sys_run_atexit();
+    unsafe { nextstep_sys::exit(code) }
+}
+
+extern "Rust" {
+    // Generated by #[nextstep_rt::main]
+    fn __nextstep_rt_main() -> i32;
+}
+
+/// Common startup: record the process vectors, run constructors, call main
+unsafe fn run(argc: usize, argv: *const *const u8, envp: *const *const u8) -> ! {
+    args::init(argc, argv, envp);
+
+    // Lets panic and crash reports symbolize their backtraces
+    if let Some(program) = args().next() {
+        nextstep_backtrace::set_executable_path(program.to_bytes_with_nul());
+    }
+
+    init::run_section(INIT_SECTION);
+
+    exit(__nextstep_rt_main())
+}
+
+/// Rust half of `_start`, called with the initial stack pointer
+///
+/// The kernel leaves `argc`, then the NULL-terminated `argv` and `envp`
+/// vectors, at the top of the stack. Without crt0 the C library globals
+/// are set here as well.
+#[cfg(feature = "start")]
+#[no_mangle]
+unsafe extern "C" fn nextstep_rt_start(sp: *const usize) -> ! {
+    let argc = *sp;
+    let argv = sp.add(1) as *const *const u8;
+    let envp = argv.add(argc + 1);
+
+    nextstep_sys::environ = envp;
+    nextstep_sys::NXArgc = argc as i32;
+    nextstep_sys::NXArgv = argv;
+
+    run(argc, argv, envp)
+}
+
+/// C `main` for builds that link the platform crt0 instead of `_start`
+#[cfg(not(feature = "start"))]
+#[no_mangle]
+unsafe extern "C" fn main(argc: i32, argv: *const *const u8, envp: *const *const u8) -> i32 {
+    run(argc as usize, argv, envp)
+}
+
+// Process entry point. a6 is cleared so backtraces stop at this frame.
+#[cfg(all(feature = "start", target_arch = "m68k"))]
+core::arch::global_asm!(
+    ".text",
+    ".globl _start",
+    "_start:",
+    "    movea.l #0, %a6",
+    "    move.l %sp, -(%sp)",
+    "    jsr nextstep_rt_start",
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Failed;
+
+    #[test]
+    fn test_termination_exit_codes() {
+        assert_eq!(().report(), 0);
+        assert_eq!(42.report(), 42);
+        assert_eq!(Ok::<(), Failed>(()).report(), 0);
+        assert_eq!(Ok::<i32, Failed>(3).report(), 3);
+        assert_eq!(Err::<(), Failed>(Failed).report(), 1);
+        assert_eq!(Ok::<Result<(), Failed>, Failed>(Err(Failed)).report(), 1);
+    }
+}
@@ -0,0 +1,66 @@
+//! Expansion tests for `#[nextstep_rt::main]`, run as doctests so each
+//! case is compiled on its own
+//!
+//! The generated `__nextstep_rt_main` returns the exit status `_start`
+//! hands to [`crate::exit`]:
+//!
+//! ```
+//! #[nextstep_rt::main]
+//! fn start() {}
+//!
+//! assert_eq!(__nextstep_rt_main(), 0);
+//! ```
+//!
+//! ```
+//! #[nextstep_rt::main]
+//! fn start() -> i32 {
+//!     7
+//! }
+//!
+//! assert_eq!(__nextstep_rt_main(), 7);
+//! ```
+//!
+//! ```
+//! #[derive(Debug)]
+//! struct Failed;
+//!
+//! #[nextstep_rt::main]
+//! fn start() -> Result<(), Failed> {
+//!     Err(Failed)
+//! }
+//!
+//! assert_eq!(__nextstep_rt_main(), 1);
+//! ```
+//!
+//! Signatures `_start` cannot call are rejected with `compile_error!`:
+//!
+//! ```compile_fail
+//! #[nextstep_rt::main]
+//! fn start(argc: i32) -> i32 {
+//!     argc
+//! }
+//! ```
+//!
+//! ```compile_fail
+//! #[nextstep_rt::main]
+//! fn start<T: Default>() {}
+//! ```
+//!
+//! ```compile_fail
+//! #[nextstep_rt::main(stack = 4096)]
+//! fn start() {}
+//! ```
+//!
+//! ```compile_fail
+//! #[nextstep_rt::main]
+//! static START: i32 = 0;
+//! ```
+//!
+//! A return type without a [`crate::Termination`] impl fails to type-check:
+//!
+//! ```compile_fail
+//! #[nextstep_rt::main]
+//! fn start() -> &'static str {
+//!     "done"
+//! }
+//! ```
@@ -0,0 +1,198 @@
+//! nextstep-runner - Run an m68k NeXTSTEP executable in the Previous emulator
+//!
+//! Usage: nextstep-runner [OPTIONS] EXECUTABLE [ARGS...]
+//!        nextstep-runner --print-hook
+//!
+//!   --mode previous|stub|user  Boot Previous, only check the job and
+//!                          answer for the guest, or run the program in
+//!                          nextstep-emu (NEXTSTEP_RUNNER_MODE, or previous)
+//!   --inject disk|shared   Hand the job over on a transfer disk or in a
+//!                          shared directory (NEXTSTEP_INJECT, or disk)
+//!   --shared-dir DIR       Directory the guest mounts at /nextrust
+//!                          (NEXTSTEP_SHARED_DIR)
+//!   --timeout SECS         Give up after SECS (EMULATOR_TIMEOUT, or 120)
+//!   --cpu generic|68030|68040  Machine to emulate (NEXTSTEP_CPU, or a 68040)
+//!   --stub-status N        Exit status the stub reports (0)
+//!   --keep                 Keep the work directory, with the emulator's log
+//!                          (it is kept anyway, less the boot disk, on failure)
+//!   --print-hook           Print the guest's /etc/rc.nextrust
+//!
+//! Previous comes from PREVIOUS_BINARY (or `previous` on PATH), the ROM
+//! from NEXTSTEP_ROM (or ~/NextStep/ROM/Rev_2.5_v66.BIN) and the boot disk
+//! from NEXTSTEP_DISK (or ci/emulation/disk-templates/nextstep-scsi-template.img
+//! above the current directory). The boot disk is copied, never changed.
+//!
+//! Everything after EXECUTABLE goes to the program, so this works as
+//! cargo's `runner`. The program's output is written to standard output
+//! and its exit status is the runner's; 124 means it timed out, 125 that
+//! it could not be run.
+
+use cargo_nextstep::target::Cpu;
+use nextstep_runner::previous::{Emulator, DEFAULT_MEMORY};
+use nextstep_runner::runner::DEFAULT_TIMEOUT;
+use nextstep_runner::{job, Finish, Inject, Job, Mode, Runner};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+const USAGE: &str = "usage: nextstep-runner [--mode previous|stub|user] [--inject disk|shared] [--shared-dir DIR]
+                       [--timeout SECS] [--cpu generic|68030|68040] [--stub-status N] [--keep]
+                       EXECUTABLE [ARGS...]
+       nextstep-runner --print-hook";
+
+const TIMED_OUT: u8 = 124;
+const RUNNER_FAILURE: u8 = 125;
+
+const DISK_TEMPLATE: &str = "ci/emulation/disk-templates/nextstep-scsi-template.img";
+const DEFAULT_ROM: &str = "NextStep/ROM/Rev_2.5_v66.BIN";
+
+struct Args {
+    mode: String,
+    inject: String,
+    shared_dir: Option<PathBuf>,
+    timeout: u64,
+    cpu: Cpu,
+    stub_status: i32,
+    keep: bool,
+    print_hook: bool,
+    executable: PathBuf,
+    args: Vec<String>,
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+fn cpu(name: &str) -> Result<Cpu, String> {
+    Cpu::from_name(name).ok_or_else(|| format!("unknown CPU {}\n{}", name, USAGE))
+}
+
+fn number<T: std::str::FromStr>(what: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("bad {} {:?}\n{}", what, value, USAGE))
+}
+
+fn value(args: &mut impl Iterator<Item = String>) -> Result<String, String> {
+    args.next().ok_or_else(|| USAGE.to_string())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        mode: env("NEXTSTEP_RUNNER_MODE").unwrap_or_else(|| "previous".into()),
+        inject: env("NEXTSTEP_INJECT").unwrap_or_else(|| "disk".into()),
+        shared_dir: env("NEXTSTEP_SHARED_DIR").map(PathBuf::from),
+        timeout: env("EMULATOR_TIMEOUT").map_or(Ok(DEFAULT_TIMEOUT), |v| number("EMULATOR_TIMEOUT", &v))?,
+        cpu: env("NEXTSTEP_CPU").map_or(Ok(Cpu::Generic), |v| cpu(&v))?,
+        stub_status: 0,
+        keep: false,
+        print_hook: false,
+        executable: PathBuf::new(),
+        args: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--mode" => parsed.mode = value(&mut args)?,
+            "--inject" => parsed.inject = value(&mut args)?,
+            "--shared-dir" => parsed.shared_dir = Some(value(&mut args)?.into()),
+            "--timeout" => parsed.timeout = number("timeout", &value(&mut args)?)?,
+            "--cpu" => parsed.cpu = cpu(&value(&mut args)?)?,
+            "--stub-status" => parsed.stub_status = number("status", &value(&mut args)?)?,
+            "--keep" => parsed.keep = true,
+            "--print-hook" => parsed.print_hook = true,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ => {
+                parsed.executable = arg.into();
+                parsed.args = args.collect();
+                return Ok(parsed);
+            }
+        }
+    }
+    if parsed.print_hook {
+        return Ok(parsed);
+    }
+    Err(USAGE.to_string())
+}
+
+// The disk template in the first directory above `start` that has one
+fn find_disk_template(start: &Path) -> Option<PathBuf> {
+    start.ancestors().map(|dir| dir.join(DISK_TEMPLATE)).find(|path| path.exists())
+}
+
+fn runner(args: &Args, work_dir: PathBuf) -> Result<Runner, String> {
+    let inject = match args.inject.as_str() {
+        "disk" => Inject::Disk,
+        "shared" => Inject::Shared(
+            args.shared_dir.clone().ok_or("--inject shared needs --shared-dir or NEXTSTEP_SHARED_DIR")?,
+        ),
+        other => return Err(format!("unknown injection {}\n{}", other, USAGE)),
+    };
+    let mode = match args.mode.as_str() {
+        "stub" => Mode::Stub { status: args.stub_status },
+        "user" => Mode::User,
+        "previous" => {
+            let rom = env("NEXTSTEP_ROM").map(PathBuf::from).or_else(|| {
+                env("HOME").map(|home| Path::new(&home).join(DEFAULT_ROM))
+            });
+            let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+            let boot_disk = env("NEXTSTEP_DISK").map(PathBuf::from).or_else(|| find_disk_template(&cwd));
+            Mode::Previous(Emulator {
+                binary: env("PREVIOUS_BINARY").unwrap_or_else(|| "previous".into()).into(),
+                rom: rom.ok_or("no ROM: set NEXTSTEP_ROM")?,
+                boot_disk: boot_disk.ok_or_else(|| format!("no boot disk: set NEXTSTEP_DISK or add {}", DISK_TEMPLATE))?,
+                cpu: args.cpu,
+                memory: DEFAULT_MEMORY,
+            })
+        }
+        other => return Err(format!("unknown mode {}\n{}", other, USAGE)),
+    };
+    Ok(Runner { mode, inject, timeout: Duration::from_secs(args.timeout), work_dir })
+}
+
+fn run(args: &Args, work_dir: &Path) -> Result<u8, String> {
+    let runner = runner(args, work_dir.to_path_buf())?;
+    let job = Job::load(&args.executable, &args.args).map_err(|e| e.to_string())?;
+    let finish = runner.run(&job).map_err(|e| e.to_string())?;
+    let Finish::Exited(outcome) = finish else {
+        eprintln!("nextstep-runner: {}: timed out after {} s", args.executable.display(), args.timeout);
+        return Ok(TIMED_OUT);
+    };
+    let mut stdout = std::io::stdout();
+    stdout.write_all(&outcome.output).and_then(|()| stdout.flush()).map_err(|e| e.to_string())?;
+    // The guest shell reports 0-255, or 128 + signal
+    Ok(u8::try_from(outcome.status).unwrap_or(RUNNER_FAILURE))
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::from(RUNNER_FAILURE);
+        }
+    };
+    if args.print_hook {
+        print!("{}", job::HOOK);
+        return ExitCode::SUCCESS;
+    }
+    let work_dir = std::env::temp_dir().join(format!("nextstep-runner-{}", std::process::id()));
+    let (code, failed) = match run(&args, &work_dir) {
+        Ok(TIMED_OUT) => (TIMED_OUT, true),
+        Ok(code) => (code, false),
+        Err(message) => {
+            eprintln!("nextstep-runner: {}", message);
+            (RUNNER_FAILURE, true)
+        }
+    };
+    if args.keep || failed && work_dir.exists() {
+        // Without the copy of the boot disk, which is large and unchanged
+        // but for the boot
+        if !args.keep {
+            let _ = std::fs::remove_file(work_dir.join("boot.img"));
+        }
+        eprintln!("nextstep-runner: work directory kept in {}", work_dir.display());
+    } else {
+        let _ = std::fs::remove_dir_all(&work_dir);
+    }
+    ExitCode::from(code)
+}
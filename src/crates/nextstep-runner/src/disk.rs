@@ -0,0 +1,176 @@
+//! Transfer disks
+//!
+//! The runner hands a job to the guest on a raw disk image attached as
+//! SCSI target 1; the guest's hook reads it with `dd`, so no file system
+//! is involved:
+//!
+//! - sector 0: `NEXTRUST-JOB 1 <tar sectors> <output sector>`
+//! - sector 1: the job, a `gnutar` archive of `program` and `run`
+//! - output sector: left zero; the guest writes
+//!   `NEXTRUST-EXIT <status> <length>` there once the job is done, with
+//!   the output in the sectors after it
+//!
+//! The header of the result is written last, so once it is there the
+//! output is complete.
+
+use crate::error::{Error, Result};
+use crate::job::{Job, Outcome};
+use cargo_nextstep::app::{Entry, EXEC_MODE};
+use cargo_nextstep::tar::{tar, BLOCK_SIZE};
+
+pub const SECTOR_SIZE: usize = 512;
+/// Room for the job's output
+pub const OUTPUT_SECTORS: usize = 2048;
+
+const JOB_MAGIC: &str = "NEXTRUST-JOB";
+const EXIT_MAGIC: &str = "NEXTRUST-EXIT";
+
+/// Where a transfer disk keeps the job and its result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// Sectors of the job archive, from sector 1
+    pub job_sectors: usize,
+    /// First sector of the result
+    pub output_sector: usize,
+}
+
+impl Layout {
+    /// Byte offset of the result
+    pub fn output_offset(&self) -> usize {
+        self.output_sector * SECTOR_SIZE
+    }
+
+    /// Size of the whole image
+    pub fn image_size(&self) -> usize {
+        (self.output_sector + 1 + OUTPUT_SECTORS) * SECTOR_SIZE
+    }
+}
+
+// First line of a sector, without the padding
+fn first_line(sector: &[u8]) -> String {
+    let end = sector.iter().position(|&b| b == b'\n' || b == 0).unwrap_or(sector.len());
+    String::from_utf8_lossy(&sector[..end]).into_owned()
+}
+
+/// A transfer disk image holding `job`
+pub fn write_disk(job: &Job) -> Result<(Vec<u8>, Layout)> {
+    let entries = [
+        Entry::file("program", EXEC_MODE, job.program.clone()),
+        Entry::file("run", EXEC_MODE, job.script().into_bytes()),
+    ];
+    let archive = tar(&entries, 0).map_err(|e| Error::BadTransfer(e.to_string()))?;
+    let job_sectors = archive.len() / BLOCK_SIZE;
+    let layout = Layout { job_sectors, output_sector: 1 + job_sectors };
+
+    let mut image = format!("{} 1 {} {}\n", JOB_MAGIC, layout.job_sectors, layout.output_sector).into_bytes();
+    image.resize(SECTOR_SIZE, 0);
+    image.extend_from_slice(&archive);
+    image.resize(layout.image_size(), 0);
+    Ok((image, layout))
+}
+
+/// The layout a transfer disk's header gives
+pub fn read_layout(image: &[u8]) -> Result<Layout> {
+    let header = first_line(image.get(..SECTOR_SIZE).ok_or_else(|| Error::BadTransfer("no header sector".into()))?);
+    let words: Vec<&str> = header.split_whitespace().collect();
+    match words.as_slice() {
+        [JOB_MAGIC, "1", sectors, output] => match (sectors.parse(), output.parse()) {
+            (Ok(job_sectors), Ok(output_sector)) => Ok(Layout { job_sectors, output_sector }),
+            _ => Err(Error::BadTransfer(format!("header {:?}", header))),
+        },
+        _ => Err(Error::BadTransfer(format!("header {:?}", header))),
+    }
+}
+
+/// The result in `image`, if the guest has written it
+pub fn read_outcome(image: &[u8], layout: &Layout) -> Result<Option<Outcome>> {
+    let at = layout.output_offset();
+    let Some(sector) = image.get(at..at + SECTOR_SIZE) else {
+        return Ok(None);
+    };
+    let header = first_line(sector);
+    let words: Vec<&str> = header.split_whitespace().collect();
+    let (status, length) = match words.as_slice() {
+        [] => return Ok(None),
+        [EXIT_MAGIC, status, length] => match (status.parse(), length.parse::<usize>()) {
+            (Ok(status), Ok(length)) => (status, length),
+            _ => return Err(Error::BadTransfer(format!("result {:?}", header))),
+        },
+        _ => return Err(Error::BadTransfer(format!("result {:?}", header))),
+    };
+    let start = at + SECTOR_SIZE;
+    let output = image.get(start..start + length).ok_or_else(|| Error::BadTransfer("output runs past the disk".into()))?;
+    Ok(Some(Outcome { status, output: output.to_vec() }))
+}
+
+/// The files of the job archive in `image`: name and contents
+pub fn read_job(image: &[u8], layout: &Layout) -> Result<Vec<(String, Vec<u8>)>> {
+    let archive = image
+        .get(SECTOR_SIZE..SECTOR_SIZE * (1 + layout.job_sectors))
+        .ok_or_else(|| Error::BadTransfer("job runs past the disk".into()))?;
+    let mut files = Vec::new();
+    let mut at = 0;
+    while let Some(header) = archive.get(at..at + BLOCK_SIZE) {
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let name = first_line(&header[..100]);
+        let size_field = first_line(&header[124..136]);
+        let size = usize::from_str_radix(size_field.trim(), 8)
+            .map_err(|_| Error::BadTransfer(format!("tar size {:?}", size_field)))?;
+        let data = archive
+            .get(at + BLOCK_SIZE..at + BLOCK_SIZE + size)
+            .ok_or_else(|| Error::BadTransfer(format!("{} runs past the archive", name)))?;
+        if header[156] == b'0' {
+            files.push((name.trim_start_matches("./").to_string(), data.to_vec()));
+        }
+        at += BLOCK_SIZE + size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+    }
+    Ok(files)
+}
+
+/// Write a result into `image`, as the guest's hook does
+pub fn write_outcome(image: &mut [u8], layout: &Layout, outcome: &Outcome) -> Result<()> {
+    let at = layout.output_offset();
+    let room = OUTPUT_SECTORS * SECTOR_SIZE;
+    let output = &outcome.output[..outcome.output.len().min(room)];
+    let end = at + SECTOR_SIZE + output.len();
+    if image.len() < end {
+        return Err(Error::BadTransfer("disk too small for the output".into()));
+    }
+    image[at + SECTOR_SIZE..end].copy_from_slice(output);
+    let header = format!("{} {} {}\n", EXIT_MAGIC, outcome.status, output.len());
+    image[at..at + SECTOR_SIZE].fill(0);
+    image[at..at + header.len()].copy_from_slice(header.as_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::tests::executable;
+
+    #[test]
+    fn test_round_trip() {
+        let job = Job { program: executable(), args: vec!["x".into()] };
+        let (mut image, layout) = write_disk(&job).unwrap();
+        assert_eq!(read_layout(&image).unwrap(), layout);
+        assert!(image.starts_with(format!("NEXTRUST-JOB 1 {} {}\n", layout.job_sectors, layout.output_sector).as_bytes()));
+        assert_eq!(image.len(), layout.image_size());
+
+        let files = read_job(&image, &layout).unwrap();
+        let names: Vec<&str> = files.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["program", "run"]);
+        assert_eq!(files[0].1, job.program);
+        assert_eq!(files[1].1, job.script().into_bytes());
+
+        assert_eq!(read_outcome(&image, &layout).unwrap(), None);
+        let outcome = Outcome { status: 3, output: b"hello\n".to_vec() };
+        write_outcome(&mut image, &layout, &outcome).unwrap();
+        assert_eq!(read_outcome(&image, &layout).unwrap(), Some(outcome));
+
+        image[layout.output_offset()] = b'?';
+        assert!(read_outcome(&image, &layout).is_err());
+        assert!(read_layout(&[0; 512]).is_err());
+    }
+}
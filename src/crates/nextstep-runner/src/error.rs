@@ -0,0 +1,38 @@
+//! Runner errors
+
+use std::fmt;
+
+/// Why a program could not be run
+#[derive(Debug)]
+pub enum Error {
+    /// A file could not be read or written
+    Io { path: String, error: std::io::Error },
+    /// The program is not an m68k NeXTSTEP executable
+    BadExecutable { path: String, reason: String },
+    /// The emulator, ROM or disk images are missing or unusable
+    Setup(String),
+    /// A transfer disk or shared directory holds something unexpected
+    BadTransfer(String),
+}
+
+impl Error {
+    pub(crate) fn io(path: impl AsRef<std::path::Path>, error: std::io::Error) -> Error {
+        Error::Io { path: path.as_ref().display().to_string(), error }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, error } => write!(f, "{}: {}", path, error),
+            Error::BadExecutable { path, reason } => write!(f, "{}: not an m68k NeXTSTEP executable: {}", path, reason),
+            Error::Setup(reason) => f.write_str(reason),
+            Error::BadTransfer(reason) => write!(f, "bad transfer data: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Result of running a program
+pub type Result<T> = core::result::Result<T, Error>;
@@ -0,0 +1,45 @@
+#!/bin/sh
+# rc.nextrust - run a job left by nextstep-runner, then halt
+#
+# Install in the boot image as /etc/rc.nextrust and run it at the end of
+# /etc/rc.local:
+#
+#	if [ -f /etc/rc.nextrust ]; then sh /etc/rc.nextrust; fi
+#
+# A job is a directory holding `program` and a `run` script that leaves
+# `output` and `status` next to them. It comes from the shared directory
+# mounted at /nextrust, or from the raw transfer disk on SCSI target 1:
+# sector 0 reads "NEXTRUST-JOB 1 <tar sectors> <output sector>", the job
+# follows as a tar archive, and the result goes back at the output
+# sector as "NEXTRUST-EXIT <status> <length>" with the output after it.
+#
+# Written for NeXTSTEP's Bourne shell: no $(...) and no $((...)).
+
+SHARE=/nextrust/job
+DISK=/dev/rsd1h
+WORK=/tmp/nextrust-job
+
+if [ -f $SHARE/run ]; then
+	sh $SHARE/run
+	/usr/etc/halt -q
+	exit 0
+fi
+
+header=`dd if=$DISK bs=512 count=1 2>/dev/null | head -1`
+set -- $header
+if [ "$1" != "NEXTRUST-JOB" ]; then
+	exit 0
+fi
+sectors=$3
+output=$4
+
+rm -rf $WORK
+mkdir -p $WORK
+dd if=$DISK bs=512 skip=1 count=$sectors 2>/dev/null | (cd $WORK; gnutar xf -)
+sh $WORK/run
+
+# Output first, then the header that says it is complete
+length=`wc -c < $WORK/output`
+dd if=$WORK/output of=$DISK bs=512 seek=`expr $output + 1` conv=sync 2>/dev/null
+echo "NEXTRUST-EXIT `cat $WORK/status` $length" | dd of=$DISK bs=512 seek=$output conv=sync 2>/dev/null
+/usr/etc/halt -q
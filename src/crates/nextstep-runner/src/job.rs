@@ -0,0 +1,98 @@
+//! Jobs: a program, its arguments and how it ends
+//!
+//! A job is what the guest runs: the executable, saved as `program`, and
+//! a `run` script that calls it with the arguments and leaves its output
+//! and exit status in `output` and `status` beside it. [`HOOK`] is the
+//! boot-time script in the guest image that finds a job, runs it and
+//! halts the machine.
+
+use crate::error::{Error, Result};
+use std::path::Path;
+
+/// `/etc/rc.nextrust` for the guest image
+pub const HOOK: &str = include_str!("hook.sh");
+
+/// What to run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    pub program: Vec<u8>,
+    pub args: Vec<String>,
+}
+
+/// How a job ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    /// The program's exit status, as the guest shell reports it
+    pub status: i32,
+    /// Its standard output and error
+    pub output: Vec<u8>,
+}
+
+/// Check `data` is something the guest can run: an m68k `MH_EXECUTE`,
+/// thin or with an m68k slice
+pub fn check_executable(path: &Path, data: &[u8]) -> Result<()> {
+    cargo_nextstep::app::check_executable(path, data).map_err(|e| match e {
+        cargo_nextstep::Error::NotExecutable { path, reason } => Error::BadExecutable { path, reason },
+        other => Error::BadExecutable { path: path.display().to_string(), reason: other.to_string() },
+    })
+}
+
+// `arg` quoted for the Bourne shell
+fn quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+impl Job {
+    /// Read and check the executable at `path`
+    pub fn load(path: &Path, args: &[String]) -> Result<Job> {
+        let program = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+        check_executable(path, &program)?;
+        Ok(Job { program, args: args.to_vec() })
+    }
+
+    /// The `run` script
+    pub fn script(&self) -> String {
+        let mut command = String::from("./program");
+        for arg in &self.args {
+            command.push(' ');
+            command.push_str(&quote(arg));
+        }
+        format!(
+            "#!/bin/sh\n# Job written by nextstep-runner\ncd `dirname $0`\nrm -f output status\n\
+             {} < /dev/null > output 2>&1\necho $? > status\n",
+            command
+        )
+    }
+}
+
+/// The status a guest wrote, e.g. `"0\n"`
+pub fn parse_status(text: &str) -> Result<i32> {
+    text.trim().parse().map_err(|_| Error::BadTransfer(format!("exit status {:?}", text.trim())))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use nextstep_macho::consts::{CPU_SUBTYPE_MC68040, CPU_TYPE_MC680X0, MH_EXECUTE, MH_MAGIC, MH_OBJECT};
+
+    /// Header of an m68k executable with no load commands
+    pub(crate) fn executable() -> Vec<u8> {
+        let words = [MH_MAGIC, CPU_TYPE_MC680X0 as u32, CPU_SUBTYPE_MC68040 as u32, MH_EXECUTE, 0, 0, 0];
+        words.iter().flat_map(|w| w.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn test_job() {
+        let path = Path::new("hello");
+        assert!(check_executable(path, &executable()).is_ok());
+        let mut object = executable();
+        object[12..16].copy_from_slice(&MH_OBJECT.to_be_bytes());
+        assert!(matches!(check_executable(path, &object), Err(Error::BadExecutable { .. })));
+
+        let job = Job { program: executable(), args: vec!["-v".into(), "it's".into()] };
+        assert!(job.script().contains("\n./program '-v' 'it'\\''s' < /dev/null > output 2>&1\necho $? > status\n"));
+        assert_eq!(parse_status("3\n").unwrap(), 3);
+        assert!(parse_status("").is_err());
+        assert!(HOOK.contains("NEXTRUST-EXIT"));
+    }
+}
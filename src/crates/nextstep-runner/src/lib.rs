@@ -0,0 +1,32 @@
+//! nextstep-runner - Run m68k NeXTSTEP executables in the Previous emulator
+//!
+//! `cargo test` and `cargo run` for `m68k-next-nextstep` need something
+//! that can execute the target's binaries. This crate, and its
+//! `nextstep-runner` binary, do it as a cargo `runner`: the executable is
+//! checked, packed into a job ([`job`]) and handed to a NeXTSTEP guest on
+//! a raw transfer disk ([`disk`]) or in a shared directory ([`shared`]).
+//! Previous boots headless ([`previous`]), a hook in the boot image runs
+//! the job and leaves its output and exit status for the runner to pick
+//! up, and the runner ([`runner`]) stops the emulator and exits the way
+//! the program did.
+//!
+//! ```toml
+//! [target.m68k-next-nextstep]
+//! runner = "nextstep-runner"
+//! ```
+//!
+//! A stub mode stands in for the emulator, so the runner can be tested on
+//! machines without a NeXT ROM, and a user mode runs the program in
+//! `nextstep-emu` instead of booting NeXTSTEP. See `ci/emulation/disk-templates/README.md`
+//! for preparing the boot disk.
+
+pub mod disk;
+mod error;
+pub mod job;
+pub mod previous;
+pub mod runner;
+pub mod shared;
+
+pub use error::{Error, Result};
+pub use job::{Job, Outcome};
+pub use runner::{Finish, Inject, Mode, Runner};
@@ -0,0 +1,163 @@
+//! The Previous emulator
+//!
+//! Previous is started headless (SDL's dummy video and audio drivers)
+//! with a configuration written for each run: the ROM, a private copy of
+//! the boot disk on SCSI target 0 and either the transfer disk on target
+//! 1 or an NFS export of the shared directory. Its own output goes to a
+//! log in the work directory; the guest never halts the emulator itself,
+//! so the runner stops it once the job's result is in.
+
+use crate::error::{Error, Result};
+use cargo_nextstep::target::Cpu;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+/// Default memory size, in megabytes
+pub const DEFAULT_MEMORY: u32 = 32;
+
+// Values of Previous's configuration enums
+const MACHINE_CUBE030: u32 = 0;
+const MACHINE_STATION: u32 = 2;
+const BOOT_SCSI: u32 = 1;
+const DEVICE_NONE: u32 = 0;
+const DEVICE_HARDDISK: u32 = 1;
+
+/// Where the job reaches the guest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Attach<'a> {
+    /// A transfer disk on SCSI target 1
+    Disk(&'a Path),
+    /// A directory exported over NFS
+    Shared(&'a Path),
+}
+
+/// How to start Previous
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Emulator {
+    /// The `previous` binary
+    pub binary: PathBuf,
+    pub rom: PathBuf,
+    /// NeXTSTEP boot disk with the runner's hook installed
+    pub boot_disk: PathBuf,
+    pub cpu: Cpu,
+    /// Memory, in megabytes
+    pub memory: u32,
+}
+
+// Memory banks for `megabytes`: Previous has four, of up to 16 MB on a
+// NeXTstation
+fn banks(megabytes: u32) -> [u32; 4] {
+    let mut banks = [0; 4];
+    let mut left = megabytes;
+    for bank in &mut banks {
+        *bank = left.min(16);
+        left -= *bank;
+    }
+    banks
+}
+
+impl Emulator {
+    /// Check the ROM and boot disk are there and not placeholders
+    pub fn check(&self) -> Result<()> {
+        for (what, path) in [("ROM", &self.rom), ("boot disk", &self.boot_disk)] {
+            match std::fs::metadata(path) {
+                Ok(metadata) if metadata.len() > 0 => {}
+                Ok(_) => return Err(Error::Setup(format!("{} {} is an empty placeholder", what, path.display()))),
+                Err(e) => return Err(Error::Setup(format!("{} {}: {}", what, path.display(), e))),
+            }
+        }
+        Ok(())
+    }
+
+    /// The configuration file for a run booting `boot_disk`
+    pub fn config(&self, boot_disk: &Path, attach: &Attach<'_>) -> String {
+        let (machine, level) = match self.cpu {
+            Cpu::M68030 => (MACHINE_CUBE030, 3),
+            Cpu::Generic | Cpu::M68040 => (MACHINE_STATION, 4),
+        };
+        let rom = self.rom.display();
+        let mut out = String::from("# Written by nextstep-runner\n");
+        out += &format!("\n[System]\nnMachineType = {}\nnCpuLevel = {}\nbColor = FALSE\nbTurbo = FALSE\n", machine, level);
+        out += "\n[Memory]\n";
+        for (index, size) in banks(self.memory).iter().enumerate() {
+            out += &format!("nMemoryBankSize{} = {}\n", index, size);
+        }
+        out += &format!("\n[ROM]\nszRom030FileName = {}\nszRom040FileName = {}\n", rom, rom);
+        out += &format!("\n[Boot]\nnBootDevice = {}\nbEnterMonitor = FALSE\n", BOOT_SCSI);
+        out += "\n[SCSI]\n";
+        let transfer = match attach {
+            Attach::Disk(path) => Some(*path),
+            Attach::Shared(_) => None,
+        };
+        for (target, image) in [Some(boot_disk), transfer].iter().enumerate() {
+            let (kind, name, inserted) = match image {
+                Some(path) => (DEVICE_HARDDISK, path.display().to_string(), "TRUE"),
+                None => (DEVICE_NONE, String::new(), "FALSE"),
+            };
+            out += &format!(
+                "szImageName{t} = {}\nnDeviceType{t} = {}\nbDiskInserted{t} = {}\nbWriteProtected{t} = FALSE\n",
+                name,
+                kind,
+                inserted,
+                t = target
+            );
+        }
+        match attach {
+            Attach::Shared(dir) => {
+                out += &format!("\n[Ethernet]\nbEthernetConnected = TRUE\nszNFSroot = {}\n", dir.display());
+            }
+            Attach::Disk(_) => out += "\n[Ethernet]\nbEthernetConnected = FALSE\n",
+        }
+        out
+    }
+
+    /// Start Previous with the configuration at `config`, logging to `log`
+    pub fn spawn(&self, config: &Path, log: &Path) -> Result<Child> {
+        let stdout = std::fs::File::create(log).map_err(|e| Error::io(log, e))?;
+        let stderr = stdout.try_clone().map_err(|e| Error::io(log, e))?;
+        Command::new(&self.binary)
+            .arg("-c")
+            .arg(config)
+            .env("SDL_VIDEODRIVER", "dummy")
+            .env("SDL_AUDIODRIVER", "dummy")
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr)
+            .spawn()
+            .map_err(|e| Error::Setup(format!("cannot start {}: {}", self.binary.display(), e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let emulator = Emulator {
+            binary: "previous".into(),
+            rom: "/roms/Rev_2.5_v66.BIN".into(),
+            boot_disk: "template.img".into(),
+            cpu: Cpu::M68030,
+            memory: 40,
+        };
+        assert_eq!(banks(40), [16, 16, 8, 0]);
+        let config = emulator.config(Path::new("/work/boot.img"), &Attach::Disk(Path::new("/work/transfer.img")));
+        for line in [
+            "nMachineType = 0\nnCpuLevel = 3\n",
+            "nMemoryBankSize2 = 8\n",
+            "szRom040FileName = /roms/Rev_2.5_v66.BIN\n",
+            "szImageName0 = /work/boot.img\nnDeviceType0 = 1\nbDiskInserted0 = TRUE\n",
+            "szImageName1 = /work/transfer.img\nnDeviceType1 = 1\n",
+            "bEthernetConnected = FALSE\n",
+        ] {
+            assert!(config.contains(line), "{}", line);
+        }
+        let config = emulator.config(Path::new("/work/boot.img"), &Attach::Shared(Path::new("/share")));
+        assert!(config.contains("szImageName1 = \nnDeviceType1 = 0\nbDiskInserted1 = FALSE\n"));
+        assert!(config.contains("szNFSroot = /share\n"));
+
+        let missing = Emulator { rom: "/nonexistent/rom.bin".into(), ..emulator };
+        assert!(matches!(missing.check(), Err(Error::Setup(_))));
+    }
+}
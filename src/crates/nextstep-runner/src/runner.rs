@@ -0,0 +1,268 @@
+//! Running a job
+//!
+//! [`Runner::run`] hands a job to the guest, through a transfer disk
+//! ([`crate::disk`]) or a shared directory ([`crate::shared`]), starts the
+//! machine and waits, up to a timeout, for the result to come back the
+//! same way. In stub mode there is no emulator: the runner plays the
+//! guest's part itself, reading the job back and answering with a fixed
+//! status, which checks everything but the NeXTSTEP side without a ROM.
+//! In user mode there is no guest either: `nextstep-emu` runs the program
+//! in-process, with its system calls done on the host.
+
+use crate::disk::{self, Layout};
+use crate::error::{Error, Result};
+use crate::job::{check_executable, Job, Outcome};
+use crate::previous::{Attach, Emulator};
+use crate::shared;
+use nextstep_emu::{Exit, Options, Process};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Default timeout, in seconds
+pub const DEFAULT_TIMEOUT: u64 = 120;
+/// How often the result is looked for
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// What plays the guest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mode {
+    Previous(Emulator),
+    /// No emulator; every job "exits" with `status`
+    Stub { status: i32 },
+    /// Run the program in `nextstep-emu`, without booting NeXTSTEP
+    User,
+}
+
+/// How the job reaches the guest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inject {
+    /// A raw transfer disk written into the work directory
+    Disk,
+    /// A directory the guest mounts at `/nextrust`
+    Shared(PathBuf),
+}
+
+/// How a run ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finish {
+    Exited(Outcome),
+    /// No result within the timeout
+    TimedOut,
+}
+
+/// Settings for running jobs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Runner {
+    pub mode: Mode,
+    pub inject: Inject,
+    pub timeout: Duration,
+    /// Scratch directory for disk copies, configuration and logs
+    pub work_dir: PathBuf,
+}
+
+// A job handed over and waiting for its result
+enum Transfer {
+    Disk { path: PathBuf, layout: Layout },
+    Shared { job_dir: PathBuf },
+}
+
+impl Transfer {
+    fn write(job: &Job, inject: &Inject, work_dir: &Path) -> Result<Transfer> {
+        match inject {
+            Inject::Disk => {
+                let (image, layout) = disk::write_disk(job)?;
+                let path = work_dir.join("transfer.img");
+                std::fs::write(&path, image).map_err(|e| Error::io(&path, e))?;
+                Ok(Transfer::Disk { path, layout })
+            }
+            Inject::Shared(dir) => Ok(Transfer::Shared { job_dir: shared::write_job(dir, job)? }),
+        }
+    }
+
+    fn attach(&self) -> Attach<'_> {
+        match self {
+            Transfer::Disk { path, .. } => Attach::Disk(path),
+            // The guest mounts the directory above the job
+            Transfer::Shared { job_dir } => Attach::Shared(job_dir.parent().unwrap_or(job_dir)),
+        }
+    }
+
+    fn outcome(&self) -> Result<Option<Outcome>> {
+        match self {
+            Transfer::Disk { path, layout } => {
+                let image = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+                disk::read_outcome(&image, layout)
+            }
+            Transfer::Shared { job_dir } => shared::read_outcome(job_dir),
+        }
+    }
+
+    // Do what the guest's hook would, without running anything
+    fn stub(&self, status: i32) -> Result<()> {
+        let files = match self {
+            Transfer::Disk { path, .. } => {
+                let image = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+                disk::read_job(&image, &disk::read_layout(&image)?)?
+            }
+            Transfer::Shared { job_dir } => shared::read_job(job_dir)?,
+        };
+        let file = |name: &str| {
+            files
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, data)| data)
+                .ok_or_else(|| Error::BadTransfer(format!("job has no {}", name)))
+        };
+        let program = file("program")?;
+        check_executable(Path::new("program"), program)?;
+        let script = String::from_utf8_lossy(file("run")?).into_owned();
+        let command = script
+            .lines()
+            .find(|line| line.starts_with("./program"))
+            .ok_or_else(|| Error::BadTransfer("run script does not call the program".into()))?;
+        let command = command.split(" < ").next().unwrap_or(command);
+        let output = format!("nextstep-runner stub: {} byte executable, would run {}\n", program.len(), command);
+        let outcome = Outcome { status, output: output.into_bytes() };
+        match self {
+            Transfer::Disk { path, layout } => {
+                let mut image = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+                disk::write_outcome(&mut image, layout, &outcome)?;
+                std::fs::write(path, image).map_err(|e| Error::io(path, e))
+            }
+            Transfer::Shared { job_dir } => shared::write_outcome(job_dir, &outcome),
+        }
+    }
+}
+
+impl Runner {
+    /// Run `job` and wait for it
+    pub fn run(&self, job: &Job) -> Result<Finish> {
+        match &self.mode {
+            Mode::Stub { status } => {
+                let transfer = self.transfer(job)?;
+                transfer.stub(*status)?;
+                Ok(transfer.outcome()?.map_or(Finish::TimedOut, Finish::Exited))
+            }
+            Mode::Previous(emulator) => self.run_previous(emulator, &self.transfer(job)?),
+            Mode::User => self.run_user(job),
+        }
+    }
+
+    fn transfer(&self, job: &Job) -> Result<Transfer> {
+        std::fs::create_dir_all(&self.work_dir).map_err(|e| Error::io(&self.work_dir, e))?;
+        Transfer::write(job, &self.inject, &self.work_dir)
+    }
+
+    fn run_user(&self, job: &Job) -> Result<Finish> {
+        let args = std::iter::once("program").chain(job.args.iter().map(String::as_str));
+        let options = Options {
+            args: args.map(|arg| arg.as_bytes().to_vec()).collect(),
+            capture: true,
+            ..Options::default()
+        };
+        let mut process = Process::load(&job.program, &options)
+            .map_err(|e| Error::BadExecutable { path: "program".into(), reason: e.to_string() })?;
+        let exit = process.run(Some(self.timeout));
+        if exit == Exit::TimedOut {
+            return Ok(Finish::TimedOut);
+        }
+        let mut output = process.output().to_vec();
+        if let Exit::Signaled { signal, reason } = &exit {
+            output.extend(format!("nextstep-emu: killed by signal {}: {}\n", signal, reason).into_bytes());
+        }
+        Ok(Finish::Exited(Outcome { status: exit.code(), output }))
+    }
+
+    fn run_previous(&self, emulator: &Emulator, transfer: &Transfer) -> Result<Finish> {
+        emulator.check()?;
+        // Previous writes to its disks; keep the template as it is
+        let boot_disk = self.work_dir.join("boot.img");
+        std::fs::copy(&emulator.boot_disk, &boot_disk).map_err(|e| Error::io(&emulator.boot_disk, e))?;
+        let config = self.work_dir.join("previous.cfg");
+        std::fs::write(&config, emulator.config(&boot_disk, &transfer.attach())).map_err(|e| Error::io(&config, e))?;
+        let log = self.log();
+        let mut child = emulator.spawn(&config, &log)?;
+
+        let start = Instant::now();
+        let finish = loop {
+            if let Some(outcome) = transfer.outcome()? {
+                break Finish::Exited(outcome);
+            }
+            if let Some(status) = child.try_wait().map_err(|e| Error::io(&emulator.binary, e))? {
+                // It may have finished the job just before stopping
+                break match transfer.outcome()? {
+                    Some(outcome) => Finish::Exited(outcome),
+                    None => {
+                        return Err(Error::Setup(format!(
+                            "{} stopped ({}) before the job finished; see {}",
+                            emulator.binary.display(),
+                            status,
+                            log.display()
+                        )))
+                    }
+                };
+            }
+            if start.elapsed() >= self.timeout {
+                break Finish::TimedOut;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        };
+        let _ = child.kill();
+        let _ = child.wait();
+        Ok(finish)
+    }
+
+    /// Where the emulator's own output goes
+    pub fn log(&self) -> PathBuf {
+        self.work_dir.join("previous.log")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::tests::executable;
+
+    #[test]
+    fn test_stub() {
+        let work_dir = std::env::temp_dir().join(format!("nextstep-runner-test-{}", std::process::id()));
+        let job = Job { program: executable(), args: vec!["a b".into()] };
+        for inject in [Inject::Disk, Inject::Shared(work_dir.join("share"))] {
+            let runner = Runner {
+                mode: Mode::Stub { status: 7 },
+                inject,
+                timeout: Duration::from_secs(1),
+                work_dir: work_dir.clone(),
+            };
+            let Finish::Exited(outcome) = runner.run(&job).unwrap() else { panic!("stub timed out") };
+            assert_eq!(outcome.status, 7);
+            let output = String::from_utf8(outcome.output).unwrap();
+            assert_eq!(output, "nextstep-runner stub: 28 byte executable, would run ./program 'a b'\n");
+        }
+        assert!(work_dir.join("share/job/run").exists());
+
+        let mut bad = job.clone();
+        bad.program[0] = 0;
+        let runner = Runner {
+            mode: Mode::Stub { status: 0 },
+            inject: Inject::Disk,
+            timeout: Duration::from_secs(1),
+            work_dir: work_dir.clone(),
+        };
+        assert!(matches!(runner.run(&bad), Err(Error::BadExecutable { .. })));
+        std::fs::remove_dir_all(&work_dir).unwrap();
+    }
+    #[test]
+    fn test_user() {
+        // A header with no LC_UNIXTHREAD has nowhere to start
+        let runner = Runner {
+            mode: Mode::User,
+            inject: Inject::Disk,
+            timeout: Duration::from_secs(1),
+            work_dir: std::env::temp_dir().join("nextstep-runner-test-user"),
+        };
+        let job = Job { program: executable(), args: Vec::new() };
+        assert!(matches!(runner.run(&job), Err(Error::BadExecutable { .. })));
+        assert!(!runner.work_dir.exists());
+    }
+}
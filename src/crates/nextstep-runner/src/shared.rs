@@ -0,0 +1,74 @@
+//! Shared directories
+//!
+//! With an emulator that exports a host directory to the guest, the job
+//! is written to `<dir>/job` and the guest's hook runs it from
+//! `/nextrust/job` in place. The job's `run` script writes `status` after
+//! `output`, so once `status` is there the job is done.
+
+use crate::error::{Error, Result};
+use crate::job::{parse_status, Job, Outcome};
+use std::path::{Path, PathBuf};
+
+/// Directory under the shared directory that holds the job
+pub const JOB_DIR: &str = "job";
+
+fn write(path: &Path, data: &[u8], mode: u32) -> Result<()> {
+    std::fs::write(path, data).map_err(|e| Error::io(path, e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).map_err(|e| Error::io(path, e))?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    Ok(())
+}
+
+/// Write `job` into the shared directory `dir`, clearing any earlier
+/// result, and return the job's directory
+pub fn write_job(dir: &Path, job: &Job) -> Result<PathBuf> {
+    let job_dir = dir.join(JOB_DIR);
+    std::fs::create_dir_all(&job_dir).map_err(|e| Error::io(&job_dir, e))?;
+    for stale in ["output", "status"] {
+        let path = job_dir.join(stale);
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(Error::io(&path, e)),
+            _ => {}
+        }
+    }
+    write(&job_dir.join("program"), &job.program, 0o755)?;
+    write(&job_dir.join("run"), job.script().as_bytes(), 0o755)?;
+    Ok(job_dir)
+}
+
+/// The result in `job_dir`, if the guest has written it
+pub fn read_outcome(job_dir: &Path) -> Result<Option<Outcome>> {
+    let status_path = job_dir.join("status");
+    let status = match std::fs::read_to_string(&status_path) {
+        Ok(status) if status.ends_with('\n') => parse_status(&status)?,
+        // Not there yet, or still being written
+        Ok(_) => return Ok(None),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::io(&status_path, e)),
+    };
+    let output_path = job_dir.join("output");
+    let output = std::fs::read(&output_path).map_err(|e| Error::io(&output_path, e))?;
+    Ok(Some(Outcome { status, output }))
+}
+
+/// Write a result into `job_dir`, as the job's `run` script does
+pub fn write_outcome(job_dir: &Path, outcome: &Outcome) -> Result<()> {
+    write(&job_dir.join("output"), &outcome.output, 0o644)?;
+    write(&job_dir.join("status"), format!("{}\n", outcome.status).as_bytes(), 0o644)
+}
+
+/// The files of the job in `job_dir`: name and contents
+pub fn read_job(job_dir: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    ["program", "run"]
+        .iter()
+        .map(|name| {
+            let path = job_dir.join(name);
+            std::fs::read(&path).map(|data| (name.to_string(), data)).map_err(|e| Error::io(&path, e))
+        })
+        .collect()
+}
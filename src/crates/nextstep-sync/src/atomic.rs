@@ -0,0 +1,72 @@
+//! 32-bit atomic word over the nextstep-atomics libcalls
+//!
+//! The base `m68k-next-nextstep` target has `max-atomic-width: 0`, so
+//! `core::sync::atomic::AtomicU32` does not exist there. The primitives
+//! call the libcalls directly instead, which works on every target.
+
+use core::cell::UnsafeCell;
+use nextstep_atomics::{
+    __atomic_compare_exchange_4, __atomic_fetch_sub_4, __atomic_load_4, __atomic_store_4,
+};
+
+pub(crate) use nextstep_atomics::{ATOMIC_ACQUIRE, ATOMIC_RELAXED, ATOMIC_RELEASE};
+
+pub(crate) struct AtomicWord(UnsafeCell<u32>);
+
+// All access goes through the atomic libcalls
+unsafe impl Sync for AtomicWord {}
+unsafe impl Send for AtomicWord {}
+
+impl AtomicWord {
+    pub(crate) const fn new(value: u32) -> AtomicWord {
+        AtomicWord(UnsafeCell::new(value))
+    }
+
+    #[inline]
+    pub(crate) fn load(&self, ordering: i32) -> u32 {
+        unsafe { __atomic_load_4(self.0.get(), ordering) }
+    }
+
+    #[inline]
+    pub(crate) fn store(&self, value: u32, ordering: i32) {
+        unsafe { __atomic_store_4(self.0.get(), value, ordering) }
+    }
+
+    #[inline]
+    pub(crate) fn compare_exchange(&self, current: u32, new: u32, success: i32, failure: i32) -> Result<u32, u32> {
+        let mut expected = current;
+        if unsafe { __atomic_compare_exchange_4(self.0.get(), &mut expected, new, success, failure) } {
+            Ok(current)
+        } else {
+            Err(expected)
+        }
+    }
+
+    #[inline]
+    pub(crate) fn fetch_sub(&self, value: u32, ordering: i32) -> u32 {
+        unsafe { __atomic_fetch_sub_4(self.0.get(), value, ordering) }
+    }
+
+    #[inline]
+    pub(crate) fn get_mut(&mut self) -> &mut u32 {
+        self.0.get_mut()
+    }
+}
+
+/// Back off while another thread holds what we are waiting for
+///
+/// With the `mach-yield` feature this gives the CPU away through
+/// `swtch_pri()`, which on a uniprocessor lets the holder run instead of
+/// spinning out the rest of our quantum.
+#[inline]
+pub(crate) fn relax() {
+    #[cfg(all(feature = "mach-yield", not(test)))]
+    unsafe {
+        nextstep_sys::swtch_pri(0);
+    }
+    #[cfg(not(all(feature = "mach-yield", not(test))))]
+    core::hint::spin_loop();
+    // Host test threads may share one CPU with the holder
+    #[cfg(test)]
+    std::thread::yield_now();
+}
@@ -0,0 +1,102 @@
+//! Rendezvous point for a fixed number of threads
+
+use crate::atomic::{relax, AtomicWord, ATOMIC_ACQUIRE, ATOMIC_RELEASE};
+use crate::Mutex;
+use core::fmt;
+
+/// Blocks threads until `n` of them have called [`Barrier::wait`]
+pub struct Barrier {
+    // Threads arrived in the current generation
+    count: Mutex<usize>,
+    // Bumped each time the barrier opens
+    generation: AtomicWord,
+    n: usize,
+}
+
+/// Returned by [`Barrier::wait`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    /// True for exactly one thread per opening: the last one to arrive
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl Barrier {
+    pub const fn new(n: usize) -> Barrier {
+        Barrier {
+            count: Mutex::new(0),
+            generation: AtomicWord::new(0),
+            n,
+        }
+    }
+
+    /// Wait until all `n` threads have arrived; the barrier can be reused
+    pub fn wait(&self) -> BarrierWaitResult {
+        let mut count = self.count.lock();
+        let generation = self.generation.load(ATOMIC_ACQUIRE);
+        *count += 1;
+        if *count < self.n {
+            drop(count);
+            while self.generation.load(ATOMIC_ACQUIRE) == generation {
+                relax();
+            }
+            BarrierWaitResult(false)
+        } else {
+            *count = 0;
+            self.generation.store(generation.wrapping_add(1), ATOMIC_RELEASE);
+            BarrierWaitResult(true)
+        }
+    }
+}
+
+impl fmt::Debug for Barrier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Barrier").field("n", &self.n).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_one_leader_per_round() {
+        const THREADS: usize = 4;
+        const ROUNDS: usize = 3;
+
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let arrived = Arc::new(Mutex::new(0usize));
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let barrier = Arc::clone(&barrier);
+                let arrived = Arc::clone(&arrived);
+                thread::spawn(move || {
+                    let mut leaders = 0;
+                    for round in 0..ROUNDS {
+                        *arrived.lock() += 1;
+                        if barrier.wait().is_leader() {
+                            leaders += 1;
+                        }
+                        // Nobody passes the barrier before everyone arrived
+                        assert!(*arrived.lock() >= (round + 1) * THREADS);
+                        barrier.wait();
+                    }
+                    leaders
+                })
+            })
+            .collect();
+        let leaders: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(leaders, ROUNDS);
+    }
+
+    #[test]
+    fn test_single_thread_barrier_leads() {
+        assert!(Barrier::new(1).wait().is_leader());
+    }
+}
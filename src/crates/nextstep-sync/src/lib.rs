@@ -0,0 +1,37 @@
+//! nextstep-sync - Synchronization primitives for no_std NeXTSTEP code
+//!
+//! `Mutex`, `RwLock`, `Once`, `OnceCell`, `Lazy`, `Barrier` and
+//! `AtomicRefCell`, built directly on the nextstep-atomics libcalls so they
+//! work on every m68k target, including those without native atomics.
+//! Waiting threads spin by default; the `mach-yield` feature makes them
+//! give up the CPU through Mach `swtch_pri()` instead.
+//!
+//! ```ignore
+//! use nextstep_sync::Mutex;
+//!
+//! static COUNTER: Mutex<u32> = Mutex::new(0);
+//!
+//! fn bump() -> u32 {
+//!     let mut count = COUNTER.lock();
+//!     *count += 1;
+//!     *count
+//! }
+//! ```
+
+#![no_std]
+
+#[cfg(test)]
+extern crate std;
+
+mod atomic;
+mod barrier;
+mod mutex;
+mod once;
+mod refcell;
+mod rwlock;
+
+pub use barrier::{Barrier, BarrierWaitResult};
+pub use mutex::{Mutex, MutexGuard};
+pub use once::{Lazy, Once, OnceCell};
+pub use refcell::{AtomicRef, AtomicRefCell, AtomicRefMut, BorrowError, BorrowMutError};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
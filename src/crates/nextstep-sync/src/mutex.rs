@@ -0,0 +1,172 @@
+//! Mutual exclusion lock
+
+use crate::atomic::{relax, AtomicWord, ATOMIC_ACQUIRE, ATOMIC_RELAXED, ATOMIC_RELEASE};
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+
+/// A mutual exclusion lock protecting a `T`
+///
+/// Usable in statics, which replaces `static mut` plus raw libcalls:
+///
+/// ```ignore
+/// static COUNTER: Mutex<u32> = Mutex::new(0);
+/// *COUNTER.lock() += 1;
+/// ```
+pub struct Mutex<T: ?Sized> {
+    state: AtomicWord,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+/// Access to the data of a locked [`Mutex`]; unlocks when dropped
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+    // The lock belongs to the thread that took it
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T> Mutex<T> {
+    /// Create an unlocked mutex
+    pub const fn new(value: T) -> Mutex<T> {
+        Mutex {
+            state: AtomicWord::new(UNLOCKED),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    /// Consume the mutex and return the data
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Lock the mutex, waiting until it is free
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            while self.is_locked() {
+                relax();
+            }
+        }
+    }
+
+    /// Lock the mutex if it is free
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, ATOMIC_ACQUIRE, ATOMIC_RELAXED)
+            .ok()
+            .map(|_| MutexGuard { mutex: self, _not_send: PhantomData })
+    }
+
+    /// Whether the mutex is currently locked
+    pub fn is_locked(&self) -> bool {
+        self.state.load(ATOMIC_RELAXED) != UNLOCKED
+    }
+
+    /// Access the data without locking, through a unique borrow
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Mutex<T> {
+        Mutex::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => f.debug_struct("Mutex").field("data", &&*guard).finish(),
+            None => f.write_str("Mutex { <locked> }"),
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.state.store(UNLOCKED, ATOMIC_RELEASE);
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for MutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_try_lock_excludes() {
+        let mutex = Mutex::new(1);
+        let guard = mutex.lock();
+        assert!(mutex.try_lock().is_none());
+        drop(guard);
+        assert_eq!(*mutex.try_lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_threads_increment() {
+        const THREADS: usize = 4;
+        const ITERATIONS: u32 = 2_000;
+
+        let counter = Arc::new(Mutex::new(0u32));
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let counter = Arc::clone(&counter);
+                thread::spawn(move || {
+                    for _ in 0..ITERATIONS {
+                        let mut guard = counter.lock();
+                        let value = *guard;
+                        thread::yield_now();
+                        *guard = value + 1;
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*counter.lock(), THREADS as u32 * ITERATIONS);
+    }
+
+    #[test]
+    fn test_static_mutex() {
+        static COUNTER: Mutex<u32> = Mutex::new(0);
+        *COUNTER.lock() += 5;
+        assert!(*COUNTER.lock() >= 5);
+    }
+}
@@ -0,0 +1,247 @@
+//! One-time initialization: `Once`, `OnceCell` and `Lazy`
+
+use crate::atomic::{relax, AtomicWord, ATOMIC_ACQUIRE, ATOMIC_RELEASE};
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ops::Deref;
+
+const INCOMPLETE: u32 = 0;
+const RUNNING: u32 = 1;
+const COMPLETE: u32 = 2;
+
+/// Runs an initialization routine exactly once
+pub struct Once {
+    state: AtomicWord,
+}
+
+impl Once {
+    pub const fn new() -> Once {
+        Once { state: AtomicWord::new(INCOMPLETE) }
+    }
+
+    /// Run `f` if no call has run yet; other callers wait until it returns
+    ///
+    /// Calling `call_once` on the same `Once` from inside `f` deadlocks.
+    pub fn call_once(&self, f: impl FnOnce()) {
+        if self.is_completed() {
+            return;
+        }
+        match self.state.compare_exchange(INCOMPLETE, RUNNING, ATOMIC_ACQUIRE, ATOMIC_ACQUIRE) {
+            Ok(_) => {
+                f();
+                self.state.store(COMPLETE, ATOMIC_RELEASE);
+            }
+            Err(_) => {
+                while !self.is_completed() {
+                    relax();
+                }
+            }
+        }
+    }
+
+    /// Whether a `call_once` has finished
+    pub fn is_completed(&self) -> bool {
+        self.state.load(ATOMIC_ACQUIRE) == COMPLETE
+    }
+}
+
+impl Default for Once {
+    fn default() -> Once {
+        Once::new()
+    }
+}
+
+impl fmt::Debug for Once {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Once").field("completed", &self.is_completed()).finish()
+    }
+}
+
+/// A cell written at most once
+pub struct OnceCell<T> {
+    once: Once,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send> Send for OnceCell<T> {}
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    pub const fn new() -> OnceCell<T> {
+        OnceCell {
+            once: Once::new(),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// The value, if it has been set
+    pub fn get(&self) -> Option<&T> {
+        if self.once.is_completed() {
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// The value, if it has been set, through a unique borrow
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if self.once.is_completed() {
+            Some(unsafe { self.value.get_mut().assume_init_mut() })
+        } else {
+            None
+        }
+    }
+
+    /// Set the value, or hand it back if the cell was already set
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.once.call_once(|| unsafe {
+            (*self.value.get()).write(value.take().unwrap());
+        });
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    /// The value, initializing it with `f` if the cell is empty
+    pub fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+        self.once.call_once(|| unsafe {
+            (*self.value.get()).write(f());
+        });
+        unsafe { (*self.value.get()).assume_init_ref() }
+    }
+
+    /// Consume the cell and return the value, if set
+    pub fn into_inner(mut self) -> Option<T> {
+        if self.once.is_completed() {
+            // Mark empty so Drop does not drop the value again
+            *self.once.state.get_mut() = INCOMPLETE;
+            Some(unsafe { self.value.get_mut().assume_init_read() })
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> OnceCell<T> {
+        OnceCell::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OnceCell").field(&self.get()).finish()
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        if self.once.is_completed() {
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+/// A value initialized on first access
+///
+/// ```ignore
+/// static TABLE: Lazy<[u8; 256]> = Lazy::new(build_table);
+/// ```
+pub struct Lazy<T, F = fn() -> T> {
+    cell: OnceCell<T>,
+    init: Cell<Option<F>>,
+}
+
+// `init` is only taken inside the cell's `call_once`
+unsafe impl<T: Send + Sync, F: Send> Sync for Lazy<T, F> {}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    pub const fn new(init: F) -> Lazy<T, F> {
+        Lazy {
+            cell: OnceCell::new(),
+            init: Cell::new(Some(init)),
+        }
+    }
+
+    /// Initialize the value if needed and return it
+    pub fn force(this: &Lazy<T, F>) -> &T {
+        this.cell.get_or_init(|| match this.init.take() {
+            Some(init) => init(),
+            None => panic!("Lazy initializer already ran"),
+        })
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        Lazy::force(self)
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for Lazy<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Lazy").field(&self.cell.get()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_once_runs_once_across_threads() {
+        static RUNS: crate::Mutex<u32> = crate::Mutex::new(0);
+        let once = Arc::new(Once::new());
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let once = Arc::clone(&once);
+                thread::spawn(move || {
+                    once.call_once(|| {
+                        thread::yield_now();
+                        *RUNS.lock() += 1;
+                    });
+                    assert!(once.is_completed());
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*RUNS.lock(), 1);
+    }
+
+    #[test]
+    fn test_once_cell_set_and_get() {
+        let cell = OnceCell::new();
+        assert_eq!(cell.get(), None);
+        assert_eq!(cell.set(1), Ok(()));
+        assert_eq!(cell.set(2), Err(2));
+        assert_eq!(cell.get_or_init(|| 3), &1);
+        assert_eq!(cell.into_inner(), Some(1));
+    }
+
+    #[test]
+    fn test_once_cell_drops_value() {
+        let marker = Arc::new(());
+        let cell = OnceCell::new();
+        cell.set(Arc::clone(&marker)).unwrap();
+        assert_eq!(Arc::strong_count(&marker), 2);
+        drop(cell);
+        assert_eq!(Arc::strong_count(&marker), 1);
+    }
+
+    #[test]
+    fn test_lazy_initializes_on_first_use() {
+        static VALUE: Lazy<u32> = Lazy::new(|| 6 * 7);
+        assert_eq!(*VALUE, 42);
+        assert_eq!(*Lazy::force(&VALUE), 42);
+    }
+}
@@ -0,0 +1,202 @@
+//! `RefCell` whose borrow flag is atomic, so it can be shared between threads
+//!
+//! Borrows never wait: a conflicting borrow fails (or panics) just as with
+//! `RefCell`. This suits data that is normally touched by one thread at a
+//! time, where a lock would only add overhead.
+
+use crate::atomic::{AtomicWord, ATOMIC_ACQUIRE, ATOMIC_RELAXED, ATOMIC_RELEASE};
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+// Borrow flag: shared borrow count, or WRITING for the mutable borrow
+const WRITING: u32 = 1 << 31;
+
+/// A mutable memory location with dynamically checked, thread-safe borrows
+pub struct AtomicRefCell<T: ?Sized> {
+    borrow: AtomicWord,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for AtomicRefCell<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for AtomicRefCell<T> {}
+
+/// Shared borrow of an [`AtomicRefCell`]
+pub struct AtomicRef<'a, T: ?Sized> {
+    cell: &'a AtomicRefCell<T>,
+}
+
+/// Mutable borrow of an [`AtomicRefCell`]
+pub struct AtomicRefMut<'a, T: ?Sized> {
+    cell: &'a AtomicRefCell<T>,
+}
+
+/// The value is mutably borrowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BorrowError;
+
+/// The value is already borrowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BorrowMutError;
+
+impl fmt::Display for BorrowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("already mutably borrowed")
+    }
+}
+
+impl fmt::Display for BorrowMutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("already borrowed")
+    }
+}
+
+impl<T> AtomicRefCell<T> {
+    pub const fn new(value: T) -> AtomicRefCell<T> {
+        AtomicRefCell {
+            borrow: AtomicWord::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Consume the cell and return the value
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> AtomicRefCell<T> {
+    /// Borrow the value, panicking if it is mutably borrowed
+    pub fn borrow(&self) -> AtomicRef<'_, T> {
+        match self.try_borrow() {
+            Ok(borrow) => borrow,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Borrow the value if it is not mutably borrowed
+    pub fn try_borrow(&self) -> Result<AtomicRef<'_, T>, BorrowError> {
+        let mut state = self.borrow.load(ATOMIC_RELAXED);
+        loop {
+            if state & WRITING != 0 || state == WRITING - 1 {
+                return Err(BorrowError);
+            }
+            match self.borrow.compare_exchange(state, state + 1, ATOMIC_ACQUIRE, ATOMIC_RELAXED) {
+                Ok(_) => return Ok(AtomicRef { cell: self }),
+                Err(current) => state = current,
+            }
+        }
+    }
+
+    /// Mutably borrow the value, panicking if it is borrowed
+    pub fn borrow_mut(&self) -> AtomicRefMut<'_, T> {
+        match self.try_borrow_mut() {
+            Ok(borrow) => borrow,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Mutably borrow the value if it is not borrowed
+    pub fn try_borrow_mut(&self) -> Result<AtomicRefMut<'_, T>, BorrowMutError> {
+        self.borrow
+            .compare_exchange(0, WRITING, ATOMIC_ACQUIRE, ATOMIC_RELAXED)
+            .map(|_| AtomicRefMut { cell: self })
+            .map_err(|_| BorrowMutError)
+    }
+
+    /// Access the value through a unique borrow of the cell
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+impl<T: Default> Default for AtomicRefCell<T> {
+    fn default() -> AtomicRefCell<T> {
+        AtomicRefCell::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for AtomicRefCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_borrow() {
+            Ok(borrow) => f.debug_struct("AtomicRefCell").field("value", &&*borrow).finish(),
+            Err(_) => f.write_str("AtomicRefCell { <borrowed> }"),
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for AtomicRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.cell.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for AtomicRef<'_, T> {
+    fn drop(&mut self) {
+        self.cell.borrow.fetch_sub(1, ATOMIC_RELEASE);
+    }
+}
+
+impl<T: ?Sized> Deref for AtomicRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.cell.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for AtomicRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.cell.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for AtomicRefMut<'_, T> {
+    fn drop(&mut self) {
+        self.cell.borrow.store(0, ATOMIC_RELEASE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_borrow_rules() {
+        let cell = AtomicRefCell::new(1);
+        {
+            let a = cell.borrow();
+            let b = cell.borrow();
+            assert_eq!(*a + *b, 2);
+            assert_eq!(cell.try_borrow_mut().err(), Some(BorrowMutError));
+        }
+        {
+            let mut m = cell.borrow_mut();
+            *m = 2;
+            assert_eq!(cell.try_borrow().err(), Some(BorrowError));
+            assert!(cell.try_borrow_mut().is_err());
+        }
+        assert_eq!(*cell.borrow(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "already mutably borrowed")]
+    fn test_borrow_while_mutably_borrowed_panics() {
+        let cell = AtomicRefCell::new(0);
+        let _m = cell.borrow_mut();
+        let _ = cell.borrow();
+    }
+
+    #[test]
+    fn test_borrow_from_other_thread() {
+        let cell = Arc::new(AtomicRefCell::new(5));
+        let other = Arc::clone(&cell);
+        *cell.borrow_mut() += 1;
+        let seen = thread::spawn(move || *other.borrow()).join().unwrap();
+        assert_eq!(seen, 6);
+    }
+}
@@ -0,0 +1,234 @@
+//! Reader-writer lock
+
+use crate::atomic::{relax, AtomicWord, ATOMIC_ACQUIRE, ATOMIC_RELAXED, ATOMIC_RELEASE};
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+
+// State word: reader count in the low bits, plus two flags
+const WRITER: u32 = 1 << 31;
+// A writer is waiting; new readers hold off so writers are not starved
+const WRITER_WAITING: u32 = 1 << 30;
+const READERS: u32 = WRITER_WAITING - 1;
+
+/// A lock allowing many readers or one writer
+pub struct RwLock<T: ?Sized> {
+    state: AtomicWord,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+/// Shared access to the data of an [`RwLock`]
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for RwLockReadGuard<'_, T> {}
+
+/// Exclusive access to the data of an [`RwLock`]
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for RwLockWriteGuard<'_, T> {}
+
+impl<T> RwLock<T> {
+    /// Create an unlocked lock
+    pub const fn new(value: T) -> RwLock<T> {
+        RwLock {
+            state: AtomicWord::new(0),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    /// Consume the lock and return the data
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Lock for reading, waiting while a writer holds or wants the lock
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_read() {
+                return guard;
+            }
+            relax();
+        }
+    }
+
+    /// Lock for reading if no writer holds or wants the lock
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let mut state = self.state.load(ATOMIC_RELAXED);
+        loop {
+            if state & (WRITER | WRITER_WAITING) != 0 || state & READERS == READERS {
+                return None;
+            }
+            match self.state.compare_exchange(state, state + 1, ATOMIC_ACQUIRE, ATOMIC_RELAXED) {
+                Ok(_) => return Some(RwLockReadGuard { lock: self, _not_send: PhantomData }),
+                Err(current) => state = current,
+            }
+        }
+    }
+
+    /// Lock for writing, waiting for readers and other writers to leave
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        loop {
+            let state = self.state.load(ATOMIC_RELAXED);
+            if state & (WRITER | READERS) == 0 {
+                if self.state.compare_exchange(state, WRITER, ATOMIC_ACQUIRE, ATOMIC_RELAXED).is_ok() {
+                    return RwLockWriteGuard { lock: self, _not_send: PhantomData };
+                }
+            } else if state & WRITER_WAITING == 0 {
+                let _ = self
+                    .state
+                    .compare_exchange(state, state | WRITER_WAITING, ATOMIC_RELAXED, ATOMIC_RELAXED);
+            }
+            relax();
+        }
+    }
+
+    /// Lock for writing if the lock is free
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        let state = self.state.load(ATOMIC_RELAXED);
+        if state & (WRITER | READERS) != 0 {
+            return None;
+        }
+        self.state
+            .compare_exchange(state, WRITER, ATOMIC_ACQUIRE, ATOMIC_RELAXED)
+            .ok()
+            .map(|_| RwLockWriteGuard { lock: self, _not_send: PhantomData })
+    }
+
+    /// Access the data without locking, through a unique borrow
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> RwLock<T> {
+        RwLock::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => f.debug_struct("RwLock").field("data", &&*guard).finish(),
+            None => f.write_str("RwLock { <locked> }"),
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(1, ATOMIC_RELEASE);
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        // Also clears WRITER_WAITING; other waiting writers set it again
+        self.lock.state.store(0, ATOMIC_RELEASE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_readers_share_writer_excludes() {
+        let lock = RwLock::new(5);
+        let a = lock.read();
+        let b = lock.read();
+        assert_eq!(*a + *b, 10);
+        assert!(lock.try_write().is_none());
+        drop((a, b));
+
+        let mut writer = lock.write();
+        *writer = 6;
+        assert!(lock.try_read().is_none());
+        assert!(lock.try_write().is_none());
+        drop(writer);
+        assert_eq!(*lock.read(), 6);
+    }
+
+    #[test]
+    fn test_waiting_writer_blocks_new_readers() {
+        let lock = RwLock::new(0);
+        let reader = lock.read();
+        // What a writer does when it finds the lock held by readers
+        lock.state.store(1 | WRITER_WAITING, ATOMIC_RELAXED);
+        assert!(lock.try_read().is_none());
+        drop(reader);
+        drop(lock.write());
+        assert!(lock.try_read().is_some());
+    }
+
+    #[test]
+    fn test_writers_and_readers_threads() {
+        const WRITERS: usize = 3;
+        const ITERATIONS: u32 = 500;
+
+        let lock = Arc::new(RwLock::new((0u32, 0u32)));
+        let mut handles: Vec<_> = (0..WRITERS)
+            .map(|_| {
+                let lock = Arc::clone(&lock);
+                thread::spawn(move || {
+                    for _ in 0..ITERATIONS {
+                        let mut pair = lock.write();
+                        pair.0 += 1;
+                        thread::yield_now();
+                        pair.1 += 1;
+                    }
+                })
+            })
+            .collect();
+        for _ in 0..2 {
+            let lock = Arc::clone(&lock);
+            handles.push(thread::spawn(move || {
+                for _ in 0..ITERATIONS {
+                    let pair = lock.read();
+                    assert_eq!(pair.0, pair.1);
+                }
+            }));
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*lock.read(), (WRITERS as u32 * ITERATIONS, WRITERS as u32 * ITERATIONS));
+    }
+}
@@ -1,6 +1,13 @@
 // build.rs - Link against NeXTSTEP system libraries
 
+use std::env;
+
 fn main() {
+    // Host builds (unit tests) link the host C library instead
+    if env::var("CARGO_CFG_TARGET_ARCH").as_deref() != Ok("m68k") {
+        return;
+    }
+
     // Tell cargo to link against libSystem
     println!("cargo:rustc-link-arg=-lSystem");
     
@@ -9,4 +16,4 @@ fn main() {
     
     // Add library search path if needed
     // println!("cargo:rustc-link-search=native=/usr/lib");
-}
\ No newline at end of file
+}
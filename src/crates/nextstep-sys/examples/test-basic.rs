@@ -4,6 +4,9 @@
 #![no_std]
 #![no_main]
 
+// Panics are reported by the standard handler
+extern crate nextstep_panic;
+
 use nextstep_sys::*;
 
 #[no_mangle]
@@ -25,8 +28,3 @@ pub extern "C" fn main() -> i32 {
     
     0
 }
-
-#[panic_handler]
-fn panic(_info: &core::panic::PanicInfo) -> ! {
-    sys_exit(1);
-}
\ No newline at end of file
@@ -0,0 +1,237 @@
+//! Exit handler registry behind `sys_atexit`
+//!
+//! The registry is shared by every cthread and may be reached from a signal
+//! handler, so it sits behind a `TAS` spinlock that is only held with
+//! signals blocked. Handlers are called with the lock released: they may
+//! register further handlers, which then run next.
+
+use crate::{atexit, ENOMEM};
+use core::cell::UnsafeCell;
+
+/// Maximum number of exit handlers (the ANSI C minimum for atexit)
+pub const ATEXIT_MAX: usize = 32;
+
+// Handlers in registration order, run from the top
+pub(crate) struct Registry {
+    handlers: [Option<fn()>; ATEXIT_MAX],
+    count: usize,
+    // Whether the registry is hooked into the C library's atexit
+    hooked: bool,
+}
+
+impl Registry {
+    pub(crate) const fn new() -> Registry {
+        Registry { handlers: [None; ATEXIT_MAX], count: 0, hooked: false }
+    }
+
+    pub(crate) fn push(&mut self, f: fn()) -> Result<(), i32> {
+        if self.count == ATEXIT_MAX {
+            return Err(ENOMEM);
+        }
+        self.handlers[self.count] = Some(f);
+        self.count += 1;
+        Ok(())
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<fn()> {
+        if self.count == 0 {
+            return None;
+        }
+        self.count -= 1;
+        self.handlers[self.count].take()
+    }
+}
+
+// A value behind a TAS spinlock held with signals blocked
+pub(crate) struct Locked<T> {
+    locked: UnsafeCell<u8>,
+    value: UnsafeCell<T>,
+}
+
+// The value is only reached through `with`, which holds the lock
+unsafe impl<T: Send> Sync for Locked<T> {}
+
+impl<T> Locked<T> {
+    pub(crate) const fn new(value: T) -> Locked<T> {
+        Locked { locked: UnsafeCell::new(0), value: UnsafeCell::new(value) }
+    }
+
+    pub(crate) fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        unsafe {
+            let mask = signals::block();
+            while !self.try_lock() {
+                while core::ptr::read_volatile(self.locked.get()) != 0 {
+                    core::hint::spin_loop();
+                }
+            }
+            let result = f(&mut *self.value.get());
+            core::ptr::write_volatile(self.locked.get(), 0);
+            signals::restore(mask);
+            result
+        }
+    }
+
+    // Test-and-set the lock byte, returning true if the lock was free
+    #[cfg(target_arch = "m68k")]
+    unsafe fn try_lock(&self) -> bool {
+        let was_set: u8;
+        core::arch::asm!(
+            "tas ({ptr})",
+            "smi {was_set}",
+            ptr = in(reg_addr) self.locked.get(),
+            was_set = out(reg_data) was_set,
+            options(nostack),
+        );
+        was_set == 0
+    }
+
+    // Host stand-in for TAS
+    #[cfg(not(target_arch = "m68k"))]
+    unsafe fn try_lock(&self) -> bool {
+        use core::sync::atomic::{AtomicU8, Ordering};
+        let byte = &*(self.locked.get() as *const AtomicU8);
+        byte.swap(0x80, Ordering::Acquire) == 0
+    }
+}
+
+// Block every signal but the synchronous fault signals while the lock is
+// held, so a handler cannot spin on a lock its own thread holds
+#[cfg(target_arch = "m68k")]
+mod signals {
+    use crate::{c_int, sigblock, sigmask, sigsetmask, SIGBUS, SIGEMT, SIGFPE, SIGILL, SIGSEGV, SIGTRAP};
+
+    const UNBLOCKED: c_int =
+        sigmask(SIGBUS) | sigmask(SIGSEGV) | sigmask(SIGILL) | sigmask(SIGFPE) | sigmask(SIGTRAP) | sigmask(SIGEMT);
+
+    pub(super) unsafe fn block() -> c_int {
+        sigblock(!UNBLOCKED)
+    }
+
+    pub(super) unsafe fn restore(mask: c_int) {
+        sigsetmask(mask);
+    }
+}
+
+// Host builds have no handlers that touch the registry
+#[cfg(not(target_arch = "m68k"))]
+mod signals {
+    pub(super) unsafe fn block() -> i32 {
+        0
+    }
+
+    pub(super) unsafe fn restore(_mask: i32) {}
+}
+
+static REGISTRY: Locked<Registry> = Locked::new(Registry::new());
+
+// Runs the registry from the C library's exit(), which is how crt0 ends
+// the process when main returns
+extern "C" fn run_atexit_from_c() {
+    sys_run_atexit();
+}
+
+/// Register `f` to run at process exit
+///
+/// Handlers run in reverse order of registration. Fails with `ENOMEM`
+/// once [`ATEXIT_MAX`] handlers are registered.
+///
+/// The first registration also hooks the registry into the C library's
+/// `atexit`, so handlers run when `main` returns or C code calls `exit()`.
+pub fn sys_atexit(f: fn()) -> Result<(), i32> {
+    REGISTRY.with(|registry| {
+        if !registry.hooked {
+            if unsafe { atexit(run_atexit_from_c) } != 0 {
+                return Err(ENOMEM);
+            }
+            registry.hooked = true;
+        }
+        registry.push(f)
+    })
+}
+
+/// Run and unregister every exit handler, most recent first
+///
+/// Each handler is removed before it is called, so a handler that exits
+/// the process itself does not run twice.
+pub fn sys_run_atexit() {
+    run(&REGISTRY);
+}
+
+fn run(registry: &Locked<Registry>) {
+    while let Some(f) = registry.with(Registry::pop) {
+        f();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::sync::Mutex;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_registry_full() {
+        fn noop() {}
+
+        let mut registry = Registry::new();
+        for _ in 0..ATEXIT_MAX {
+            registry.push(noop).unwrap();
+        }
+        assert_eq!(registry.push(noop), Err(ENOMEM));
+        assert!(registry.pop().is_some());
+        assert_eq!(registry.push(noop), Ok(()));
+    }
+
+    #[test]
+    fn test_handlers_run_lifo() {
+        static ORDER: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+        static REGISTRY: Locked<Registry> = Locked::new(Registry::new());
+
+        fn first() {
+            ORDER.lock().unwrap().push(1);
+        }
+        fn second() {
+            ORDER.lock().unwrap().push(2);
+        }
+        fn third() {
+            ORDER.lock().unwrap().push(3);
+        }
+
+        REGISTRY.with(|r| {
+            r.push(first).unwrap();
+            r.push(second).unwrap();
+            r.push(third).unwrap();
+        });
+        run(&REGISTRY);
+        assert_eq!(*ORDER.lock().unwrap(), [3, 2, 1]);
+        // Emptied: running again does nothing
+        run(&REGISTRY);
+        assert_eq!(ORDER.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_handler_registering_handler_runs_it() {
+        static ORDER: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+        static REGISTRY: Locked<Registry> = Locked::new(Registry::new());
+
+        fn first() {
+            ORDER.lock().unwrap().push(1);
+        }
+        fn late() {
+            ORDER.lock().unwrap().push(3);
+        }
+        fn registers_late() {
+            ORDER.lock().unwrap().push(2);
+            REGISTRY.with(|r| r.push(late)).unwrap();
+        }
+
+        REGISTRY.with(|r| {
+            r.push(first).unwrap();
+            r.push(registers_late).unwrap();
+        });
+        run(&REGISTRY);
+        assert_eq!(*ORDER.lock().unwrap(), [2, 3, 1]);
+    }
+}
@@ -7,6 +7,9 @@
 
 use core::ffi::c_void;
 
+mod atexit;
+pub use atexit::{sys_atexit, sys_run_atexit, ATEXIT_MAX};
+
 // Type definitions
 pub type c_int = i32;
 pub type c_uint = u32;
@@ -187,6 +190,11 @@ pub const SYS_SBRK: i32 = 69;
 pub const SYS_MMAP: i32 = 71;
 pub const SYS_MUNMAP: i32 = 73;
 pub const SYS_MPROTECT: i32 = 74;
+pub const SYS_SIGVEC: i32 = 108;
+pub const SYS_SIGBLOCK: i32 = 109;
+pub const SYS_SIGSETMASK: i32 = 110;
+pub const SYS_SIGPAUSE: i32 = 111;
+pub const SYS_SIGSTACK: i32 = 112;
 pub const SYS_GETTIMEOFDAY: i32 = 116;
 pub const SYS_GETRUSAGE: i32 = 117;
 pub const SYS_GETSOCKOPT: i32 = 118;
@@ -217,10 +225,12 @@ pub const SYS_VM_STATISTICS: i32 = -72;
 pub const SYS_TASK_CREATE: i32 = -168;
 
 // Raw system call interface
-#[link(name = "System")]
+#[cfg_attr(target_arch = "m68k", link(name = "System"))]
 extern "C" {
     // Process control
     pub fn _exit(status: i32) -> !;
+    pub fn exit(status: i32) -> !;
+    pub fn atexit(func: extern "C" fn()) -> c_int;
     pub fn fork() -> pid_t;
     pub fn vfork() -> pid_t;
     pub fn getpid() -> pid_t;
@@ -296,7 +306,7 @@ extern "C" {
 
 // Mach VM system calls
 // These use negative syscall numbers and different calling conventions
-#[link(name = "System")]
+#[cfg_attr(target_arch = "m68k", link(name = "System"))]
 extern "C" {
     // VM operations
     pub fn vm_allocate(target_task: c_int, address: *mut *mut c_void, size: size_t, anywhere: c_int) -> c_int;
@@ -312,6 +322,32 @@ extern "C" {
     // Task operations
     pub fn task_create(parent_task: c_int, inherit_memory: c_int, child_task: *mut c_int) -> c_int;
     pub fn task_self() -> c_int;
+
+    // Thread scheduling
+    pub fn swtch() -> c_int;
+    pub fn swtch_pri(pri: c_int) -> c_int;
+    pub fn thread_switch(thread: mach_port_t, option: c_int, option_time: c_int) -> kern_return_t;
+}
+
+// C threads (cthreads.h)
+#[cfg_attr(target_arch = "m68k", link(name = "System"))]
+extern "C" {
+    pub fn cthread_self() -> cthread_t;
+    pub fn cthread_fork(func: extern "C" fn(any_t) -> any_t, arg: any_t) -> cthread_t;
+    pub fn cthread_join(t: cthread_t) -> any_t;
+    pub fn cthread_detach(t: cthread_t);
+    pub fn cthread_exit(result: any_t) -> !;
+    // One pointer of per-thread data; nextstep-tls keeps its key table here
+    pub fn cthread_data(t: cthread_t) -> any_t;
+    pub fn cthread_set_data(t: cthread_t, data: any_t);
+}
+
+// Process globals normally initialized by crt0
+#[cfg_attr(target_arch = "m68k", link(name = "System"))]
+extern "C" {
+    pub static mut environ: *const *const u8;
+    pub static mut NXArgc: c_int;
+    pub static mut NXArgv: *const *const u8;
 }
 
 /// Safe wrapper for write syscall
@@ -359,11 +395,21 @@ pub fn sys_close(fd: i32) -> Result<(), i32> {
 }
 
 /// Safe wrapper for exit syscall
+///
+/// Terminates immediately without running exit handlers; use
+/// [`sys_process_exit`] for an orderly shutdown.
 #[inline]
 pub fn sys_exit(code: i32) -> ! {
     unsafe { _exit(code) }
 }
 
+/// Run the exit handlers, then terminate the process
+#[inline]
+pub fn sys_process_exit(code: i32) -> ! {
+    sys_run_atexit();
+    sys_exit(code)
+}
+
 /// Safe wrapper for getpid syscall
 #[inline] 
 pub fn sys_getpid() -> pid_t {
@@ -411,12 +457,19 @@ pub type vm_size_t = c_ulong;
 pub type vm_address_t = vm_offset_t;
 pub type mach_port_t = c_int;
 pub type kern_return_t = c_int;
+pub type any_t = *mut c_void;
+pub type cthread_t = *mut c_void;
 
 // VM inheritance values
 pub const VM_INHERIT_SHARE: c_int = 0;
 pub const VM_INHERIT_COPY: c_int = 1;
 pub const VM_INHERIT_NONE: c_int = 2;
 
+// thread_switch() options
+pub const SWITCH_OPTION_NONE: c_int = 0;
+pub const SWITCH_OPTION_DEPRESS: c_int = 1;
+pub const SWITCH_OPTION_WAIT: c_int = 2;
+
 // Signal numbers
 pub const SIGHUP: c_int = 1;
 pub const SIGINT: c_int = 2;
@@ -434,6 +487,67 @@ pub const SIGPIPE: c_int = 13;
 pub const SIGALRM: c_int = 14;
 pub const SIGTERM: c_int = 15;
 
+pub const SIGURG: c_int = 16;
+pub const SIGSTOP: c_int = 17;
+pub const SIGTSTP: c_int = 18;
+pub const SIGCONT: c_int = 19;
+pub const SIGCHLD: c_int = 20;
+
+// Signal dispositions for sigvec.sv_handler
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+// sigvec flags
+pub const SV_ONSTACK: c_int = 0x0001;
+pub const SV_INTERRUPT: c_int = 0x0002;
+pub const SV_RESETHAND: c_int = 0x0004;
+
+/// Build the signal mask bit for `sig`, as the BSD `sigmask()` macro does
+#[inline]
+pub const fn sigmask(sig: c_int) -> c_int {
+    1 << (sig - 1)
+}
+
+// sigvec structure (4.3BSD signal interface)
+#[repr(C)]
+pub struct sigvec {
+    pub sv_handler: usize,   // handler address, SIG_DFL or SIG_IGN
+    pub sv_mask: c_int,      // signals to block while handling
+    pub sv_flags: c_int,     // SV_* flags
+}
+
+// sigstack structure for the alternate signal stack
+#[repr(C)]
+pub struct sigstack {
+    pub ss_sp: *mut c_void,  // top of the alternate stack
+    pub ss_onstack: c_int,   // non-zero while executing on it
+}
+
+// sigcontext passed as the third argument to signal handlers (m68k)
+#[repr(C)]
+pub struct sigcontext {
+    pub sc_onstack: c_int,   // sigstack state to restore
+    pub sc_mask: c_int,      // signal mask to restore
+    pub sc_sp: c_int,        // a7 to restore
+    pub sc_fp: c_int,        // a6 to restore
+    pub sc_ap: c_int,        // VAX argument pointer, unused on m68k
+    pub sc_pc: c_int,        // pc to restore
+    pub sc_ps: c_int,        // status register to restore
+}
+
+/// Signature of a BSD-style signal handler: `(sig, code, scp)`
+pub type sig_handler_t = unsafe extern "C" fn(c_int, c_int, *mut sigcontext);
+
+// Signal handling
+#[cfg_attr(target_arch = "m68k", link(name = "System"))]
+extern "C" {
+    pub fn sigvec(sig: c_int, vec: *const sigvec, ovec: *mut sigvec) -> c_int;
+    pub fn sigblock(mask: c_int) -> c_int;
+    pub fn sigsetmask(mask: c_int) -> c_int;
+    pub fn sigpause(mask: c_int) -> c_int;
+    pub fn sigstack(ss: *const sigstack, oss: *mut sigstack) -> c_int;
+}
+
 // Wait options
 pub const WNOHANG: c_int = 1;
 pub const WUNTRACED: c_int = 2;
@@ -0,0 +1,173 @@
+//! `__emutls_get_address`, the libcall LLVM emits for emulated TLS
+//!
+//! Each thread-local variable gets a control block holding its size,
+//! alignment and initial value. The first access from any thread hands the
+//! variable a process-wide index; the first access from each thread
+//! allocates that thread's copy and fills it from the initial value (or
+//! with zeros when there is none). Layout and behaviour match libgcc's and
+//! compiler-rt's `emutls.c`.
+
+use crate::thread;
+use alloc::alloc::{alloc, handle_alloc_error, Layout};
+use core::cell::UnsafeCell;
+use core::mem;
+use core::ptr::{self, NonNull};
+use nextstep_atomics::{ATOMIC_ACQUIRE, ATOMIC_RELEASE};
+use nextstep_sync::Mutex;
+
+/// Control block the compiler emits for each thread-local variable
+/// (`__emutls_v.<name>`)
+#[repr(C)]
+pub struct EmutlsControl {
+    size: usize,
+    align: usize,
+    // Index from 1, or 0 before the first access (a union with a pointer
+    // in the C declaration, which only uses the index)
+    index: UnsafeCell<usize>,
+    // Initial value (`__emutls_t.<name>`), or null for all zeros
+    value: *const u8,
+}
+
+unsafe impl Sync for EmutlsControl {}
+
+impl EmutlsControl {
+    /// Control block for an object of `size` bytes, initialized from `value`
+    pub const fn new(size: usize, align: usize, value: *const u8) -> EmutlsControl {
+        EmutlsControl {
+            size,
+            align,
+            index: UnsafeCell::new(0),
+            value,
+        }
+    }
+}
+
+// Highest index handed out so far
+static LAST_INDEX: Mutex<usize> = Mutex::new(0);
+
+#[cfg(target_pointer_width = "32")]
+unsafe fn load_index(index: *mut usize) -> usize {
+    nextstep_atomics::__atomic_load_4(index as *const u32, ATOMIC_ACQUIRE) as usize
+}
+
+#[cfg(target_pointer_width = "32")]
+unsafe fn store_index(index: *mut usize, value: usize) {
+    nextstep_atomics::__atomic_store_4(index as *mut u32, value as u32, ATOMIC_RELEASE)
+}
+
+#[cfg(target_pointer_width = "64")]
+unsafe fn load_index(index: *mut usize) -> usize {
+    nextstep_atomics::__atomic_load_8(index as *const u64, ATOMIC_ACQUIRE) as usize
+}
+
+#[cfg(target_pointer_width = "64")]
+unsafe fn store_index(index: *mut usize, value: usize) {
+    nextstep_atomics::__atomic_store_8(index as *mut u64, value as u64, ATOMIC_RELEASE)
+}
+
+// Index of the variable, assigning the next free one on first access
+fn index_of(control: &EmutlsControl) -> usize {
+    let index = unsafe { load_index(control.index.get()) };
+    if index != 0 {
+        return index;
+    }
+    let mut last = LAST_INDEX.lock();
+    // Indices are only assigned with the lock held
+    let index = unsafe { *control.index.get() };
+    if index != 0 {
+        return index;
+    }
+    *last += 1;
+    unsafe { store_index(control.index.get(), *last) };
+    *last
+}
+
+// Allocate and initialize this thread's copy of the variable
+fn allocate(control: &EmutlsControl) -> (NonNull<u8>, Layout) {
+    let align = control.align.max(mem::align_of::<usize>());
+    let layout = match Layout::from_size_align(control.size.max(1), align) {
+        Ok(layout) => layout,
+        Err(_) => panic!("invalid emutls object layout: {} bytes, align {}", control.size, control.align),
+    };
+    let object = unsafe { alloc(layout) };
+    let Some(object) = NonNull::new(object) else {
+        handle_alloc_error(layout)
+    };
+    unsafe {
+        if control.value.is_null() {
+            ptr::write_bytes(object.as_ptr(), 0, control.size);
+        } else {
+            ptr::copy_nonoverlapping(control.value, object.as_ptr(), control.size);
+        }
+    }
+    (object, layout)
+}
+
+/// Address of the calling thread's copy of the variable behind `control`
+///
+/// # Safety
+///
+/// `control` must point to a control block that lives for the rest of the
+/// process and whose size, alignment and initial value never change.
+#[no_mangle]
+pub unsafe extern "C" fn __emutls_get_address(control: *mut EmutlsControl) -> *mut u8 {
+    let control = &*control;
+    thread::object(index_of(control), || allocate(control))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_thread_dtors;
+    use std::thread;
+
+    static TEMPLATE: u32 = 7;
+    static WITH_TEMPLATE: EmutlsControl = EmutlsControl::new(4, 4, &TEMPLATE as *const u32 as *const u8);
+    static ZEROED: EmutlsControl = EmutlsControl::new(16, 1, ptr::null());
+
+    fn address(control: &'static EmutlsControl) -> *mut u8 {
+        unsafe { __emutls_get_address(control as *const EmutlsControl as *mut EmutlsControl) }
+    }
+
+    #[test]
+    fn test_each_thread_gets_initialized_copy() {
+        let mine = address(&WITH_TEMPLATE) as *mut u32;
+        assert_eq!(unsafe { *mine }, 7);
+        unsafe { *mine = 8 };
+        assert_eq!(address(&WITH_TEMPLATE) as *mut u32, mine);
+
+        let other = thread::spawn(|| {
+            let value = unsafe { *(address(&WITH_TEMPLATE) as *mut u32) };
+            run_thread_dtors();
+            value
+        });
+        assert_eq!(other.join().unwrap(), 7);
+        assert_eq!(unsafe { *mine }, 8);
+        run_thread_dtors();
+    }
+
+    #[test]
+    fn test_zero_fill_and_alignment() {
+        static ALIGNED: EmutlsControl = EmutlsControl::new(8, 64, ptr::null());
+        let zeroed = address(&ZEROED);
+        assert!(unsafe { core::slice::from_raw_parts(zeroed, 16) }.iter().all(|&b| b == 0));
+        assert_eq!(address(&ALIGNED) as usize % 64, 0);
+        run_thread_dtors();
+    }
+
+    #[test]
+    fn test_index_assigned_once() {
+        static CONTROL: EmutlsControl = EmutlsControl::new(4, 4, ptr::null());
+        let handles: std::vec::Vec<_> = (0..4)
+            .map(|_| {
+                thread::spawn(|| {
+                    address(&CONTROL);
+                    run_thread_dtors();
+                    unsafe { *CONTROL.index.get() }
+                })
+            })
+            .collect();
+        let indices: std::vec::Vec<usize> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert!(indices[0] != 0 && indices.iter().all(|&i| i == indices[0]));
+    }
+}
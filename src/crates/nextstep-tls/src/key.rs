@@ -0,0 +1,234 @@
+//! `LocalKey` and `thread_local!` for no_std code
+
+use crate::emutls::{EmutlsControl, __emutls_get_address};
+use crate::thread::register_dtor;
+use core::fmt;
+use core::mem::{self, MaybeUninit};
+use core::ptr;
+
+// Slot states; a freshly allocated (zeroed) slot is UNINIT
+const UNINIT: u8 = 0;
+const ALIVE: u8 = 1;
+const DESTROYED: u8 = 2;
+
+// What each thread's copy of a `LocalKey` holds
+#[repr(C)]
+struct Slot<T> {
+    state: u8,
+    value: MaybeUninit<T>,
+}
+
+/// A thread-local value, initialized lazily on each thread's first access
+///
+/// Declare with [`thread_local!`](crate::thread_local). Values that need
+/// dropping are dropped when their thread runs its TLS destructors.
+pub struct LocalKey<T: 'static> {
+    control: EmutlsControl,
+    init: fn() -> T,
+}
+
+// Each thread only ever sees its own value
+unsafe impl<T> Sync for LocalKey<T> {}
+
+/// The value was accessed during or after its thread's TLS destruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessError;
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("already destroyed")
+    }
+}
+
+impl<T: 'static> LocalKey<T> {
+    #[doc(hidden)]
+    pub const fn new(init: fn() -> T) -> LocalKey<T> {
+        LocalKey {
+            control: EmutlsControl::new(mem::size_of::<Slot<T>>(), mem::align_of::<Slot<T>>(), ptr::null()),
+            init,
+        }
+    }
+
+    /// Run `f` with this thread's value, initializing it if needed
+    ///
+    /// Panics if the value has already been destroyed.
+    pub fn with<R>(&'static self, f: impl FnOnce(&T) -> R) -> R {
+        match self.try_with(f) {
+            Ok(result) => result,
+            Err(_) => panic!("cannot access a thread-local value during or after destruction"),
+        }
+    }
+
+    /// Run `f` with this thread's value, unless it has been destroyed
+    pub fn try_with<R>(&'static self, f: impl FnOnce(&T) -> R) -> Result<R, AccessError> {
+        let slot = unsafe { __emutls_get_address(&self.control as *const EmutlsControl as *mut EmutlsControl) } as *mut Slot<T>;
+        unsafe {
+            match (*slot).state {
+                ALIVE => {}
+                DESTROYED => return Err(AccessError),
+                state => {
+                    debug_assert_eq!(state, UNINIT);
+                    self.initialize(slot)
+                }
+            }
+            Ok(f((*slot).value.assume_init_ref()))
+        }
+    }
+
+    // `init` runs before the slot is marked alive, so an initializer that
+    // uses its own key recurses
+    unsafe fn initialize(&'static self, slot: *mut Slot<T>) {
+        let value = (self.init)();
+        (*slot).value.write(value);
+        (*slot).state = ALIVE;
+        if mem::needs_drop::<T>() {
+            register_dtor(slot as *mut u8, destroy::<T>);
+        }
+    }
+}
+
+impl<T: 'static> fmt::Debug for LocalKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LocalKey { .. }")
+    }
+}
+
+unsafe extern "C" fn destroy<T>(slot: *mut u8) {
+    let slot = slot as *mut Slot<T>;
+    // Mark first so the value's own Drop sees it as gone
+    (*slot).state = DESTROYED;
+    ptr::drop_in_place((*slot).value.as_mut_ptr());
+}
+
+/// Declare thread-local values, like std's `thread_local!`
+///
+/// ```ignore
+/// use core::cell::Cell;
+///
+/// nextstep_tls::thread_local! {
+///     static DEPTH: Cell<u32> = Cell::new(0);
+/// }
+///
+/// DEPTH.with(|depth| depth.set(depth.get() + 1));
+/// ```
+#[macro_export]
+macro_rules! thread_local {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+        $(#[$attr])* $vis static $name: $crate::LocalKey<$t> = $crate::LocalKey::new({
+            fn __init() -> $t {
+                $init
+            }
+            __init
+        });
+        $crate::thread_local!($($rest)*);
+    };
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        $crate::thread_local!($(#[$attr])* $vis static $name: $t = $init;);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_thread_dtors;
+    use core::cell::Cell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    static INITS: AtomicUsize = AtomicUsize::new(0);
+
+    crate::thread_local! {
+        static COUNTER: Cell<u32> = {
+            INITS.fetch_add(1, Ordering::SeqCst);
+            Cell::new(10)
+        };
+    }
+
+    #[test]
+    fn test_lazy_init_per_thread() {
+        COUNTER.with(|c| c.set(c.get() + 1));
+        COUNTER.with(|c| assert_eq!(c.get(), 11));
+        let seen = thread::spawn(|| {
+            let value = COUNTER.with(|c| c.get());
+            run_thread_dtors();
+            value
+        });
+        assert_eq!(seen.join().unwrap(), 10);
+        assert!(INITS.load(Ordering::SeqCst) >= 2);
+        run_thread_dtors();
+    }
+
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Noisy;
+
+    impl Drop for Noisy {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::SeqCst);
+            // The value is gone while it is being dropped
+            assert_eq!(NOISY.try_with(|_| ()), Err(AccessError));
+        }
+    }
+
+    crate::thread_local! {
+        static NOISY: Noisy = Noisy;
+    }
+
+    #[test]
+    fn test_destructor_runs_on_thread_exit() {
+        thread::spawn(|| {
+            NOISY.with(|_| ());
+            assert_eq!(DROPS.load(Ordering::SeqCst), 0);
+            run_thread_dtors();
+            assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+            // Nothing left to run
+            run_thread_dtors();
+        })
+        .join()
+        .unwrap();
+        assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_returning_thread_runs_destructors() {
+        static RAN: AtomicUsize = AtomicUsize::new(0);
+
+        unsafe extern "C" fn count(_: *mut u8) {
+            RAN.fetch_add(1, Ordering::SeqCst);
+        }
+
+        // The body just returns; run_thread does the cleanup
+        let value = thread::spawn(|| {
+            crate::run_thread(|| {
+                crate::register_dtor(ptr::null_mut(), count);
+                crate::register_dtor(ptr::null_mut(), count);
+                7
+            })
+        })
+        .join()
+        .unwrap();
+        assert_eq!((value, RAN.load(Ordering::SeqCst)), (7, 2));
+    }
+
+    #[test]
+    fn test_destructor_registered_during_destruction_runs() {
+        static LATE: AtomicUsize = AtomicUsize::new(0);
+
+        unsafe extern "C" fn late(_: *mut u8) {
+            LATE.fetch_add(1, Ordering::SeqCst);
+        }
+
+        unsafe extern "C" fn early(_: *mut u8) {
+            crate::register_dtor(ptr::null_mut(), late);
+        }
+
+        thread::spawn(|| {
+            crate::register_dtor(ptr::null_mut(), early);
+            run_thread_dtors();
+        })
+        .join()
+        .unwrap();
+        assert_eq!(LATE.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,79 @@
+//! nextstep-tls - Emulated thread-local storage for NeXTSTEP
+//!
+//! NeXTSTEP has no TLS model, so the target specs enable LLVM's emulated
+//! TLS: every access to a `#[thread_local]` variable becomes a call to
+//! [`__emutls_get_address`], implemented here on top of the cthread data
+//! word. Each thread's copy is allocated and initialized on its first
+//! access. Destructors registered with [`register_dtor`] (or
+//! `__cxa_thread_atexit_impl`, as std-like layers do) run when the thread
+//! ends: threads started with [`spawn`] run them after their body
+//! returns, [`thread_exit`] runs them before `cthread_exit()`, and the
+//! thread that calls `exit()` runs them from an atexit handler.
+//!
+//! no_std code can use [`thread_local!`] directly:
+//!
+//! ```ignore
+//! use core::cell::RefCell;
+//!
+//! nextstep_tls::thread_local! {
+//!     static SCRATCH: RefCell<[u8; 256]> = RefCell::new([0; 256]);
+//! }
+//!
+//! SCRATCH.with(|buf| buf.borrow_mut()[0] = 1);
+//! ```
+//!
+//! Needs a global allocator (e.g. nextstep-alloc).
+
+#![no_std]
+
+extern crate alloc;
+#[cfg(test)]
+extern crate std;
+
+mod emutls;
+mod key;
+mod slot;
+mod thread;
+
+pub use emutls::{EmutlsControl, __emutls_get_address};
+pub use key::{AccessError, LocalKey};
+pub use thread::{register_dtor, run_thread, run_thread_dtors, Dtor, __cxa_thread_atexit_impl};
+
+use nextstep_sync::Once;
+
+// Run the exiting thread's destructors from exit(), registered on first use
+fn register_exit_hook() {
+    static REGISTERED: Once = Once::new();
+    REGISTERED.call_once(|| {
+        #[cfg(target_arch = "m68k")]
+        let _ = nextstep_sys::sys_atexit(run_thread_dtors);
+    });
+}
+
+/// Run this thread's TLS destructors, then end the thread
+///
+/// Use instead of `cthread_exit()` for threads that use TLS.
+#[cfg(target_arch = "m68k")]
+pub fn thread_exit(result: *mut core::ffi::c_void) -> ! {
+    run_thread_dtors();
+    unsafe { nextstep_sys::cthread_exit(result) }
+}
+
+/// Start a cthread running `f`, whose TLS destructors run when it returns
+///
+/// Use instead of `cthread_fork()` for threads that use TLS. The returned
+/// thread can be joined or detached as usual.
+#[cfg(target_arch = "m68k")]
+pub fn spawn<F>(f: F) -> nextstep_sys::cthread_t
+where
+    F: FnOnce() + Send + 'static,
+{
+    extern "C" fn start<F: FnOnce()>(arg: nextstep_sys::any_t) -> nextstep_sys::any_t {
+        let f = unsafe { alloc::boxed::Box::from_raw(arg as *mut F) };
+        run_thread(*f);
+        core::ptr::null_mut()
+    }
+
+    let arg = alloc::boxed::Box::into_raw(alloc::boxed::Box::new(f));
+    unsafe { nextstep_sys::cthread_fork(start::<F>, arg as nextstep_sys::any_t) }
+}
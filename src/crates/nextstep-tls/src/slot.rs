@@ -0,0 +1,76 @@
+//! The one per-thread pointer everything else hangs off
+//!
+//! On NeXTSTEP this is the cthread data word (`cthread_data()`), so this
+//! crate owns it: code that calls `cthread_set_data()` itself cannot be
+//! mixed with emulated TLS. Before cthreads are initialized (no crt0, so
+//! `cthread_self()` is null) the process is single-threaded and a static
+//! word stands in.
+
+#[cfg(target_arch = "m68k")]
+mod imp {
+    use core::ptr;
+    use nextstep_sys::{any_t, cthread_data, cthread_self, cthread_set_data};
+
+    // Slot for the initial thread while cthreads is not set up
+    static mut MAIN_SLOT: *mut u8 = ptr::null_mut();
+
+    pub(crate) fn get() -> *mut u8 {
+        unsafe {
+            let thread = cthread_self();
+            if thread.is_null() {
+                MAIN_SLOT
+            } else {
+                cthread_data(thread) as *mut u8
+            }
+        }
+    }
+
+    pub(crate) fn set(data: *mut u8) {
+        unsafe {
+            let thread = cthread_self();
+            if thread.is_null() {
+                MAIN_SLOT = data;
+            } else {
+                cthread_set_data(thread, data as any_t);
+            }
+        }
+    }
+}
+
+// Host tests run on real threads
+#[cfg(all(not(target_arch = "m68k"), test))]
+mod imp {
+    use core::cell::Cell;
+    use core::ptr;
+
+    std::thread_local! {
+        static SLOT: Cell<*mut u8> = const { Cell::new(ptr::null_mut()) };
+    }
+
+    pub(crate) fn get() -> *mut u8 {
+        SLOT.with(|slot| slot.get())
+    }
+
+    pub(crate) fn set(data: *mut u8) {
+        SLOT.with(|slot| slot.set(data))
+    }
+}
+
+// Other host builds are treated as single-threaded
+#[cfg(all(not(target_arch = "m68k"), not(test)))]
+mod imp {
+    use core::ptr;
+    use core::sync::atomic::{AtomicPtr, Ordering};
+
+    static SLOT: AtomicPtr<u8> = AtomicPtr::new(ptr::null_mut());
+
+    pub(crate) fn get() -> *mut u8 {
+        SLOT.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set(data: *mut u8) {
+        SLOT.store(data, Ordering::Relaxed)
+    }
+}
+
+pub(crate) use imp::{get, set};
@@ -0,0 +1,109 @@
+//! Per-thread key table and destructor list
+
+use crate::slot;
+use alloc::alloc::{dealloc, Layout};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ptr::{self, NonNull};
+
+/// Destructor called with the object it was registered for
+pub type Dtor = unsafe extern "C" fn(*mut u8);
+
+// Everything a thread owns, reached through its slot
+struct ThreadData {
+    // TLS objects by index - 1, allocated on first access
+    objects: Vec<Option<(NonNull<u8>, Layout)>>,
+    // Run in reverse order of registration
+    dtors: Vec<(Dtor, *mut u8)>,
+}
+
+// This thread's data, created on first use
+//
+// Callers keep the borrow short and never call out to user code with it
+// held: destructors may register more destructors or touch TLS.
+fn current() -> *mut ThreadData {
+    let data = slot::get() as *mut ThreadData;
+    if !data.is_null() {
+        return data;
+    }
+    let data = Box::into_raw(Box::new(ThreadData {
+        objects: Vec::new(),
+        dtors: Vec::new(),
+    }));
+    slot::set(data as *mut u8);
+    data
+}
+
+/// Address of TLS object `index` (from 1) for this thread
+///
+/// `allocate` creates the object on the thread's first access.
+pub(crate) fn object(index: usize, allocate: impl FnOnce() -> (NonNull<u8>, Layout)) -> *mut u8 {
+    let data = unsafe { &mut *current() };
+    if data.objects.len() < index {
+        data.objects.resize(index, None);
+    }
+    let entry = &mut data.objects[index - 1];
+    match entry {
+        Some((object, _)) => object.as_ptr(),
+        None => entry.insert(allocate()).0.as_ptr(),
+    }
+}
+
+/// Call `dtor(object)` when this thread exits
+///
+/// Destructors run in reverse order of registration, from
+/// [`run_thread_dtors`]. On the thread that calls `exit()` they run from
+/// an atexit handler.
+pub fn register_dtor(object: *mut u8, dtor: Dtor) {
+    crate::register_exit_hook();
+    let data = unsafe { &mut *current() };
+    data.dtors.push((dtor, object));
+}
+
+/// Run this thread's TLS destructors and free its TLS objects
+///
+/// Thread entry wrappers call this as the thread's last action (see
+/// [`run_thread`] and [`crate::thread_exit`]). Destructors registered while it runs are run
+/// too. Calling it again later, or on a thread that never used TLS, does
+/// nothing.
+pub fn run_thread_dtors() {
+    let data = slot::get() as *mut ThreadData;
+    if data.is_null() {
+        return;
+    }
+    loop {
+        let next = unsafe { (*data).dtors.pop() };
+        match next {
+            Some((dtor, object)) => unsafe { dtor(object) },
+            None => break,
+        }
+    }
+    slot::set(ptr::null_mut());
+    let data = unsafe { Box::from_raw(data) };
+    for (object, layout) in data.objects.into_iter().flatten() {
+        unsafe { dealloc(object.as_ptr(), layout) };
+    }
+}
+
+/// Run `f` as a thread body, then this thread's TLS destructors
+///
+/// The body of every thread that uses TLS goes through this, so a thread
+/// that simply returns still runs its destructors and frees its objects.
+/// [`crate::spawn`] wraps it around `cthread_fork()`.
+pub fn run_thread<R>(f: impl FnOnce() -> R) -> R {
+    let result = f();
+    run_thread_dtors();
+    result
+}
+
+/// `__cxa_thread_atexit_impl`, which std-like layers use for TLS destructors
+///
+/// # Safety
+///
+/// `dtor` must be safe to call with `object` when the thread exits.
+// Not exported on the host, where the C library's version serves std
+#[cfg_attr(target_arch = "m68k", no_mangle)]
+pub unsafe extern "C" fn __cxa_thread_atexit_impl(dtor: Dtor, object: *mut u8, _dso_handle: *mut u8) -> i32 {
+    register_dtor(object, dtor);
+    0
+}
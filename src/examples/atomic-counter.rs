@@ -8,6 +8,7 @@
 extern crate nextstep_alloc;
 extern crate nextstep_atomics;
 extern crate nextstep_io;
+extern crate nextstep_panic;
 
 use nextstep_io::{println, eprintln};
 use core::panic::PanicInfo;
@@ -28,6 +29,7 @@ const SEQ_CST: i32 = 5;
 
 #[no_mangle]
 pub extern "C" fn main() -> i32 {
+    nextstep_panic::set_hook(report_failure);
     println!("=== NeXTRust Atomic Operations Test ===");
     
     unsafe {
@@ -107,9 +109,8 @@ pub extern "C" fn main() -> i32 {
     0
 }
 
-#[panic_handler]
-fn panic(info: &PanicInfo) -> ! {
-    eprintln!("PANIC: {}", info);
+// Mark panics as test failures for the emulator harness
+fn report_failure(info: &PanicInfo) {
+    nextstep_panic::default_hook(info);
     eprintln!("TEST_FAIL");
-    nextstep_sys::sys_exit(1);
 }
\ No newline at end of file
@@ -7,11 +7,11 @@
 extern crate alloc;
 extern crate nextstep_alloc;
 extern crate nextstep_io;
+extern crate nextstep_panic;
 
 use alloc::string::String;
 use alloc::vec::Vec;
-use nextstep_io::{println, eprintln};
-use core::panic::PanicInfo;
+use nextstep_io::println;
 
 #[no_mangle]
 pub extern "C" fn main() -> i32 {
@@ -34,9 +34,3 @@ pub extern "C" fn main() -> i32 {
     
     0
 }
-
-#[panic_handler]
-fn panic(info: &PanicInfo) -> ! {
-    eprintln!("PANIC: {}", info);
-    nextstep_sys::sys_exit(1);
-}
\ No newline at end of file
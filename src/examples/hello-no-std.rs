@@ -3,9 +3,10 @@
 
 use core::panic::PanicInfo;
 
+// Keeps its own handler: this example makes raw traps and links no
+// libSystem, which nextstep-panic needs for write() and _exit()
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
-    // In a real implementation, we might write to console or halt
     loop {}
 }
 
@@ -8,18 +8,22 @@
 pub extern "C" fn _start() -> ! {
     // Direct assembly to avoid any runtime dependencies
     unsafe {
-        // Write "OK\n" to stdout using NeXT syscalls
+        // Write "OK\n" to stdout using NeXT syscalls, which take their
+        // arguments on the stack above a return address, as libsys's
+        // stubs leave them, and the call number in d0
         core::arch::asm!(
+            "move.l #3, -(sp)",   // length
+            "pea message",        // buffer
+            "move.l #1, -(sp)",   // stdout
+            "clr.l -(sp)",        // return address slot
             "move.l #4, d0",      // write syscall
-            "move.l #1, d1",      // stdout
-            "lea message, a0",    // message address
-            "move.l a0, d2",      // buffer
-            "move.l #3, d3",      // length
             "trap #0",            // syscall
+            "add.l #16, sp",      // pop the arguments
             
             // Exit
+            "clr.l -(sp)",        // status code
+            "clr.l -(sp)",        // return address slot
             "move.l #1, d0",      // exit syscall
-            "move.l #0, d1",      // status code
             "trap #0",            // syscall
             
             "message:",
@@ -29,8 +33,9 @@ pub extern "C" fn _start() -> ! {
     }
 }
 
+// Keeps its own handler: this example links nothing, not even libSystem,
+// which nextstep-panic needs for write() and _exit()
 #[panic_handler]
 fn panic(_: &core::panic::PanicInfo) -> ! {
-    // Simple panic handler - just loop forever
     loop {}
 }
\ No newline at end of file
@@ -4,18 +4,10 @@
 #![no_std]
 #![no_main]
 
+extern crate nextstep_panic;
 extern crate nextstep_sys;
 
-use core::panic::PanicInfo;
-use nextstep_sys::{sys_write, sys_exit, STDOUT_FILENO, STDERR_FILENO};
-
-#[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    // Write error message and exit
-    let msg = b"PANIC\n";
-    let _ = sys_write(STDERR_FILENO, msg);
-    sys_exit(1);
-}
+use nextstep_sys::{sys_write, sys_exit, STDOUT_FILENO};
 
 #[no_mangle]
 pub extern "C" fn main() -> i32 {
@@ -1,26 +1,19 @@
 //! atomic_smoke.rs - Basic atomic operations test for m68k-next-nextstep
 //! Tests that our spin-lock based atomics work correctly
+//!
+//! `AtomicU32` and friends only exist on the CPU-specific target specs
+//! (`target-specs/m68k-next-nextstep-68030.json`, `-68040.json`), which set
+//! `max-atomic-width: 32`. The generic spec keeps `max-atomic-width: 0` so
+//! its code still runs on a 68000; there, use the nextstep-atomics
+//! libcalls directly.
 
 #![no_std]
 #![no_main]
 
-use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
-use core::panic::PanicInfo;
+// Failed assertions are reported by the standard panic handler
+extern crate nextstep_panic;
 
-#[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    // Write error to console using nextstep-sys
-    extern "C" {
-        fn write(fd: i32, buf: *const u8, count: usize) -> isize;
-        fn _exit(status: i32) -> !;
-    }
-    
-    let msg = b"PANIC\n";
-    unsafe {
-        write(2, msg.as_ptr(), msg.len());
-        _exit(1);
-    }
-}
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
 
 #[no_mangle]
 pub extern "C" fn main() -> i32 {
@@ -0,0 +1,110 @@
+//! atomic-probe - Every core atomic operation, for the symbol audit
+//!
+//! Built by `ci/scripts/audit-atomic-symbols.sh` for each target spec. Each
+//! operation sits in its own public function so it is code-generated into
+//! the rlib, and the audit then lists the `__atomic_*`/`__sync_*` libcalls
+//! the objects reference. Widths the target spec does not enable are
+//! skipped via `target_has_atomic`.
+
+#![no_std]
+
+use core::sync::atomic::{fence, Ordering};
+
+macro_rules! probe_width {
+    ($width:literal, $module:ident, $atomic:ident, $int:ty) => {
+        #[cfg(target_has_atomic = $width)]
+        pub mod $module {
+            use super::Ordering;
+            use core::sync::atomic::$atomic;
+
+            pub fn load(a: &$atomic) -> $int {
+                a.load(Ordering::SeqCst)
+            }
+
+            pub fn load_relaxed(a: &$atomic) -> $int {
+                a.load(Ordering::Relaxed)
+            }
+
+            pub fn store(a: &$atomic, v: $int) {
+                a.store(v, Ordering::SeqCst)
+            }
+
+            pub fn store_release(a: &$atomic, v: $int) {
+                a.store(v, Ordering::Release)
+            }
+
+            pub fn swap(a: &$atomic, v: $int) -> $int {
+                a.swap(v, Ordering::SeqCst)
+            }
+
+            pub fn compare_exchange(a: &$atomic, old: $int, new: $int) -> Result<$int, $int> {
+                a.compare_exchange(old, new, Ordering::AcqRel, Ordering::Acquire)
+            }
+
+            pub fn compare_exchange_weak(a: &$atomic, old: $int, new: $int) -> Result<$int, $int> {
+                a.compare_exchange_weak(old, new, Ordering::SeqCst, Ordering::Relaxed)
+            }
+
+            pub fn fetch_add(a: &$atomic, v: $int) -> $int {
+                a.fetch_add(v, Ordering::SeqCst)
+            }
+
+            pub fn fetch_sub(a: &$atomic, v: $int) -> $int {
+                a.fetch_sub(v, Ordering::SeqCst)
+            }
+
+            pub fn fetch_and(a: &$atomic, v: $int) -> $int {
+                a.fetch_and(v, Ordering::SeqCst)
+            }
+
+            pub fn fetch_nand(a: &$atomic, v: $int) -> $int {
+                a.fetch_nand(v, Ordering::SeqCst)
+            }
+
+            pub fn fetch_or(a: &$atomic, v: $int) -> $int {
+                a.fetch_or(v, Ordering::SeqCst)
+            }
+
+            pub fn fetch_xor(a: &$atomic, v: $int) -> $int {
+                a.fetch_xor(v, Ordering::SeqCst)
+            }
+
+            pub fn fetch_max(a: &$atomic, v: $int) -> $int {
+                a.fetch_max(v, Ordering::SeqCst)
+            }
+
+            pub fn fetch_min(a: &$atomic, v: $int) -> $int {
+                a.fetch_min(v, Ordering::SeqCst)
+            }
+
+            pub fn fetch_update(a: &$atomic) -> Result<$int, $int> {
+                a.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |v| Some(v.wrapping_mul(3)))
+            }
+        }
+    };
+}
+
+probe_width!("8", atomic_u8, AtomicU8, u8);
+probe_width!("8", atomic_i8, AtomicI8, i8);
+probe_width!("16", atomic_u16, AtomicU16, u16);
+probe_width!("16", atomic_i16, AtomicI16, i16);
+probe_width!("32", atomic_u32, AtomicU32, u32);
+probe_width!("32", atomic_i32, AtomicI32, i32);
+probe_width!("64", atomic_u64, AtomicU64, u64);
+probe_width!("64", atomic_i64, AtomicI64, i64);
+
+#[cfg(target_has_atomic = "8")]
+pub fn bool_fetch_or(a: &core::sync::atomic::AtomicBool) -> bool {
+    a.fetch_or(true, Ordering::SeqCst)
+}
+
+#[cfg(target_has_atomic = "ptr")]
+pub fn ptr_swap(a: &core::sync::atomic::AtomicPtr<u8>, p: *mut u8) -> *mut u8 {
+    a.swap(p, Ordering::SeqCst)
+}
+
+pub fn fences() {
+    fence(Ordering::Acquire);
+    fence(Ordering::Release);
+    fence(Ordering::SeqCst);
+}
@@ -0,0 +1,64 @@
+//! stabs-probe - A debug executable for the STABS check
+//!
+//! Built and linked by `ci/scripts/check-stabs-debug-info.sh`, which then
+//! requires function stabs with line entries for the `probe_*` functions
+//! and type stabs for `Point` and `Shape`. The functions are unmangled so
+//! their stab names do not depend on the compiler version. Nothing here
+//! is meant to run.
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+pub enum Shape {
+    Dot(Point),
+    Line(Point, Point),
+}
+
+static mut COUNTER: u32 = 0;
+
+#[no_mangle]
+pub extern "C" fn probe_add(a: i32, b: i32) -> i32 {
+    // A local variable, for a stab of its own
+    let sum = a.wrapping_add(b);
+    sum
+}
+
+#[no_mangle]
+pub extern "C" fn probe_length(shape: &Shape) -> i32 {
+    match shape {
+        Shape::Dot(_) => 0,
+        Shape::Line(from, to) => {
+            let dx = probe_add(to.x, -from.x);
+            let dy = probe_add(to.y, -from.y);
+            dx.abs() + dy.abs()
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn probe_count() -> u32 {
+    unsafe {
+        COUNTER += 1;
+        COUNTER
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let line = Shape::Line(Point { x: 1, y: 2 }, Point { x: 4, y: 6 });
+    let _ = probe_length(&line);
+    let _ = probe_count();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    loop {}
+}